@@ -23,6 +23,18 @@ except ImportError as e:
     sys.exit(1)
 
 
+def unrpyc_version():
+    """Version of the vendored unrpyc, read without running its CLI."""
+    try:
+        source = (VENDOR_DIR / "unrpyc.py").read_text(encoding="utf-8")
+    except OSError:
+        return None
+    for line in source.splitlines():
+        if line.startswith("__version__"):
+            return line.split("=", 1)[1].strip().strip("'\"")
+    return None
+
+
 def read_ast_from_file(in_file):
     """Read AST from RPYC file."""
     raw_contents = in_file.read()
@@ -56,9 +68,50 @@ def read_ast_from_file(in_file):
     return stmts
 
 
-def decompile_file(input_path: Path, output_path: Path) -> dict:
+def ast_to_json(node, seen=None):
+    """Convert an unpickled AST into JSON-compatible data."""
+    if seen is None:
+        seen = set()
+
+    if node is None or isinstance(node, (bool, int, float)):
+        return node
+    if isinstance(node, str):
+        # PyExpr and friends are str subclasses carrying extra attributes
+        return str(node)
+    if isinstance(node, bytes):
+        return node.decode("utf-8", errors="replace")
+    if isinstance(node, (list, tuple, set, frozenset)):
+        return [ast_to_json(item, seen) for item in node]
+    if isinstance(node, dict):
+        return {str(key): ast_to_json(value, seen) for key, value in node.items()}
+
+    if id(node) in seen:
+        return {"_ref": type(node).__name__}
+    seen.add(id(node))
+
+    result = {"_type": f"{type(node).__module__}.{type(node).__name__}"}
+    state = dict(getattr(node, "__dict__", None) or {})
+    for slot in getattr(type(node), "__slots__", ()):
+        if hasattr(node, slot):
+            state.setdefault(slot, getattr(node, slot))
+    for key, value in state.items():
+        # `next` links every statement to its successor and would inline the whole script
+        if key == "next":
+            continue
+        result[key] = ast_to_json(value, seen)
+
+    seen.discard(id(node))
+    return result
+
+
+def decompile_file(input_path: Path, output_path: Path, ast_json: bool = False) -> dict:
     """Decompile a single RPYC file."""
-    result = {"input": str(input_path), "output": str(output_path), "success": False}
+    result = {
+        "input": str(input_path),
+        "output": str(output_path),
+        "success": False,
+        "unrpyc_version": unrpyc_version(),
+    }
 
     try:
         with open(input_path, "rb") as f:
@@ -70,6 +123,12 @@ def decompile_file(input_path: Path, output_path: Path) -> dict:
             options = decompiler.Options()
             decompiler.pprint(out_file, ast, options)
 
+        if ast_json:
+            ast_path = output_path.with_suffix(".ast.json")
+            with open(ast_path, "w", encoding="utf-8") as ast_file:
+                json.dump(ast_to_json(ast), ast_file, ensure_ascii=False, indent=2)
+            result["ast"] = str(ast_path)
+
         result["success"] = True
 
     except Exception as e:
@@ -79,14 +138,18 @@ def decompile_file(input_path: Path, output_path: Path) -> dict:
 
 
 def main():
-    if len(sys.argv) < 2:
-        print(json.dumps({"error": "Usage: decompile.py <input> [output]"}))
+    args = sys.argv[1:]
+    ast_json = "--ast-json" in args
+    args = [a for a in args if a != "--ast-json"]
+
+    if len(args) < 1:
+        print(json.dumps({"error": "Usage: decompile.py [--ast-json] <input> [output]"}))
         sys.exit(1)
 
-    input_path = Path(sys.argv[1])
+    input_path = Path(args[0])
     
-    if len(sys.argv) >= 3:
-        output_path = Path(sys.argv[2])
+    if len(args) >= 2:
+        output_path = Path(args[1])
     else:
         # Default output: same name with .rpy extension
         if input_path.suffix == ".rpyc":
@@ -96,7 +159,7 @@ def main():
         else:
             output_path = input_path.with_suffix(".rpy")
 
-    result = decompile_file(input_path, output_path)
+    result = decompile_file(input_path, output_path, ast_json)
     print(json.dumps(result))
     sys.exit(0 if result["success"] else 1)
 
@@ -7,11 +7,22 @@ use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
 use crate::cli::{AutoArgs, DecompileArgs, PatchArgs};
+use crate::config::Config;
 use crate::decompile;
 use crate::patch;
-use crate::unpack::rpa::RpaArchive;
+use crate::translate::extractor::TextExtractor;
+use crate::translate::llm::LlmProvider;
+use crate::translate::machine_translate::{DEEPL_BATCH_SIZE, GOOGLE_BATCH_SIZE};
+use crate::translate::renpy_tl::RenpyTranslationGenerator;
+use crate::translate::report_replaced;
+use crate::unpack::rpa::{EntryOrder, RpaArchive};
+use crate::utils::{decode_lossy, is_tl_dir};
+
+pub fn run(args: AutoArgs, cfg: &Config) -> Result<()> {
+    if args.dry_run {
+        return print_plan(&args);
+    }
 
-pub fn run(args: AutoArgs) -> Result<()> {
     println!(
         "{}",
         "[Auto] Starting automatic translation workflow".green()
@@ -35,7 +46,7 @@ pub fn run(args: AutoArgs) -> Result<()> {
             archive.version,
             archive.file_count()
         );
-        archive.extract_all(&extract_dir, None)?;
+        archive.extract_all(&extract_dir, None, EntryOrder::Offset)?;
         println!("  Extracted to: {}", extract_dir.display());
 
         work_dir = extract_dir.clone();
@@ -62,9 +73,17 @@ pub fn run(args: AutoArgs) -> Result<()> {
             output: None,
             recursive: true,
             force: true,
+            ast_json: false,
+            no_cache: false,
         };
 
         decompile::run(decompile_args)?;
+    } else if !rpy_files.is_empty() && rpyc_files.is_empty() && !is_rpa_file(input) {
+        println!(
+            "\n{}",
+            "[Step 2/3] Plain .rpy sources (no .rpyc or archives), no decompilation needed".cyan()
+        );
+        println!("  Found {} RPY file(s)", rpy_files.len());
     } else if !rpy_files.is_empty() {
         println!(
             "\n{}",
@@ -78,32 +97,58 @@ pub fn run(args: AutoArgs) -> Result<()> {
     // Step 3: Generate translation patch
     println!("\n{}", "[Step 3/3] Generating translation patch...".cyan());
 
-    let output_dir = args.output.unwrap_or_else(|| {
-        if args.input.is_dir() {
-            args.input.join("game")
-        } else {
-            let stem = args
-                .input
-                .file_stem()
-                .map(|s| s.to_string_lossy().to_string())
-                .unwrap_or_else(|| "game".to_string());
-            PathBuf::from(format!("{}_translation", stem))
-        }
-    });
+    let output_dir = args
+        .output
+        .clone()
+        .unwrap_or_else(|| default_output_dir(&args.input));
 
     let patch_args = PatchArgs {
         input: work_dir,
         output: Some(output_dir.clone()),
         lang: args.lang,
+        lang_name: args.lang_name,
         api: args.api,
         api_key: args.api_key,
         api_base: args.api_base,
         model: args.model,
+        script: args.script,
+        batch_size: args.batch_size,
+        max_failures: args.max_failures,
+        wait_for_recovery: args.wait_for_recovery,
+        recovery_poll: args.recovery_poll,
+        min_concurrency: args.min_concurrency,
+        max_concurrency: args.max_concurrency,
+        max_concurrent_files: args.max_concurrent_files,
+        source_lang: args.source_lang,
+        ca_cert: args.ca_cert,
+        length_hint: args.length_hint,
+        context_file: args.context_file,
+        prompt_log: args.prompt_log,
+        json_mode: args.json_mode,
+        batch_translate: args.batch_translate,
+        files: Vec::new(),
         template_only: args.template_only,
+        strings_only: false,
+        dialogue_only: false,
         glossary: args.glossary,
+        translate_names: args.translate_names,
+        translate_defines: args.translate_defines,
+        fuzzy_cache: args.fuzzy_cache,
+        preview_tags: args.preview_tags,
+        revert_bad_tags: args.revert_bad_tags,
+        validate: args.validate,
+        back_check: args.back_check,
+        flatten_tl: args.flatten_tl,
+        target_dir_layout: args.target_dir_layout,
+        encoding: args.encoding,
+        output_encoding: args.output_encoding,
+        force: args.force,
+        merge: args.merge,
+        manifest: args.manifest,
+        dry_run_prompts: None,
     };
 
-    patch::run(patch_args)?;
+    patch::run(patch_args, cfg)?;
 
     // Cleanup temporary files
     if !args.keep_temp {
@@ -120,13 +165,156 @@ pub fn run(args: AutoArgs) -> Result<()> {
     Ok(())
 }
 
+/// Walk through the workflow without unpacking, decompiling, translating or writing anything.
+/// Scripts inside an archive are read in memory; compiled-only games cannot be counted.
+fn print_plan(args: &AutoArgs) -> Result<()> {
+    println!(
+        "{}",
+        "[Auto] Dry run: nothing is unpacked, decompiled, translated or written".green()
+    );
+
+    let input = &args.input;
+    let encoding = encoding_rs::Encoding::for_label(args.encoding.as_bytes())
+        .with_context(|| format!("Unknown encoding: {}", args.encoding))?;
+    // (path, raw source) of every .rpy, and the number of compiled scripts
+    let (scripts, rpyc_count): (Vec<(PathBuf, Vec<u8>)>, usize) = if is_rpa_file(input) {
+        let archive = RpaArchive::open(input).context("Failed to open RPA archive")?;
+        println!("\n{}", "[Step 1/3] Would unpack RPA archive".cyan());
+        println!(
+            "  Version: {}, Files: {}",
+            archive.version,
+            archive.file_count()
+        );
+
+        let mut names: Vec<&String> = archive
+            .index
+            .keys()
+            .filter(|name| !name.starts_with("tl/") && !name.contains("/tl/"))
+            .collect();
+        names.sort();
+        let has_ext = |name: &str, exts: &[&str]| {
+            Path::new(name)
+                .extension()
+                .is_some_and(|e| exts.iter().any(|x| e == *x))
+        };
+        let rpyc_count = names
+            .iter()
+            .filter(|n| has_ext(n, &["rpyc", "rpymc"]))
+            .count();
+        let mut scripts = Vec::new();
+        for name in names.into_iter().filter(|n| has_ext(n, &["rpy", "rpym"])) {
+            scripts.push((PathBuf::from(name), archive.read_file_bytes(name)?));
+        }
+        (scripts, rpyc_count)
+    } else if input.is_dir() {
+        println!("\n{}", "[Step 1/3] Would use directory as input".cyan());
+        println!("  Path: {}", input.display());
+        let mut scripts = Vec::new();
+        for path in find_rpy_files(input) {
+            let content =
+                fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            let rel = path.strip_prefix(input).unwrap_or(&path).to_path_buf();
+            scripts.push((rel, content));
+        }
+        (scripts, find_rpyc_files(input).len())
+    } else {
+        anyhow::bail!("Input must be an RPA file or directory");
+    };
+
+    if rpyc_count > 0 && scripts.is_empty() {
+        println!("\n{}", "[Step 2/3] Would decompile RPYC scripts".cyan());
+        println!("  {} RPYC file(s)", rpyc_count);
+        println!(
+            "{}",
+            "  Dialogue can only be counted after decompiling; run `derenpy decompile` first for a full estimate"
+                .yellow()
+        );
+        return Ok(());
+    } else if scripts.is_empty() {
+        println!("\n{}", "[Step 2/3] No scripts found".yellow());
+        return Ok(());
+    }
+    println!(
+        "\n{}",
+        "[Step 2/3] RPY files found, no decompilation needed".cyan()
+    );
+    println!("  Found {} RPY file(s)", scripts.len());
+
+    println!("\n{}", "[Step 3/3] Would generate translation patch".cyan());
+    let generator = RenpyTranslationGenerator::new(args.lang_name.as_deref().unwrap_or(&args.lang));
+    let extractor = TextExtractor::new()
+        .with_character_names(args.translate_names)
+        .with_defines(args.translate_defines);
+    let mut texts: Vec<String> = Vec::new();
+    let (mut dialogues, mut strings) = (0, 0);
+    let mut replaced = Vec::new();
+    for (rel, bytes) in &scripts {
+        let (content, replaced_chars) = decode_lossy(bytes, encoding);
+        if replaced_chars > 0 {
+            replaced.push((rel.clone(), replaced_chars));
+        }
+        let (d, s) = patch::extract_script_str(&generator, &extractor, &content, rel);
+        dialogues += d.len();
+        strings += s.len();
+        texts.extend(d.into_iter().map(|d| d.original_text));
+        texts.extend(s.into_iter().map(|s| s.original));
+    }
+    let chars: usize = texts.iter().map(|t| t.chars().count()).sum();
+    println!("  Total: {} dialogues, {} strings", dialogues, strings);
+    report_replaced(&replaced, encoding);
+    println!("  Characters: {}", chars);
+
+    if args.template_only {
+        println!("  Template only: no translation requests");
+    } else {
+        let requests = estimated_requests(args, texts.len());
+        println!(
+            "  Estimated requests: {} ({}, before cache hits)",
+            requests, args.api
+        );
+    }
+    println!(
+        "  Output: {}",
+        args.output
+            .clone()
+            .unwrap_or_else(|| default_output_dir(input))
+            .display()
+    );
+
+    Ok(())
+}
+
+/// Requests a translation of `count` texts takes with the chosen provider and batching
+fn estimated_requests(args: &AutoArgs, count: usize) -> usize {
+    match LlmProvider::from_str(&args.api) {
+        LlmProvider::Google => count.div_ceil(GOOGLE_BATCH_SIZE),
+        LlmProvider::DeepL => count.div_ceil(args.batch_size.unwrap_or(DEEPL_BATCH_SIZE).max(1)),
+        _ => count.div_ceil(args.batch_translate.unwrap_or(1).max(1)),
+    }
+}
+
+fn default_output_dir(input: &Path) -> PathBuf {
+    if input.is_dir() {
+        patch::game_dir_for(input)
+    } else {
+        let stem = input
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "game".to_string());
+        PathBuf::from(format!("{}_translation", stem))
+    }
+}
+
+/// Archives are recognized by their header too, since some games rename them to `.dat`
 fn is_rpa_file(path: &Path) -> bool {
-    path.is_file() && path.extension().map(|e| e == "rpa").unwrap_or(false)
+    path.is_file()
+        && (path.extension().map(|e| e == "rpa").unwrap_or(false) || RpaArchive::has_magic(path))
 }
 
 fn find_rpyc_files(dir: &Path) -> Vec<PathBuf> {
     WalkDir::new(dir)
         .into_iter()
+        .filter_entry(|e| !is_tl_dir(e))
         .filter_map(|e| e.ok())
         .filter(|e| {
             e.path()
@@ -141,6 +329,7 @@ fn find_rpyc_files(dir: &Path) -> Vec<PathBuf> {
 fn find_rpy_files(dir: &Path) -> Vec<PathBuf> {
     WalkDir::new(dir)
         .into_iter()
+        .filter_entry(|e| !is_tl_dir(e))
         .filter_map(|e| e.ok())
         .filter(|e| {
             e.path()
@@ -0,0 +1,155 @@
+//! Translation cache management commands
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::fs;
+use std::path::Path;
+
+use crate::cli::{CacheAction, CacheArgs};
+use crate::translate::cache::{CacheEntry, RepairOutcome, TranslationCache};
+use crate::utils::{format_timestamp, parse_timestamp};
+
+pub fn run(args: CacheArgs) -> Result<()> {
+    match args.action {
+        CacheAction::Stats => show_stats(),
+        CacheAction::Clear => clear_cache(),
+        CacheAction::Repair => repair_cache(),
+        CacheAction::List { since, json } => list_entries(since.as_deref(), json),
+        CacheAction::Delete {
+            source,
+            lang,
+            provider,
+        } => delete_entry(&source, &lang, &provider),
+        CacheAction::Export { file } => export_cache(&file),
+        CacheAction::Import { file } => import_cache(&file),
+    }
+}
+
+fn show_stats() -> Result<()> {
+    let cache = TranslationCache::open()?;
+    let stats = cache.stats()?;
+
+    println!("{}", "[Cache]".green());
+    println!("  Total entries: {}", stats.total_entries);
+    for (provider, count) in &stats.providers {
+        println!("    {}: {}", provider, count);
+    }
+
+    Ok(())
+}
+
+fn clear_cache() -> Result<()> {
+    let cache = TranslationCache::open()?;
+    cache.clear()?;
+    println!("{}", "[Cache] Cleared".green());
+    Ok(())
+}
+
+fn repair_cache() -> Result<()> {
+    match TranslationCache::repair()? {
+        RepairOutcome::Healthy => println!("{}", "[Cache] No problems found".green()),
+        RepairOutcome::Repaired { backup, salvaged } => {
+            println!(
+                "{}",
+                format!(
+                    "[Cache] Rebuilt the cache with {} salvaged entries",
+                    salvaged
+                )
+                .green()
+            );
+            println!("  Damaged database kept at {}", backup.display());
+        }
+    }
+    Ok(())
+}
+
+fn delete_entry(source: &str, lang: &str, provider: &str) -> Result<()> {
+    let cache = TranslationCache::open()?;
+    if cache.delete(source, lang, provider)? {
+        println!(
+            "{}",
+            format!("[Cache] Deleted [{}/{}] {}", provider, lang, source).green()
+        );
+    } else {
+        println!(
+            "{}",
+            format!(
+                "[WARN] No cached translation for [{}/{}] {}",
+                provider, lang, source
+            )
+            .yellow()
+        );
+    }
+    Ok(())
+}
+
+fn list_entries(since: Option<&str>, json: bool) -> Result<()> {
+    let since_ts = since
+        .map(|s| {
+            parse_timestamp(s).with_context(|| format!("Invalid date: {} (expected YYYY-MM-DD)", s))
+        })
+        .transpose()?;
+
+    let cache = TranslationCache::open()?;
+    let entries = cache.entries_since(since_ts)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+        return Ok(());
+    }
+
+    println!("{}", format!("[Cache] {} entries", entries.len()).green());
+    for entry in &entries {
+        let created = entry
+            .created_at
+            .map(format_timestamp)
+            .unwrap_or_else(|| "-".to_string());
+        println!(
+            "  {} [{}/{}] {} -> {}",
+            created, entry.provider, entry.lang, entry.source, entry.translated
+        );
+    }
+
+    Ok(())
+}
+
+fn export_cache(file: &Path) -> Result<()> {
+    let cache = TranslationCache::open()?;
+    let entries = cache.export_entries()?;
+
+    let content = serde_json::to_string_pretty(&entries).context("Failed to serialize cache")?;
+    fs::write(file, content).context("Failed to write export file")?;
+
+    println!(
+        "{}",
+        format!(
+            "[Cache] Exported {} entries -> {}",
+            entries.len(),
+            file.display()
+        )
+        .green()
+    );
+
+    Ok(())
+}
+
+fn import_cache(file: &Path) -> Result<()> {
+    let content = fs::read_to_string(file).context("Failed to read import file")?;
+    let entries: Vec<CacheEntry> =
+        serde_json::from_str(&content).context("Failed to parse import file")?;
+
+    let mut cache = TranslationCache::open()?;
+    let inserted = cache.import_entries(&entries)?;
+
+    println!(
+        "{}",
+        format!(
+            "[Cache] Imported {} new entries ({} already cached)",
+            inserted,
+            entries.len() - inserted
+        )
+        .green()
+    );
+
+    Ok(())
+}
@@ -5,6 +5,10 @@ use std::path::PathBuf;
 #[command(name = "derenpy")]
 #[command(author, version, about = "Renpy game reverse engineering and translation toolkit", long_about = None)]
 pub struct Cli {
+    /// Use this config file instead of the one in the platform config directory
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -29,8 +33,14 @@ pub enum Commands {
     /// Manage configuration
     Config(ConfigArgs),
 
+    /// Manage the translation cache
+    Cache(CacheArgs),
+
     /// Auto workflow: unpack, decompile, and translate in one command
     Auto(AutoArgs),
+
+    /// Compare the files in two RPA archives
+    Diff(DiffArgs),
 }
 
 #[derive(Parser, Debug)]
@@ -72,6 +82,76 @@ pub enum ConfigAction {
     Edit,
 }
 
+#[derive(Parser, Debug)]
+pub struct CacheArgs {
+    #[command(subcommand)]
+    pub action: CacheAction,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheAction {
+    /// Show cache statistics
+    Stats,
+
+    /// Delete all cached translations
+    Clear,
+
+    /// Check the cache database and rebuild it from its readable entries if damaged
+    Repair,
+
+    /// List cached translations
+    List {
+        /// Only show entries created since this date (YYYY-MM-DD or unix timestamp)
+        #[arg(long)]
+        since: Option<String>,
+
+        /// Output as JSON
+        #[arg(long, default_value_t = false)]
+        json: bool,
+    },
+
+    /// Delete a single cached translation so the next run fetches it again
+    Delete {
+        /// Source text of the cached translation
+        source: String,
+
+        /// Target language it was cached under (e.g., zh-CN)
+        #[arg(short, long)]
+        lang: String,
+
+        /// Provider it was cached under (google, deepl)
+        #[arg(long)]
+        provider: String,
+    },
+
+    /// Export all cached translations to a JSON file
+    Export {
+        /// Output JSON file
+        file: PathBuf,
+    },
+
+    /// Import translations from a JSON file (existing entries are kept)
+    Import {
+        /// Input JSON file
+        file: PathBuf,
+    },
+}
+
+#[derive(Parser, Debug)]
+pub struct DiffArgs {
+    /// Original RPA archive
+    #[arg(required = true)]
+    pub old: PathBuf,
+
+    /// Modified RPA archive
+    #[arg(required = true)]
+    pub new: PathBuf,
+
+    /// List every added, removed and modified file
+    #[arg(short, long, default_value_t = false)]
+    pub verbose: bool,
+}
+
 #[derive(Parser, Debug)]
 pub struct UnpackArgs {
     /// Input RPA file or directory containing RPA files
@@ -89,6 +169,54 @@ pub struct UnpackArgs {
     /// Overwrite existing files
     #[arg(short, long, default_value_t = false)]
     pub force: bool,
+
+    /// Order in which entries are extracted (offset, name)
+    #[arg(long, default_value = "offset", value_parser = ["offset", "name"])]
+    pub sort: String,
+
+    /// Only extract entries matching this glob (repeatable, e.g. "images/**/*.png")
+    #[arg(long)]
+    pub include: Vec<String>,
+
+    /// Skip entries matching this glob (repeatable)
+    #[arg(long)]
+    pub exclude: Vec<String>,
+
+    /// Only extract scripts (.rpy, .rpyc, .rpym, .rpymc)
+    #[arg(long, default_value_t = false)]
+    pub scripts_only: bool,
+
+    /// Only extract images (.png, .jpg, .jpeg, .webp, .avif, .gif, .bmp)
+    #[arg(long, default_value_t = false)]
+    pub images_only: bool,
+
+    /// Only extract audio (.ogg, .opus, .mp3, .wav, .flac, .m4a)
+    #[arg(long, default_value_t = false)]
+    pub audio_only: bool,
+
+    /// Only parse the index and print version, file count, index offset and key
+    #[arg(long, default_value_t = false)]
+    pub info_only: bool,
+
+    /// Set extracted files' modification time to the archive's
+    #[arg(long, default_value_t = false)]
+    pub preserve_mtime: bool,
+
+    /// Keep files already extracted with the expected size, to resume an interrupted run
+    #[arg(long, default_value_t = false)]
+    pub skip_existing: bool,
+
+    /// Only extract the entries named in this file (one archive path per line)
+    #[arg(long, value_name = "FILE")]
+    pub from_list: Option<PathBuf>,
+
+    /// Treat the input file as an archive whatever its extension or leading bytes
+    #[arg(long, default_value_t = false)]
+    pub force_rpa: bool,
+
+    /// Debug: print the decoded pickle index of an archive as JSON, before any conversion
+    #[arg(long, default_value_t = false, hide = true)]
+    pub pretty_print_index: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -108,29 +236,55 @@ pub struct DecompileArgs {
     /// Overwrite existing files
     #[arg(short, long, default_value_t = false)]
     pub force: bool,
+
+    /// Also write each script's statement tree as <name>.ast.json
+    #[arg(long, default_value_t = false)]
+    pub ast_json: bool,
+
+    /// Always run the decompiler instead of reusing output cached for identical .rpyc files
+    #[arg(long, default_value_t = false)]
+    pub no_cache: bool,
 }
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 pub struct TranslateArgs {
+    #[command(subcommand)]
+    pub action: Option<TranslateAction>,
+
     /// Input script file or directory
     #[arg(required = true)]
-    pub input: PathBuf,
+    pub input: Option<PathBuf>,
 
     /// Output directory
     #[arg(short, long)]
     pub output: Option<PathBuf>,
 
+    /// Layout of files under the output directory (renpy: tl/<lang>/..., mirror: the input
+    /// tree, flat: one directory); defaults to mirror
+    #[arg(long, value_parser = ["renpy", "mirror", "flat"])]
+    pub target_dir_layout: Option<String>,
+
+    /// Encoding of the input .rpy scripts; undecodable bytes are replaced and reported
+    #[arg(long, default_value = "utf-8")]
+    pub encoding: String,
+
     /// Target language (e.g., zh-CN, en, ja)
     #[arg(short, long, default_value = "zh-CN")]
     pub lang: String,
 
+    /// Ren'Py language name for tl/<name>/ and `translate <name>` blocks, when it differs
+    /// from the provider's language code (e.g. --lang zh-CN --lang-name chinese)
+    #[arg(long)]
+    pub lang_name: Option<String>,
+
     /// API provider (openai, claude, ollama)
     #[arg(long, default_value = "openai")]
     pub api: String,
 
-    /// API key (can also be set via environment variable)
+    /// API key (can also be set via environment variable); repeat to rotate LLM requests over several keys
     #[arg(long)]
-    pub api_key: Option<String>,
+    pub api_key: Vec<String>,
 
     /// API base URL (for custom endpoints)
     #[arg(long)]
@@ -147,6 +301,149 @@ pub struct TranslateArgs {
     /// Generate Renpy translation files instead of modifying source
     #[arg(long, default_value_t = false)]
     pub patch_mode: bool,
+
+    /// Chinese script for Chinese targets (simplified, traditional)
+    #[arg(long, value_parser = ["simplified", "traditional"])]
+    pub script: Option<String>,
+
+    /// Maximum texts per DeepL request (default 50); long texts are split further by size
+    #[arg(long, value_parser = clap::value_parser!(usize))]
+    pub batch_size: Option<usize>,
+
+    /// Stop translating after this many consecutive failed requests (default 10, 0 disables)
+    #[arg(long, value_parser = clap::value_parser!(usize))]
+    pub max_failures: Option<usize>,
+
+    /// Instead of stopping at --max-failures, wait up to this many minutes for the provider to recover
+    #[arg(long, value_name = "MINUTES", value_parser = clap::value_parser!(u64))]
+    pub wait_for_recovery: Option<u64>,
+
+    /// Seconds between retries while waiting for recovery (default 60, doubling up to 8x)
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64))]
+    pub recovery_poll: Option<u64>,
+
+    /// Fewest concurrent Google requests when backing off from 429s (default 1)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(usize))]
+    pub min_concurrency: Option<usize>,
+
+    /// Most concurrent Google requests; the limit starts at 4 and grows while requests succeed (default 16)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(usize))]
+    pub max_concurrency: Option<usize>,
+
+    /// Number of script files processed in parallel
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(usize))]
+    pub max_concurrent_files: usize,
+
+    /// Only process scripts matching this glob (repeatable, e.g. "chapter*.rpy")
+    #[arg(long, value_name = "GLOB")]
+    pub files: Vec<String>,
+
+    /// Language of the game text for Google/DeepL (detected from the scripts when omitted)
+    #[arg(long)]
+    pub source_lang: Option<String>,
+
+    /// Additional root certificate (PEM) to trust, for TLS-inspecting proxies
+    #[arg(long, value_name = "PEM")]
+    pub ca_cert: Option<PathBuf>,
+
+    /// Ask LLMs to keep menu choices and screen text about as long as the source
+    #[arg(long, default_value_t = false)]
+    pub length_hint: bool,
+
+    /// Background notes (characters, setting, tone) prepended to the LLM system prompt
+    #[arg(long, value_name = "MD")]
+    pub context_file: Option<PathBuf>,
+
+    /// Append every LLM request and response to this JSONL file (API key redacted)
+    #[arg(long, value_name = "JSONL")]
+    pub prompt_log: Option<PathBuf>,
+
+    /// Ask OpenAI-compatible endpoints for JSON output (response_format json_object)
+    #[arg(long, default_value_t = false)]
+    pub json_mode: bool,
+
+    /// Pack up to N texts (default 20) into one numbered LLM request: much cheaper, slightly less careful per line
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "20")]
+    pub batch_translate: Option<usize>,
+
+    /// Keep each original line as a comment above its translation
+    #[arg(long, default_value_t = false)]
+    pub keep_original: bool,
+
+    /// Allow the output to overwrite the input script itself
+    #[arg(long, default_value_t = false)]
+    pub in_place: bool,
+
+    /// Also translate full-line comments matching this regex; its first capture group
+    /// (or the whole match) is translated and written back, e.g. '^#\s*TL:\s*(.+)$'
+    #[arg(long, value_name = "PATTERN")]
+    pub translate_comments: Option<String>,
+
+    /// Convert curly quotes in translations to straight quotes and escape them
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set)]
+    pub normalize_quotes: bool,
+
+    /// Only retranslate entries listed in <output>.failures.json from a previous run
+    #[arg(long, default_value_t = false)]
+    pub retry_failed: bool,
+
+    /// Fetch fresh machine translations even for cached texts and replace the cached ones
+    #[arg(long, default_value_t = false)]
+    pub overwrite_cache: bool,
+
+    /// Translate only from the cache, without network access; uncached texts stay untranslated
+    #[arg(long, default_value_t = false, conflicts_with = "overwrite_cache")]
+    pub from_cache_only: bool,
+
+    /// Fill empty translations in an existing Ren'Py tl/<lang> directory in place
+    #[arg(long, default_value_t = false, conflicts_with_all = ["output", "retry_failed"])]
+    pub fill_tl: bool,
+
+    /// Print the LLM prompts for the first N entries (default 5) without sending requests
+    #[arg(long, num_args = 0..=1, default_missing_value = "5")]
+    pub dry_run_prompts: Option<usize>,
+
+    /// Report to stderr, per file, which quoted strings were skipped and why
+    #[arg(long, default_value_t = false)]
+    pub report_skipped: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum TranslateAction {
+    /// List frequent capitalized words (likely names and places) as a glossary skeleton
+    Analyze(AnalyzeArgs),
+    /// Export the dialogue, in script order, as a Markdown document for proofreading
+    Script(ScriptArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct ScriptArgs {
+    /// Script file or directory to export
+    #[arg(required = true)]
+    pub input: PathBuf,
+
+    /// Write the document to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Parser, Debug)]
+pub struct AnalyzeArgs {
+    /// Script file or directory to scan
+    #[arg(required = true)]
+    pub input: PathBuf,
+
+    /// Write the glossary skeleton to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Number of candidates to list
+    #[arg(long, default_value_t = 50, value_parser = clap::value_parser!(usize))]
+    pub top: usize,
+
+    /// Ignore words seen fewer times than this
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(usize))]
+    pub min_count: usize,
 }
 
 #[derive(Parser, Debug)]
@@ -159,9 +456,48 @@ pub struct RepackArgs {
     #[arg(short, long)]
     pub output: Option<PathBuf>,
 
-    /// RPA version (2.0 or 3.0)
+    /// RPA version (2.0, 3.0, 3.2, 4.0 or ALT-1.0)
     #[arg(long)]
     pub version: Option<String>,
+
+    /// Write the same RPA version as this existing archive
+    #[arg(long = "match", value_name = "RPA", conflicts_with = "version")]
+    pub match_archive: Option<PathBuf>,
+
+    /// Only pack files matching this glob (repeatable, e.g. "images/**")
+    #[arg(long)]
+    pub include: Vec<String>,
+
+    /// Skip files matching this glob (repeatable)
+    #[arg(long)]
+    pub exclude: Vec<String>,
+
+    /// Also pack OS and editor junk (.DS_Store, Thumbs.db, *~, *.swp, ...)
+    #[arg(long, default_value_t = false)]
+    pub no_default_excludes: bool,
+
+    /// Only pack the files named in this file (one archive path per line, relative to the input)
+    #[arg(long, value_name = "FILE")]
+    pub from_list: Option<PathBuf>,
+
+    /// Order of the file data: walk (directory traversal), name, list (as in --from-list)
+    /// or match (as in the --match archive, to reproduce its layout)
+    #[arg(
+        long,
+        default_value = "walk",
+        value_parser = ["walk", "name", "list", "match"],
+        requires_ifs = [("list", "from_list"), ("match", "match_archive")]
+    )]
+    pub order: String,
+
+    /// Add or replace the files in the existing output archive instead of rebuilding it;
+    /// replaced data is left in place as dead bytes
+    #[arg(long, default_value_t = false, conflicts_with_all = ["version", "match_archive"])]
+    pub update: bool,
+
+    /// With --update, rewrite the archive without the replaced data
+    #[arg(long, default_value_t = false, requires = "update")]
+    pub compact: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -178,13 +514,18 @@ pub struct PatchArgs {
     #[arg(short, long, default_value = "chinese")]
     pub lang: String,
 
+    /// Ren'Py language name for tl/<name>/ and `translate <name>` blocks, when it differs
+    /// from the provider's language code (e.g. --lang zh-CN --lang-name chinese)
+    #[arg(long)]
+    pub lang_name: Option<String>,
+
     /// API provider (openai, claude, ollama)
     #[arg(long, default_value = "openai")]
     pub api: String,
 
-    /// API key
+    /// API key; repeat to rotate LLM requests over several keys
     #[arg(long)]
-    pub api_key: Option<String>,
+    pub api_key: Vec<String>,
 
     /// API base URL
     #[arg(long)]
@@ -194,13 +535,146 @@ pub struct PatchArgs {
     #[arg(long)]
     pub model: Option<String>,
 
+    /// Chinese script for Chinese targets (simplified, traditional)
+    #[arg(long, value_parser = ["simplified", "traditional"])]
+    pub script: Option<String>,
+
+    /// Maximum texts per DeepL request (default 50); long texts are split further by size
+    #[arg(long, value_parser = clap::value_parser!(usize))]
+    pub batch_size: Option<usize>,
+
+    /// Stop translating after this many consecutive failed requests (default 10, 0 disables)
+    #[arg(long, value_parser = clap::value_parser!(usize))]
+    pub max_failures: Option<usize>,
+
+    /// Instead of stopping at --max-failures, wait up to this many minutes for the provider to recover
+    #[arg(long, value_name = "MINUTES", value_parser = clap::value_parser!(u64))]
+    pub wait_for_recovery: Option<u64>,
+
+    /// Seconds between retries while waiting for recovery (default 60, doubling up to 8x)
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64))]
+    pub recovery_poll: Option<u64>,
+
+    /// Fewest concurrent Google requests when backing off from 429s (default 1)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(usize))]
+    pub min_concurrency: Option<usize>,
+
+    /// Most concurrent Google requests; the limit starts at 4 and grows while requests succeed (default 16)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(usize))]
+    pub max_concurrency: Option<usize>,
+
+    /// Number of script files processed in parallel
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(usize))]
+    pub max_concurrent_files: usize,
+
+    /// Only process scripts matching this glob (repeatable, e.g. "chapter*.rpy")
+    #[arg(long, value_name = "GLOB")]
+    pub files: Vec<String>,
+
+    /// Language of the game text for Google/DeepL (detected from the scripts when omitted)
+    #[arg(long)]
+    pub source_lang: Option<String>,
+
+    /// Additional root certificate (PEM) to trust, for TLS-inspecting proxies
+    #[arg(long, value_name = "PEM")]
+    pub ca_cert: Option<PathBuf>,
+
+    /// Ask LLMs to keep menu choices and screen text about as long as the source
+    #[arg(long, default_value_t = false)]
+    pub length_hint: bool,
+
+    /// Background notes (characters, setting, tone) prepended to the LLM system prompt
+    #[arg(long, value_name = "MD")]
+    pub context_file: Option<PathBuf>,
+
+    /// Append every LLM request and response to this JSONL file (API key redacted)
+    #[arg(long, value_name = "JSONL")]
+    pub prompt_log: Option<PathBuf>,
+
+    /// Ask OpenAI-compatible endpoints for JSON output (response_format json_object)
+    #[arg(long, default_value_t = false)]
+    pub json_mode: bool,
+
+    /// Pack up to N texts (default 20) into one numbered LLM request: much cheaper, slightly less careful per line
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "20")]
+    pub batch_translate: Option<usize>,
+
     /// Skip translation, only generate template files
     #[arg(long, default_value_t = false)]
     pub template_only: bool,
 
-    /// Glossary file for consistent term translation
+    /// Only translate UI, menu and other strings; dialogue is left as empty templates
+    #[arg(long, default_value_t = false, conflicts_with = "dialogue_only")]
+    pub strings_only: bool,
+
+    /// Only translate dialogue; strings are left as empty templates
+    #[arg(long, default_value_t = false)]
+    pub dialogue_only: bool,
+
+    /// Glossary file for consistent term translation (repeatable, later files override earlier)
+    #[arg(long)]
+    pub glossary: Vec<PathBuf>,
+
+    /// Also translate character display names from Character(...) definitions
+    #[arg(long, default_value_t = false)]
+    pub translate_names: bool,
+
+    /// Also translate string values of define/default statements via a translate python block
+    #[arg(long, default_value_t = false)]
+    pub translate_defines: bool,
+
+    /// Reuse cached translations for strings differing only in whitespace
+    #[arg(long, default_value_t = false)]
+    pub fuzzy_cache: bool,
+
+    /// Check that translations keep the source's {tags} and [variables], writing a report
+    #[arg(long, default_value_t = false)]
+    pub preview_tags: bool,
+
+    /// With --preview-tags, fall back to the source text for mismatched translations
+    #[arg(long, default_value_t = false, requires = "preview_tags")]
+    pub revert_bad_tags: bool,
+
+    /// Re-read the generated tl/ files and fail if any is not well-formed Ren'Py
+    #[arg(long, default_value_t = false)]
+    pub validate: bool,
+
+    /// Translate results back to the source language and flag divergent ones for review (machine providers)
+    #[arg(long, default_value_t = false)]
+    pub back_check: bool,
+
+    /// Merge all generated translations into a single tl/<lang>/translations.rpy
+    #[arg(long, default_value_t = false)]
+    pub flatten_tl: bool,
+
+    /// Layout of files under the output directory (renpy: tl/<lang>/..., mirror: the input
+    /// tree, flat: one directory); defaults to renpy
+    #[arg(long, value_parser = ["renpy", "mirror", "flat"])]
+    pub target_dir_layout: Option<String>,
+
+    /// Encoding of the game's .rpy scripts; undecodable bytes are replaced and reported
+    #[arg(long, default_value = "utf-8")]
+    pub encoding: String,
+
+    /// Encoding of generated .rpy files (UTF-8 is strongly recommended for Ren'Py 7+)
+    #[arg(long, default_value = "utf-8")]
+    pub output_encoding: String,
+
+    /// Overwrite an existing tl/<lang> directory
+    #[arg(short, long, default_value_t = false)]
+    pub force: bool,
+
+    /// Keep translations already present in an existing tl/<lang> directory
+    #[arg(long, default_value_t = false, conflicts_with = "force")]
+    pub merge: bool,
+
+    /// Translation manifest (JSON) to reuse translations from and update after the run
     #[arg(long)]
-    pub glossary: Option<PathBuf>,
+    pub manifest: Option<PathBuf>,
+
+    /// Print the LLM prompts for the first N texts (default 5) without sending requests
+    #[arg(long, num_args = 0..=1, default_missing_value = "5", conflicts_with = "template_only")]
+    pub dry_run_prompts: Option<usize>,
 }
 
 #[derive(Parser, Debug)]
@@ -217,13 +691,18 @@ pub struct AutoArgs {
     #[arg(short, long, default_value = "chinese")]
     pub lang: String,
 
+    /// Ren'Py language name for tl/<name>/ and `translate <name>` blocks, when it differs
+    /// from the provider's language code (e.g. --lang zh-CN --lang-name chinese)
+    #[arg(long)]
+    pub lang_name: Option<String>,
+
     /// API provider (openai, claude, ollama, google, deepl)
     #[arg(long, default_value = "google")]
     pub api: String,
 
-    /// API key
+    /// API key; repeat to rotate LLM requests over several keys
     #[arg(long)]
-    pub api_key: Option<String>,
+    pub api_key: Vec<String>,
 
     /// API base URL
     #[arg(long)]
@@ -233,6 +712,66 @@ pub struct AutoArgs {
     #[arg(long)]
     pub model: Option<String>,
 
+    /// Chinese script for Chinese targets (simplified, traditional)
+    #[arg(long, value_parser = ["simplified", "traditional"])]
+    pub script: Option<String>,
+
+    /// Maximum texts per DeepL request (default 50); long texts are split further by size
+    #[arg(long, value_parser = clap::value_parser!(usize))]
+    pub batch_size: Option<usize>,
+
+    /// Stop translating after this many consecutive failed requests (default 10, 0 disables)
+    #[arg(long, value_parser = clap::value_parser!(usize))]
+    pub max_failures: Option<usize>,
+
+    /// Instead of stopping at --max-failures, wait up to this many minutes for the provider to recover
+    #[arg(long, value_name = "MINUTES", value_parser = clap::value_parser!(u64))]
+    pub wait_for_recovery: Option<u64>,
+
+    /// Seconds between retries while waiting for recovery (default 60, doubling up to 8x)
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64))]
+    pub recovery_poll: Option<u64>,
+
+    /// Fewest concurrent Google requests when backing off from 429s (default 1)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(usize))]
+    pub min_concurrency: Option<usize>,
+
+    /// Most concurrent Google requests; the limit starts at 4 and grows while requests succeed (default 16)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(usize))]
+    pub max_concurrency: Option<usize>,
+
+    /// Number of script files processed in parallel
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(usize))]
+    pub max_concurrent_files: usize,
+
+    /// Language of the game text for Google/DeepL (detected from the scripts when omitted)
+    #[arg(long)]
+    pub source_lang: Option<String>,
+
+    /// Additional root certificate (PEM) to trust, for TLS-inspecting proxies
+    #[arg(long, value_name = "PEM")]
+    pub ca_cert: Option<PathBuf>,
+
+    /// Ask LLMs to keep menu choices and screen text about as long as the source
+    #[arg(long, default_value_t = false)]
+    pub length_hint: bool,
+
+    /// Background notes (characters, setting, tone) prepended to the LLM system prompt
+    #[arg(long, value_name = "MD")]
+    pub context_file: Option<PathBuf>,
+
+    /// Append every LLM request and response to this JSONL file (API key redacted)
+    #[arg(long, value_name = "JSONL")]
+    pub prompt_log: Option<PathBuf>,
+
+    /// Ask OpenAI-compatible endpoints for JSON output (response_format json_object)
+    #[arg(long, default_value_t = false)]
+    pub json_mode: bool,
+
+    /// Pack up to N texts (default 20) into one numbered LLM request: much cheaper, slightly less careful per line
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "20")]
+    pub batch_translate: Option<usize>,
+
     /// Skip translation, only generate template files
     #[arg(long, default_value_t = false)]
     pub template_only: bool,
@@ -241,7 +780,68 @@ pub struct AutoArgs {
     #[arg(long, default_value_t = false)]
     pub keep_temp: bool,
 
-    /// Glossary file for consistent term translation
+    /// Only report what would be unpacked, decompiled and translated; no requests, no files
+    #[arg(long, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Glossary file for consistent term translation (repeatable, later files override earlier)
+    #[arg(long)]
+    pub glossary: Vec<PathBuf>,
+
+    /// Also translate character display names from Character(...) definitions
+    #[arg(long, default_value_t = false)]
+    pub translate_names: bool,
+
+    /// Also translate string values of define/default statements via a translate python block
+    #[arg(long, default_value_t = false)]
+    pub translate_defines: bool,
+
+    /// Reuse cached translations for strings differing only in whitespace
+    #[arg(long, default_value_t = false)]
+    pub fuzzy_cache: bool,
+
+    /// Check that translations keep the source's {tags} and [variables], writing a report
+    #[arg(long, default_value_t = false)]
+    pub preview_tags: bool,
+
+    /// With --preview-tags, fall back to the source text for mismatched translations
+    #[arg(long, default_value_t = false, requires = "preview_tags")]
+    pub revert_bad_tags: bool,
+
+    /// Re-read the generated tl/ files and fail if any is not well-formed Ren'Py
+    #[arg(long, default_value_t = false)]
+    pub validate: bool,
+
+    /// Translate results back to the source language and flag divergent ones for review (machine providers)
+    #[arg(long, default_value_t = false)]
+    pub back_check: bool,
+
+    /// Merge all generated translations into a single tl/<lang>/translations.rpy
+    #[arg(long, default_value_t = false)]
+    pub flatten_tl: bool,
+
+    /// Layout of files under the output directory (renpy: tl/<lang>/..., mirror: the input
+    /// tree, flat: one directory); defaults to renpy
+    #[arg(long, value_parser = ["renpy", "mirror", "flat"])]
+    pub target_dir_layout: Option<String>,
+
+    /// Encoding of the game's .rpy scripts; undecodable bytes are replaced and reported
+    #[arg(long, default_value = "utf-8")]
+    pub encoding: String,
+
+    /// Encoding of generated .rpy files (UTF-8 is strongly recommended for Ren'Py 7+)
+    #[arg(long, default_value = "utf-8")]
+    pub output_encoding: String,
+
+    /// Overwrite an existing tl/<lang> directory
+    #[arg(short, long, default_value_t = false)]
+    pub force: bool,
+
+    /// Keep translations already present in an existing tl/<lang> directory
+    #[arg(long, default_value_t = false, conflicts_with = "force")]
+    pub merge: bool,
+
+    /// Translation manifest (JSON) to reuse translations from and update after the run
     #[arg(long)]
-    pub glossary: Option<PathBuf>,
+    pub manifest: Option<PathBuf>,
 }
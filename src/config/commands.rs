@@ -2,23 +2,30 @@
 
 use anyhow::{Context, Result};
 use colored::Colorize;
+use std::path::Path;
 
 use super::Config;
 use crate::cli::{ConfigAction, ConfigArgs};
 
-pub fn run(args: ConfigArgs) -> Result<()> {
+/// `path` is the effective config file: `--config` if given, else the platform default
+pub fn run(args: ConfigArgs, path: Option<&Path>) -> Result<()> {
+    if let ConfigAction::Path = args.action {
+        return show_path(path);
+    }
+
+    let path = path.context("Could not determine config path")?;
     match args.action {
-        ConfigAction::Show => show_config(),
-        ConfigAction::Init { force } => init_config(force),
-        ConfigAction::Set { key, value } => set_config(&key, &value),
-        ConfigAction::Get { key } => get_config(&key),
-        ConfigAction::Path => show_path(),
-        ConfigAction::Edit => edit_config(),
+        ConfigAction::Show => show_config(path),
+        ConfigAction::Init { force } => init_config(path, force),
+        ConfigAction::Set { key, value } => set_config(path, &key, &value),
+        ConfigAction::Get { key } => get_config(path, &key),
+        ConfigAction::Path => unreachable!(),
+        ConfigAction::Edit => edit_config(path),
     }
 }
 
-fn show_config() -> Result<()> {
-    let config = Config::load()?;
+fn show_config(path: &Path) -> Result<()> {
+    let config = Config::load_from(path)?;
     let content = toml::to_string_pretty(&config)?;
 
     println!("{}", "[Config]".green());
@@ -27,9 +34,7 @@ fn show_config() -> Result<()> {
     Ok(())
 }
 
-fn init_config(force: bool) -> Result<()> {
-    let path = Config::config_path().context("Could not determine config path")?;
-
+fn init_config(path: &Path, force: bool) -> Result<()> {
     if path.exists() && !force {
         println!(
             "{}",
@@ -40,10 +45,10 @@ fn init_config(force: bool) -> Result<()> {
     }
 
     let config = Config::default();
-    let saved_path = config.save()?;
+    config.save_to(path)?;
 
     println!("{}", "[Config] Initialized".green());
-    println!("  Created: {}", saved_path.display());
+    println!("  Created: {}", path.display());
     println!();
     println!("Edit the config file to set your API keys:");
     println!("  derenpy config edit");
@@ -51,134 +56,78 @@ fn init_config(force: bool) -> Result<()> {
     Ok(())
 }
 
-fn set_config(key: &str, value: &str) -> Result<()> {
-    let mut config = Config::load()?;
+fn set_config(path: &Path, key: &str, value: &str) -> Result<()> {
+    let config = Config::load_from(path)?;
+    let mut tree = toml::Value::try_from(&config)?;
 
     // Parse key path (e.g., "api.openai_api_key")
     let parts: Vec<&str> = key.split('.').collect();
 
-    match parts.as_slice() {
-        ["general", "output_dir"] => {
-            config.general.output_dir = if value.is_empty() {
-                None
-            } else {
-                Some(value.to_string())
-            };
-        }
-        ["general", "verbose"] => {
-            config.general.verbose = value.parse().unwrap_or(false);
-        }
-        ["api", "provider"] => {
-            config.api.provider = value.to_string();
-        }
-        ["api", "openai_api_key"] => {
-            config.api.openai_api_key = if value.is_empty() {
-                None
-            } else {
-                Some(value.to_string())
-            };
-        }
-        ["api", "openai_api_base"] => {
-            config.api.openai_api_base = if value.is_empty() {
-                None
-            } else {
-                Some(value.to_string())
-            };
-        }
-        ["api", "openai_model"] => {
-            config.api.openai_model = if value.is_empty() {
-                None
-            } else {
-                Some(value.to_string())
-            };
-        }
-        ["api", "anthropic_api_key"] => {
-            config.api.anthropic_api_key = if value.is_empty() {
-                None
-            } else {
-                Some(value.to_string())
-            };
-        }
-        ["api", "anthropic_api_base"] => {
-            config.api.anthropic_api_base = if value.is_empty() {
-                None
-            } else {
-                Some(value.to_string())
-            };
-        }
-        ["api", "anthropic_model"] => {
-            config.api.anthropic_model = if value.is_empty() {
-                None
-            } else {
-                Some(value.to_string())
-            };
-        }
-        ["api", "ollama_api_base"] => {
-            config.api.ollama_api_base = value.to_string();
-        }
-        ["api", "ollama_model"] => {
-            config.api.ollama_model = value.to_string();
-        }
-        ["translation", "default_language"] => {
-            config.translation.default_language = value.to_string();
-        }
-        ["translation", "patch_mode"] => {
-            config.translation.patch_mode = value.parse().unwrap_or(true);
-        }
-        ["translation", "custom_prompt"] => {
-            config.translation.custom_prompt = if value.is_empty() {
-                None
-            } else {
-                Some(value.to_string())
-            };
-        }
-        ["paths", "python"] => {
-            config.paths.python = if value.is_empty() {
-                None
-            } else {
-                Some(value.to_string())
-            };
-        }
-        ["paths", "unrpyc"] => {
-            config.paths.unrpyc = if value.is_empty() {
-                None
-            } else {
-                Some(value.to_string())
-            };
-        }
-        _ => {
+    if value.is_empty() {
+        // Clearing a key resets it to its default
+        if !is_known_key(&tree, &parts) {
             anyhow::bail!("Unknown config key: {}", key);
         }
+        assign(&mut tree, &parts, None)?;
+    } else {
+        // Keep the type of the current value so booleans stay booleans
+        let new_value = match lookup(&tree, &parts) {
+            Some(toml::Value::Boolean(_)) => toml::Value::Boolean(
+                value
+                    .parse()
+                    .with_context(|| format!("{} expects true or false", key))?,
+            ),
+            Some(toml::Value::Integer(_)) => toml::Value::Integer(
+                value
+                    .parse()
+                    .with_context(|| format!("{} expects an integer", key))?,
+            ),
+            Some(toml::Value::Float(_)) => toml::Value::Float(
+                value
+                    .parse()
+                    .with_context(|| format!("{} expects a number", key))?,
+            ),
+            _ => toml::Value::String(value.to_string()),
+        };
+        assign(&mut tree, &parts, Some(new_value))?;
     }
 
-    config.save()?;
+    let config: Config = tree
+        .try_into()
+        .with_context(|| format!("Invalid value for {}", key))?;
+
+    // Unknown keys are silently dropped by serde, so check the value survived
+    if !value.is_empty() && lookup(&toml::Value::try_from(&config)?, &parts).is_none() {
+        anyhow::bail!("Unknown config key: {}", key);
+    }
+
+    config.save_to(path)?;
     println!("{}", format!("[Config] Set {} = {}", key, value).green());
 
     Ok(())
 }
 
-fn get_config(key: &str) -> Result<()> {
-    let config = Config::load()?;
+fn get_config(path: &Path, key: &str) -> Result<()> {
+    let config = Config::load_from(path)?;
+    let tree = toml::Value::try_from(&config)?;
     let parts: Vec<&str> = key.split('.').collect();
 
-    let value: Option<String> = match parts.as_slice() {
-        ["general", "output_dir"] => config.general.output_dir,
-        ["general", "verbose"] => Some(config.general.verbose.to_string()),
-        ["api", "provider"] => Some(config.api.provider),
-        ["api", "openai_api_key"] => config.api.openai_api_key.map(|k| mask_key(&k)),
-        ["api", "openai_api_base"] => config.api.openai_api_base,
-        ["api", "openai_model"] => config.api.openai_model,
-        ["api", "anthropic_api_key"] => config.api.anthropic_api_key.map(|k| mask_key(&k)),
-        ["api", "anthropic_api_base"] => config.api.anthropic_api_base,
-        ["api", "anthropic_model"] => config.api.anthropic_model,
-        ["api", "ollama_api_base"] => Some(config.api.ollama_api_base),
-        ["api", "ollama_model"] => Some(config.api.ollama_model),
-        ["translation", "default_language"] => Some(config.translation.default_language),
-        ["translation", "patch_mode"] => Some(config.translation.patch_mode.to_string()),
-        ["translation", "custom_prompt"] => config.translation.custom_prompt,
-        ["paths", "python"] => config.paths.python,
-        ["paths", "unrpyc"] => config.paths.unrpyc,
-        _ => {
+    let value: Option<String> = match lookup(&tree, &parts) {
+        Some(toml::Value::String(s)) if key.ends_with("api_key") => Some(mask_key(s)),
+        Some(toml::Value::Array(keys)) if key.ends_with("api_key") => Some(format!(
+            "[{}]",
+            keys.iter()
+                .map(|k| mask_key(k.as_str().unwrap_or_default()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        Some(toml::Value::String(s)) => Some(s.clone()),
+        Some(toml::Value::Table(_)) => {
+            anyhow::bail!("{} is a section, not a value", key);
+        }
+        Some(other) => Some(other.to_string()),
+        None if is_known_key(&tree, &parts) => None,
+        None => {
             anyhow::bail!("Unknown config key: {}", key);
         }
     };
@@ -191,8 +140,59 @@ fn get_config(key: &str) -> Result<()> {
     Ok(())
 }
 
-fn show_path() -> Result<()> {
-    match Config::config_path() {
+fn lookup<'a>(tree: &'a toml::Value, parts: &[&str]) -> Option<&'a toml::Value> {
+    parts.iter().try_fold(tree, |node, part| node.get(*part))
+}
+
+/// Set (or remove, with `None`) the value at a dotted path, creating tables on the way
+fn assign(tree: &mut toml::Value, parts: &[&str], value: Option<toml::Value>) -> Result<()> {
+    let (last, parents) = parts.split_last().context("Empty config key")?;
+
+    let mut node = tree;
+    for part in parents {
+        let table = node
+            .as_table_mut()
+            .context("Config key goes through a value")?;
+        node = table
+            .entry(part.to_string())
+            .or_insert_with(|| toml::Value::Table(Default::default()));
+    }
+
+    let table = node
+        .as_table_mut()
+        .context("Config key goes through a value")?;
+    match value {
+        Some(value) => {
+            table.insert(last.to_string(), value);
+        }
+        None => {
+            table.remove(*last);
+        }
+    }
+    Ok(())
+}
+
+/// Whether a key maps to a config field, even if it is currently unset
+fn is_known_key(tree: &toml::Value, parts: &[&str]) -> bool {
+    if lookup(tree, parts).is_some() {
+        return true;
+    }
+
+    let mut probe = tree.clone();
+    if assign(&mut probe, parts, Some(toml::Value::String("probe".into()))).is_err() {
+        return false;
+    }
+    match probe.try_into::<Config>() {
+        Ok(config) => toml::Value::try_from(&config)
+            .ok()
+            .is_some_and(|t| lookup(&t, parts).is_some()),
+        // A type error means a real, non-string field was hit
+        Err(_) => true,
+    }
+}
+
+fn show_path(path: Option<&Path>) -> Result<()> {
+    match path {
         Some(path) => {
             println!("{}", path.display());
             if path.exists() {
@@ -208,13 +208,11 @@ fn show_path() -> Result<()> {
     Ok(())
 }
 
-fn edit_config() -> Result<()> {
-    let path = Config::config_path().context("Could not determine config path")?;
-
+fn edit_config(path: &Path) -> Result<()> {
     // Create default config if it doesn't exist
     if !path.exists() {
         let config = Config::default();
-        config.save()?;
+        config.save_to(path)?;
         println!("{}", "[Config] Created default config".green());
     }
 
@@ -233,7 +231,7 @@ fn edit_config() -> Result<()> {
     println!("Path: {}", path.display());
 
     std::process::Command::new(&editor)
-        .arg(&path)
+        .arg(path)
         .status()
         .context(format!("Failed to open editor: {}", editor))?;
 
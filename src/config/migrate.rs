@@ -0,0 +1,99 @@
+//! Upgrades of config files written by older versions
+
+use toml::{Table, Value};
+
+/// Version written to new config files; bump it when adding a migration
+pub const CURRENT_VERSION: u32 = 1;
+
+/// Keys read under another name by version 0: the CLI calls the provider `claude`,
+/// but its settings were only ever read from the `anthropic_*` keys
+const V1_RENAMES: &[(&str, &str, &str)] = &[
+    ("api", "claude_api_key", "anthropic_api_key"),
+    ("api", "claude_api_base", "anthropic_api_base"),
+    ("api", "claude_model", "anthropic_model"),
+];
+
+/// The version a raw config file was written with; files without one predate versioning
+pub fn version_of(table: &Table) -> u32 {
+    table
+        .get("config_version")
+        .and_then(Value::as_integer)
+        .map_or(0, |v| v.clamp(0, u32::MAX as i64) as u32)
+}
+
+/// Bring a raw config up to `CURRENT_VERSION`, describing each change made
+pub fn migrate(table: &mut Table) -> Vec<String> {
+    let mut changes = Vec::new();
+    let from = version_of(table);
+
+    if from < 1 {
+        for (section, old, new) in V1_RENAMES {
+            rename(table, section, old, new, &mut changes);
+        }
+    }
+
+    if from < CURRENT_VERSION {
+        table.insert(
+            "config_version".to_string(),
+            Value::Integer(CURRENT_VERSION as i64),
+        );
+        changes.push(format!(
+            "set config_version = {} (was {})",
+            CURRENT_VERSION, from
+        ));
+    }
+
+    changes
+}
+
+fn rename(table: &mut Table, section: &str, old: &str, new: &str, changes: &mut Vec<String>) {
+    let Some(Value::Table(section_table)) = table.get_mut(section) else {
+        return;
+    };
+    let Some(value) = section_table.remove(old) else {
+        return;
+    };
+
+    if section_table.contains_key(new) {
+        changes.push(format!(
+            "removed {}.{} ({}.{} is already set)",
+            section, old, section, new
+        ));
+    } else {
+        section_table.insert(new.to_string(), value);
+        changes.push(format!(
+            "renamed {}.{} to {}.{}",
+            section, old, section, new
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_v0() {
+        let mut table: Table = toml::from_str(
+            "[api]\nclaude_api_key = \"sk-ant-1\"\nclaude_model = \"old\"\nanthropic_model = \"new\"\n",
+        )
+        .unwrap();
+
+        let changes = migrate(&mut table);
+        assert_eq!(
+            changes,
+            [
+                "renamed api.claude_api_key to api.anthropic_api_key",
+                "removed api.claude_model (api.anthropic_model is already set)",
+                "set config_version = 1 (was 0)",
+            ]
+        );
+        let api = table["api"].as_table().unwrap();
+        assert_eq!(api["anthropic_api_key"].as_str(), Some("sk-ant-1"));
+        assert_eq!(api["anthropic_model"].as_str(), Some("new"));
+        assert!(!api.contains_key("claude_model"));
+
+        // Already current: nothing to do
+        assert!(migrate(&mut table).is_empty());
+    }
+}
@@ -1,17 +1,24 @@
 //! Configuration management
 
 pub mod commands;
+pub mod migrate;
 
 use anyhow::{Context, Result};
+use colored::Colorize;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 const CONFIG_FILE_NAME: &str = "config.toml";
 const APP_NAME: &str = "derenpy";
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
+    /// Format version of the file; older files are migrated on load
+    #[serde(default)]
+    pub config_version: u32,
+
     #[serde(default)]
     pub general: GeneralConfig,
 
@@ -25,6 +32,18 @@ pub struct Config {
     pub paths: PathsConfig,
 }
 
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            config_version: migrate::CURRENT_VERSION,
+            general: GeneralConfig::default(),
+            api: ApiConfig::default(),
+            translation: TranslationConfig::default(),
+            paths: PathsConfig::default(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct GeneralConfig {
     #[serde(default)]
@@ -39,9 +58,9 @@ pub struct ApiConfig {
     #[serde(default = "default_provider")]
     pub provider: String,
 
-    /// OpenAI API key
+    /// OpenAI API key, or a list of keys used in rotation
     #[serde(default)]
-    pub openai_api_key: Option<String>,
+    pub openai_api_key: Option<ApiKeys>,
 
     /// OpenAI API base URL
     #[serde(default)]
@@ -51,9 +70,9 @@ pub struct ApiConfig {
     #[serde(default)]
     pub openai_model: Option<String>,
 
-    /// Anthropic API key
+    /// Anthropic API key, or a list of keys used in rotation
     #[serde(default)]
-    pub anthropic_api_key: Option<String>,
+    pub anthropic_api_key: Option<ApiKeys>,
 
     /// Anthropic API base URL
     #[serde(default)]
@@ -74,6 +93,27 @@ pub struct ApiConfig {
     /// DeepL API key (free or pro)
     #[serde(default)]
     pub deepl_api_key: Option<String>,
+
+    /// Extra root certificate (PEM) to trust, e.g. a corporate TLS-inspecting proxy's CA
+    #[serde(default)]
+    pub ca_cert: Option<String>,
+}
+
+/// A single API key, or several to spread requests over per-key rate limits
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum ApiKeys {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl ApiKeys {
+    pub fn to_vec(&self) -> Vec<String> {
+        match self {
+            Self::One(key) => vec![key.clone()],
+            Self::Many(keys) => keys.clone(),
+        }
+    }
 }
 
 fn default_provider() -> String {
@@ -101,6 +141,7 @@ impl Default for ApiConfig {
             ollama_api_base: default_ollama_base(),
             ollama_model: default_ollama_model(),
             deepl_api_key: None,
+            ca_cert: None,
         }
     }
 }
@@ -118,6 +159,34 @@ pub struct TranslationConfig {
     /// Custom translation prompt
     #[serde(default)]
     pub custom_prompt: Option<String>,
+
+    /// System prompt templates keyed by target language ({lang} is substituted)
+    #[serde(default)]
+    pub prompts: HashMap<String, String>,
+
+    /// Literal separator for merged Google requests (numbered markers when unset)
+    #[serde(default)]
+    pub google_separator: Option<String>,
+
+    /// Consecutive failed requests before a run stops translating (0 disables)
+    #[serde(default)]
+    pub max_failures: Option<usize>,
+
+    /// Minutes to wait for a failing provider to recover instead of stopping
+    #[serde(default)]
+    pub wait_for_recovery: Option<u64>,
+
+    /// Seconds between retries while waiting for recovery
+    #[serde(default)]
+    pub recovery_poll: Option<u64>,
+
+    /// Approximate token budget for `--context-file` contents
+    #[serde(default)]
+    pub context_max_tokens: Option<usize>,
+
+    /// JSONL file every LLM request and response is appended to
+    #[serde(default)]
+    pub prompt_log: Option<String>,
 }
 
 fn default_language() -> String {
@@ -130,6 +199,13 @@ impl Default for TranslationConfig {
             default_language: default_language(),
             patch_mode: true,
             custom_prompt: None,
+            prompts: HashMap::new(),
+            google_separator: None,
+            max_failures: None,
+            wait_for_recovery: None,
+            recovery_poll: None,
+            context_max_tokens: None,
+            prompt_log: None,
         }
     }
 }
@@ -159,45 +235,83 @@ impl Config {
     /// Load config from default location
     pub fn load() -> Result<Self> {
         let path = Self::config_path().context("Could not determine config path")?;
+        Self::load_from(&path)
+    }
 
+    /// Load config from a specific file, falling back to defaults if it does not exist.
+    /// Files written by older versions are migrated and saved back.
+    pub fn load_from(path: &Path) -> Result<Self> {
         if !path.exists() {
             return Ok(Self::default());
         }
 
-        let content = fs::read_to_string(&path)
+        let content = fs::read_to_string(path)
             .context(format!("Failed to read config file: {}", path.display()))?;
 
-        let config: Config = toml::from_str(&content).context("Failed to parse config file")?;
+        let mut table: toml::Table =
+            toml::from_str(&content).context("Failed to parse config file")?;
+        let version = migrate::version_of(&table);
+        if version > migrate::CURRENT_VERSION {
+            eprintln!(
+                "{}",
+                format!(
+                    "[WARN] {} is config version {}, newer than this derenpy understands ({}); unknown keys are ignored",
+                    path.display(),
+                    version,
+                    migrate::CURRENT_VERSION
+                )
+                .yellow()
+            );
+        }
+        let changes = migrate::migrate(&mut table);
+
+        let config: Config = toml::Value::Table(table)
+            .try_into()
+            .context("Failed to parse config file")?;
+
+        if !changes.is_empty() {
+            eprintln!(
+                "{}",
+                format!(
+                    "[Config] Migrated {} to version {}:",
+                    path.display(),
+                    config.config_version
+                )
+                .yellow()
+            );
+            for change in &changes {
+                eprintln!("  {}", change);
+            }
+            if let Err(e) = config.save_to(path) {
+                eprintln!(
+                    "{}",
+                    format!("[WARN] Failed to save migrated config: {:#}", e).yellow()
+                );
+            }
+        }
 
         Ok(config)
     }
 
-    /// Save config to default location
-    pub fn save(&self) -> Result<PathBuf> {
-        let dir = Self::config_dir().context("Could not determine config directory")?;
-        fs::create_dir_all(&dir).context("Failed to create config directory")?;
+    /// Save config to the given file, creating its directory if needed
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent()
+            && !dir.as_os_str().is_empty()
+        {
+            fs::create_dir_all(dir).context("Failed to create config directory")?;
+        }
 
-        let path = dir.join(CONFIG_FILE_NAME);
         let content = toml::to_string_pretty(self).context("Failed to serialize config")?;
 
-        fs::write(&path, content).context("Failed to write config file")?;
+        fs::write(path, content).context("Failed to write config file")?;
 
-        Ok(path)
+        Ok(())
     }
 
-    /// Get API key for the specified provider
+    /// Get API key for the specified provider (the first one when several are configured)
     pub fn get_api_key(&self, provider: &str) -> Option<String> {
         match provider.to_lowercase().as_str() {
-            "openai" => self
-                .api
-                .openai_api_key
-                .clone()
-                .or_else(|| std::env::var("OPENAI_API_KEY").ok()),
-            "claude" | "anthropic" => self
-                .api
-                .anthropic_api_key
-                .clone()
-                .or_else(|| std::env::var("ANTHROPIC_API_KEY").ok()),
+            "openai" | "claude" | "anthropic" => self.get_api_keys(provider).into_iter().next(),
             "deepl" => self
                 .api
                 .deepl_api_key
@@ -208,6 +322,19 @@ impl Config {
         }
     }
 
+    /// Get every API key configured for an LLM provider, in rotation order
+    pub fn get_api_keys(&self, provider: &str) -> Vec<String> {
+        let (configured, env) = match provider.to_lowercase().as_str() {
+            "openai" => (&self.api.openai_api_key, "OPENAI_API_KEY"),
+            "claude" | "anthropic" => (&self.api.anthropic_api_key, "ANTHROPIC_API_KEY"),
+            _ => return Vec::new(),
+        };
+        match configured {
+            Some(keys) => keys.to_vec(),
+            None => std::env::var(env).ok().into_iter().collect(),
+        }
+    }
+
     /// Get API base URL for the specified provider
     pub fn get_api_base(&self, provider: &str) -> Option<String> {
         match provider.to_lowercase().as_str() {
@@ -218,6 +345,28 @@ impl Config {
         }
     }
 
+    /// Get the extra root certificate, preferring the command line over the config file
+    pub fn get_ca_cert(&self, cli: Option<&Path>) -> Option<PathBuf> {
+        cli.map(Path::to_path_buf)
+            .or_else(|| self.api.ca_cert.as_ref().map(PathBuf::from))
+    }
+
+    /// Get the LLM prompt log path, preferring the command line over the config file
+    pub fn get_prompt_log(&self, cli: Option<&Path>) -> Option<PathBuf> {
+        cli.map(Path::to_path_buf)
+            .or_else(|| self.translation.prompt_log.as_ref().map(PathBuf::from))
+    }
+
+    /// Get the system prompt template for a target language, if one is configured
+    pub fn get_prompt(&self, lang: &str) -> Option<String> {
+        self.translation
+            .prompts
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(lang))
+            .map(|(_, v)| v.clone())
+            .or_else(|| self.translation.custom_prompt.clone())
+    }
+
     /// Get model for the specified provider
     pub fn get_model(&self, provider: &str) -> Option<String> {
         match provider.to_lowercase().as_str() {
@@ -228,3 +377,29 @@ impl Config {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt_per_language() {
+        let mut cfg = Config::default();
+        assert_eq!(cfg.get_prompt("ja"), None);
+
+        cfg.translation.custom_prompt = Some("Translate into {lang}.".to_string());
+        cfg.translation.prompts.insert(
+            "ja".to_string(),
+            "Translate into {lang}; keep honorifics like -san.".to_string(),
+        );
+        assert_eq!(
+            cfg.get_prompt("JA").as_deref(),
+            Some("Translate into {lang}; keep honorifics like -san.")
+        );
+        // Languages without a template fall back to the generic prompt
+        assert_eq!(
+            cfg.get_prompt("ko").as_deref(),
+            Some("Translate into {lang}.")
+        );
+    }
+}
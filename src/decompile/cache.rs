@@ -0,0 +1,81 @@
+//! Decompiled scripts cached by the hash of their `.rpyc`
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::utils::cache_root;
+
+pub struct DecompileCache {
+    dir: PathBuf,
+    /// Mixed into every key so a different decompiler never serves stale output
+    salt: String,
+}
+
+impl DecompileCache {
+    pub fn open(salt: &[u8]) -> Result<Self> {
+        Self::open_at(&Self::cache_dir()?, salt)
+    }
+
+    pub fn open_at(dir: &Path, salt: &[u8]) -> Result<Self> {
+        fs::create_dir_all(dir).context("Failed to create decompile cache directory")?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            salt: format!("{:x}", md5::compute(salt)),
+        })
+    }
+
+    /// Cache key for a compiled script's raw bytes
+    pub fn key(&self, rpyc: &[u8]) -> String {
+        let mut context = md5::Context::new();
+        context.consume(self.salt.as_bytes());
+        context.consume(rpyc);
+        format!("{:x}", context.compute())
+    }
+
+    /// The `.rpy` produced for this key by an earlier run, if any
+    pub fn get(&self, key: &str) -> Option<String> {
+        fs::read_to_string(self.entry_path(key)).ok()
+    }
+
+    pub fn put(&self, key: &str, source: &str) -> Result<()> {
+        // Write then rename, so an interrupted run never leaves a truncated entry behind
+        let path = self.entry_path(key);
+        let tmp = path.with_extension("rpy.tmp");
+        fs::write(&tmp, source).context("Failed to write decompile cache entry")?;
+        fs::rename(&tmp, &path).context("Failed to write decompile cache entry")?;
+        Ok(())
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.rpy", key))
+    }
+
+    fn cache_dir() -> Result<PathBuf> {
+        Ok(cache_root()?.join("decompiled"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyed_by_content_and_decompiler() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let cache = DecompileCache::open_at(temp_dir.path(), b"decompile.py v1").unwrap();
+
+        let key = cache.key(b"compiled script");
+        assert_eq!(cache.get(&key), None);
+        cache.put(&key, "label start:\n    return\n").unwrap();
+        assert_eq!(
+            cache.get(&key).as_deref(),
+            Some("label start:\n    return\n")
+        );
+
+        // A changed script or a changed decompiler misses
+        assert_ne!(cache.key(b"compiled script, edited"), key);
+        let upgraded = DecompileCache::open_at(temp_dir.path(), b"decompile.py v2").unwrap();
+        assert_eq!(upgraded.get(&upgraded.key(b"compiled script")), None);
+    }
+}
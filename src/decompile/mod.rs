@@ -1,3 +1,4 @@
+pub mod cache;
 pub mod rpyc;
 
 use anyhow::{Context, Result};
@@ -7,15 +8,24 @@ use std::path::Path;
 use walkdir::WalkDir;
 
 use crate::cli::DecompileArgs;
-use rpyc::RpycDecompiler;
+use rpyc::{RpycDecompiler, RpycInfo};
 
 pub fn run(args: DecompileArgs) -> Result<()> {
     let input = &args.input;
 
-    let decompiler = RpycDecompiler::new().context("Failed to initialize decompiler")?;
+    let decompiler = RpycDecompiler::new()
+        .context("Failed to initialize decompiler")?
+        .with_ast_json(args.ast_json)
+        .with_cache(!args.no_cache);
 
     if input.is_file() {
-        decompile_single(&decompiler, input, args.output.as_deref(), args.force)?;
+        decompile_single(
+            &decompiler,
+            input,
+            args.output.as_deref(),
+            args.force,
+            args.ast_json,
+        )?;
     } else if input.is_dir() {
         decompile_directory(
             &decompiler,
@@ -36,8 +46,12 @@ fn decompile_single(
     input: &Path,
     output: Option<&Path>,
     force: bool,
+    ast_json: bool,
 ) -> Result<()> {
     println!("{}", format!("[Decompile] {}", input.display()).green());
+    if let Ok(info) = RpycInfo::read(input) {
+        println!("  {}", info.describe());
+    }
 
     let output_path = match output {
         Some(p) => {
@@ -68,6 +82,12 @@ fn decompile_single(
 
     let result = decompiler.decompile(input, Some(&output_path))?;
     println!("{}", format!("[OK] {}", result.display()).green());
+    if ast_json {
+        println!(
+            "{}",
+            format!("[OK] {}", RpycDecompiler::ast_json_path(&result).display()).green()
+        );
+    }
 
     Ok(())
 }
@@ -179,6 +199,12 @@ fn decompile_directory(
         )
         .green()
     );
+    if decompiler.cache_hits() > 0 {
+        println!(
+            "  {} unchanged file(s) served from cache",
+            decompiler.cache_hits()
+        );
+    }
 
     Ok(())
 }
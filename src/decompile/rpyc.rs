@@ -1,20 +1,134 @@
 //! RPYC decompiler - Python bridge for unrpyc
 
 use anyhow::{Context, Result};
+use flate2::read::ZlibDecoder;
 use serde::Deserialize;
+use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::cache::DecompileCache;
+
+const RPC2_MAGIC: &[u8] = b"RENPY RPC2";
 
 #[derive(Debug, Deserialize)]
 struct DecompileResult {
     output: String,
     success: bool,
     error: Option<String>,
+    #[serde(default)]
+    unrpyc_version: Option<String>,
+}
+
+/// Version information read from a compiled script without decompiling it
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpycInfo {
+    /// 1 for a bare zlib pickle, 2 for the `RENPY RPC2` slot container
+    pub format: u8,
+    pub pickle_protocol: Option<u8>,
+    /// `renpy.script_version` stored alongside the statements
+    pub script_version: Option<i64>,
+}
+
+impl RpycInfo {
+    pub fn read(path: &Path) -> Result<Self> {
+        let raw = fs::read(path).context("Failed to read RPYC file")?;
+        Self::parse(&raw)
+    }
+
+    pub fn parse(raw: &[u8]) -> Result<Self> {
+        let (format, compressed) = if raw.starts_with(RPC2_MAGIC) {
+            (
+                2,
+                Self::rpc2_slot(raw, 1).context("RPYC file has no data slot")?,
+            )
+        } else {
+            (1, raw)
+        };
+
+        let mut pickle = Vec::new();
+        ZlibDecoder::new(compressed)
+            .read_to_end(&mut pickle)
+            .context("Failed to decompress RPYC data")?;
+
+        // PROTO opcode, present from protocol 2 on
+        let pickle_protocol = match pickle.as_slice() {
+            [0x80, proto, ..] => Some(*proto),
+            _ => None,
+        };
+
+        Ok(Self {
+            format,
+            pickle_protocol,
+            script_version: Self::find_script_version(&pickle),
+        })
+    }
+
+    fn rpc2_slot(raw: &[u8], wanted: u32) -> Option<&[u8]> {
+        let read_u32 = |pos: usize| -> Option<u32> {
+            Some(u32::from_le_bytes(raw.get(pos..pos + 4)?.try_into().ok()?))
+        };
+
+        let mut pos = RPC2_MAGIC.len();
+        loop {
+            let slot = read_u32(pos)?;
+            if slot == 0 {
+                return None;
+            }
+            let start = read_u32(pos + 4)? as usize;
+            let length = read_u32(pos + 8)? as usize;
+            if slot == wanted {
+                return raw.get(start..start + length);
+            }
+            pos += 12;
+        }
+    }
+
+    /// Find the integer stored under the `version` key of the pickled header dict
+    fn find_script_version(pickle: &[u8]) -> Option<i64> {
+        let key = b"version";
+        let at = pickle.windows(key.len()).position(|w| w == key)?;
+        let mut pos = at + key.len();
+
+        // Skip memo opcodes between the key and its value
+        loop {
+            match pickle.get(pos)? {
+                b'q' => pos += 2,
+                b'r' => pos += 5,
+                0x94 => pos += 1,
+                _ => break,
+            }
+        }
+
+        let bytes = |n: usize| pickle.get(pos + 1..pos + 1 + n);
+        match pickle.get(pos)? {
+            b'K' => Some(*bytes(1)?.first()? as i64),
+            b'M' => Some(u16::from_le_bytes(bytes(2)?.try_into().ok()?) as i64),
+            b'J' => Some(i32::from_le_bytes(bytes(4)?.try_into().ok()?) as i64),
+            _ => None,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let mut parts = vec![format!("RPYC v{}", self.format)];
+        if let Some(proto) = self.pickle_protocol {
+            parts.push(format!("pickle protocol {}", proto));
+        }
+        if let Some(version) = self.script_version {
+            parts.push(format!("script version {}", version));
+        }
+        parts.join(", ")
+    }
 }
 
 pub struct RpycDecompiler {
     python_path: String,
     script_path: PathBuf,
+    ast_json: bool,
+    cache: Option<DecompileCache>,
+    cache_hits: AtomicUsize,
 }
 
 impl RpycDecompiler {
@@ -24,9 +138,50 @@ impl RpycDecompiler {
         Ok(Self {
             python_path: "python3".to_string(),
             script_path,
+            ast_json: false,
+            cache: None,
+            cache_hits: AtomicUsize::new(0),
         })
     }
 
+    /// Serve unchanged `.rpyc` files from the on-disk cache instead of running Python.
+    /// A cache that cannot be opened only costs speed, so decompilation goes on without it.
+    pub fn with_cache(mut self, enabled: bool) -> Self {
+        self.cache = if enabled {
+            fs::read(&self.script_path)
+                .ok()
+                .and_then(|script| DecompileCache::open(&script).ok())
+        } else {
+            None
+        };
+        self
+    }
+
+    /// Files served from the cache so far
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hits.load(Ordering::Relaxed)
+    }
+
+    /// Also write the statement tree as `<name>.ast.json` next to each `.rpy`
+    pub fn with_ast_json(mut self, enabled: bool) -> Self {
+        self.ast_json = enabled;
+        self
+    }
+
+    /// Where the AST dump for a decompiled script is written
+    pub fn ast_json_path(output: &Path) -> PathBuf {
+        output.with_extension("ast.json")
+    }
+
+    /// Where `decompile.py` writes when no output is given: `.rpymc` -> `.rpym`, else `.rpy`
+    fn default_output(input: &Path) -> PathBuf {
+        if input.extension().is_some_and(|e| e == "rpymc") {
+            input.with_extension("rpym")
+        } else {
+            input.with_extension("rpy")
+        }
+    }
+
     fn find_script_path() -> Result<PathBuf> {
         // Try to find the decompile.py script relative to the executable
         let exe_path = std::env::current_exe().context("Failed to get executable path")?;
@@ -55,8 +210,32 @@ impl RpycDecompiler {
     pub fn decompile<P: AsRef<Path>>(&self, input: P, output: Option<&Path>) -> Result<PathBuf> {
         let input = input.as_ref();
 
+        // AST dumps are not cached, so those runs always go through Python
+        let cache = self.cache.as_ref().filter(|_| !self.ast_json);
+        let key = match cache {
+            Some(cache) => Some(cache.key(&fs::read(input).context("Failed to read RPYC file")?)),
+            None => None,
+        };
+        if let (Some(cache), Some(key)) = (cache, &key)
+            && let Some(source) = cache.get(key)
+        {
+            let out = output
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| Self::default_output(input));
+            if let Some(parent) = out.parent() {
+                fs::create_dir_all(parent).context("Failed to create output directory")?;
+            }
+            fs::write(&out, source).context("Failed to write decompiled script")?;
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(out);
+        }
+
         let mut cmd = Command::new(&self.python_path);
-        cmd.arg(&self.script_path).arg(input);
+        cmd.arg(&self.script_path);
+        if self.ast_json {
+            cmd.arg("--ast-json");
+        }
+        cmd.arg(input);
 
         if let Some(out) = output {
             cmd.arg(out);
@@ -80,12 +259,28 @@ impl RpycDecompiler {
             serde_json::from_str(&stdout).context("Failed to parse decompiler output")?;
 
         if result.success {
-            Ok(PathBuf::from(result.output))
+            let out = PathBuf::from(result.output);
+            if let (Some(cache), Some(key)) = (cache, &key)
+                && let Ok(source) = fs::read_to_string(&out)
+            {
+                // Caching is best effort and never fails a decompilation
+                let _ = cache.put(key, &source);
+            }
+            Ok(out)
         } else {
-            anyhow::bail!(
-                "Decompilation failed: {}",
-                result.error.unwrap_or_else(|| "Unknown error".to_string())
-            )
+            let error = result.error.unwrap_or_else(|| "Unknown error".to_string());
+            match RpycInfo::read(input) {
+                Ok(info) => anyhow::bail!(
+                    "Decompilation failed: {}\n  This file was built as {}, which unrpyc {} may not support",
+                    error,
+                    info.describe(),
+                    result
+                        .unrpyc_version
+                        .as_deref()
+                        .unwrap_or("(unknown version)")
+                ),
+                Err(_) => anyhow::bail!("Decompilation failed: {}", error),
+            }
         }
     }
 }
@@ -95,3 +290,51 @@ impl Default for RpycDecompiler {
         Self::new().expect("Failed to create RpycDecompiler")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn test_read_rpc2_info() {
+        // (({'version': 5003000, ...}, [...])) as written by a protocol 2 pickler
+        let mut pickle = b"\x80\x02}q\x00(U\x07versionq\x01J".to_vec();
+        pickle.extend_from_slice(&5_003_000i32.to_le_bytes());
+        pickle.extend_from_slice(b"u]q\x02\x86q\x03.");
+        let data = zlib(&pickle);
+
+        let header_len = RPC2_MAGIC.len() + 12 * 2;
+        let mut raw = RPC2_MAGIC.to_vec();
+        for value in [1, header_len as u32, data.len() as u32, 0, 0, 0] {
+            raw.extend_from_slice(&value.to_le_bytes());
+        }
+        raw.extend_from_slice(&data);
+
+        let info = RpycInfo::parse(&raw).unwrap();
+        assert_eq!(
+            info,
+            RpycInfo {
+                format: 2,
+                pickle_protocol: Some(2),
+                script_version: Some(5_003_000),
+            }
+        );
+        assert_eq!(
+            info.describe(),
+            "RPYC v2, pickle protocol 2, script version 5003000"
+        );
+
+        let legacy = RpycInfo::parse(&zlib(b"(dp0\n.")).unwrap();
+        assert_eq!(legacy.format, 1);
+        assert_eq!(legacy.pickle_protocol, None);
+    }
+}
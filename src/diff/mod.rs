@@ -0,0 +1,84 @@
+//! Archive comparison: which files a repack added, removed or changed
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::path::Path;
+
+use crate::cli::DiffArgs;
+use crate::unpack::rpa::RpaArchive;
+
+#[derive(Debug, Default)]
+pub struct ArchiveDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    /// Shared files whose contents differ, with their old and new sizes
+    pub modified: Vec<(String, u64, u64)>,
+    pub unchanged: usize,
+}
+
+pub fn run(args: DiffArgs) -> Result<()> {
+    println!(
+        "{}",
+        format!("[Diff] {} -> {}", args.old.display(), args.new.display()).green()
+    );
+
+    let diff = diff_archives(&args.old, &args.new)?;
+
+    println!("  Added: {}", diff.added.len());
+    println!("  Removed: {}", diff.removed.len());
+    println!("  Modified: {}", diff.modified.len());
+    println!("  Unchanged: {}", diff.unchanged);
+
+    if args.verbose {
+        for name in &diff.added {
+            println!("{}", format!("  + {}", name).green());
+        }
+        for name in &diff.removed {
+            println!("{}", format!("  - {}", name).red());
+        }
+        for (name, old_size, new_size) in &diff.modified {
+            println!(
+                "{}",
+                format!("  ~ {} ({} -> {} bytes)", name, old_size, new_size).yellow()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare two archives by file name, size and, when sizes match, MD5 of the contents
+pub fn diff_archives(old: &Path, new: &Path) -> Result<ArchiveDiff> {
+    let old = RpaArchive::open(old)
+        .with_context(|| format!("Failed to open RPA archive: {}", old.display()))?;
+    let new = RpaArchive::open(new)
+        .with_context(|| format!("Failed to open RPA archive: {}", new.display()))?;
+
+    let mut diff = ArchiveDiff::default();
+
+    let mut old_names: Vec<&String> = old.index.keys().collect();
+    old_names.sort();
+    for name in old_names {
+        let (Some(old_size), Some(new_size)) = (old.entry_size(name), new.entry_size(name)) else {
+            diff.removed.push(name.clone());
+            continue;
+        };
+
+        let changed = old_size != new_size || old.content_hash(name)? != new.content_hash(name)?;
+        if changed {
+            diff.modified.push((name.clone(), old_size, new_size));
+        } else {
+            diff.unchanged += 1;
+        }
+    }
+
+    diff.added = new
+        .index
+        .keys()
+        .filter(|name| !old.index.contains_key(*name))
+        .cloned()
+        .collect();
+    diff.added.sort();
+
+    Ok(diff)
+}
@@ -1,7 +1,9 @@
 mod auto;
+mod cache;
 mod cli;
 mod config;
 mod decompile;
+mod diff;
 mod patch;
 mod repack;
 mod translate;
@@ -10,6 +12,7 @@ mod utils;
 
 use anyhow::Result;
 use clap::Parser;
+use config::Config;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use cli::{Cli, Commands};
@@ -22,14 +25,23 @@ fn main() -> Result<()> {
 
     let cli = Cli::parse();
 
+    let config_path = cli.config.clone().or_else(Config::config_path);
+    // An explicit --config must parse; the default location falls back to defaults
+    let load_config = || match &cli.config {
+        Some(path) => Config::load_from(path),
+        None => Ok(Config::load().unwrap_or_default()),
+    };
+
     match cli.command {
         Commands::Unpack(args) => unpack::run(args)?,
         Commands::Decompile(args) => decompile::run(args)?,
-        Commands::Translate(args) => translate::run(args)?,
+        Commands::Translate(args) => translate::run(args, &load_config()?)?,
         Commands::Repack(args) => repack::run(args)?,
-        Commands::Patch(args) => patch::run(args)?,
-        Commands::Config(args) => config::commands::run(args)?,
-        Commands::Auto(args) => auto::run(args)?,
+        Commands::Patch(args) => patch::run(args, &load_config()?)?,
+        Commands::Config(args) => config::commands::run(args, config_path.as_deref())?,
+        Commands::Cache(args) => cache::run(args)?,
+        Commands::Auto(args) => auto::run(args, &load_config()?)?,
+        Commands::Diff(args) => diff::run(args)?,
     }
 
     Ok(())
@@ -1,22 +1,33 @@
 //! Game translation patch generator
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use walkdir::WalkDir;
 
 use crate::cli::PatchArgs;
 use crate::config::Config;
+use crate::translate::back_check::{SIMILARITY_THRESHOLD, similarity};
 use crate::translate::cache::TranslationCache;
-use crate::translate::extractor::TextExtractor;
+use crate::translate::circuit::ctrl_c_flag;
+use crate::translate::extractor::{EntryType, TextExtractor};
 use crate::translate::glossary::Glossary;
-use crate::translate::llm::{LlmClient, LlmConfig, LlmProvider};
+use crate::translate::lang_detect::SAMPLE_SIZE;
+use crate::translate::llm::{ChineseScript, LlmClient, LlmConfig, LlmProvider};
 use crate::translate::machine_translate::{MachineTranslateClient, MachineTranslateConfig};
+use crate::translate::manifest::TranslationManifest;
+use crate::translate::markup::check_markup;
 use crate::translate::renpy_tl::{DialogueEntry, RenpyTranslationGenerator, StringEntry};
-use crate::unpack::rpa::RpaArchive;
+use crate::translate::{
+    OutputLayout, load_context, open_cache, print_prompt_preview, recovery, report_replaced,
+    resolve_source_lang,
+};
+use crate::unpack::rpa::{EntryOrder, RpaArchive};
+use crate::utils::{FileFilter, decode_lossy, is_tl_dir};
 
 struct TranslationStats {
     cache_hits: usize,
@@ -29,9 +40,25 @@ enum Translator {
 }
 
 impl Translator {
+    fn trip_message(&self) -> Option<String> {
+        match self {
+            Self::Llm(c) => c.trip_message(),
+            Self::Machine(c) => c.trip_message(),
+        }
+    }
+
+    /// Texts the cache answers without a request; LLM translations are never cached
+    fn cached_count(&self, texts: &[String], cache: &TranslationCache) -> usize {
+        match self {
+            Self::Machine(c) => c.cached_count(texts, cache),
+            Self::Llm(_) => 0,
+        }
+    }
+
     fn translate_batch_with_stats<F>(
         &self,
         texts: &[String],
+        layout_sensitive: &[bool],
         cache: Option<&TranslationCache>,
         progress_callback: Option<F>,
     ) -> (Vec<Result<String>>, TranslationStats)
@@ -57,20 +84,10 @@ impl Translator {
                 }
             }
             Self::Llm(c) => {
-                let results: Vec<Result<String>> = texts
-                    .iter()
-                    .enumerate()
-                    .map(|(i, t)| {
-                        let result = c.translate(t, None);
-                        if let Some(ref cb) = progress_callback {
-                            cb(i + 1);
-                        }
-                        result
-                    })
-                    .collect();
+                let results = c.translate_all(texts, layout_sensitive, progress_callback);
                 let stats = TranslationStats {
                     cache_hits: 0,
-                    api_calls: texts.len(),
+                    api_calls: c.request_count(texts.len()),
                 };
                 (results, stats)
             }
@@ -78,10 +95,10 @@ impl Translator {
     }
 }
 
-pub fn run(args: PatchArgs) -> Result<()> {
-    let cfg = Config::load().unwrap_or_default();
+pub fn run(args: PatchArgs, cfg: &Config) -> Result<()> {
     let input = &args.input;
-    let mut temp_dir_to_cleanup: Option<PathBuf> = None;
+    // Dropped on every return, so an extracted archive never outlives the run
+    let mut _extracted: Option<ExtractedDir> = None;
 
     println!("{}", "[Patch] Translation Patch Generator".green());
 
@@ -90,8 +107,8 @@ pub fn run(args: PatchArgs) -> Result<()> {
         let temp_dir = std::env::temp_dir().join(format!("derenpy_{}", std::process::id()));
         let archive = RpaArchive::open(input)?;
         fs::create_dir_all(&temp_dir)?;
-        archive.extract_all(&temp_dir, None)?;
-        temp_dir_to_cleanup = Some(temp_dir.clone());
+        archive.extract_all(&temp_dir, None, EntryOrder::Offset)?;
+        _extracted = Some(ExtractedDir(temp_dir.clone()));
         temp_dir
     } else if input.is_dir() {
         input.clone()
@@ -99,9 +116,10 @@ pub fn run(args: PatchArgs) -> Result<()> {
         anyhow::bail!("Input must be a game directory or RPA file");
     };
 
-    // Find all RPY files
+    // Find all RPY files, leaving out translations generated by earlier runs
     let rpy_files: Vec<_> = WalkDir::new(&work_dir)
         .into_iter()
+        .filter_entry(|e| !is_tl_dir(e))
         .filter_map(|e| e.ok())
         .filter(|e| {
             e.path()
@@ -117,9 +135,55 @@ pub fn run(args: PatchArgs) -> Result<()> {
 
     println!("  Found {} script file(s)", rpy_files.len());
 
+    let files = FileFilter::new(&args.files);
+    let rpy_files: Vec<_> = if files.is_empty() {
+        rpy_files
+    } else {
+        let total = rpy_files.len();
+        let selected: Vec<_> = rpy_files
+            .into_iter()
+            .filter(|e| files.matches(e.path().strip_prefix(&work_dir).unwrap_or(e.path())))
+            .collect();
+        println!("  {} of {} match --files", selected.len(), total);
+        if selected.is_empty() {
+            anyhow::bail!("No script files match --files");
+        }
+        selected
+    };
+
     // Setup translation generator
-    let generator = RenpyTranslationGenerator::new(&args.lang);
-    let extractor = TextExtractor::new();
+    let source_encoding = encoding_rs::Encoding::for_label(args.encoding.as_bytes())
+        .with_context(|| format!("Unknown encoding: {}", args.encoding))?;
+    let encoding = RenpyTranslationGenerator::encoding_for_label(&args.output_encoding)?;
+    if encoding != encoding_rs::UTF_8 {
+        println!(
+            "{}",
+            format!(
+                "[WARN] Writing {} files; Ren'Py 7+ expects UTF-8 scripts",
+                encoding.name()
+            )
+            .yellow()
+        );
+    }
+    let layout = args
+        .target_dir_layout
+        .as_deref()
+        .map_or(OutputLayout::Renpy, OutputLayout::from_str);
+    if layout != OutputLayout::Renpy && args.output.is_none() {
+        anyhow::bail!(
+            "--target-dir-layout {:?} requires an output directory (-o)",
+            layout
+        );
+    }
+    // Ren'Py identifies a translation by language name, providers by language code
+    let lang_name = args.lang_name.as_deref().unwrap_or(&args.lang);
+    let generator = RenpyTranslationGenerator::new(lang_name)
+        .with_flatten(args.flatten_tl)
+        .with_layout(layout)
+        .with_encoding(encoding);
+    let extractor = TextExtractor::new()
+        .with_character_names(args.translate_names)
+        .with_defines(args.translate_defines);
 
     // Extract all dialogues
     let mut all_dialogues: HashMap<PathBuf, Vec<DialogueEntry>> = HashMap::new();
@@ -127,24 +191,38 @@ pub fn run(args: PatchArgs) -> Result<()> {
 
     println!("  Extracting dialogues...");
 
-    for entry in &rpy_files {
+    let extract = |entry: &walkdir::DirEntry| -> Result<ExtractedScript> {
         let path = entry.path();
-        let dialogues = generator.extract_dialogues(path)?;
-
-        // Also extract menu choices as strings
-        let entries = extractor.extract_from_file(path).unwrap_or_default();
-        for e in entries {
-            if e.entry_type == crate::translate::extractor::EntryType::MenuChoice {
-                all_strings.push(StringEntry {
-                    original: e.text,
-                    translated: None,
-                });
-            }
-        }
+        let rel_path = path.strip_prefix(&work_dir).unwrap_or(path);
+        let bytes = fs::read(path).context("Failed to read script file")?;
+        let (content, replaced) = decode_lossy(&bytes, source_encoding);
+        let (dialogues, strings) = extract_script_str(&generator, &extractor, &content, rel_path);
+        Ok((rel_path.to_path_buf(), dialogues, strings, replaced))
+    };
 
+    // Files may be parsed in parallel, but are merged in their original order
+    let extracted = if args.max_concurrent_files > 1 {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(args.max_concurrent_files)
+            .build()?
+            .install(|| {
+                rpy_files
+                    .par_iter()
+                    .map(extract)
+                    .collect::<Result<Vec<_>>>()
+            })?
+    } else {
+        rpy_files.iter().map(extract).collect::<Result<Vec<_>>>()?
+    };
+
+    let mut replaced: Vec<(PathBuf, usize)> = Vec::new();
+    for (rel_path, dialogues, strings, replaced_chars) in extracted {
+        if replaced_chars > 0 {
+            replaced.push((rel_path.clone(), replaced_chars));
+        }
+        all_strings.extend(strings);
         if !dialogues.is_empty() {
-            let rel_path = path.strip_prefix(&work_dir).unwrap_or(path);
-            all_dialogues.insert(rel_path.to_path_buf(), dialogues);
+            all_dialogues.insert(rel_path, dialogues);
         }
     }
 
@@ -154,161 +232,288 @@ pub fn run(args: PatchArgs) -> Result<()> {
         total_dialogues,
         all_strings.len()
     );
+    report_replaced(&replaced, source_encoding);
 
-    // Load glossary if provided
-    let glossary = if let Some(ref glossary_path) = args.glossary {
+    // Load glossaries in order, later files overriding earlier ones
+    let mut glossary: Option<Glossary> = None;
+    let mut glossary_files = 0;
+    for glossary_path in &args.glossary {
         match Glossary::load(glossary_path) {
             Ok(g) => {
-                println!("  Loaded {} glossary terms", g.len());
-                Some(g)
+                glossary.get_or_insert_with(Glossary::new).merge(g);
+                glossary_files += 1;
             }
             Err(e) => {
                 eprintln!(
                     "{}",
-                    format!("[WARN] Failed to load glossary: {}", e).yellow()
+                    format!(
+                        "[WARN] Failed to load glossary {}: {}",
+                        glossary_path.display(),
+                        e
+                    )
+                    .yellow()
                 );
-                None
             }
         }
+    }
+    if let Some(ref g) = glossary {
+        println!(
+            "  Loaded {} glossary terms from {} file(s)",
+            g.len(),
+            glossary_files
+        );
+    }
+
+    // Determine output directory
+    let output_dir = args.output.clone().unwrap_or_else(|| {
+        if input.is_dir() {
+            game_dir_for(input)
+        } else {
+            PathBuf::from("game")
+        }
+    });
+
+    if args.strings_only {
+        println!("  Translating strings only; dialogue is left as empty templates");
+    } else if args.dialogue_only {
+        println!("  Translating dialogue only; strings are left as empty templates");
+    }
+
+    // Previews neither send nor write anything, so they never reach the overwrite guard
+    if let Some(count) = args.dry_run_prompts {
+        return preview_prompts(&args, cfg, &all_dialogues, &all_strings, count);
+    }
+
+    // Protect hand-edited translations from being overwritten
+    let tl_dir = generator.target_dir(&output_dir);
+    if tl_dir.exists() {
+        if args.merge {
+            let existing = generator.read_existing_translations(&tl_dir)?;
+            let kept = existing.apply(&mut all_dialogues, &mut all_strings);
+            println!("  Merging: kept {} existing translation(s)", kept);
+        } else if !args.force {
+            anyhow::bail!(
+                "Translation directory already exists: {} (use -f to overwrite or --merge to keep existing translations)",
+                tl_dir.display()
+            );
+        }
+    }
+
+    // Reuse translations from earlier episodes regardless of the provider that made them
+    let mut manifest = match args.manifest {
+        Some(ref path) => {
+            let manifest = TranslationManifest::load(path, lang_name)?;
+            let carried = manifest.apply(&mut all_dialogues, &mut all_strings);
+            println!(
+                "  Manifest: carried over {} translation(s) from {} known {} string(s)",
+                carried,
+                manifest.len(),
+                lang_name
+            );
+            Some(manifest)
+        }
+        None => None,
+    };
+
+    // Set when repeated failures cut the translation short
+    let mut trip_message = None;
+    let mut back_check_issues = Vec::new();
+
+    // --strings-only still has work to do when a project has no dialogue
+    let has_work = if args.strings_only {
+        !all_strings.is_empty()
     } else {
-        None
+        total_dialogues > 0
     };
 
     // Translate if not template only
-    if !args.template_only && total_dialogues > 0 {
-        let provider_str = if args.api != "openai" {
-            args.api.clone()
-        } else {
-            cfg.api.provider.clone()
-        };
-        let provider = LlmProvider::from_str(&provider_str);
-
-        // Determine language
-        let lang = if args.lang != "chinese" {
-            args.lang.clone()
-        } else {
-            cfg.translation.default_language.clone()
-        };
+    if !args.template_only && has_work {
+        let (provider_str, provider, lang) = resolve_provider(&args, cfg);
 
         // Create translator based on provider type
         let translator = if provider.is_machine_translate() {
-            create_machine_translator(provider, &lang, &cfg, &args)?
+            let sample: Vec<String> = all_dialogues
+                .values()
+                .flatten()
+                .map(|d| d.original_text.clone())
+                .chain(all_strings.iter().map(|s| s.original.clone()))
+                .take(SAMPLE_SIZE)
+                .collect();
+            create_machine_translator(provider, &lang, cfg, &args, &sample)?
         } else {
-            create_llm_translator(provider, &provider_str, &lang, &cfg, &args)?
+            create_llm_translator(provider, &provider_str, &lang, cfg, &args)?
         };
 
         if let Some(translator) = translator {
             // Initialize cache
-            let cache = TranslationCache::open().ok();
+            let cache = open_cache().map(|c| c.with_fuzzy(args.fuzzy_cache));
             if cache.is_some() {
                 println!("  Translation cache enabled");
             }
 
-            println!("  Translating dialogues...");
+            // Dialogues and strings share one deduplicated batch
+            let mut unique_texts: Vec<String> = Vec::new();
+            let mut unique_index: HashMap<String, usize> = HashMap::new();
+            let mut slot_of = |text: &str| -> usize {
+                if let Some(&slot) = unique_index.get(text) {
+                    return slot;
+                }
+                unique_texts.push(text.to_string());
+                unique_index.insert(text.to_string(), unique_texts.len() - 1);
+                unique_texts.len() - 1
+            };
 
-            let pb = ProgressBar::new(total_dialogues as u64);
+            let mut dialogue_slots: Vec<(PathBuf, usize, usize)> = Vec::new();
+            for (path, dialogues) in all_dialogues.iter().filter(|_| !args.strings_only) {
+                for (i, entry) in dialogues.iter().enumerate() {
+                    if entry.translated_text.is_some() {
+                        continue;
+                    }
+                    dialogue_slots.push((path.clone(), i, slot_of(&entry.original_text)));
+                }
+            }
+            let string_slots: Vec<(usize, usize)> = all_strings
+                .iter()
+                .enumerate()
+                .filter(|(_, s)| s.translated.is_none() && !args.dialogue_only)
+                .map(|(i, s)| (i, slot_of(&s.original)))
+                .collect();
+
+            let mut layout_sensitive = vec![false; unique_texts.len()];
+            for &(i, slot) in &string_slots {
+                layout_sensitive[slot] |= all_strings[i].layout;
+            }
+
+            println!(
+                "  Translating {} unique texts ({} dialogues, {} strings)...",
+                unique_texts.len(),
+                dialogue_slots.len(),
+                string_slots.len()
+            );
+
+            let pb = ProgressBar::new(unique_texts.len() as u64);
             pb.set_style(
                 ProgressStyle::default_bar()
-                    .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len}")?
+                    .template(
+                        "{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} ({per_sec}, ETA {eta})",
+                    )?
                     .progress_chars("=>-"),
             );
-            pb.enable_steady_tick(std::time::Duration::from_millis(100));
 
-            let mut all_texts: Vec<String> = Vec::new();
-            let mut text_indices: Vec<(PathBuf, usize)> = Vec::new();
+            if let Translator::Machine(ref client) = translator {
+                client.check_deepl_quota(&unique_texts, cache.as_ref());
+            }
 
-            for (path, dialogues) in all_dialogues.iter() {
-                for (i, entry) in dialogues.iter().enumerate() {
-                    all_texts.push(entry.original_text.clone());
-                    text_indices.push((path.clone(), i));
+            // Start a resumed run where the cache leaves off, so the ETA only
+            // reflects texts that still need a request
+            if let Some(ref cache) = cache {
+                let cached = translator.cached_count(&unique_texts, cache);
+                if cached > 0 {
+                    println!("  {} text(s) already cached", cached);
+                    pb.set_position(cached as u64);
+                    pb.tick();
+                    pb.reset_eta();
                 }
             }
+            pb.enable_steady_tick(std::time::Duration::from_millis(100));
 
-            let (results, dialogue_stats) = translator.translate_batch_with_stats(
-                &all_texts,
+            let (results, stats) = translator.translate_batch_with_stats(
+                &unique_texts,
+                &layout_sensitive,
                 cache.as_ref(),
                 Some(|count| {
                     pb.set_position(count as u64);
                 }),
             );
 
-            for ((path, idx), result) in text_indices.into_iter().zip(results.into_iter()) {
-                if let Some(dialogues) = all_dialogues.get_mut(&path)
-                    && let Some(entry) = dialogues.get_mut(idx)
-                {
-                    match result {
-                        Ok(translated) => {
-                            // Apply glossary if available
-                            let final_text = match &glossary {
-                                Some(g) => g.apply(&translated),
-                                None => translated,
-                            };
-                            entry.translated_text = Some(final_text);
-                        }
-                        Err(e) => {
+            trip_message = translator.trip_message();
+            let translations: Vec<Option<String>> = results
+                .into_iter()
+                .map(|result| match result {
+                    Ok(translated) => Some(match &glossary {
+                        Some(g) => g.apply(&translated),
+                        None => translated,
+                    }),
+                    Err(e) => {
+                        if trip_message.is_none() {
                             pb.suspend(|| {
                                 eprintln!("{}", format!("[ERROR] Translation failed: {}", e).red());
                             });
                         }
+                        None
                     }
-                }
-            }
+                })
+                .collect();
 
             pb.finish_and_clear();
 
-            // Translate strings
-            let mut string_stats = TranslationStats {
-                cache_hits: 0,
-                api_calls: 0,
-            };
-            if !all_strings.is_empty() {
-                println!("  Translating strings...");
-                let string_texts: Vec<String> =
-                    all_strings.iter().map(|s| s.original.clone()).collect();
-                let (string_results, stats) = translator.translate_batch_with_stats(
-                    &string_texts,
-                    cache.as_ref(),
-                    None::<fn(usize)>,
-                );
-                string_stats = stats;
-
-                for (string, result) in all_strings.iter_mut().zip(string_results.into_iter()) {
-                    if let Ok(translated) = result {
-                        let final_text = match &glossary {
-                            Some(g) => g.apply(&translated),
-                            None => translated,
-                        };
-                        string.translated = Some(final_text);
+            if args.back_check {
+                match translator {
+                    Translator::Machine(ref client) => {
+                        back_check_issues =
+                            back_check(client, &unique_texts, &translations, cache.as_ref())?;
                     }
+                    Translator::Llm(_) => println!(
+                        "{}",
+                        "[WARN] --back-check needs a machine provider (google, deepl), skipping"
+                            .yellow()
+                    ),
                 }
             }
 
+            for (path, idx, slot) in dialogue_slots {
+                if let Some(dialogues) = all_dialogues.get_mut(&path)
+                    && let Some(entry) = dialogues.get_mut(idx)
+                {
+                    entry.translated_text = translations[slot].clone();
+                }
+            }
+            for (i, slot) in string_slots {
+                all_strings[i].translated = translations[slot].clone();
+            }
+
             // Print statistics
-            let total_cache_hits = dialogue_stats.cache_hits + string_stats.cache_hits;
-            let total_api_calls = dialogue_stats.api_calls + string_stats.api_calls;
-            if total_cache_hits > 0 {
+            if stats.cache_hits > 0 {
                 println!(
                     "  Stats: {} cached, {} API calls",
-                    format!("{}", total_cache_hits).green(),
-                    total_api_calls
+                    format!("{}", stats.cache_hits).green(),
+                    stats.api_calls
                 );
             }
         }
     }
 
-    // Determine output directory
-    let output_dir = args.output.unwrap_or_else(|| {
-        if input.is_dir() {
-            input.join("game")
-        } else {
-            PathBuf::from("game")
-        }
-    });
+    let tag_issues = if args.preview_tags {
+        check_translated_markup(&mut all_dialogues, &mut all_strings, args.revert_bad_tags)
+    } else {
+        Vec::new()
+    };
+
+    if let (Some(manifest), Some(path)) = (manifest.as_mut(), args.manifest.as_ref()) {
+        manifest.update(&all_dialogues, &all_strings);
+        manifest.save(path)?;
+        println!("  Updated manifest: {}", path.display());
+    }
 
     // Generate translation files
     println!("  Generating translation files...");
     let created = generator.write_translation_files(&output_dir, &all_dialogues, &all_strings)?;
 
+    let problems = if args.validate {
+        generator.validate_files(&created)?
+    } else {
+        Vec::new()
+    };
+    if !problems.is_empty() {
+        for problem in &problems {
+            eprintln!("    {}", problem);
+        }
+        anyhow::bail!(
+            "Generated translation files failed validation ({} problem(s))",
+            problems.len()
+        );
+    }
+
     println!(
         "{}",
         format!("[OK] Created {} translation file(s)", created.len()).green()
@@ -318,16 +523,319 @@ pub fn run(args: PatchArgs) -> Result<()> {
         println!("    {}", file.display());
     }
 
+    if args.preview_tags {
+        if tag_issues.is_empty() {
+            println!("{}", "[OK] All translations keep their tags".green());
+        } else {
+            let report_path = output_dir.join("tag_report.txt");
+            fs::write(&report_path, tag_issues.join("\n") + "\n")?;
+            println!(
+                "{}",
+                format!(
+                    "[WARN] {} translation(s) with tag mismatches{}, see {}",
+                    tag_issues.len(),
+                    if args.revert_bad_tags {
+                        " reverted to source"
+                    } else {
+                        ""
+                    },
+                    report_path.display()
+                )
+                .yellow()
+            );
+        }
+    }
+
+    if args.back_check && !back_check_issues.is_empty() {
+        let report_path = output_dir.join("back_check.txt");
+        fs::write(&report_path, back_check_issues.join("\n") + "\n")?;
+        println!(
+            "{}",
+            format!(
+                "[WARN] {} translation(s) diverge on back-translation, review {}",
+                back_check_issues.len(),
+                report_path.display()
+            )
+            .yellow()
+        );
+    }
+
+    if let Some(message) = trip_message {
+        anyhow::bail!(message);
+    }
+
     println!();
     println!("To use this translation:");
-    println!("  1. Copy the 'tl' folder to your game's 'game' directory");
+    if layout == OutputLayout::Renpy {
+        println!("  1. Copy the 'tl' folder to your game's 'game' directory");
+    } else {
+        println!(
+            "  1. Move the generated files into your game's 'game/tl/{}' directory",
+            lang_name
+        );
+    }
     println!("  2. The game will auto-detect the translation");
     println!("  3. Add language selector to preferences if needed");
 
-    if let Some(temp_dir) = temp_dir_to_cleanup {
-        let _ = fs::remove_dir_all(temp_dir);
+    Ok(())
+}
+
+/// Directory an RPA input was extracted to, removed when dropped
+struct ExtractedDir(PathBuf);
+
+impl Drop for ExtractedDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A script's path, its dialogues and strings, and how many undecodable sequences it had
+type ExtractedScript = (PathBuf, Vec<DialogueEntry>, Vec<StringEntry>, usize);
+
+/// The dialogues and strings of one script's source, as `patch` collects them
+pub fn extract_script_str(
+    generator: &RenpyTranslationGenerator,
+    extractor: &TextExtractor,
+    content: &str,
+    rel_path: &Path,
+) -> (Vec<DialogueEntry>, Vec<StringEntry>) {
+    let dialogues = generator.extract_dialogues_from_str(content);
+    let mut strings = Vec::new();
+
+    // Strings are grouped by origin
+    let entries = extractor.extract_from_string(content).unwrap_or_default();
+    for e in entries {
+        if e.entry_type == EntryType::CharacterName {
+            strings.push(StringEntry {
+                original: e.text,
+                translated: None,
+                variable: None,
+                context: Some("Character names".to_string()),
+                layout: false,
+            });
+        } else if let EntryType::Define(variable) = e.entry_type {
+            strings.push(StringEntry {
+                original: e.text,
+                translated: None,
+                variable: Some(variable),
+                context: None,
+                layout: false,
+            });
+        } else if e.entry_type == EntryType::ScreenText {
+            strings.push(StringEntry {
+                original: e.text,
+                translated: None,
+                variable: None,
+                context: Some(format!("Screen text ({})", rel_path.display())),
+                layout: true,
+            });
+        } else if e.entry_type == EntryType::MenuChoice {
+            let context = e.menu.map(|m| match m.prompt {
+                Some(prompt) => format!(
+                    "Menu ({}:{}): \"{}\"",
+                    rel_path.display(),
+                    m.line_number,
+                    prompt
+                ),
+                None => format!("Menu ({}:{})", rel_path.display(), m.line_number),
+            });
+            strings.push(StringEntry {
+                original: e.text,
+                translated: None,
+                variable: None,
+                context,
+                layout: true,
+            });
+        }
+    }
+
+    (dialogues, strings)
+}
+
+/// The directory a game's `tl/` belongs in: the input itself when it is the `game/`
+/// directory or a loose folder of scripts, otherwise the project's `game/` subdirectory
+pub fn game_dir_for(input: &Path) -> PathBuf {
+    if input.file_name().is_some_and(|name| name == "game") {
+        return input.to_path_buf();
+    }
+    let game = input.join("game");
+    if game.is_dir() || !has_top_level_scripts(input) {
+        game
+    } else {
+        input.to_path_buf()
+    }
+}
+
+fn has_top_level_scripts(dir: &Path) -> bool {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries.filter_map(|e| e.ok()).any(|e| {
+                e.path()
+                    .extension()
+                    .is_some_and(|ext| ext == "rpy" || ext == "rpyc")
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// Translate results back to the source language and report the ones that drifted too far
+fn back_check(
+    client: &MachineTranslateClient,
+    sources: &[String],
+    translations: &[Option<String>],
+    cache: Option<&TranslationCache>,
+) -> Result<Vec<String>> {
+    let checked: Vec<(&String, &String)> = sources
+        .iter()
+        .zip(translations)
+        .filter_map(|(source, translated)| translated.as_ref().map(|t| (source, t)))
+        .collect();
+    if checked.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    println!("  Back-translating {} texts for review...", checked.len());
+    let reverse = client.reversed()?;
+    let texts: Vec<String> = checked.iter().map(|(_, t)| (*t).clone()).collect();
+    let results = match cache {
+        Some(cache) => {
+            reverse
+                .translate_batch_cached(&texts, cache, None::<fn(usize)>)
+                .translations
+        }
+        None => reverse.translate_batch(&texts, None::<fn(usize)>),
+    };
+
+    let mut report = Vec::new();
+    for ((source, translated), back) in checked.into_iter().zip(results) {
+        let Ok(back) = back else {
+            continue;
+        };
+        let score = similarity(source, &back);
+        if score < SIMILARITY_THRESHOLD {
+            report.push(format!(
+                "similarity {:.2}\n    original:   {}\n    translated: {}\n    back:       {}",
+                score, source, translated, back
+            ));
+        }
+    }
+
+    Ok(report)
+}
+
+fn check_translated_markup(
+    dialogues: &mut HashMap<PathBuf, Vec<DialogueEntry>>,
+    strings: &mut [StringEntry],
+    revert: bool,
+) -> Vec<String> {
+    let mut report = Vec::new();
+
+    for (path, entries) in dialogues.iter_mut() {
+        for entry in entries.iter_mut() {
+            let Some(ref translated) = entry.translated_text else {
+                continue;
+            };
+            let issues = check_markup(&entry.original_text, translated);
+            if issues.is_empty() {
+                continue;
+            }
+            report.push(format!(
+                "{}:{}: {}\n    original:   {}\n    translated: {}",
+                path.display(),
+                entry.line_number,
+                issues.join("; "),
+                entry.original_text,
+                translated
+            ));
+            if revert {
+                entry.translated_text = None;
+            }
+        }
+    }
+
+    for entry in strings.iter_mut() {
+        let Some(ref translated) = entry.translated else {
+            continue;
+        };
+        let issues = check_markup(&entry.original, translated);
+        if issues.is_empty() {
+            continue;
+        }
+        report.push(format!(
+            "strings: {}\n    original:   {}\n    translated: {}",
+            issues.join("; "),
+            entry.original,
+            translated
+        ));
+        if revert {
+            entry.translated = None;
+        }
+    }
+
+    report
+}
+
+/// The provider name from `--api` or the config file, the provider, and the target language
+fn resolve_provider(args: &PatchArgs, cfg: &Config) -> (String, LlmProvider, String) {
+    let provider_str = if args.api != "openai" {
+        args.api.clone()
+    } else {
+        cfg.api.provider.clone()
+    };
+    let provider = LlmProvider::from_str(&provider_str);
+
+    let lang = if args.lang != "chinese" {
+        args.lang.clone()
+    } else {
+        cfg.translation.default_language.clone()
+    };
+    (provider_str, provider, lang)
+}
+
+/// `--dry-run-prompts`: print the prompts for the first untranslated texts, in the order
+/// a real run would send them
+fn preview_prompts(
+    args: &PatchArgs,
+    cfg: &Config,
+    all_dialogues: &HashMap<PathBuf, Vec<DialogueEntry>>,
+    all_strings: &[StringEntry],
+    count: usize,
+) -> Result<()> {
+    let (provider_str, provider, lang) = resolve_provider(args, cfg);
+    if provider.is_machine_translate() {
+        anyhow::bail!("--dry-run-prompts only applies to LLM providers (openai, claude, ollama)");
     }
+    let Some(Translator::Llm(client)) =
+        create_llm_translator(provider, &provider_str, &lang, cfg, args)?
+    else {
+        anyhow::bail!("--dry-run-prompts needs an API key for {}", provider_str);
+    };
 
+    // Deduplicated like a real run, where a text shared with a layout-sensitive string gets a hint
+    let mut texts: Vec<String> = Vec::new();
+    let mut layout_sensitive: Vec<bool> = Vec::new();
+    let dialogues = all_dialogues
+        .values()
+        .flatten()
+        .filter(|_| !args.strings_only)
+        .filter(|d| d.translated_text.is_none())
+        .map(|d| (&d.original_text, false));
+    let strings = all_strings
+        .iter()
+        .filter(|_| !args.dialogue_only)
+        .filter(|s| s.translated.is_none())
+        .map(|s| (&s.original, s.layout));
+    let mut slots: HashMap<&String, usize> = HashMap::new();
+    for (text, layout) in dialogues.chain(strings) {
+        let slot = *slots.entry(text).or_insert_with(|| {
+            texts.push(text.clone());
+            layout_sensitive.push(false);
+            texts.len() - 1
+        });
+        layout_sensitive[slot] |= layout;
+    }
+
+    print_prompt_preview(&client, &texts, &layout_sensitive, count);
     Ok(())
 }
 
@@ -336,6 +844,7 @@ fn create_machine_translator(
     lang: &str,
     cfg: &Config,
     args: &PatchArgs,
+    sample: &[String],
 ) -> Result<Option<Translator>> {
     let config = match provider {
         LlmProvider::Google => {
@@ -343,7 +852,11 @@ fn create_machine_translator(
             MachineTranslateConfig::google(lang)
         }
         LlmProvider::DeepL => {
-            let api_key = args.api_key.clone().or_else(|| cfg.get_api_key("deepl"));
+            let api_key = args
+                .api_key
+                .first()
+                .cloned()
+                .or_else(|| cfg.get_api_key("deepl"));
 
             if api_key.is_none() {
                 println!(
@@ -361,7 +874,17 @@ fn create_machine_translator(
         _ => unreachable!(),
     };
 
-    let client = MachineTranslateClient::new(config)?;
+    let source_lang = resolve_source_lang(args.source_lang.as_deref(), sample);
+    let config = config
+        .with_source_lang(source_lang.as_deref())
+        .with_script(args.script.as_deref().and_then(ChineseScript::from_str))
+        .with_google_separator(cfg.translation.google_separator.clone())
+        .with_batch_size(args.batch_size)
+        .with_max_failures(args.max_failures.or(cfg.translation.max_failures))
+        .with_recovery(recovery(args.wait_for_recovery, args.recovery_poll, cfg))
+        .with_concurrency(args.min_concurrency, args.max_concurrency)
+        .with_ca_cert(cfg.get_ca_cert(args.ca_cert.as_deref()));
+    let client = MachineTranslateClient::new(config)?.with_cancel_flag(ctrl_c_flag());
     Ok(Some(Translator::Machine(client)))
 }
 
@@ -372,12 +895,13 @@ fn create_llm_translator(
     cfg: &Config,
     args: &PatchArgs,
 ) -> Result<Option<Translator>> {
-    let api_key = args
-        .api_key
-        .clone()
-        .or_else(|| cfg.get_api_key(provider_str));
+    let api_keys = if args.api_key.is_empty() {
+        cfg.get_api_keys(provider_str)
+    } else {
+        args.api_key.clone()
+    };
 
-    if api_key.is_none() && provider != LlmProvider::Ollama {
+    if api_keys.is_empty() && provider != LlmProvider::Ollama {
         println!(
             "{}",
             "[WARN] No API key provided, generating template only".yellow()
@@ -394,9 +918,19 @@ fn create_llm_translator(
     let model = args.model.clone().or_else(|| cfg.get_model(provider_str));
 
     let config = LlmConfig::new(provider, lang)
-        .with_api_key(api_key)
+        .with_api_keys(api_keys)
         .with_base_url(api_base)
-        .with_model(model);
+        .with_model(model)
+        .with_system_prompt(cfg.get_prompt(lang))
+        .with_script(args.script.as_deref().and_then(ChineseScript::from_str))
+        .with_max_failures(args.max_failures.or(cfg.translation.max_failures))
+        .with_recovery(recovery(args.wait_for_recovery, args.recovery_poll, cfg))
+        .with_ca_cert(cfg.get_ca_cert(args.ca_cert.as_deref()))
+        .with_length_hint(args.length_hint)
+        .with_context(load_context(args.context_file.as_deref(), cfg)?)
+        .with_prompt_log(cfg.get_prompt_log(args.prompt_log.as_deref()))
+        .with_json_mode(args.json_mode)
+        .with_batch_translate(args.batch_translate);
 
     let client = LlmClient::new(config)?;
     Ok(Some(Translator::Llm(client)))
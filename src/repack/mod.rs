@@ -5,10 +5,27 @@ pub mod rpa;
 use anyhow::{Context, Result};
 use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
 use walkdir::WalkDir;
 
 use crate::cli::RepackArgs;
-use rpa::RpaWriter;
+use crate::unpack::rpa::{EntryFilter, EntryOrder, RpaArchive, read_entry_list};
+use rpa::{RpaWriter, RpaWriterVersion};
+
+/// OS metadata and editor leftovers that should never ship in an archive
+const DEFAULT_EXCLUDES: &[&str] = &[
+    "**/.DS_Store",
+    "**/._*",
+    "**/Thumbs.db",
+    "**/desktop.ini",
+    "**/*~",
+    "**/*.swp",
+    "**/*.swo",
+    "**/.#*",
+    "**/*.tmp",
+];
 
 pub fn run(args: RepackArgs) -> Result<()> {
     let input = &args.input;
@@ -19,20 +36,125 @@ pub fn run(args: RepackArgs) -> Result<()> {
 
     let output = args.output.unwrap_or_else(|| input.with_extension("rpa"));
 
+    let matched = args
+        .match_archive
+        .as_ref()
+        .map(|path| {
+            RpaArchive::open(path).with_context(|| format!("Failed to open {}", path.display()))
+        })
+        .transpose()?;
+    let version = match (&matched, &args.match_archive) {
+        (Some(source), Some(path)) => {
+            let version = RpaWriterVersion::for_archive(source.version).with_context(|| {
+                format!(
+                    "{} archives cannot be written; choose a format with --version",
+                    source.version
+                )
+            })?;
+            println!("  Matching {} of {}", source.version, path.display());
+            version
+        }
+        _ => RpaWriterVersion::from_str(args.version.as_deref().unwrap_or("3.0")),
+    };
+
     println!("{}", format!("[Repack] {}", input.display()).green());
 
+    let listed = match &args.from_list {
+        Some(path) => read_entry_list(path)?,
+        None => Vec::new(),
+    };
+    if args.from_list.is_some() && listed.is_empty() {
+        anyhow::bail!("List file names no files");
+    }
+
+    // An explicit list is taken at its word, junk included
+    let mut exclude = args.exclude.clone();
+    if !args.no_default_excludes && listed.is_empty() {
+        exclude.extend(DEFAULT_EXCLUDES.iter().map(|p| p.to_string()));
+    }
+    let filter = EntryFilter::new(&args.include, &exclude).with_names(&listed);
+
     // Collect all files
-    let files: Vec<_> = WalkDir::new(input)
+    let all_files: Vec<_> = WalkDir::new(input)
         .into_iter()
         .filter_map(|e| e.ok())
         .filter(|e| e.path().is_file())
         .collect();
+    let found = all_files.len();
+
+    let mut files: Vec<_> = all_files
+        .into_iter()
+        .filter(|e| filter.matches(&archive_path(input, e.path())))
+        .collect();
+
+    // Data is written in this order; the index itself is always sorted by name
+    let ranked = |names: Vec<&str>| -> HashMap<String, usize> {
+        names
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| (name.to_string(), i))
+            .collect()
+    };
+    let rank = match args.order.as_str() {
+        "list" => Some(ranked(listed.iter().map(String::as_str).collect())),
+        "match" => matched
+            .as_ref()
+            .map(|source| ranked(source.sorted_names(EntryOrder::Offset))),
+        _ => None,
+    };
+    if args.order != "walk" {
+        // Files the order does not mention go last, by name
+        files.sort_by_cached_key(|e| {
+            let name = archive_path(input, e.path());
+            let position = rank
+                .as_ref()
+                .map_or(0, |rank| rank.get(&name).copied().unwrap_or(usize::MAX));
+            (position, name)
+        });
+    }
+
+    let packed: HashSet<String> = files
+        .iter()
+        .map(|e| archive_path(input, e.path()))
+        .collect();
+    let missing: Vec<&String> = listed.iter().filter(|n| !packed.contains(*n)).collect();
+    if !missing.is_empty() {
+        println!(
+            "{}",
+            format!("[WARN] {} listed file(s) not found:", missing.len()).yellow()
+        );
+        for name in missing {
+            println!("  {}", name);
+        }
+    }
 
     if files.is_empty() {
         anyhow::bail!("No files found in directory");
     }
 
-    println!("  Found {} file(s)", files.len());
+    if files.len() < found {
+        println!(
+            "  Found {} file(s), skipped {} excluded",
+            files.len(),
+            found - files.len()
+        );
+    } else {
+        println!("  Found {} file(s)", files.len());
+    }
+
+    // The archive being updated, and how many of its entries the input replaces
+    let existing = if args.update {
+        let archive = RpaArchive::open(&output)
+            .with_context(|| format!("--update needs an existing archive: {}", output.display()))?;
+        let replaced = packed
+            .iter()
+            .filter(|name| archive.index.contains_key(*name))
+            .count();
+        Some((archive, replaced))
+    } else {
+        None
+    };
+    let compact_path = output.with_extension("rpa.tmp");
 
     let pb = ProgressBar::new(files.len() as u64);
     pb.set_style(
@@ -41,7 +163,11 @@ pub fn run(args: RepackArgs) -> Result<()> {
             .progress_chars("=>-"),
     );
 
-    let mut writer = RpaWriter::new(&output, args.version.as_deref().unwrap_or("3.0"))?;
+    let mut writer = match &existing {
+        None => RpaWriter::with_version(&output, version)?,
+        Some((archive, _)) if args.compact => compacted(archive, &compact_path, &packed)?,
+        Some(_) => RpaWriter::update(&output)?,
+    };
 
     for entry in &files {
         let file_path = entry.path();
@@ -60,7 +186,50 @@ pub fn run(args: RepackArgs) -> Result<()> {
 
     pb.finish_and_clear();
 
-    println!("{}", format!("[OK] Created {}", output.display()).green());
+    let Some((_, replaced)) = existing else {
+        println!("{}", format!("[OK] Created {}", output.display()).green());
+        return Ok(());
+    };
+    if args.compact {
+        fs::rename(&compact_path, &output).context("Failed to replace the archive")?;
+    }
+    println!(
+        "{}",
+        format!(
+            "[OK] Updated {} ({} replaced, {} added)",
+            output.display(),
+            replaced,
+            files.len() - replaced
+        )
+        .green()
+    );
+    if replaced > 0 && !args.compact {
+        println!("  Replaced data is left in the archive; pass --compact to reclaim the space");
+    }
 
     Ok(())
 }
+
+/// A new archive at `path` in the format of `archive`, holding its entries except those
+/// about to be replaced
+fn compacted(archive: &RpaArchive, path: &Path, replaced: &HashSet<String>) -> Result<RpaWriter> {
+    let version = RpaWriterVersion::for_archive(archive.version)
+        .with_context(|| format!("{} archives cannot be updated", archive.version))?;
+    let mut writer = RpaWriter::with_version(path, version)?;
+
+    let mut names: Vec<&String> = archive.index.keys().collect();
+    names.sort();
+    for name in names.into_iter().filter(|n| !replaced.contains(*n)) {
+        writer
+            .add_bytes(&archive.read_file_bytes(name)?, name)
+            .with_context(|| format!("Failed to copy {}", name))?;
+    }
+
+    Ok(writer)
+}
+
+/// A file's path inside the archive: relative to the input directory, with forward slashes
+fn archive_path(input: &Path, file: &Path) -> String {
+    let relative = file.strip_prefix(input).unwrap_or(file);
+    relative.to_string_lossy().replace('\\', "/")
+}
@@ -7,48 +7,72 @@ use serde::ser::SerializeMap;
 use serde::{Serialize, Serializer};
 use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
 use std::path::Path;
 
+use crate::unpack::rpa::{ALT_KEY_MASK, RpaArchive, RpaVersion};
+
 #[derive(Debug, Clone, Copy)]
 pub enum RpaWriterVersion {
     Rpa2,
     Rpa3,
+    Rpa32,
+    Rpa4,
+    Alt1,
 }
 
 impl RpaWriterVersion {
     pub fn from_str(s: &str) -> Self {
         match s {
             "2.0" | "2" => Self::Rpa2,
+            "3.2" => Self::Rpa32,
+            "4.0" | "4" => Self::Rpa4,
+            "ALT-1.0" | "alt-1.0" | "alt" => Self::Alt1,
             _ => Self::Rpa3,
         }
     }
+
+    /// The writer producing the same format as an existing archive; split RPA-1.0 has none
+    pub fn for_archive(version: RpaVersion) -> Option<Self> {
+        match version {
+            RpaVersion::Rpa1 => None,
+            RpaVersion::Rpa2 => Some(Self::Rpa2),
+            RpaVersion::Rpa3 => Some(Self::Rpa3),
+            RpaVersion::Rpa32 => Some(Self::Rpa32),
+            RpaVersion::Rpa40 => Some(Self::Rpa4),
+            RpaVersion::Alt1 => Some(Self::Alt1),
+        }
+    }
 }
 
 struct FileEntry {
     offset: u64,
     length: u64,
-    archive_path: String,
+    prefix: Vec<u8>,
 }
 
 pub struct RpaWriter {
     file: BufWriter<File>,
     version: RpaWriterVersion,
     key: u64,
-    entries: Vec<FileEntry>,
+    /// Keyed by archive path, so adding a file that is already there replaces it
+    entries: BTreeMap<String, FileEntry>,
+    /// Length of the existing header line when updating in place; new archives reserve 51 bytes
+    header_len: Option<usize>,
 }
 
 impl RpaWriter {
-    pub fn new<P: AsRef<Path>>(path: P, version: &str) -> Result<Self> {
+    pub fn with_version<P: AsRef<Path>>(path: P, version: RpaWriterVersion) -> Result<Self> {
         let file = File::create(path.as_ref()).context("Failed to create RPA file")?;
         let mut writer = BufWriter::new(file);
-        let version = RpaWriterVersion::from_str(version);
 
-        // Generate random key for RPA-3.0
-        let key = if matches!(version, RpaWriterVersion::Rpa3) {
-            rand_key()
-        } else {
-            0
+        // Generate random key: 32 bits for RPA-3.x and ALT-1.0, the full 64 bits for RPA-4.0
+        let key = match version {
+            RpaWriterVersion::Rpa2 => 0,
+            RpaWriterVersion::Rpa3 | RpaWriterVersion::Rpa32 | RpaWriterVersion::Alt1 => {
+                rand_key() & 0xFFFFFFFF
+            }
+            RpaWriterVersion::Rpa4 => rand_key(),
         };
 
         // Write placeholder header (will be updated at the end)
@@ -59,30 +83,85 @@ impl RpaWriter {
             file: writer,
             version,
             key,
-            entries: Vec::new(),
+            entries: BTreeMap::new(),
+            header_len: None,
+        })
+    }
+
+    /// Reopen an existing archive to add or replace files in place. New data is appended after
+    /// the old index and `finish` writes a new index and header; replaced data stays behind as
+    /// dead bytes. The archive keeps its version and key.
+    pub fn update<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let archive = RpaArchive::open(path)?;
+        let version = RpaWriterVersion::for_archive(archive.version)
+            .with_context(|| format!("{} archives cannot be updated", archive.version))?;
+        if archive.index_offset.is_none() {
+            anyhow::bail!("Archives indexed by a separate .rpi file cannot be updated");
+        }
+
+        let mut header = Vec::new();
+        BufReader::new(File::open(path).context("Failed to open RPA file")?)
+            .read_until(b'\n', &mut header)
+            .context("Failed to read RPA header")?;
+
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .open(path)
+            .context("Failed to open RPA file for writing")?;
+        let mut writer = BufWriter::new(file);
+        // Appending after the old index keeps the archive readable until the header is rewritten
+        writer.seek(SeekFrom::End(0))?;
+
+        let entries = archive
+            .index
+            .into_iter()
+            .map(|(name, entry)| {
+                let entry = FileEntry {
+                    offset: entry.offset,
+                    length: entry.length,
+                    prefix: entry.prefix,
+                };
+                (name, entry)
+            })
+            .collect();
+
+        Ok(Self {
+            file: writer,
+            version,
+            key: archive.key.unwrap_or(0),
+            entries,
+            header_len: Some(header.len()),
         })
     }
 
     pub fn add_file<P: AsRef<Path>>(&mut self, file_path: P, archive_path: &Path) -> Result<()> {
         let mut file = File::open(file_path.as_ref()).context("Failed to open input file")?;
 
-        let offset = self.file.stream_position()?;
-
         // Copy file data
         let mut buffer = Vec::new();
         file.read_to_end(&mut buffer)?;
-        let length = buffer.len() as u64;
-
-        self.file.write_all(&buffer)?;
 
         // Normalize path to use forward slashes
         let archive_path_str = archive_path.to_string_lossy().replace('\\', "/");
 
-        self.entries.push(FileEntry {
-            offset,
-            length,
-            archive_path: archive_path_str,
-        });
+        self.add_bytes(&buffer, &archive_path_str)
+    }
+
+    /// Add a file's contents under `archive_path`, replacing any entry already there
+    pub fn add_bytes(&mut self, data: &[u8], archive_path: &str) -> Result<()> {
+        let offset = self.file.stream_position()?;
+        self.file.write_all(data)?;
+
+        self.entries.insert(
+            archive_path.to_string(),
+            FileEntry {
+                offset,
+                length: data.len() as u64,
+                prefix: Vec::new(),
+            },
+        );
 
         Ok(())
     }
@@ -105,6 +184,9 @@ impl RpaWriter {
         // Write compressed index
         self.file.write_all(&compressed)?;
 
+        // Everything the header will point at must be on disk before it changes
+        self.file.flush()?;
+
         // Seek back to start and write proper header
         self.file.seek(SeekFrom::Start(0))?;
 
@@ -115,10 +197,36 @@ impl RpaWriter {
             RpaWriterVersion::Rpa3 => {
                 format!("RPA-3.0 {:016x} {:08x}\n", index_offset, self.key)
             }
+            RpaWriterVersion::Rpa32 => {
+                format!("RPA-3.2 {:016x} {:08x}\n", index_offset, self.key)
+            }
+            RpaWriterVersion::Rpa4 => {
+                format!("RPA-4.0 {:016x} {:016x}\n", index_offset, self.key)
+            }
+            // The key comes first and is stored masked
+            RpaWriterVersion::Alt1 => {
+                format!(
+                    "ALT-1.0 {:08x} {:016x}\n",
+                    self.key ^ ALT_KEY_MASK,
+                    index_offset
+                )
+            }
         };
 
-        // Pad header to exactly 51 bytes
-        let header = format!("{:0<51}", header);
+        let header = match self.header_len {
+            // Pad header to exactly 51 bytes
+            None => format!("{:0<51}", header),
+            // Data may start right after the old header line, so the new one must fit in it
+            Some(len) => {
+                if header.len() > len {
+                    anyhow::bail!(
+                        "The new header does not fit in the archive's {}-byte header; repack it instead",
+                        len
+                    );
+                }
+                format!("{:<width$}\n", header.trim_end(), width = len - 1)
+            }
+        };
         self.file.write_all(header.as_bytes())?;
 
         self.file.flush()?;
@@ -129,15 +237,15 @@ impl RpaWriter {
     fn build_index(&self) -> RpaIndex {
         let mut entries = BTreeMap::new();
 
-        for entry in &self.entries {
+        for (archive_path, entry) in &self.entries {
             let (offset, length) = match self.version {
                 RpaWriterVersion::Rpa2 => (entry.offset, entry.length),
-                RpaWriterVersion::Rpa3 => (entry.offset ^ self.key, entry.length ^ self.key),
+                _ => (entry.offset ^ self.key, entry.length ^ self.key),
             };
 
             entries.insert(
-                entry.archive_path.clone(),
-                vec![(offset as i64, length as i64, Vec::new())],
+                archive_path.clone(),
+                vec![(offset, length, Prefix(entry.prefix.clone()))],
             );
         }
 
@@ -146,7 +254,20 @@ impl RpaWriter {
 }
 
 struct RpaIndex {
-    entries: BTreeMap<String, Vec<(i64, i64, Vec<u8>)>>,
+    // Unsigned, so masked values past `i64::MAX` are pickled as positive longs like Python's
+    entries: BTreeMap<String, Vec<(u64, u64, Prefix)>>,
+}
+
+/// Leading bytes of an entry kept in the index rather than the data, pickled as `bytes`
+struct Prefix(Vec<u8>);
+
+impl Serialize for Prefix {
+    fn serialize<S>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(&self.0)
+    }
 }
 
 impl Serialize for RpaIndex {
@@ -169,5 +290,6 @@ fn rand_key() -> u64 {
     let duration = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap_or_default();
-    (duration.as_nanos() as u64) & 0xFFFFFFFF
+    // Spread the clock's low bits over the whole word so 64-bit keys use the upper half too
+    (duration.as_nanos() as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
 }
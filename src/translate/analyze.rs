@@ -0,0 +1,140 @@
+//! Glossary candidate discovery: recurring capitalized words are likely names and places
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::collections::HashMap;
+use std::fs;
+use walkdir::WalkDir;
+
+use super::extractor::TextExtractor;
+use crate::cli::AnalyzeArgs;
+
+pub fn run(args: AnalyzeArgs) -> Result<()> {
+    if !args.input.exists() {
+        anyhow::bail!("Input path does not exist: {}", args.input.display());
+    }
+
+    let extractor = TextExtractor::new().with_character_names(true);
+    let texts: Vec<String> = WalkDir::new(&args.input)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.path()
+                .extension()
+                .is_some_and(|ext| ext == "rpy" || ext == "rpym")
+        })
+        .flat_map(|e| extractor.extract_from_file(e.path()).unwrap_or_default())
+        .map(|e| e.text)
+        .collect();
+
+    let mut candidates = name_candidates(&texts, args.min_count);
+    candidates.truncate(args.top);
+
+    let mut skeleton = format!(
+        "# Glossary candidates from {}: fill in translations and delete lines you don't need\n\n",
+        args.input.display()
+    );
+    for (name, count) in &candidates {
+        skeleton.push_str(&format!("{} =  # {} occurrence(s)\n", name, count));
+    }
+
+    match args.output {
+        Some(ref path) => {
+            fs::write(path, skeleton).context("Failed to write glossary skeleton")?;
+            println!(
+                "{}",
+                format!(
+                    "[OK] Wrote {} candidate(s) from {} text(s) to {}",
+                    candidates.len(),
+                    texts.len(),
+                    path.display()
+                )
+                .green()
+            );
+        }
+        None => print!("{}", skeleton),
+    }
+
+    Ok(())
+}
+
+/// Capitalized words seen at least `min_count` times, most frequent first
+///
+/// A word only counts as a name if it is capitalized somewhere other than the start of a
+/// sentence and is not more common in lowercase, which rules out "The", "What" and the like.
+pub fn name_candidates<S: AsRef<str>>(texts: &[S], min_count: usize) -> Vec<(String, usize)> {
+    let mut mid_sentence: HashMap<String, usize> = HashMap::new();
+    let mut sentence_initial: HashMap<String, usize> = HashMap::new();
+    let mut lowercase: HashMap<String, usize> = HashMap::new();
+
+    for text in texts {
+        let mut word = String::new();
+        let mut at_start = true;
+        let mut depth = 0usize;
+
+        for c in text.as_ref().chars().chain(std::iter::once(' ')) {
+            // `{tags}` and `[interpolations]` are code, not prose
+            match c {
+                '{' | '[' => depth += 1,
+                '}' | ']' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            if depth == 0 && c.is_alphanumeric() {
+                word.push(c);
+                continue;
+            }
+
+            if !word.is_empty() {
+                let first = word.chars().next().unwrap_or_default();
+                if first.is_uppercase() && word.chars().count() >= 2 {
+                    let counts = if at_start {
+                        &mut sentence_initial
+                    } else {
+                        &mut mid_sentence
+                    };
+                    *counts.entry(word.clone()).or_insert(0) += 1;
+                } else if first.is_lowercase() {
+                    *lowercase.entry(word.clone()).or_insert(0) += 1;
+                }
+                at_start = false;
+                word.clear();
+            }
+            if matches!(c, '.' | '!' | '?' | '…' | ':' | '"' | '“' | '”') {
+                at_start = true;
+            }
+        }
+    }
+
+    let mut candidates: Vec<(String, usize)> = mid_sentence
+        .into_iter()
+        .filter(|(word, mid)| lowercase.get(&word.to_lowercase()).copied().unwrap_or(0) < *mid)
+        .map(|(word, mid)| {
+            let total = mid + sentence_initial.get(&word).copied().unwrap_or(0);
+            (word, total)
+        })
+        .filter(|(_, total)| *total >= min_count)
+        .collect();
+    candidates.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_candidates() {
+        let texts = [
+            "Sylvie waved at me from across the Quad.",
+            "I think Sylvie is waiting at the Quad.",
+            "The professor smiled. Professor Eileen asked about Sylvie's sketches.",
+            "The lecture is over, so the professor leaves.",
+            "What a day. {b}Bold{/b} [player_name] sighs.",
+        ];
+        let candidates = name_candidates(&texts, 2);
+        assert_eq!(
+            candidates,
+            [("Sylvie".to_string(), 3), ("Quad".to_string(), 2)]
+        );
+    }
+}
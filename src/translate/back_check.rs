@@ -0,0 +1,53 @@
+//! Back-translation checks that flag likely mistranslations for human review
+
+use std::collections::HashMap;
+
+/// Back-translations sharing fewer words than this with the source are flagged
+pub const SIMILARITY_THRESHOLD: f64 = 0.4;
+
+/// Dice coefficient over lowercase word tokens: 1.0 for the same words, 0.0 for none in common
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let a = word_counts(a);
+    let b = word_counts(b);
+    let total: usize = a.values().chain(b.values()).sum();
+    if total == 0 {
+        return 1.0;
+    }
+
+    let shared: usize = a
+        .iter()
+        .map(|(word, count)| (*count).min(b.get(word).copied().unwrap_or(0)))
+        .sum();
+    2.0 * shared as f64 / total as f64
+}
+
+fn word_counts(text: &str) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    for word in text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+    {
+        *counts.entry(word.to_lowercase()).or_insert(0) += 1;
+    }
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_similarity() {
+        assert_eq!(similarity("Hello there!", "hello, there"), 1.0);
+        assert_eq!(similarity("", "..."), 1.0);
+        assert_eq!(similarity("I love you", "The weather is nice"), 0.0);
+
+        let close = similarity(
+            "Let's go to the beach tomorrow.",
+            "Tomorrow let's go to the sea.",
+        );
+        assert!(close >= SIMILARITY_THRESHOLD, "{}", close);
+        let far = similarity("Let's go to the beach tomorrow.", "The dog is sleeping.");
+        assert!(far < SIMILARITY_THRESHOLD, "{}", far);
+    }
+}
@@ -1,28 +1,175 @@
 //! Translation cache using SQLite
 
 use anyhow::{Context, Result};
-use rusqlite::{Connection, params};
-use std::path::PathBuf;
+use rusqlite::{Connection, ErrorCode, OpenFlags, params};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::utils::cache_root;
+
+const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct TranslationCache {
     conn: Connection,
+    fuzzy: bool,
+    overwrite: bool,
+    cache_only: bool,
 }
 
 #[derive(Debug, Default)]
-#[allow(dead_code)]
 pub struct CacheStats {
     pub total_entries: usize,
     pub providers: Vec<(String, usize)>,
 }
 
+/// Result of `cache repair`
+#[derive(Debug, PartialEq)]
+pub enum RepairOutcome {
+    Healthy,
+    /// The damaged file was moved to `backup` and `salvaged` readable entries were kept
+    Repaired {
+        backup: PathBuf,
+        salvaged: usize,
+    },
+}
+
+/// A single cached translation, as exported for sharing translation memory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub source: String,
+    pub lang: String,
+    pub provider: String,
+    pub translated: String,
+    #[serde(default)]
+    pub created_at: Option<i64>,
+}
+
 impl TranslationCache {
     pub fn open() -> Result<Self> {
-        let path = Self::cache_path()?;
+        Self::open_at(&Self::cache_path()?)
+    }
+
+    /// Like `open`, but a corrupt database is moved aside and replaced by an empty one.
+    /// Returns where the damaged file went, if it had to be replaced.
+    pub fn open_recovering() -> Result<(Self, Option<PathBuf>)> {
+        Self::open_recovering_at(&Self::cache_path()?)
+    }
+
+    pub fn open_recovering_at(path: &Path) -> Result<(Self, Option<PathBuf>)> {
+        match Self::open_at(path) {
+            Ok(cache) => Ok((cache, None)),
+            Err(e) if Self::is_corruption(&e) => {
+                let backup = Self::move_aside(path)?;
+                Ok((Self::open_at(path)?, Some(backup)))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Check the database page by page and rebuild it from whatever rows are still readable
+    pub fn repair() -> Result<RepairOutcome> {
+        Self::repair_at(&Self::cache_path()?)
+    }
+
+    pub fn repair_at(path: &Path) -> Result<RepairOutcome> {
+        if !path.exists() {
+            return Ok(RepairOutcome::Healthy);
+        }
+        // A locked or unreadable database is not a damaged one; leave it alone
+        let healthy = match Self::open_at(path).and_then(|cache| cache.integrity_ok()) {
+            Ok(healthy) => healthy,
+            Err(e) if Self::is_corruption(&e) => false,
+            Err(e) => {
+                return Err(e.context(format!("Failed to check {}", path.display())));
+            }
+        };
+        if healthy {
+            return Ok(RepairOutcome::Healthy);
+        }
+
+        let backup = Self::move_aside(path)?;
+        let entries = Self::salvage(&backup);
+        let mut cache = Self::open_at(path)?;
+        let salvaged = cache.import_entries(&entries)?;
+        Ok(RepairOutcome::Repaired { backup, salvaged })
+    }
+
+    fn integrity_ok(&self) -> Result<bool> {
+        let result: String = self
+            .conn
+            .query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+        Ok(result == "ok")
+    }
+
+    /// Rows readable from a damaged database, up to the first unreadable one
+    fn salvage(path: &Path) -> Vec<CacheEntry> {
+        let Ok(conn) = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY) else {
+            return Vec::new();
+        };
+        let Ok(mut stmt) = conn.prepare(
+            "SELECT source_text, target_lang, provider, translated_text, created_at
+             FROM translations",
+        ) else {
+            return Vec::new();
+        };
+        let Ok(rows) = stmt.query_map([], |row| {
+            Ok(CacheEntry {
+                source: row.get(0)?,
+                lang: row.get(1)?,
+                provider: row.get(2)?,
+                translated: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        }) else {
+            return Vec::new();
+        };
+        rows.map_while(|r| r.ok()).collect()
+    }
+
+    fn is_corruption(e: &anyhow::Error) -> bool {
+        e.chain().any(|cause| {
+            cause
+                .downcast_ref::<rusqlite::Error>()
+                .and_then(|e| e.sqlite_error_code())
+                .is_some_and(|code| {
+                    matches!(code, ErrorCode::NotADatabase | ErrorCode::DatabaseCorrupt)
+                })
+        })
+    }
+
+    /// Rename a damaged database, with its WAL files, to `<name>.corrupt-<timestamp>`
+    fn move_aside(path: &Path) -> Result<PathBuf> {
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(format!(".corrupt-{}", stamp));
+        let backup = PathBuf::from(backup);
+
+        std::fs::rename(path, &backup)
+            .with_context(|| format!("Failed to move corrupt cache to {}", backup.display()))?;
+        for suffix in ["-wal", "-shm"] {
+            let mut from = path.as_os_str().to_owned();
+            from.push(suffix);
+            let mut to = backup.as_os_str().to_owned();
+            to.push(suffix);
+            let _ = std::fs::rename(from, to);
+        }
+        Ok(backup)
+    }
+
+    pub fn open_at(path: &Path) -> Result<Self> {
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent)?;
         }
 
-        let conn = Connection::open(&path).context("Failed to open translation cache")?;
+        let conn = Connection::open(path).context("Failed to open translation cache")?;
+
+        // WAL lets concurrent derenpy processes share the cache without "database is locked"
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
 
         conn.execute(
             "CREATE TABLE IF NOT EXISTS translations (
@@ -43,10 +190,57 @@ impl TranslationCache {
             [],
         )?;
 
-        Ok(Self { conn })
+        Ok(Self {
+            conn,
+            fuzzy: false,
+            overwrite: false,
+            cache_only: false,
+        })
+    }
+
+    /// Fall back to a whitespace-normalized key when the exact text misses
+    pub fn with_fuzzy(mut self, enabled: bool) -> Self {
+        self.fuzzy = enabled;
+        self
+    }
+
+    /// Treat every lookup as a miss so fresh translations replace the cached ones
+    pub fn with_overwrite(mut self, enabled: bool) -> Self {
+        self.overwrite = enabled;
+        self
+    }
+
+    pub fn overwrites(&self) -> bool {
+        self.overwrite
+    }
+
+    /// Translate strictly from the cache: misses are left untranslated instead of fetched
+    pub fn with_cache_only(mut self, enabled: bool) -> Self {
+        self.cache_only = enabled;
+        self
+    }
+
+    pub fn cache_only(&self) -> bool {
+        self.cache_only
     }
 
     pub fn get(&self, text: &str, lang: &str, provider: &str) -> Option<String> {
+        if self.overwrite {
+            return None;
+        }
+        self.get_exact(text, lang, provider).or_else(|| {
+            if !self.fuzzy {
+                return None;
+            }
+            let normalized = Self::normalize(text);
+            if normalized == text {
+                return None;
+            }
+            self.get_exact(&normalized, lang, provider)
+        })
+    }
+
+    fn get_exact(&self, text: &str, lang: &str, provider: &str) -> Option<String> {
         self.conn
             .query_row(
                 "SELECT translated_text FROM translations 
@@ -58,6 +252,17 @@ impl TranslationCache {
     }
 
     pub fn set(&self, text: &str, lang: &str, provider: &str, translated: &str) -> Result<()> {
+        self.set_exact(text, lang, provider, translated)?;
+        if self.fuzzy {
+            let normalized = Self::normalize(text);
+            if normalized != text {
+                self.set_exact(&normalized, lang, provider, translated)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_exact(&self, text: &str, lang: &str, provider: &str, translated: &str) -> Result<()> {
         self.conn.execute(
             "INSERT OR REPLACE INTO translations (source_text, target_lang, provider, translated_text)
              VALUES (?1, ?2, ?3, ?4)",
@@ -66,7 +271,11 @@ impl TranslationCache {
         Ok(())
     }
 
-    #[allow(dead_code)]
+    /// Trim and collapse internal whitespace runs to a single space
+    fn normalize(text: &str) -> String {
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
     pub fn stats(&self) -> Result<CacheStats> {
         let total: usize = self
             .conn
@@ -86,16 +295,276 @@ impl TranslationCache {
         })
     }
 
-    #[allow(dead_code)]
+    /// Remove one cached translation, returning whether it existed
+    pub fn delete(&self, text: &str, lang: &str, provider: &str) -> Result<bool> {
+        let deleted = self.conn.execute(
+            "DELETE FROM translations
+             WHERE source_text = ?1 AND target_lang = ?2 AND provider = ?3",
+            params![text, lang, provider],
+        )?;
+        Ok(deleted > 0)
+    }
+
     pub fn clear(&self) -> Result<()> {
         self.conn.execute("DELETE FROM translations", [])?;
         Ok(())
     }
 
+    pub fn export_entries(&self) -> Result<Vec<CacheEntry>> {
+        self.entries_since(None)
+    }
+
+    /// Entries created at or after the given unix timestamp, oldest first; without one,
+    /// every entry, including those migrated without a creation time
+    pub fn entries_since(&self, ts: Option<i64>) -> Result<Vec<CacheEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT source_text, target_lang, provider, translated_text, created_at
+             FROM translations WHERE ?1 IS NULL OR created_at >= ?1 ORDER BY created_at, id",
+        )?;
+        let entries = stmt
+            .query_map(params![ts], |row| {
+                Ok(CacheEntry {
+                    source: row.get(0)?,
+                    lang: row.get(1)?,
+                    provider: row.get(2)?,
+                    translated: row.get(3)?,
+                    created_at: row.get(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    /// Insert entries without overwriting existing translations, returning how many were added
+    pub fn import_entries(&mut self, entries: &[CacheEntry]) -> Result<usize> {
+        let tx = self.conn.transaction()?;
+        let mut inserted = 0;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO translations
+                 (source_text, target_lang, provider, translated_text, created_at)
+                 VALUES (?1, ?2, ?3, ?4, COALESCE(?5, strftime('%s', 'now')))",
+            )?;
+            for entry in entries {
+                inserted += stmt.execute(params![
+                    entry.source,
+                    entry.lang,
+                    entry.provider,
+                    entry.translated,
+                    entry.created_at
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(inserted)
+    }
+
     fn cache_path() -> Result<PathBuf> {
-        let cache_dir = dirs::cache_dir()
-            .context("Failed to find cache directory")?
-            .join("derenpy");
-        Ok(cache_dir.join("translations.db"))
+        Ok(cache_root()?.join("translations.db"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_recover_corrupt_db() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("translations.db");
+        std::fs::write(
+            &path,
+            b"this is not an sqlite database, just leftover bytes",
+        )
+        .unwrap();
+
+        assert!(TranslationCache::open_at(&path).is_err());
+        let (cache, backup) = TranslationCache::open_recovering_at(&path).unwrap();
+        let backup = backup.expect("corrupt file should be moved aside");
+        assert!(backup.exists());
+        cache.set("Hello", "zh-CN", "google", "你好").unwrap();
+        assert_eq!(
+            cache.get("Hello", "zh-CN", "google").as_deref(),
+            Some("你好")
+        );
+
+        let (_, backup) = TranslationCache::open_recovering_at(&path).unwrap();
+        assert_eq!(backup, None);
+    }
+
+    #[test]
+    fn test_repair() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("translations.db");
+        TranslationCache::open_at(&path)
+            .unwrap()
+            .set("Hello", "zh-CN", "google", "你好")
+            .unwrap();
+        assert_eq!(
+            TranslationCache::repair_at(&path).unwrap(),
+            RepairOutcome::Healthy
+        );
+
+        std::fs::write(&path, b"garbage").unwrap();
+        let RepairOutcome::Repaired { backup, salvaged } =
+            TranslationCache::repair_at(&path).unwrap()
+        else {
+            panic!("garbage database should be repaired");
+        };
+        assert!(backup.exists());
+        assert_eq!(salvaged, 0);
+        assert!(TranslationCache::open_at(&path).is_ok());
+    }
+
+    #[test]
+    fn test_repair_leaves_unopenable_db_alone() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        // Cannot be opened, but is not corrupt either
+        let path = temp_dir.path().join("translations.db");
+        std::fs::create_dir(&path).unwrap();
+
+        assert!(TranslationCache::repair_at(&path).is_err());
+        assert!(path.is_dir());
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("translations.db");
+        TranslationCache::open_at(&path).unwrap();
+
+        let handles: Vec<_> = (0..2)
+            .map(|t| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    let cache = TranslationCache::open_at(&path).unwrap();
+                    for i in 0..100 {
+                        let text = format!("text {} {}", t, i);
+                        cache.set(&text, "zh-CN", "google", "translated").unwrap();
+                    }
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let cache = TranslationCache::open_at(&path).unwrap();
+        assert_eq!(cache.stats().unwrap().total_entries, 200);
+    }
+
+    #[test]
+    fn test_export_import() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let source = TranslationCache::open_at(&temp_dir.path().join("a.db")).unwrap();
+        source.set("Hello", "zh-CN", "google", "你好").unwrap();
+        source.set("Bye", "zh-CN", "google", "再见").unwrap();
+
+        let mut target = TranslationCache::open_at(&temp_dir.path().join("b.db")).unwrap();
+        target.set("Hello", "zh-CN", "google", "您好").unwrap();
+
+        let exported = source.export_entries().unwrap();
+        assert_eq!(exported.len(), 2);
+
+        let inserted = target.import_entries(&exported).unwrap();
+        assert_eq!(inserted, 1);
+        assert_eq!(
+            target.get("Hello", "zh-CN", "google").as_deref(),
+            Some("您好")
+        );
+        assert_eq!(
+            target.get("Bye", "zh-CN", "google").as_deref(),
+            Some("再见")
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_lookup() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("f.db");
+
+        let exact = TranslationCache::open_at(&path).unwrap();
+        exact
+            .set("Hello  there ", "zh-CN", "google", "你好")
+            .unwrap();
+        assert_eq!(exact.get("Hello there", "zh-CN", "google"), None);
+
+        let fuzzy = TranslationCache::open_at(&path).unwrap().with_fuzzy(true);
+        fuzzy
+            .set("Good  night ", "zh-CN", "google", "晚安")
+            .unwrap();
+        assert_eq!(
+            fuzzy.get(" Good night", "zh-CN", "google").as_deref(),
+            Some("晚安")
+        );
+        assert_eq!(
+            fuzzy.get("Good night", "zh-CN", "google").as_deref(),
+            Some("晚安")
+        );
+    }
+
+    #[test]
+    fn test_delete_and_overwrite() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("d.db");
+        let cache = TranslationCache::open_at(&path).unwrap();
+        cache.set("Hello", "zh-CN", "google", "喂").unwrap();
+        cache.set("Bye", "zh-CN", "google", "再见").unwrap();
+
+        let overwriting = TranslationCache::open_at(&path)
+            .unwrap()
+            .with_overwrite(true);
+        assert_eq!(overwriting.get("Hello", "zh-CN", "google"), None);
+        overwriting.set("Hello", "zh-CN", "google", "你好").unwrap();
+        assert_eq!(
+            cache.get("Hello", "zh-CN", "google").as_deref(),
+            Some("你好")
+        );
+
+        assert!(cache.delete("Bye", "zh-CN", "google").unwrap());
+        assert!(!cache.delete("Bye", "zh-CN", "google").unwrap());
+        assert_eq!(cache.get("Bye", "zh-CN", "google"), None);
+        assert_eq!(cache.stats().unwrap().total_entries, 1);
+    }
+
+    #[test]
+    fn test_entries_since() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let mut cache = TranslationCache::open_at(&temp_dir.path().join("c.db")).unwrap();
+        let entry = |source: &str, created_at| CacheEntry {
+            source: source.to_string(),
+            lang: "zh-CN".to_string(),
+            provider: "google".to_string(),
+            translated: source.to_string(),
+            created_at: Some(created_at),
+        };
+        cache
+            .import_entries(&[entry("old", 1_000), entry("new", 2_000)])
+            .unwrap();
+
+        let recent = cache.entries_since(Some(1_500)).unwrap();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].source, "new");
+        assert_eq!(cache.entries_since(Some(0)).unwrap().len(), 2);
+
+        // Rows migrated from the old schema have no creation time but still export
+        cache
+            .conn
+            .execute(
+                "UPDATE translations SET created_at = NULL WHERE source_text = 'old'",
+                [],
+            )
+            .unwrap();
+        let all = cache.export_entries().unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].created_at, None);
+        assert_eq!(cache.entries_since(Some(0)).unwrap().len(), 1);
+
+        let since = crate::utils::parse_timestamp("2024-02-29").unwrap();
+        assert_eq!(since, 1_709_164_800);
+        assert_eq!(crate::utils::format_timestamp(since), "2024-02-29 00:00:00");
     }
 }
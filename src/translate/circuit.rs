@@ -0,0 +1,305 @@
+//! Run-level circuit breaker that stops translating after repeated failures
+
+use anyhow::Result;
+use colored::Colorize;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Consecutive failed requests tolerated before giving up on the rest of the run
+pub const DEFAULT_MAX_FAILURES: usize = 10;
+/// Seconds between probes while waiting out an outage
+pub const DEFAULT_RECOVERY_POLL_SECS: u64 = 60;
+/// Probe delays double up to this multiple of the poll interval
+const MAX_BACKOFF_FACTOR: u32 = 8;
+/// How often a wait for recovery looks at the cancel flag
+const CANCEL_POLL: Duration = Duration::from_millis(100);
+
+/// Error given to texts left untranslated because the run was cancelled;
+/// detect it with `err.is::<Cancelled>()`
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Translation cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Set by the first Ctrl-C, so a run can stop after the requests in flight and keep
+/// what it translated; a second Ctrl-C exits immediately
+pub fn ctrl_c_flag() -> Arc<AtomicBool> {
+    static FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+    FLAG.get_or_init(|| {
+        let flag = Arc::new(AtomicBool::new(false));
+        let handler = flag.clone();
+        thread::spawn(move || {
+            let Ok(runtime) = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+            else {
+                return;
+            };
+            runtime.block_on(async {
+                if tokio::signal::ctrl_c().await.is_err() {
+                    return;
+                }
+                handler.store(true, Ordering::SeqCst);
+                eprintln!(
+                    "{}",
+                    "[WARN] Cancelling after the requests in flight; press Ctrl-C again to quit"
+                        .yellow()
+                );
+                let _ = tokio::signal::ctrl_c().await;
+                std::process::exit(130);
+            });
+        });
+        flag
+    })
+    .clone()
+}
+
+/// Wait for the provider to come back instead of stopping when the breaker would trip
+#[derive(Debug, Clone, Copy)]
+pub struct Recovery {
+    pub max_wait: Duration,
+    pub poll_interval: Duration,
+}
+
+/// An outage being waited out: when it began and how many probes have failed since
+#[derive(Debug, Default)]
+struct Outage {
+    since: Option<Instant>,
+    probes: u32,
+}
+
+/// Trips after a run of consecutive failures so auth, quota or network problems fail fast
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    /// Zero disables the breaker
+    threshold: usize,
+    consecutive: AtomicUsize,
+    tripped: AtomicBool,
+    last_error: Mutex<Option<String>>,
+    recovery: Option<Recovery>,
+    outage: Mutex<Outage>,
+    /// Stops new requests and interrupts a wait for recovery once set
+    cancel: Option<Arc<AtomicBool>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            consecutive: AtomicUsize::new(0),
+            tripped: AtomicBool::new(false),
+            last_error: Mutex::new(None),
+            recovery: None,
+            outage: Mutex::new(Outage::default()),
+            cancel: None,
+        }
+    }
+
+    pub fn with_recovery(mut self, recovery: Option<Recovery>) -> Self {
+        self.recovery = recovery;
+        self
+    }
+
+    pub fn with_cancel(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(flag);
+        self
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel
+            .as_ref()
+            .is_some_and(|flag| flag.load(Ordering::SeqCst))
+    }
+
+    /// Refuse to start another request once the breaker has tripped. During an outage
+    /// this sleeps until the next probe is due, tripping once the maximum wait is spent.
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        if self.tripped.load(Ordering::SeqCst) {
+            anyhow::bail!("Skipped after {} consecutive failures", self.threshold);
+        }
+
+        let Some(recovery) = self.recovery else {
+            return Ok(());
+        };
+        let delay = {
+            let mut outage = self.outage.lock().unwrap();
+            let Some(since) = outage.since else {
+                return Ok(());
+            };
+            let waited = since.elapsed();
+            if waited >= recovery.max_wait {
+                self.tripped.store(true, Ordering::SeqCst);
+                anyhow::bail!("Skipped after waiting {}s for recovery", waited.as_secs());
+            }
+            let factor = 2u32.pow(outage.probes.min(MAX_BACKOFF_FACTOR.ilog2()));
+            outage.probes += 1;
+            (recovery.poll_interval * factor).min(recovery.max_wait - waited)
+        };
+        self.sleep_unless_cancelled(delay)
+    }
+
+    fn sleep_unless_cancelled(&self, delay: Duration) -> Result<()> {
+        let until = Instant::now() + delay;
+        loop {
+            if self.is_cancelled() {
+                return Err(Cancelled.into());
+            }
+            let left = until.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return Ok(());
+            }
+            thread::sleep(left.min(CANCEL_POLL));
+        }
+    }
+
+    pub fn record<T>(&self, result: &Result<T>) {
+        match result {
+            Ok(_) => {
+                self.consecutive.store(0, Ordering::SeqCst);
+                *self.outage.lock().unwrap() = Outage::default();
+            }
+            Err(e) => {
+                *self.last_error.lock().unwrap() = Some(e.to_string());
+                let failures = self.consecutive.fetch_add(1, Ordering::SeqCst) + 1;
+                if self.threshold > 0 && failures >= self.threshold {
+                    match self.recovery {
+                        Some(recovery) => {
+                            let mut outage = self.outage.lock().unwrap();
+                            if outage.since.is_none() {
+                                outage.since = Some(Instant::now());
+                                eprintln!(
+                                    "{}",
+                                    format!(
+                                        "[WARN] {} consecutive failures; waiting up to {} min for the provider to recover",
+                                        failures,
+                                        recovery.max_wait.as_secs() / 60
+                                    )
+                                    .yellow()
+                                );
+                            }
+                        }
+                        None => self.tripped.store(true, Ordering::SeqCst),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Send a request through the breaker; failures during an outage are retried
+    /// after each wait, so nothing in flight is lost to a temporary outage
+    pub fn run<T>(&self, mut request: impl FnMut() -> Result<T>) -> Result<T> {
+        loop {
+            self.check()?;
+            let result = request();
+            self.record(&result);
+            if result.is_ok() || !self.in_outage() {
+                return result;
+            }
+        }
+    }
+
+    fn in_outage(&self) -> bool {
+        self.outage.lock().unwrap().since.is_some()
+    }
+
+    /// Explain why translation stopped, if it did
+    pub fn trip_message(&self) -> Option<String> {
+        if self.is_cancelled() {
+            return Some("Translation cancelled; texts finished before then were kept".to_string());
+        }
+        if !self.tripped.load(Ordering::SeqCst) {
+            return None;
+        }
+        let last_error = self.last_error.lock().unwrap().clone().unwrap_or_default();
+        Some(format!(
+            "Stopped translating after {} consecutive failures; check your API key, quota and network. Last error: {}",
+            self.threshold, last_error
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_trips_on_consecutive_failures() {
+        let breaker = CircuitBreaker::new(2);
+        let failure: Result<()> = Err(anyhow::anyhow!("401 Unauthorized"));
+
+        breaker.record(&failure);
+        breaker.record(&Ok(()));
+        breaker.record(&failure);
+        assert!(breaker.check().is_ok());
+
+        breaker.record(&failure);
+        assert!(breaker.check().is_err());
+        assert!(breaker.trip_message().unwrap().contains("401 Unauthorized"));
+
+        let disabled = CircuitBreaker::new(0);
+        for _ in 0..100 {
+            disabled.record(&failure);
+        }
+        assert!(disabled.check().is_ok());
+    }
+
+    #[test]
+    fn test_breaker_waits_for_recovery() {
+        let breaker = CircuitBreaker::new(1).with_recovery(Some(Recovery {
+            max_wait: Duration::from_millis(200),
+            poll_interval: Duration::from_millis(10),
+        }));
+
+        // The provider comes back on the fourth attempt; the request is retried until then
+        let mut attempts = 0;
+        let result = breaker.run(|| {
+            attempts += 1;
+            if attempts < 4 {
+                anyhow::bail!("503 Service Unavailable")
+            }
+            Ok("translated")
+        });
+        assert_eq!(result.unwrap(), "translated");
+        assert_eq!(attempts, 4);
+        assert!(breaker.trip_message().is_none());
+
+        // An outage that outlasts the maximum wait trips the breaker
+        let result: Result<()> = breaker.run(|| anyhow::bail!("503 Service Unavailable"));
+        assert!(result.is_err());
+        assert!(breaker.trip_message().is_some());
+    }
+
+    #[test]
+    fn test_cancel_interrupts_wait_for_recovery() {
+        let flag = Arc::new(AtomicBool::new(false));
+        let breaker = CircuitBreaker::new(1)
+            .with_recovery(Some(Recovery {
+                max_wait: Duration::from_secs(600),
+                poll_interval: Duration::from_secs(60),
+            }))
+            .with_cancel(flag.clone());
+
+        let setter = flag.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            setter.store(true, Ordering::SeqCst);
+        });
+
+        let started = Instant::now();
+        let result: Result<()> = breaker.run(|| anyhow::bail!("503 Service Unavailable"));
+        assert!(result.is_err_and(|e| e.is::<Cancelled>()));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(breaker.trip_message().unwrap().contains("cancelled"));
+    }
+}
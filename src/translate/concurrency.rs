@@ -0,0 +1,146 @@
+//! Adaptive request concurrency that backs off when the provider rate-limits
+
+use std::sync::{Condvar, Mutex};
+
+/// Requests allowed in flight before the endpoint has shown what it tolerates
+pub const DEFAULT_START_CONCURRENCY: usize = 4;
+
+#[derive(Debug)]
+struct State {
+    limit: usize,
+    in_flight: usize,
+    /// Successes since the limit last changed
+    successes: usize,
+    /// Bumped on every decrease, so one burst of 429s only halves the limit once
+    epoch: u64,
+    backoffs: usize,
+}
+
+/// AIMD limiter: the limit grows by one after a full window of successful requests
+/// and halves on a 429, staying within `min..=max`
+#[derive(Debug)]
+pub struct AdaptiveConcurrency {
+    min: usize,
+    max: usize,
+    state: Mutex<State>,
+    freed: Condvar,
+}
+
+/// A slot for one request, given back when dropped
+pub struct Permit<'a> {
+    limiter: &'a AdaptiveConcurrency,
+    epoch: u64,
+}
+
+impl AdaptiveConcurrency {
+    pub fn new(min: usize, max: usize) -> Self {
+        let max = max.max(1);
+        let min = min.clamp(1, max);
+        Self {
+            min,
+            max,
+            state: Mutex::new(State {
+                limit: DEFAULT_START_CONCURRENCY.clamp(min, max),
+                in_flight: 0,
+                successes: 0,
+                epoch: 0,
+                backoffs: 0,
+            }),
+            freed: Condvar::new(),
+        }
+    }
+
+    /// Wait until fewer than the current limit of requests are in flight
+    pub fn acquire(&self) -> Permit<'_> {
+        let mut state = self.state.lock().unwrap();
+        while state.in_flight >= state.limit {
+            state = self.freed.wait(state).unwrap();
+        }
+        state.in_flight += 1;
+        Permit {
+            limiter: self,
+            epoch: state.epoch,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    /// How often the limit was halved
+    pub fn backoffs(&self) -> usize {
+        self.state.lock().unwrap().backoffs
+    }
+
+    pub fn bounds(&self) -> (usize, usize) {
+        (self.min, self.max)
+    }
+}
+
+impl Permit<'_> {
+    pub fn succeeded(self) {
+        let mut state = self.limiter.state.lock().unwrap();
+        state.successes += 1;
+        if state.successes >= state.limit && state.limit < self.limiter.max {
+            state.limit += 1;
+            state.successes = 0;
+            self.limiter.freed.notify_one();
+        }
+    }
+
+    /// Halve the limit, unless it already dropped since this request was sent
+    pub fn rate_limited(self) {
+        let mut state = self.limiter.state.lock().unwrap();
+        if state.epoch == self.epoch {
+            state.limit = (state.limit / 2).max(self.limiter.min);
+            state.successes = 0;
+            state.epoch += 1;
+            state.backoffs += 1;
+        }
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        self.limiter.state.lock().unwrap().in_flight -= 1;
+        self.limiter.freed.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_additive_increase_multiplicative_decrease() {
+        let limiter = AdaptiveConcurrency::new(2, 10);
+        assert_eq!(limiter.limit(), DEFAULT_START_CONCURRENCY);
+
+        // A full window of successes at each limit raises it by one, up to the maximum
+        for _ in 0..4 + 5 + 6 + 7 + 8 + 9 + 20 {
+            limiter.acquire().succeeded();
+        }
+        assert_eq!(limiter.limit(), 10);
+
+        // Requests sent before the first 429 came back do not halve the limit again
+        let permits: Vec<_> = (0..3).map(|_| limiter.acquire()).collect();
+        for permit in permits {
+            permit.rate_limited();
+        }
+        assert_eq!(limiter.limit(), 5);
+        assert_eq!(limiter.backoffs(), 1);
+
+        for _ in 0..3 {
+            limiter.acquire().rate_limited();
+        }
+        assert_eq!(limiter.limit(), 2);
+        assert_eq!(limiter.backoffs(), 4);
+    }
+
+    #[test]
+    fn test_bounds_are_clamped() {
+        assert_eq!(AdaptiveConcurrency::new(0, 0).bounds(), (1, 1));
+        assert_eq!(AdaptiveConcurrency::new(8, 3).limit(), 3);
+        assert_eq!(AdaptiveConcurrency::new(6, 32).limit(), 6);
+    }
+}
@@ -1,11 +1,13 @@
 //! Text extraction from Ren'Py scripts
 
 use anyhow::{Context, Result};
+use encoding_rs::Encoding;
 use regex::Regex;
+use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
 
-use crate::utils::{is_code_like, is_renpy_keyword, unquote};
+use crate::utils::{decode_lossy, is_code_like, is_renpy_keyword, opens_style_block, unquote};
 
 #[derive(Debug, Clone)]
 pub struct TranslatableEntry {
@@ -13,6 +15,14 @@ pub struct TranslatableEntry {
     pub text: String,
     pub line_number: usize,
     pub entry_type: EntryType,
+    pub menu: Option<MenuContext>,
+}
+
+/// The `menu:` block a choice belongs to, with its optional prompt line
+#[derive(Debug, Clone, PartialEq)]
+pub struct MenuContext {
+    pub line_number: usize,
+    pub prompt: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -20,12 +30,62 @@ pub enum EntryType {
     Dialogue,
     Narration,
     MenuChoice,
+    CharacterName,
+    ScreenText,
+    /// String value of a `define`/`default`, with the variable name
+    Define(String),
+    /// Text captured from a comment by `--translate-comments`
+    Comment,
+}
+
+/// Why a quoted string was left out of extraction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SkipReason {
+    /// The line starts with a Ren'Py statement keyword (`show`, `play`, ...)
+    Keyword,
+    /// The string looks like code, a tag or a format placeholder
+    CodeLike,
+    Empty,
+    /// A property value inside a `style` or `transform` block
+    Style,
+}
+
+impl SkipReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SkipReason::Keyword => "keyword",
+            SkipReason::CodeLike => "code-like",
+            SkipReason::Empty => "empty",
+            SkipReason::Style => "style",
+        }
+    }
+}
+
+/// A line holding a string that the heuristics decided not to translate
+#[derive(Debug, Clone)]
+pub struct SkippedLine {
+    pub line_number: usize,
+    pub text: String,
+    pub reason: SkipReason,
 }
 
 pub struct TextExtractor {
     dialogue_re: Regex,
     narration_re: Regex,
     menu_re: Regex,
+    menu_prompt_re: Regex,
+    character_re: Regex,
+    translate_call_re: Regex,
+    define_re: Regex,
+    screen_prop_re: Regex,
+    screen_text_re: Regex,
+    display_call_re: Regex,
+    character_names: bool,
+    defines: bool,
+    /// Comments matching this are translated; its first group (or whole match) is the text
+    comment_pattern: Option<Regex>,
+    /// Encoding scripts are read with
+    encoding: &'static Encoding,
 }
 
 impl Default for TextExtractor {
@@ -47,23 +107,312 @@ impl TextExtractor {
             .unwrap(),
             menu_re: Regex::new(r#"^\s*("[^"\\]*(?:\\.[^"\\]*)*"|'[^'\\]*(?:\\.[^'\\]*)*')\s*:"#)
                 .unwrap(),
+            menu_prompt_re: Regex::new(
+                r#"^\s*menu\s+("[^"\\]*(?:\\.[^"\\]*)*"|'[^'\\]*(?:\\.[^'\\]*)*')\s*:\s*$"#,
+            )
+            .unwrap(),
+            character_re: Regex::new(
+                r#"^\s*define\s+([\w.]+)\s*=\s*Character\(\s*(?:_\s*\(\s*)?("[^"\\]*(?:\\.[^"\\]*)*"|'[^'\\]*(?:\\.[^'\\]*)*')"#,
+            )
+            .unwrap(),
+            translate_call_re: Regex::new(
+                r#"(?:^|[^\w.])(?:__|_p|_)\s*\(\s*("[^"\\]*(?:\\.[^"\\]*)*"|'[^'\\]*(?:\\.[^'\\]*)*')\s*\)"#,
+            )
+            .unwrap(),
+            define_re: Regex::new(
+                r#"^\s*(?:define|default)\s+(?:-?\d+\s+)?([\w.]+)\s*=\s*("[^"\\]*(?:\\.[^"\\]*)*"|'[^'\\]*(?:\\.[^'\\]*)*')\s*$"#,
+            )
+            .unwrap(),
+            screen_prop_re: Regex::new(
+                r#"(?:^|\s)(?:alt|tooltip)\s+("[^"\\]*(?:\\.[^"\\]*)*"|'[^'\\]*(?:\\.[^'\\]*)*')"#,
+            )
+            .unwrap(),
+            screen_text_re: Regex::new(
+                r#"^\s*(?:text|textbutton)\s+("[^"\\]*(?:\\.[^"\\]*)*"|'[^'\\]*(?:\\.[^'\\]*)*')"#,
+            )
+            .unwrap(),
+            display_call_re: Regex::new(
+                r#"\brenpy\.(?:notify|input|confirm)\(\s*("[^"\\]*(?:\\.[^"\\]*)*"|'[^'\\]*(?:\\.[^'\\]*)*')"#,
+            )
+            .unwrap(),
+            character_names: false,
+            defines: false,
+            comment_pattern: None,
+            encoding: encoding_rs::UTF_8,
         }
     }
 
+    /// Also extract display names from `define x = Character("Name")`
+    pub fn with_character_names(mut self, enabled: bool) -> Self {
+        self.character_names = enabled;
+        self
+    }
+
+    /// Also extract plain string values of `define`/`default` statements
+    pub fn with_defines(mut self, enabled: bool) -> Self {
+        self.defines = enabled;
+        self
+    }
+
+    /// Also extract text from full-line comments matching `pattern` (e.g. `^#\s*TL:\s*(.+)$`).
+    /// The first capture group, or the whole match without one, is what gets translated.
+    pub fn with_comment_pattern(mut self, pattern: Option<Regex>) -> Self {
+        self.comment_pattern = pattern;
+        self
+    }
+
+    /// Read scripts in this encoding instead of UTF-8
+    pub fn with_encoding(mut self, encoding: &'static Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    pub fn encoding(&self) -> &'static Encoding {
+        self.encoding
+    }
+
+    /// A script's text, and how many undecodable sequences were replaced with U+FFFD
+    pub fn read_script<P: AsRef<Path>>(&self, path: P) -> Result<(String, usize)> {
+        let bytes = fs::read(path.as_ref()).context("Failed to read script file")?;
+        Ok(decode_lossy(&bytes, self.encoding))
+    }
+
+    /// Display names from `define e = Character("Eileen", ...)`, keyed by variable
+    pub fn display_names(&self, content: &str) -> HashMap<String, String> {
+        content
+            .lines()
+            .filter_map(|line| self.character_re.captures(line))
+            .map(|caps| (caps[1].to_string(), unquote(&caps[2])))
+            .collect()
+    }
+
     pub fn extract_from_file<P: AsRef<Path>>(&self, path: P) -> Result<Vec<TranslatableEntry>> {
-        let content = fs::read_to_string(path.as_ref()).context("Failed to read script file")?;
+        let (content, _) = self.read_script(path)?;
         self.extract_from_string(&content)
     }
 
     pub fn extract_from_string(&self, content: &str) -> Result<Vec<TranslatableEntry>> {
+        self.extract_with_skipped(content)
+            .map(|(entries, _)| entries)
+    }
+
+    pub fn extract_with_skipped(
+        &self,
+        content: &str,
+    ) -> Result<(Vec<TranslatableEntry>, Vec<SkippedLine>)> {
         let mut entries = Vec::new();
+        let mut skipped = Vec::new();
         let mut id = 0;
+        // Open menu blocks as (indent, context), innermost last
+        let mut menus: Vec<(usize, MenuContext)> = Vec::new();
+        // Indent of the enclosing `screen` statement, if any
+        let mut screen: Option<usize> = None;
+        // Indent of the enclosing `style`/`transform` statement, if any
+        let mut style: Option<usize> = None;
 
         for (line_num, line) in content.lines().enumerate() {
             let line_number = line_num + 1;
             let trimmed = line.trim();
 
-            if trimmed.is_empty() || trimmed.starts_with('#') || is_renpy_keyword(trimmed) {
+            if trimmed.starts_with('#') {
+                if let Some(text) = self.comment_text(trimmed)
+                    && Self::accept(&mut skipped, line_number, &text)
+                {
+                    entries.push(TranslatableEntry {
+                        id,
+                        text,
+                        line_number,
+                        entry_type: EntryType::Comment,
+                        menu: None,
+                    });
+                    id += 1;
+                }
+                continue;
+            }
+            if trimmed.is_empty() {
+                continue;
+            }
+
+            let indent = line.len() - line.trim_start().len();
+            while menus.last().is_some_and(|(i, _)| indent <= *i) {
+                menus.pop();
+            }
+            if screen.is_some_and(|i| indent <= i) {
+                screen = None;
+            }
+            if trimmed.starts_with("screen ") && trimmed.ends_with(':') {
+                screen = Some(indent);
+                continue;
+            }
+            if style.is_some_and(|i| indent <= i) {
+                style = None;
+            }
+            if screen.is_none() && opens_style_block(trimmed) {
+                style = Some(indent);
+                continue;
+            }
+            if style.is_some() {
+                if trimmed.contains('"') || trimmed.contains('\'') {
+                    skipped.push(SkippedLine {
+                        line_number,
+                        text: trimmed.to_string(),
+                        reason: SkipReason::Style,
+                    });
+                }
+                continue;
+            }
+
+            if Self::is_menu_start(trimmed) {
+                menus.push((
+                    indent,
+                    MenuContext {
+                        line_number,
+                        prompt: None,
+                    },
+                ));
+                continue;
+            }
+
+            // `menu "What now?":` carries its prompt on the menu line itself
+            if let Some(caps) = self.menu_prompt_re.captures(line) {
+                let text = unquote(caps.get(1).map(|m| m.as_str()).unwrap_or(""));
+                let prompt = Self::accept(&mut skipped, line_number, &text).then_some(text);
+                if let Some(text) = &prompt {
+                    entries.push(TranslatableEntry {
+                        id,
+                        text: text.clone(),
+                        line_number,
+                        entry_type: EntryType::Narration,
+                        menu: None,
+                    });
+                    id += 1;
+                }
+                menus.push((
+                    indent,
+                    MenuContext {
+                        line_number,
+                        prompt,
+                    },
+                ));
+                continue;
+            }
+
+            if self.character_names
+                && let Some(caps) = self.character_re.captures(line)
+            {
+                let text = unquote(caps.get(2).map(|m| m.as_str()).unwrap_or(""));
+
+                if Self::accept(&mut skipped, line_number, &text) {
+                    entries.push(TranslatableEntry {
+                        id,
+                        text,
+                        line_number,
+                        entry_type: EntryType::CharacterName,
+                        menu: None,
+                    });
+                    id += 1;
+                }
+                continue;
+            }
+
+            // _("..."), __("...") and _p("...") mark UI strings anywhere on a line;
+            // Character names are left to the opt-in branch above
+            if !self.character_re.is_match(line) && self.translate_call_re.is_match(line) {
+                for caps in self.translate_call_re.captures_iter(line) {
+                    let text = unquote(caps.get(1).map(|m| m.as_str()).unwrap_or(""));
+
+                    if Self::accept(&mut skipped, line_number, &text) {
+                        entries.push(TranslatableEntry {
+                            id,
+                            text,
+                            line_number,
+                            entry_type: EntryType::ScreenText,
+                            menu: None,
+                        });
+                        id += 1;
+                    }
+                }
+                continue;
+            }
+
+            // Python lines are code, except the message of a few functions that show it
+            // to the player; any other string on them is left alone
+            if trimmed.starts_with('$') {
+                for caps in self.display_call_re.captures_iter(line) {
+                    let text = unquote(caps.get(1).map(|m| m.as_str()).unwrap_or(""));
+
+                    if Self::accept(&mut skipped, line_number, &text) {
+                        entries.push(TranslatableEntry {
+                            id,
+                            text,
+                            line_number,
+                            entry_type: EntryType::ScreenText,
+                            menu: None,
+                        });
+                        id += 1;
+                    }
+                }
+                continue;
+            }
+
+            // Literal `text`/`textbutton` labels, plus alt/tooltip text on hotspots,
+            // imagebuttons and other screen displayables
+            if screen.is_some() {
+                let label = self.screen_text_re.captures(line).and_then(|c| c.get(1));
+                let props = self
+                    .screen_prop_re
+                    .captures_iter(line)
+                    .filter_map(|c| c.get(1));
+                for literal in label.into_iter().chain(props) {
+                    let text = unquote(literal.as_str());
+
+                    if Self::accept(&mut skipped, line_number, &text) {
+                        entries.push(TranslatableEntry {
+                            id,
+                            text,
+                            line_number,
+                            entry_type: EntryType::ScreenText,
+                            menu: None,
+                        });
+                        id += 1;
+                    }
+                }
+
+                // Say statements and menus cannot appear inside screens
+                continue;
+            }
+
+            if self.defines
+                && let Some(caps) = self.define_re.captures(line)
+            {
+                let name = caps.get(1).map(|m| m.as_str()).unwrap_or("");
+                let text = unquote(caps.get(2).map(|m| m.as_str()).unwrap_or(""));
+
+                if Self::accept(&mut skipped, line_number, &text)
+                    && !Self::is_engine_setting(name)
+                    && !Self::is_asset_like(&text)
+                {
+                    entries.push(TranslatableEntry {
+                        id,
+                        text,
+                        line_number,
+                        entry_type: EntryType::Define(name.to_string()),
+                        menu: None,
+                    });
+                    id += 1;
+                }
+                continue;
+            }
+
+            if is_renpy_keyword(trimmed) {
+                // Only keyword lines carrying a string could have hidden dialogue
+                if trimmed.contains('"') || trimmed.contains('\'') {
+                    skipped.push(SkippedLine {
+                        line_number,
+                        text: trimmed.to_string(),
+                        reason: SkipReason::Keyword,
+                    });
+                }
                 continue;
             }
 
@@ -71,12 +420,14 @@ impl TextExtractor {
                 let text = caps.get(2).map(|m| m.as_str()).unwrap_or("");
                 let text = unquote(text);
 
-                if !text.is_empty() && !is_code_like(&text) {
+                if Self::accept(&mut skipped, line_number, &text) {
+                    Self::record_menu_prompt(&mut menus, &entries, &text);
                     entries.push(TranslatableEntry {
                         id,
                         text,
                         line_number,
                         entry_type: EntryType::Dialogue,
+                        menu: None,
                     });
                     id += 1;
                 }
@@ -87,12 +438,19 @@ impl TextExtractor {
                 let text = caps.get(1).map(|m| m.as_str()).unwrap_or("");
                 let text = unquote(text);
 
-                if !text.is_empty() {
+                if text.is_empty() {
+                    skipped.push(SkippedLine {
+                        line_number,
+                        text,
+                        reason: SkipReason::Empty,
+                    });
+                } else {
                     entries.push(TranslatableEntry {
                         id,
                         text,
                         line_number,
                         entry_type: EntryType::MenuChoice,
+                        menu: menus.last().map(|(_, m)| m.clone()),
                     });
                     id += 1;
                 }
@@ -103,19 +461,90 @@ impl TextExtractor {
                 let text = caps.get(1).map(|m| m.as_str()).unwrap_or("");
                 let text = unquote(text);
 
-                if !text.is_empty() && !is_code_like(&text) {
+                if Self::accept(&mut skipped, line_number, &text) {
+                    Self::record_menu_prompt(&mut menus, &entries, &text);
                     entries.push(TranslatableEntry {
                         id,
                         text,
                         line_number,
                         entry_type: EntryType::Narration,
+                        menu: None,
                     });
                     id += 1;
                 }
             }
         }
 
-        Ok(entries)
+        Ok((entries, skipped))
+    }
+
+    /// Whether a quoted string is worth translating; records the reason when it is not
+    fn accept(skipped: &mut Vec<SkippedLine>, line_number: usize, text: &str) -> bool {
+        let reason = if text.is_empty() {
+            SkipReason::Empty
+        } else if is_code_like(text) {
+            SkipReason::CodeLike
+        } else {
+            return true;
+        };
+        skipped.push(SkippedLine {
+            line_number,
+            text: text.to_string(),
+            reason,
+        });
+        false
+    }
+
+    fn comment_text(&self, comment: &str) -> Option<String> {
+        let caps = self.comment_pattern.as_ref()?.captures(comment)?;
+        let text = caps.get(1).or(caps.get(0))?.as_str().trim();
+        Some(text.to_string())
+    }
+
+    fn is_menu_start(trimmed: &str) -> bool {
+        trimmed.ends_with(':')
+            && (trimmed == "menu:"
+                || trimmed
+                    .strip_prefix("menu ")
+                    .is_some_and(|rest| !rest.contains('"') && !rest.contains('\'')))
+    }
+
+    /// Engine namespaces whose string values are identifiers or paths, not prose
+    fn is_engine_setting(name: &str) -> bool {
+        ["config.", "build.", "gui.", "style.", "preferences."]
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+    }
+
+    /// File paths and colors are common `define` values that must stay untouched
+    fn is_asset_like(text: &str) -> bool {
+        const EXTENSIONS: &[&str] = &[
+            ".png", ".jpg", ".jpeg", ".webp", ".ogg", ".mp3", ".wav", ".opus", ".ttf", ".otf",
+            ".webm", ".mp4", ".rpy",
+        ];
+        let lower = text.to_lowercase();
+        text.contains('/')
+            || text.contains('\\')
+            || (text.starts_with('#') && text[1..].chars().all(|c| c.is_ascii_hexdigit()))
+            || EXTENSIONS.iter().any(|ext| lower.ends_with(ext))
+    }
+
+    /// A say statement directly inside a menu, before any choice, is its prompt
+    fn record_menu_prompt(
+        menus: &mut [(usize, MenuContext)],
+        entries: &[TranslatableEntry],
+        text: &str,
+    ) {
+        if let Some((_, menu)) = menus.last_mut() {
+            let has_choices = entries.iter().any(|e| {
+                e.menu
+                    .as_ref()
+                    .is_some_and(|m| m.line_number == menu.line_number)
+            });
+            if menu.prompt.is_none() && !has_choices {
+                menu.prompt = Some(text.to_string());
+            }
+        }
     }
 }
 
@@ -139,4 +568,297 @@ label start:
         let entries = extractor.extract_from_string(content).unwrap();
         assert_eq!(entries.len(), 4);
     }
+
+    #[test]
+    fn test_menu_context() {
+        let extractor = TextExtractor::new();
+        let content = r#"
+label start:
+    menu:
+        "Where should I go?"
+        "Left":
+            "You went left."
+        "Right":
+            pass
+    "Done."
+"#;
+        let entries = extractor.extract_from_string(content).unwrap();
+        let choices: Vec<_> = entries
+            .iter()
+            .filter(|e| e.entry_type == EntryType::MenuChoice)
+            .collect();
+        assert_eq!(choices.len(), 2);
+        for choice in choices {
+            let menu = choice.menu.as_ref().unwrap();
+            assert_eq!(menu.line_number, 3);
+            assert_eq!(menu.prompt.as_deref(), Some("Where should I go?"));
+        }
+        let done = entries.iter().find(|e| e.text == "Done.").unwrap();
+        assert_eq!(done.menu, None);
+    }
+
+    #[test]
+    fn test_menu_prompt_on_menu_line() {
+        let extractor = TextExtractor::new();
+        let content = r#"
+label start:
+    window show
+    menu "What now?":
+        "Wait":
+            pause 1.0
+        "Leave":
+            window hide dissolve
+    pauline "I'm still here."
+"#;
+        let entries = extractor.extract_from_string(content).unwrap();
+        let texts: Vec<_> = entries.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(texts, ["What now?", "Wait", "Leave", "I'm still here."]);
+
+        assert_eq!(entries[0].entry_type, EntryType::Narration);
+        let menu = entries[1].menu.as_ref().unwrap();
+        assert_eq!(menu.line_number, 4);
+        assert_eq!(menu.prompt.as_deref(), Some("What now?"));
+        assert_eq!(entries[3].entry_type, EntryType::Dialogue);
+    }
+
+    #[test]
+    fn test_character_names() {
+        let extractor = TextExtractor::new().with_character_names(true);
+        let content = r##"
+define e = Character("Eileen", color="#c8ffc8")
+define s = Character(_('Sylvie'))
+define m = Character(_ ("Mira"))
+define n = Character(None, kind=nvl)
+define l = Character(kind=e, name="Lucy")
+"##;
+        let entries = extractor.extract_from_string(content).unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(names, ["Eileen", "Sylvie", "Mira"]);
+        assert!(
+            entries
+                .iter()
+                .all(|e| e.entry_type == EntryType::CharacterName)
+        );
+
+        let entries = TextExtractor::new().extract_from_string(content).unwrap();
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn test_translate_calls() {
+        let extractor = TextExtractor::new();
+        let content = r#"
+screen main_menu():
+    textbutton _("Start") action Start()
+    text __("Load") + _p("Long help text")
+    $ renpy.notify(_('Saved.'))
+    $ my_func("Not marked")
+    textbutton _ ("Start") action Start()
+    textbutton __( "Quit" ) action Quit()
+"#;
+        let entries = extractor.extract_from_string(content).unwrap();
+        let texts: Vec<&str> = entries.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            ["Start", "Load", "Long help text", "Saved.", "Start", "Quit"]
+        );
+        assert!(
+            entries
+                .iter()
+                .all(|e| e.entry_type == EntryType::ScreenText)
+        );
+    }
+
+    #[test]
+    fn test_display_calls() {
+        let extractor = TextExtractor::new();
+        let content = r#"
+label start:
+    $ renpy.notify("Saved")
+    $ name = renpy.input("What is your name?", default="Sylvie", length=20)
+    $ renpy.confirm('Quit the game?')
+    $ persistent.ending = "good"
+    $ renpy.notify(message)
+"#;
+        let entries = extractor.extract_from_string(content).unwrap();
+        let texts: Vec<_> = entries.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(texts, ["Saved", "What is your name?", "Quit the game?"]);
+        assert!(
+            entries
+                .iter()
+                .all(|e| e.entry_type == EntryType::ScreenText)
+        );
+    }
+
+    #[test]
+    fn test_defines() {
+        let content = r##"
+define quest_title = "The Lost Key"
+default status_text = 'Waiting for you'
+define -2 epilogue = "Years later..."
+define config.name = "My Game"
+define gui.text_font = "DejaVuSans.ttf"
+define accent = "#ff8800"
+define bg_path = "images/bg room.png"
+define e = Character("Eileen")
+"##;
+        let entries = TextExtractor::new()
+            .with_defines(true)
+            .extract_from_string(content)
+            .unwrap();
+        let found: Vec<(&str, &EntryType)> = entries
+            .iter()
+            .map(|e| (e.text.as_str(), &e.entry_type))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (
+                    "The Lost Key",
+                    &EntryType::Define("quest_title".to_string())
+                ),
+                (
+                    "Waiting for you",
+                    &EntryType::Define("status_text".to_string())
+                ),
+                ("Years later...", &EntryType::Define("epilogue".to_string())),
+            ]
+        );
+
+        let entries = TextExtractor::new().extract_from_string(content).unwrap();
+        assert!(entries.is_empty());
+    }
+
+    #[test]
+    fn test_skipped_reasons() {
+        let content = r#"
+label start:
+    show text "Chapter One" at truecenter
+    e "[player_name]"
+    ""
+    e "Hello."
+"#;
+        let (entries, skipped) = TextExtractor::new().extract_with_skipped(content).unwrap();
+        assert_eq!(entries.len(), 1);
+        let found: Vec<(usize, SkipReason)> =
+            skipped.iter().map(|s| (s.line_number, s.reason)).collect();
+        assert_eq!(
+            found,
+            [
+                (3, SkipReason::Keyword),
+                (4, SkipReason::CodeLike),
+                (5, SkipReason::Empty),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_style_and_transform_blocks() {
+        let content = r##"
+style say_label is default:
+    text_color "#fff"
+    font "DejaVuSans.ttf"
+    hover_sound "click Sound"
+
+transform fade_in(delay=0.5):
+    alpha 0.0
+    "ready"
+
+label start:
+    e "Hello."
+"##;
+        let (entries, skipped) = TextExtractor::new().extract_with_skipped(content).unwrap();
+        let texts: Vec<&str> = entries.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(texts, ["Hello."]);
+        let found: Vec<(usize, SkipReason)> =
+            skipped.iter().map(|s| (s.line_number, s.reason)).collect();
+        assert_eq!(
+            found,
+            [
+                (3, SkipReason::Style),
+                (4, SkipReason::Style),
+                (5, SkipReason::Style),
+                (9, SkipReason::Style),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_screen_alt_and_tooltip() {
+        let extractor = TextExtractor::new();
+        let content = r#"
+screen map_screen():
+    imagemap:
+        ground "map.png"
+        hotspot (10, 10, 50, 50) action Jump("town") alt "Go to town"
+        hotspot (70, 10, 50, 50) action Jump("lake") tooltip 'The lake'
+    imagebutton:
+        idle "door.png"
+        alt "Open the door"
+
+label start:
+    "Hello."
+"#;
+        let entries = extractor.extract_from_string(content).unwrap();
+        let found: Vec<(&str, &EntryType)> = entries
+            .iter()
+            .map(|e| (e.text.as_str(), &e.entry_type))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("Go to town", &EntryType::ScreenText),
+                ("The lake", &EntryType::ScreenText),
+                ("Open the door", &EntryType::ScreenText),
+                ("Hello.", &EntryType::Narration),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_screen_text_literals() {
+        let extractor = TextExtractor::new();
+        let content = r#"
+screen title():
+    vbox:
+        text "Welcome back"
+        text "[score]"
+        textbutton "Start" action Start() tooltip "Begin a new game"
+        textbutton 'Quit' action Quit()
+
+label start:
+    text "Not a screen"
+"#;
+        let entries = extractor.extract_from_string(content).unwrap();
+        let texts: Vec<&str> = entries.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(texts, ["Welcome back", "Start", "Begin a new game", "Quit"]);
+        assert!(
+            entries
+                .iter()
+                .all(|e| e.entry_type == EntryType::ScreenText)
+        );
+    }
+
+    #[test]
+    fn test_leading_brackets() {
+        let extractor = TextExtractor::new();
+        let content = r#"
+label start:
+    "[Sighs] I'm tired"
+    e "[player] said hello"
+    "{i}Whispering{/i}"
+    "[some_function()]"
+    e "{w=0.5}[name]"
+"#;
+        let entries = extractor.extract_from_string(content).unwrap();
+        let texts: Vec<&str> = entries.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            [
+                "[Sighs] I'm tired",
+                "[player] said hello",
+                "{i}Whispering{/i}"
+            ]
+        );
+    }
 }
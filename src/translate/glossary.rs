@@ -18,11 +18,20 @@ impl Glossary {
 
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
         let content = fs::read_to_string(path.as_ref()).context("Failed to read glossary file")?;
+        Ok(Self::parse(&content))
+    }
+
+    fn parse(content: &str) -> Self {
         let mut glossary = Self::new();
 
         for (line_num, line) in content.lines().enumerate() {
+            let line = Self::strip_comment(line);
             let line = line.trim();
-            if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            // [Section] headers only organize the file for now
+            if line.starts_with('[') && line.ends_with(']') {
                 continue;
             }
             if let Some((source, target)) = Self::parse_line(line) {
@@ -31,7 +40,24 @@ impl Glossary {
                 tracing::warn!("Invalid glossary entry at line {}: {}", line_num + 1, line);
             }
         }
-        Ok(glossary)
+        glossary
+    }
+
+    /// Drop everything after an unescaped `#`; `\#` stands for a literal `#`
+    fn strip_comment(line: &str) -> String {
+        let mut result = String::with_capacity(line.len());
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' if chars.peek() == Some(&'#') => {
+                    result.push('#');
+                    chars.next();
+                }
+                '#' => break,
+                _ => result.push(c),
+            }
+        }
+        result
     }
 
     fn parse_line(line: &str) -> Option<(String, String)> {
@@ -55,6 +81,13 @@ impl Glossary {
         self.terms.insert(source, target);
     }
 
+    /// Add all terms from `other`, overriding existing translations of the same term
+    pub fn merge(&mut self, other: Glossary) {
+        for (source, target) in other.terms {
+            self.add(source, target);
+        }
+    }
+
     pub fn len(&self) -> usize {
         self.terms.len()
     }
@@ -68,7 +101,7 @@ impl Glossary {
         let mut result = text.to_string();
         // Longer terms first to avoid partial replacements
         let mut sorted_terms: Vec<_> = self.terms.iter().collect();
-        sorted_terms.sort_by(|a, b| b.0.len().cmp(&a.0.len()));
+        sorted_terms.sort_by_key(|t| std::cmp::Reverse(t.0.len()));
         for (source, target) in sorted_terms {
             result = result.replace(source, target);
         }
@@ -108,19 +141,40 @@ mod tests {
         assert_eq!(result, "Hello, 西尔维!");
     }
 
+    #[test]
+    fn test_trailing_comments() {
+        let glossary = Glossary::load_from_str(
+            "# Characters\nSylvie = 西尔维 # main heroine\nChannel \\#5 = 第\\#5频道\n",
+        );
+        assert_eq!(glossary.len(), 2);
+        assert_eq!(glossary.terms.get("Sylvie"), Some(&"西尔维".to_string()));
+        assert_eq!(
+            glossary.terms.get("Channel #5"),
+            Some(&"第#5频道".to_string())
+        );
+    }
+
+    #[test]
+    fn test_section_headers() {
+        let glossary = Glossary::load_from_str(
+            "[Characters]\nSylvie = 西尔维\n\n[Places]\nLibrary\t图书馆\n// legacy comment\n",
+        );
+        assert_eq!(glossary.len(), 2);
+        assert!(!glossary.terms.contains_key("[Characters]"));
+        assert_eq!(glossary.terms.get("Library"), Some(&"图书馆".to_string()));
+    }
+
+    #[test]
+    fn test_merge_overrides() {
+        let mut base = Glossary::load_from_str("Sylvie = 西尔维\nLibrary = 图书馆");
+        base.merge(Glossary::load_from_str("Sylvie = 希尔薇\nPark = 公园"));
+        assert_eq!(base.len(), 3);
+        assert_eq!(base.terms.get("Sylvie"), Some(&"希尔薇".to_string()));
+    }
+
     impl Glossary {
         fn load_from_str(content: &str) -> Self {
-            let mut glossary = Self::new();
-            for line in content.lines() {
-                let line = line.trim();
-                if line.is_empty() || line.starts_with('#') {
-                    continue;
-                }
-                if let Some((source, target)) = Self::parse_line(line) {
-                    glossary.add(source, target);
-                }
-            }
-            glossary
+            Self::parse(content)
         }
     }
 }
@@ -0,0 +1,102 @@
+//! Source language detection by Unicode script ranges
+
+/// Number of extracted strings sampled for detection
+pub const SAMPLE_SIZE: usize = 200;
+
+/// Fewer letters than this are not enough to tell languages apart
+const MIN_LETTERS: usize = 20;
+
+/// Guess the dominant language of a sample of game text
+///
+/// Only scripts are distinguished, so any Latin-script text reports `en`.
+pub fn detect_language<S: AsRef<str>>(texts: &[S]) -> Option<&'static str> {
+    let (mut latin, mut cyrillic, mut hangul, mut kana, mut han) = (0, 0, 0, 0, 0);
+
+    for text in texts.iter().take(SAMPLE_SIZE) {
+        // Skip `{tags}` and `[interpolations]`, which are always ASCII
+        let mut depth = 0usize;
+        for c in text.as_ref().chars() {
+            match c {
+                '{' | '[' => depth += 1,
+                '}' | ']' => depth = depth.saturating_sub(1),
+                _ if depth > 0 => {}
+                '\u{3040}'..='\u{30FF}' => kana += 1,
+                '\u{1100}'..='\u{11FF}' | '\u{3130}'..='\u{318F}' | '\u{AC00}'..='\u{D7AF}' => {
+                    hangul += 1
+                }
+                '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' => han += 1,
+                '\u{0400}'..='\u{04FF}' => cyrillic += 1,
+                'a'..='z' | 'A'..='Z' | '\u{00C0}'..='\u{024F}' => latin += 1,
+                _ => {}
+            }
+        }
+    }
+
+    let cjk = kana + han;
+    if latin + cyrillic + hangul + cjk < MIN_LETTERS {
+        return None;
+    }
+
+    let dominant = latin.max(cyrillic).max(hangul).max(cjk);
+    let lang = if dominant == cjk {
+        // Japanese mixes kana into nearly every sentence; Chinese has none
+        if kana * 10 >= cjk { "ja" } else { "zh" }
+    } else if dominant == hangul {
+        "ko"
+    } else if dominant == cyrillic {
+        "ru"
+    } else {
+        "en"
+    };
+    Some(lang)
+}
+
+/// Whether a configured language name or code agrees with a detected one
+pub fn matches_detected(configured: &str, detected: &str) -> bool {
+    let configured = configured.to_lowercase();
+    let base = match configured.as_str() {
+        "chinese" | "chs" | "cht" => "zh",
+        "japanese" | "jp" => "ja",
+        "korean" | "kr" => "ko",
+        "russian" => "ru",
+        other => other.split(['-', '_']).next().unwrap_or(other),
+    };
+    if detected == "en" {
+        // Latin script covers more than English
+        !matches!(base, "zh" | "ja" | "ko" | "ru")
+    } else {
+        base == detected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        let japanese =
+            ["こんにちは、{b}[player]{/b}さん。今日はいい天気ですね。一緒に散歩に行きませんか？"];
+        assert_eq!(detect_language(&japanese), Some("ja"));
+
+        let chinese = ["你好，[player]。今天天气很好，我们一起去公园散步吧。你觉得怎么样？"];
+        assert_eq!(detect_language(&chinese), Some("zh"));
+
+        let korean = ["안녕하세요, 오늘 날씨가 정말 좋네요. 같이 산책하러 갈까요?"];
+        assert_eq!(detect_language(&korean), Some("ko"));
+
+        let english = ["Hello {i}there{/i}, the weather is lovely today."];
+        assert_eq!(detect_language(&english), Some("en"));
+
+        assert_eq!(detect_language(&["Yes", "No"]), None);
+    }
+
+    #[test]
+    fn test_matches_detected() {
+        assert!(matches_detected("Japanese", "ja"));
+        assert!(matches_detected("zh-CN", "zh"));
+        assert!(matches_detected("fr", "en"));
+        assert!(!matches_detected("en", "ja"));
+        assert!(!matches_detected("ja", "en"));
+    }
+}
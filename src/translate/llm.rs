@@ -2,7 +2,21 @@
 
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::circuit::{CircuitBreaker, DEFAULT_MAX_FAILURES, Recovery};
+use super::lang_detect::matches_detected;
+use super::load_ca_cert;
+
+/// Messages API version sent with every Anthropic request
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+/// Output cap for one Anthropic answer; the API requires one and single lines stay far below it
+const ANTHROPIC_MAX_TOKENS: u32 = 4096;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LlmProvider {
@@ -48,13 +62,55 @@ impl LlmProvider {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChineseScript {
+    Simplified,
+    Traditional,
+}
+
+impl ChineseScript {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "simplified" | "hans" => Some(Self::Simplified),
+            "traditional" | "hant" => Some(Self::Traditional),
+            _ => None,
+        }
+    }
+
+    pub fn instruction(&self) -> &str {
+        match self {
+            Self::Simplified => "Write Chinese using Simplified characters (简体中文).",
+            Self::Traditional => "Write Chinese using Traditional characters (繁體中文).",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LlmConfig {
     pub provider: LlmProvider,
-    pub api_key: Option<String>,
+    /// Keys used round-robin, one per request; empty for keyless endpoints
+    pub api_keys: Vec<String>,
     pub base_url: String,
     pub model: String,
     pub target_lang: String,
+    pub system_prompt: Option<String>,
+    pub script: Option<ChineseScript>,
+    /// Consecutive failed requests before the rest of the run is skipped (0 disables)
+    pub max_failures: usize,
+    /// Extra root certificate (PEM) trusted for API requests
+    pub ca_cert: Option<PathBuf>,
+    /// Wait out outages instead of stopping at `max_failures`
+    pub recovery: Option<Recovery>,
+    /// Ask for menu choices and screen text to stay about as long as the source
+    pub length_hint: bool,
+    /// Lore and style notes placed ahead of the system prompt
+    pub context: Option<String>,
+    /// JSONL file each request/response pair is appended to
+    pub prompt_log: Option<PathBuf>,
+    /// Request `{"translations": [...]}` JSON from OpenAI-compatible endpoints
+    pub json_mode: bool,
+    /// Texts packed into one numbered request; `None` sends one request per text
+    pub batch_size: Option<usize>,
 }
 
 impl LlmConfig {
@@ -63,13 +119,23 @@ impl LlmConfig {
             base_url: provider.default_base_url().to_string(),
             model: provider.default_model().to_string(),
             provider,
-            api_key: None,
+            api_keys: Vec::new(),
             target_lang: target_lang.to_string(),
+            system_prompt: None,
+            script: None,
+            max_failures: DEFAULT_MAX_FAILURES,
+            ca_cert: None,
+            recovery: None,
+            length_hint: false,
+            context: None,
+            prompt_log: None,
+            json_mode: false,
+            batch_size: None,
         }
     }
 
-    pub fn with_api_key(mut self, key: Option<String>) -> Self {
-        self.api_key = key;
+    pub fn with_api_keys(mut self, keys: Vec<String>) -> Self {
+        self.api_keys = keys;
         self
     }
 
@@ -86,6 +152,73 @@ impl LlmConfig {
         }
         self
     }
+
+    pub fn with_system_prompt(mut self, prompt: Option<String>) -> Self {
+        self.system_prompt = prompt;
+        self
+    }
+
+    pub fn with_script(mut self, script: Option<ChineseScript>) -> Self {
+        self.script = script;
+        self
+    }
+
+    pub fn with_max_failures(mut self, max_failures: Option<usize>) -> Self {
+        if let Some(max) = max_failures {
+            self.max_failures = max;
+        }
+        self
+    }
+
+    pub fn with_ca_cert(mut self, ca_cert: Option<PathBuf>) -> Self {
+        self.ca_cert = ca_cert;
+        self
+    }
+
+    pub fn with_recovery(mut self, recovery: Option<Recovery>) -> Self {
+        self.recovery = recovery;
+        self
+    }
+
+    pub fn with_length_hint(mut self, length_hint: bool) -> Self {
+        self.length_hint = length_hint;
+        self
+    }
+
+    pub fn with_context(mut self, context: Option<String>) -> Self {
+        self.context = context;
+        self
+    }
+
+    pub fn with_prompt_log(mut self, path: Option<PathBuf>) -> Self {
+        self.prompt_log = path;
+        self
+    }
+
+    pub fn with_json_mode(mut self, json_mode: bool) -> Self {
+        self.json_mode = json_mode;
+        self
+    }
+
+    /// Pack up to `size` texts per request; sizes below 2 keep one request per text
+    pub fn with_batch_translate(mut self, size: Option<usize>) -> Self {
+        self.batch_size = size.filter(|&n| n > 1);
+        self
+    }
+}
+
+/// One line of the `--prompt-log` file
+#[derive(Debug, Serialize)]
+struct PromptLogRecord<'a> {
+    timestamp: u64,
+    model: &'a str,
+    source: &'a str,
+    system_prompt: String,
+    prompt: &'a str,
+    response: Option<&'a str>,
+    error: Option<String>,
+    #[serde(rename = "final")]
+    final_text: Option<&'a str>,
 }
 
 #[derive(Debug, Serialize)]
@@ -93,6 +226,20 @@ struct OpenAIRequest {
     model: String,
     messages: Vec<Message>,
     temperature: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<ResponseFormat>,
+}
+
+#[derive(Debug, Serialize)]
+struct ResponseFormat {
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+/// The JSON-mode answer: one translation per input text, in order
+#[derive(Debug, Deserialize)]
+struct JsonTranslations {
+    translations: Vec<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -111,6 +258,43 @@ struct Choice {
     message: Message,
 }
 
+/// Anthropic Messages API request; the system prompt is a top-level field, not a message
+#[derive(Debug, Serialize)]
+struct AnthropicRequest {
+    model: String,
+    max_tokens: u32,
+    system: String,
+    messages: Vec<Message>,
+    temperature: f32,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicResponse {
+    content: Vec<ContentBlock>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ContentBlock {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    text: String,
+}
+
+impl AnthropicResponse {
+    /// Concatenated text blocks, or `None` if the answer held no text
+    fn text(&self) -> Option<String> {
+        let text: String = self
+            .content
+            .iter()
+            .filter(|b| b.kind == "text")
+            .map(|b| b.text.as_str())
+            .collect();
+        let text = text.trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+}
+
 #[derive(Debug, Serialize)]
 struct OllamaRequest {
     model: String,
@@ -126,33 +310,317 @@ struct OllamaResponse {
 pub struct LlmClient {
     config: LlmConfig,
     client: reqwest::blocking::Client,
+    breaker: CircuitBreaker,
+    prompt_log: Option<Mutex<File>>,
+    /// Index of the key the next request uses
+    next_key: AtomicUsize,
 }
 
 impl LlmClient {
     pub fn new(config: LlmConfig) -> Result<Self> {
-        let client = reqwest::blocking::Client::builder()
-            .timeout(Duration::from_secs(120))
-            .build()
-            .context("Failed to create HTTP client")?;
+        let mut builder = reqwest::blocking::Client::builder().timeout(Duration::from_secs(120));
+        if let Some(ref path) = config.ca_cert {
+            builder = builder.add_root_certificate(load_ca_cert(path)?);
+        }
+        let client = builder.build().context("Failed to create HTTP client")?;
 
-        Ok(Self { config, client })
+        let prompt_log = match config.prompt_log {
+            Some(ref path) => Some(Mutex::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open prompt log: {}", path.display()))?,
+            )),
+            None => None,
+        };
+
+        Ok(Self {
+            breaker: CircuitBreaker::new(config.max_failures).with_recovery(config.recovery),
+            config,
+            client,
+            prompt_log,
+            next_key: AtomicUsize::new(0),
+        })
     }
 
-    pub fn translate(&self, text: &str, context: Option<&str>) -> Result<String> {
-        match self.config.provider {
-            LlmProvider::OpenAI | LlmProvider::Claude => {
-                self.translate_openai_compatible(text, context)
+    /// Why the run stopped early, if too many requests failed in a row
+    pub fn trip_message(&self) -> Option<String> {
+        self.breaker.trip_message()
+    }
+
+    /// Translate one text; `budget` caps its length in display columns for UI text
+    pub fn translate(
+        &self,
+        text: &str,
+        context: Option<&str>,
+        budget: Option<usize>,
+    ) -> Result<String> {
+        let user_prompt = self.build_user_prompt(text, context, budget);
+        let result = self.breaker.run(|| self.request_translation(&user_prompt));
+        let result = result.and_then(|output| {
+            let cleaned = Self::single_reply(&output, text, self.json_mode())?;
+            self.log_exchange(text, &user_prompt, Ok((&output, &cleaned)));
+            Ok(cleaned)
+        });
+        if let Err(ref e) = result {
+            self.log_exchange(text, &user_prompt, Err(e));
+        }
+        result
+    }
+
+    /// The translation in a reply to a single text
+    fn single_reply(output: &str, text: &str, json_mode: bool) -> Result<String> {
+        // Endpoints that ignore response_format answer in plain text
+        let parsed = if json_mode {
+            parse_json_translations(output)
+        } else {
+            None
+        };
+        match parsed {
+            None => Ok(Self::clean_output(output, text)),
+            Some(mut translations) if translations.len() == 1 => Ok(translations.remove(0)),
+            Some(translations) => anyhow::bail!(
+                "Expected 1 translation in the JSON reply, got {}",
+                translations.len()
+            ),
+        }
+    }
+
+    /// The translations in a reply to a numbered batch, if it holds exactly `expected` items
+    fn batch_reply(output: &str, expected: usize, json_mode: bool) -> Option<Vec<String>> {
+        let parsed = if json_mode {
+            parse_json_translations(output)
+        } else {
+            None
+        };
+        match parsed {
+            Some(translations) => (translations.len() == expected).then_some(translations),
+            // Endpoints that ignore response_format answer with the numbered lines asked for
+            None => parse_numbered_response(output, expected),
+        }
+    }
+
+    /// Translate many texts, one request each or in numbered batches with `--batch-translate`
+    ///
+    /// `layout_sensitive` marks texts (by index) whose translation must fit the UI.
+    pub fn translate_all<F>(
+        &self,
+        texts: &[String],
+        layout_sensitive: &[bool],
+        progress_callback: Option<F>,
+    ) -> Vec<Result<String>>
+    where
+        F: Fn(usize) + Send + Sync,
+    {
+        let budgets: Vec<Option<usize>> = texts
+            .iter()
+            .enumerate()
+            .map(|(i, t)| self.length_budget(t, layout_sensitive.get(i).copied().unwrap_or(false)))
+            .collect();
+        let chunk_size = self.config.batch_size.unwrap_or(1);
+
+        let mut results = Vec::with_capacity(texts.len());
+        for (chunk, chunk_budgets) in texts.chunks(chunk_size).zip(budgets.chunks(chunk_size)) {
+            if chunk.len() == 1 {
+                results.push(self.translate(&chunk[0], None, chunk_budgets[0]));
+            } else {
+                results.extend(self.translate_numbered(chunk, chunk_budgets));
+            }
+            if let Some(ref cb) = progress_callback {
+                cb(results.len());
+            }
+        }
+        results
+    }
+
+    /// Requests needed for `count` texts, not counting per-line fallbacks
+    pub fn request_count(&self, count: usize) -> usize {
+        count.div_ceil(self.config.batch_size.unwrap_or(1))
+    }
+
+    /// One request for several texts; a reply with the wrong number of items is retried per text
+    fn translate_numbered(
+        &self,
+        texts: &[String],
+        budgets: &[Option<usize>],
+    ) -> Vec<Result<String>> {
+        let source = texts.join("\n");
+        let user_prompt = self.build_numbered_prompt(texts, budgets);
+        let output = match self.breaker.run(|| self.request_translation(&user_prompt)) {
+            Ok(output) => output,
+            Err(e) => {
+                self.log_exchange(&source, &user_prompt, Err(&e));
+                let message = format!("{:#}", e);
+                return texts
+                    .iter()
+                    .map(|_| Err(anyhow::anyhow!(message.clone())))
+                    .collect();
+            }
+        };
+
+        let Some(items) = Self::batch_reply(&output, texts.len(), self.json_mode()) else {
+            self.log_exchange(&source, &user_prompt, Ok((&output, "")));
+            tracing::warn!(
+                "Batched reply did not hold {} numbered items, translating them one by one",
+                texts.len()
+            );
+            return texts
+                .iter()
+                .zip(budgets)
+                .map(|(text, &budget)| self.translate(text, None, budget))
+                .collect();
+        };
+
+        let cleaned: Vec<String> = items
+            .iter()
+            .zip(texts)
+            .map(|(item, text)| Self::clean_output(item, text))
+            .collect();
+        self.log_exchange(&source, &user_prompt, Ok((&output, &cleaned.join("\n"))));
+        cleaned.into_iter().map(Ok).collect()
+    }
+
+    fn build_numbered_prompt(&self, texts: &[String], budgets: &[Option<usize>]) -> String {
+        let count = texts.len();
+        let mut prompt = format!(
+            "Translate each of the following {} texts. Answer with exactly {} lines numbered \
+             1 to {}, one translation per line in the same order, and nothing else.",
+            count, count, count
+        );
+        if self.json_mode() {
+            prompt.push_str(&format!(
+                "\nWhen answering in JSON, give the {} translations in that order instead.",
+                count
+            ));
+        }
+
+        let limits: Vec<String> = budgets
+            .iter()
+            .enumerate()
+            .filter_map(|(i, b)| b.map(|b| format!("{} (about {})", i + 1, b)))
+            .collect();
+        if !limits.is_empty() {
+            prompt.push_str(&format!(
+                "\nThese items are UI text with limited space, keep them within the given \
+                 number of characters, counting CJK characters as two: {}",
+                limits.join(", ")
+            ));
+        }
+
+        prompt.push('\n');
+        for (i, text) in texts.iter().enumerate() {
+            prompt.push_str(&format!("\n{}. {}", i + 1, text));
+        }
+        prompt
+    }
+
+    /// Append a request and its raw and cleaned response to the prompt log, if enabled
+    fn log_exchange(
+        &self,
+        source: &str,
+        prompt: &str,
+        outcome: std::result::Result<(&str, &str), &anyhow::Error>,
+    ) {
+        let Some(ref log) = self.prompt_log else {
+            return;
+        };
+
+        let (response, final_text, error) = match outcome {
+            Ok((raw, cleaned)) => (Some(raw), Some(cleaned), None),
+            Err(e) => (None, None, Some(format!("{:#}", e))),
+        };
+        let record = PromptLogRecord {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0),
+            model: &self.config.model,
+            source,
+            system_prompt: self.build_system_prompt(),
+            prompt,
+            response,
+            error,
+            final_text,
+        };
+        let Ok(mut line) = serde_json::to_string(&record) else {
+            return;
+        };
+        for key in self.config.api_keys.iter().filter(|k| !k.is_empty()) {
+            line = line.replace(key.as_str(), "[REDACTED]");
+        }
+        line.push('\n');
+
+        // Logging is best effort and never fails a translation
+        if let Ok(mut file) = log.lock() {
+            let _ = file.write_all(line.as_bytes());
+        }
+    }
+
+    /// Remove chatter smaller models add around the translation: code fences, a
+    /// "Here is the translation:" line and quotes wrapping the whole text. Quotes are
+    /// only removed when the source was not quoted itself.
+    fn clean_output(output: &str, source: &str) -> String {
+        let mut text = output.trim();
+
+        if let Some(inner) = text.strip_prefix("```")
+            && let Some(inner) = inner.strip_suffix("```")
+        {
+            // Drop the language tag on the opening fence line
+            text = match inner.split_once('\n') {
+                Some((_, body)) => body.trim(),
+                None => inner.trim(),
+            };
+        }
+
+        if let Some((first, rest)) = text.split_once('\n') {
+            let first = first.trim().to_lowercase();
+            if first.ends_with(':') && first.contains("translat") && !rest.trim().is_empty() {
+                text = rest.trim();
             }
-            LlmProvider::Ollama => self.translate_ollama(text, context),
+        }
+
+        let lower = text.to_lowercase();
+        let source_lower = source.trim_start().to_lowercase();
+        for prefix in ["translation:", "translated text:"] {
+            if lower.starts_with(prefix) && !source_lower.starts_with(prefix) {
+                text = text[prefix.len()..].trim_start();
+                break;
+            }
+        }
+
+        let source_quoted = source.trim_start().starts_with(['"', '“']);
+        for (open, close) in [('"', '"'), ('“', '”')] {
+            if !source_quoted
+                && let Some(inner) = text.strip_prefix(open).and_then(|t| t.strip_suffix(close))
+                && !inner.contains([open, close])
+            {
+                text = inner.trim();
+                break;
+            }
+        }
+
+        text.to_string()
+    }
+
+    /// Length budget for a text when `--length-hint` is on and its layout is constrained
+    pub fn length_budget(&self, text: &str, layout_sensitive: bool) -> Option<usize> {
+        (self.config.length_hint && layout_sensitive).then(|| display_width(text))
+    }
+
+    fn request_translation(&self, user_prompt: &str) -> Result<String> {
+        match self.config.provider {
+            LlmProvider::OpenAI => self.translate_openai_compatible(user_prompt),
+            LlmProvider::Claude => self.translate_anthropic(user_prompt),
+            LlmProvider::Ollama => self.translate_ollama(user_prompt),
             LlmProvider::Google | LlmProvider::DeepL => {
                 anyhow::bail!("Use MachineTranslateClient for Google/DeepL")
             }
         }
     }
 
-    fn translate_openai_compatible(&self, text: &str, context: Option<&str>) -> Result<String> {
+    fn translate_openai_compatible(&self, user_prompt: &str) -> Result<String> {
         let system_prompt = self.build_system_prompt();
-        let user_prompt = self.build_user_prompt(text, context);
 
         let request = OpenAIRequest {
             model: self.config.model.clone(),
@@ -163,21 +631,26 @@ impl LlmClient {
                 },
                 Message {
                     role: "user".to_string(),
-                    content: user_prompt,
+                    content: user_prompt.to_string(),
                 },
             ],
             temperature: 0.3,
+            response_format: self.json_mode().then_some(ResponseFormat {
+                kind: "json_object",
+            }),
         };
 
         let url = format!("{}/chat/completions", self.config.base_url);
 
-        let mut req = self.client.post(&url).json(&request);
-
-        if let Some(ref key) = self.config.api_key {
-            req = req.header("Authorization", format!("Bearer {}", key));
-        }
-
-        let response = req.send().context("Failed to send request to LLM API")?;
+        let response = self
+            .send_rotating(|key| {
+                let req = self.client.post(&url).json(&request);
+                match key {
+                    Some(key) => req.header("Authorization", format!("Bearer {}", key)),
+                    None => req,
+                }
+            })
+            .context("Failed to send request to LLM API")?;
 
         if !response.status().is_success() {
             let status = response.status();
@@ -194,12 +667,75 @@ impl LlmClient {
             .context("No response from API")
     }
 
-    fn translate_ollama(&self, text: &str, context: Option<&str>) -> Result<String> {
-        let prompt = format!(
-            "{}\n\n{}",
-            self.build_system_prompt(),
-            self.build_user_prompt(text, context)
-        );
+    fn translate_anthropic(&self, user_prompt: &str) -> Result<String> {
+        let request = AnthropicRequest {
+            model: self.config.model.clone(),
+            max_tokens: ANTHROPIC_MAX_TOKENS,
+            system: self.build_system_prompt(),
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: user_prompt.to_string(),
+            }],
+            temperature: 0.3,
+        };
+
+        let url = format!("{}/messages", self.config.base_url);
+
+        let response = self
+            .send_rotating(|key| {
+                let req = self
+                    .client
+                    .post(&url)
+                    .header("anthropic-version", ANTHROPIC_VERSION)
+                    .json(&request);
+                match key {
+                    Some(key) => req.header("x-api-key", key),
+                    None => req,
+                }
+            })
+            .context("Failed to send request to Anthropic API")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().unwrap_or_default();
+            anyhow::bail!("Anthropic request failed ({}): {}", status, body);
+        }
+
+        let result: AnthropicResponse = response
+            .json()
+            .context("Failed to parse Anthropic response")?;
+
+        result.text().context("No response from API")
+    }
+
+    /// Send with the next key in rotation; a 429 moves on to the following key until
+    /// every key was tried once for this request
+    fn send_rotating<B>(&self, build: B) -> reqwest::Result<reqwest::blocking::Response>
+    where
+        B: Fn(Option<&str>) -> reqwest::blocking::RequestBuilder,
+    {
+        let attempts = self.config.api_keys.len().max(1);
+        let mut attempt = 1;
+        loop {
+            let response = build(self.next_key()).send()?;
+            if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS || attempt >= attempts {
+                return Ok(response);
+            }
+            attempt += 1;
+        }
+    }
+
+    fn next_key(&self) -> Option<&str> {
+        let keys = &self.config.api_keys;
+        if keys.is_empty() {
+            return None;
+        }
+        let index = self.next_key.fetch_add(1, Ordering::Relaxed) % keys.len();
+        Some(&keys[index])
+    }
+
+    fn translate_ollama(&self, user_prompt: &str) -> Result<String> {
+        let prompt = format!("{}\n\n{}", self.build_system_prompt(), user_prompt);
 
         let request = OllamaRequest {
             model: self.config.model.clone(),
@@ -227,7 +763,86 @@ impl LlmClient {
         Ok(result.response.trim().to_string())
     }
 
+    /// The prompt exactly as it would be sent, for `--dry-run-prompts`
+    pub fn render_prompt(
+        &self,
+        text: &str,
+        context: Option<&str>,
+        layout_sensitive: bool,
+    ) -> String {
+        let system = self.build_system_prompt();
+        let budget = self.length_budget(text, layout_sensitive);
+        let user = self.build_user_prompt(text, context, budget);
+        match self.config.provider {
+            // Ollama's generate endpoint takes a single combined prompt
+            LlmProvider::Ollama => format!("[prompt]\n{}\n\n{}", system, user),
+            _ => format!("[system]\n{}\n\n[user]\n{}", system, user),
+        }
+    }
+
+    /// Request settings with the API key redacted
+    pub fn describe(&self) -> String {
+        format!(
+            "provider: {:?}, model: {}, endpoint: {}, api key: {}",
+            self.config.provider,
+            self.config.model,
+            self.config.base_url,
+            match self.config.api_keys.len() {
+                0 => "<none>".to_string(),
+                1 => "<redacted>".to_string(),
+                n => format!("<redacted> ({} keys in rotation)", n),
+            }
+        )
+    }
+
+    /// JSON mode only applies to chat endpoints; Claude gets the format from the system prompt alone
+    fn json_mode(&self) -> bool {
+        self.config.json_mode
+            && matches!(
+                self.config.provider,
+                LlmProvider::OpenAI | LlmProvider::Claude
+            )
+    }
+
     fn build_system_prompt(&self) -> String {
+        let prompt = match self.config.system_prompt {
+            Some(ref template) => template.replace("{lang}", &self.config.target_lang),
+            None => self.default_system_prompt(),
+        };
+
+        let prompt = match self.config.script {
+            Some(script) if self.targets_chinese() => {
+                format!("{}\n{}", prompt, script.instruction())
+            }
+            _ => prompt,
+        };
+
+        let prompt = if self.json_mode() {
+            format!(
+                "{}\nRespond with a JSON object of the form {{\"translations\": [\"...\"]}} \
+                 holding one translated string per text, in order.",
+                prompt
+            )
+        } else {
+            prompt
+        };
+
+        match self.config.context {
+            Some(ref context) => format!(
+                "Background on the game you are translating:\n{}\n\n{}",
+                context, prompt
+            ),
+            None => prompt,
+        }
+    }
+
+    /// The script choice only means something for Chinese targets
+    fn targets_chinese(&self) -> bool {
+        let lang = &self.config.target_lang;
+        matches_detected(lang, "zh") || lang.to_lowercase().contains("chinese")
+    }
+
+    fn default_system_prompt(&self) -> String {
         format!(
             "You are a professional game translator. Translate the given text to {}. \
              Follow these rules:\n\
@@ -239,10 +854,423 @@ impl LlmClient {
         )
     }
 
-    fn build_user_prompt(&self, text: &str, context: Option<&str>) -> String {
-        match context {
+    fn build_user_prompt(
+        &self,
+        text: &str,
+        context: Option<&str>,
+        budget: Option<usize>,
+    ) -> String {
+        let mut prompt = match context {
             Some(ctx) => format!("Context: {}\n\nTranslate: {}", ctx, text),
             None => format!("Translate: {}", text),
+        };
+        if let Some(budget) = budget {
+            prompt.push_str(&format!(
+                "\n\nThis is UI text with limited space: keep the translation within about {} \
+                 characters, counting CJK characters as two.",
+                budget
+            ));
+        }
+        prompt
+    }
+}
+
+/// Default token budget for a `--context-file`
+pub const DEFAULT_CONTEXT_MAX_TOKENS: usize = 2000;
+
+/// Read a lore/style document, cut down to roughly `max_tokens`
+///
+/// Returns the text and whether it had to be truncated.
+pub fn load_context_file(path: &Path, max_tokens: usize) -> Result<(String, bool)> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read context file: {}", path.display()))?;
+    Ok(truncate_to_tokens(content.trim(), max_tokens))
+}
+
+/// Translations from a `{"translations": [...]}` answer, or `None` if it is not one
+pub fn parse_json_translations(output: &str) -> Option<Vec<String>> {
+    let text = output.trim();
+    let text = text
+        .strip_prefix("```json")
+        .or_else(|| text.strip_prefix("```"))
+        .and_then(|t| t.strip_suffix("```"))
+        .unwrap_or(text);
+    serde_json::from_str::<JsonTranslations>(text.trim())
+        .ok()
+        .map(|j| j.translations)
+}
+
+/// Items of a numbered batch reply, or `None` unless it holds exactly `1..=expected`
+///
+/// Tolerates a preamble line, blank lines, code fences and `1.`, `1)`, `1:`, `(1)` or a bare
+/// `1` as the number.
+pub fn parse_numbered_response(output: &str, expected: usize) -> Option<Vec<String>> {
+    let text = output.trim();
+    let text = text
+        .strip_prefix("```")
+        .and_then(|t| t.strip_suffix("```"))
+        .map(|t| t.split_once('\n').map_or("", |(_, body)| body))
+        .unwrap_or(text);
+
+    let mut items: Vec<String> = Vec::with_capacity(expected);
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        match split_item_number(line) {
+            Some((number, item)) if number == items.len() + 1 => items.push(item.to_string()),
+            // Out of order, repeated or skipped numbers would misalign translations
+            Some(_) => return None,
+            // Chatter before the list is dropped; text after an item cannot be placed safely
+            None if items.is_empty() => continue,
+            None => return None,
+        }
+    }
+    (items.len() == expected).then_some(items)
+}
+
+/// `"3. text"` -> `(3, "text")`
+fn split_item_number(line: &str) -> Option<(usize, &str)> {
+    let line = line.strip_prefix('(').unwrap_or(line);
+    let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits == 0 {
+        return None;
+    }
+    let number = line[..digits].parse().ok()?;
+    let rest = &line[digits..];
+    let rest = rest
+        .strip_prefix(['.', ')', ':', '、', '．'])
+        .unwrap_or(rest);
+    // A bare number must be followed by a space, or "10 apples" would read as item 10
+    if rest.len() == line.len() - digits && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+    Some((number, rest.trim()))
+}
+
+/// Rough token count: about four Latin characters per token, one per CJK character
+fn estimate_tokens(text: &str) -> usize {
+    let quarters: usize = text
+        .chars()
+        .map(|c| {
+            if display_width(&c.to_string()) == 2 {
+                4
+            } else {
+                1
+            }
+        })
+        .sum();
+    quarters.div_ceil(4)
+}
+
+/// Keep whole paragraphs, in order, while they fit the budget
+fn truncate_to_tokens(text: &str, max_tokens: usize) -> (String, bool) {
+    if estimate_tokens(text) <= max_tokens {
+        return (text.to_string(), false);
+    }
+
+    // Files saved on Windows separate paragraphs with "\r\n\r\n"
+    let text = text.replace("\r\n", "\n");
+    let mut kept = String::new();
+    for paragraph in text.split("\n\n") {
+        let candidate = if kept.is_empty() {
+            paragraph.to_string()
+        } else {
+            format!("{}\n\n{}", kept, paragraph)
+        };
+        if estimate_tokens(&candidate) > max_tokens {
+            break;
         }
+        kept = candidate;
+    }
+
+    // A single oversized first paragraph is cut mid-text rather than dropped
+    if kept.is_empty() {
+        kept = text.chars().take(max_tokens * 4).collect();
+        while estimate_tokens(&kept) > max_tokens {
+            kept.pop();
+        }
+    }
+    (kept, true)
+}
+
+/// Approximate on-screen width, with CJK and other full-width characters counting double
+fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| match c {
+            '\u{1100}'..='\u{115F}'
+            | '\u{2E80}'..='\u{A4CF}'
+            | '\u{AC00}'..='\u{D7A3}'
+            | '\u{F900}'..='\u{FAFF}'
+            | '\u{FF00}'..='\u{FF60}'
+            | '\u{FFE0}'..='\u{FFE6}' => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_system_prompt_template() {
+        let config = LlmConfig::new(LlmProvider::Ollama, "ja").with_system_prompt(Some(
+            "Translate into {lang}, keeping honorifics.".to_string(),
+        ));
+        let prompt = LlmClient::new(config).unwrap().build_system_prompt();
+        assert!(prompt.starts_with("Translate into ja, keeping honorifics."));
+
+        let config = LlmConfig::new(LlmProvider::Ollama, "ja");
+        let prompt = LlmClient::new(config).unwrap().build_system_prompt();
+        assert!(prompt.starts_with(
+            "You are a professional game translator. Translate the given text to ja."
+        ));
+    }
+
+    #[test]
+    fn test_script_instruction_only_for_chinese() {
+        let prompt = |lang: &str| {
+            let config = LlmConfig::new(LlmProvider::Ollama, lang)
+                .with_script(Some(ChineseScript::Traditional));
+            LlmClient::new(config).unwrap().build_system_prompt()
+        };
+        let instruction = ChineseScript::Traditional.instruction();
+
+        assert!(prompt("zh-TW").contains(instruction));
+        assert!(prompt("Traditional Chinese").contains(instruction));
+        assert!(!prompt("ja").contains(instruction));
+        assert!(!prompt("ko").contains(instruction));
+    }
+
+    #[test]
+    fn test_clean_output() {
+        let clean = LlmClient::clean_output;
+        assert_eq!(clean("  你好。 ", "Hello."), "你好。");
+        assert_eq!(
+            clean("Here is the translation:\n你好。", "Hello."),
+            "你好。"
+        );
+        assert_eq!(clean("Translation: 你好。", "Hello."), "你好。");
+        assert_eq!(clean("```text\n你好。\n```", "Hello."), "你好。");
+        assert_eq!(clean("\"你好。\"", "Hello."), "你好。");
+        assert_eq!(clean("“你好。”", "Hello."), "你好。");
+
+        // Quotes that belong to the dialogue are kept
+        assert_eq!(
+            clean("\"你好\"，她说。", "\"Hi,\" she said."),
+            "\"你好\"，她说。"
+        );
+        assert_eq!(clean("“你好。”", "\"Hello.\""), "“你好。”");
+        assert_eq!(clean("\"是\"还是\"否\"", "Yes or no"), "\"是\"还是\"否\"");
+        assert_eq!(clean("翻译:你好", "Hello"), "翻译:你好");
+    }
+
+    #[test]
+    fn test_parse_json_translations() {
+        assert_eq!(
+            parse_json_translations(r#"{"translations": ["你好", "再见"]}"#),
+            Some(vec!["你好".to_string(), "再见".to_string()])
+        );
+        assert_eq!(
+            parse_json_translations("```json\n{\"translations\": [\"你好\"]}\n```"),
+            Some(vec!["你好".to_string()])
+        );
+        assert_eq!(parse_json_translations("你好"), None);
+        assert_eq!(parse_json_translations(r#"{"text": "你好"}"#), None);
+
+        let reply = |output| LlmClient::single_reply(output, "Hello", true);
+        assert_eq!(reply(r#"{"translations": ["你好"]}"#).unwrap(), "你好");
+        assert_eq!(reply("Translation: 你好").unwrap(), "你好");
+        // Never write the raw JSON into the script
+        assert!(reply(r#"{"translations": ["你好", "再见"]}"#).is_err());
+        assert!(reply(r#"{"translations": []}"#).is_err());
+    }
+
+    #[test]
+    fn test_anthropic_shapes() {
+        let request = AnthropicRequest {
+            model: "claude-sonnet-4-20250514".to_string(),
+            max_tokens: ANTHROPIC_MAX_TOKENS,
+            system: "Translate to zh-CN.".to_string(),
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: "Translate: Hello".to_string(),
+            }],
+            temperature: 0.3,
+        };
+        let json = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["system"], "Translate to zh-CN.");
+        assert_eq!(json["messages"].as_array().unwrap().len(), 1);
+        assert_eq!(json["messages"][0]["role"], "user");
+
+        let response: AnthropicResponse = serde_json::from_str(
+            r#"{"id": "msg_1", "type": "message", "role": "assistant",
+                "content": [{"type": "text", "text": " 你好"}, {"type": "text", "text": "。"}],
+                "stop_reason": "end_turn"}"#,
+        )
+        .unwrap();
+        assert_eq!(response.text().as_deref(), Some("你好。"));
+
+        let empty: AnthropicResponse = serde_json::from_str(r#"{"content": []}"#).unwrap();
+        assert_eq!(empty.text(), None);
+    }
+
+    #[test]
+    fn test_parse_numbered_response() {
+        let items = |v: &[&str]| Some(v.iter().map(|s| s.to_string()).collect::<Vec<_>>());
+
+        assert_eq!(
+            parse_numbered_response("1. 你好\n2. 再见", 2),
+            items(&["你好", "再见"])
+        );
+        assert_eq!(
+            parse_numbered_response("Here you go:\n\n1 你好\n\n2) 再见\n(3) 谢谢\n", 3),
+            items(&["你好", "再见", "谢谢"])
+        );
+        assert_eq!(
+            parse_numbered_response("```\n1: 你好\n2、再见\n```", 2),
+            items(&["你好", "再见"])
+        );
+
+        // Wrong count, gaps and stray lines fall back to per-line requests
+        assert_eq!(parse_numbered_response("1. 你好", 2), None);
+        assert_eq!(parse_numbered_response("1. 你好\n3. 再见", 2), None);
+        assert_eq!(parse_numbered_response("1. 你好\n继续\n2. 再见", 2), None);
+        assert_eq!(parse_numbered_response("10apples", 1), None);
+    }
+
+    #[test]
+    fn test_numbered_prompt() {
+        let config = LlmConfig::new(LlmProvider::Ollama, "English")
+            .with_length_hint(true)
+            .with_batch_translate(Some(20));
+        let client = LlmClient::new(config).unwrap();
+        let texts = vec!["Hello".to_string(), "Start".to_string()];
+
+        let prompt = client.build_numbered_prompt(&texts, &[None, Some(5)]);
+        assert!(prompt.contains("exactly 2 lines"));
+        assert!(prompt.contains("2 (about 5)"));
+        assert!(prompt.ends_with("\n1. Hello\n2. Start"));
+    }
+
+    #[test]
+    fn test_batch_reply_in_json_mode() {
+        let config = LlmConfig::new(LlmProvider::OpenAI, "English")
+            .with_json_mode(true)
+            .with_batch_translate(Some(20));
+        let client = LlmClient::new(config).unwrap();
+        let texts = vec!["Hello".to_string(), "Start".to_string()];
+        // Endpoints that ignore response_format can still answer with numbered lines
+        assert!(
+            client
+                .build_numbered_prompt(&texts, &[None, None])
+                .contains("exactly 2 lines")
+        );
+
+        let reply = |output| LlmClient::batch_reply(output, 2, true);
+        assert_eq!(
+            reply(r#"{"translations": ["你好", "开始"]}"#),
+            Some(vec!["你好".to_string(), "开始".to_string()])
+        );
+        assert_eq!(
+            reply("1. 你好\n2. 开始"),
+            Some(vec!["你好".to_string(), "开始".to_string()])
+        );
+        assert_eq!(reply(r#"{"translations": ["你好"]}"#), None);
+        assert_eq!(reply("你好"), None);
+    }
+
+    #[test]
+    fn test_truncate_context() {
+        let short = "Eileen is the narrator's older sister.";
+        assert_eq!(truncate_to_tokens(short, 100), (short.to_string(), false));
+
+        let notes = format!(
+            "{}\n\n{}\n\n{}",
+            "a".repeat(40),
+            "b".repeat(40),
+            "c".repeat(40)
+        );
+        let (kept, truncated) = truncate_to_tokens(&notes, 25);
+        assert!(truncated);
+        assert_eq!(kept, format!("{}\n\n{}", "a".repeat(40), "b".repeat(40)));
+
+        let (kept, truncated) = truncate_to_tokens(&notes.replace('\n', "\r\n"), 25);
+        assert!(truncated);
+        assert_eq!(kept, format!("{}\n\n{}", "a".repeat(40), "b".repeat(40)));
+
+        let (kept, truncated) = truncate_to_tokens(&"设定".repeat(50), 10);
+        assert!(truncated);
+        assert_eq!(kept.chars().count(), 10);
+    }
+
+    #[test]
+    fn test_length_budget() {
+        let config = LlmConfig::new(LlmProvider::Ollama, "English").with_length_hint(true);
+        let client = LlmClient::new(config).unwrap();
+
+        assert_eq!(client.length_budget("Start", true), Some(5));
+        assert_eq!(client.length_budget("开始游戏", true), Some(8));
+        assert_eq!(client.length_budget("Start", false), None);
+
+        let prompt = client.build_user_prompt("Start", None, Some(5));
+        assert!(prompt.starts_with("Translate: Start"));
+        assert!(prompt.contains("within about 5 characters"));
+        assert_eq!(
+            client.build_user_prompt("Start", None, None),
+            "Translate: Start"
+        );
+
+        // The dry-run preview shows the same hint
+        assert!(
+            client
+                .render_prompt("Start", None, true)
+                .contains("within about 5 characters")
+        );
+        assert!(
+            !client
+                .render_prompt("Start", None, false)
+                .contains("within about")
+        );
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let config = LlmConfig::new(LlmProvider::OpenAI, "zh-CN")
+            .with_api_keys(vec!["sk-a".to_string(), "sk-b".to_string()]);
+        let client = LlmClient::new(config).unwrap();
+        let used: Vec<_> = (0..4).map(|_| client.next_key().unwrap()).collect();
+        assert_eq!(used, ["sk-a", "sk-b", "sk-a", "sk-b"]);
+
+        let keyless = LlmClient::new(LlmConfig::new(LlmProvider::Ollama, "zh-CN")).unwrap();
+        assert_eq!(keyless.next_key(), None);
+    }
+
+    #[test]
+    fn test_prompt_log_redacts_key() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("prompts.jsonl");
+        let config = LlmConfig::new(LlmProvider::OpenAI, "zh-CN")
+            .with_api_keys(vec!["sk-secret".to_string()])
+            .with_prompt_log(Some(path.clone()));
+        let client = LlmClient::new(config).unwrap();
+
+        client.log_exchange("Hi", "Translate: Hi", Ok(("\"你好\" sk-secret", "你好")));
+        client.log_exchange(
+            "Bye",
+            "Translate: Bye",
+            Err(&anyhow::anyhow!("401 for key sk-secret")),
+        );
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains("sk-secret"));
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["source"], "Hi");
+        assert_eq!(lines[0]["prompt"], "Translate: Hi");
+        assert_eq!(lines[0]["final"], "你好");
+        assert_eq!(lines[1]["error"], "401 for key [REDACTED]");
+        assert!(lines[1]["response"].is_null());
     }
 }
@@ -1,23 +1,61 @@
 //! Machine translation API clients (Google Translate, DeepL)
 
 use anyhow::{Context, Result};
+use colored::Colorize;
 use rayon::prelude::*;
-use regex::Regex;
+use regex::{Captures, Regex};
 use serde::Deserialize;
-use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::time::Duration;
 
 use super::cache::TranslationCache;
+use super::circuit::{Cancelled, CircuitBreaker, DEFAULT_MAX_FAILURES, Recovery};
+use super::concurrency::AdaptiveConcurrency;
+use super::llm::ChineseScript;
+use super::load_ca_cert;
 
 const DEFAULT_CONCURRENCY: usize = 16;
-const DEEPL_BATCH_SIZE: usize = 50;
-const GOOGLE_BATCH_SIZE: usize = 20;
-const GOOGLE_SEPARATOR: &str = "\n\u{2029}\n";
+pub const DEEPL_BATCH_SIZE: usize = 50;
+/// DeepL rejects request bodies over 128 KiB; leave room for the JSON around the texts
+const DEEPL_MAX_REQUEST_BYTES: usize = 120 * 1024;
+pub const GOOGLE_BATCH_SIZE: usize = 20;
 const MAX_RETRIES: u32 = 3;
+/// Translations remembered per client so a run never sends the same text twice
+const MAX_MEMO_ENTRIES: usize = 50_000;
 const BASE_RETRY_DELAY_MS: u64 = 500;
 
+/// Receives the (source, translation) pairs of each batch as soon as it completes
+type BatchSink<'a> = dyn Fn(Vec<(String, String)>) + Sync + 'a;
+
+/// Error given to texts missing from a cache opened with `with_cache_only`
+#[derive(Debug)]
+pub struct NotCached;
+
+impl std::fmt::Display for NotCached {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Not in translation cache")
+    }
+}
+
+impl std::error::Error for NotCached {}
+
+/// Error for a request the provider answered with 429 Too Many Requests
+#[derive(Debug)]
+pub struct RateLimited;
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Rate limited (429 Too Many Requests)")
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
 fn wrap_callback<F>(
     callback: &Option<F>,
     offset: usize,
@@ -30,19 +68,56 @@ where
         .map(move |cb| move |count: usize| cb(count + offset))
 }
 
+/// Distinct texts in first-seen order, and for each input the index of its distinct text
+fn coalesce(texts: &[String]) -> (Vec<String>, Vec<usize>) {
+    let mut unique = Vec::new();
+    let mut seen: HashMap<&str, usize> = HashMap::new();
+    let slots = texts
+        .iter()
+        .map(|text| {
+            *seen.entry(text.as_str()).or_insert_with(|| {
+                unique.push(text.clone());
+                unique.len() - 1
+            })
+        })
+        .collect();
+    (unique, slots)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum MachineTranslateProvider {
     Google,
     DeepL,
 }
 
+/// How several texts are merged into one Google request and split back apart
+#[derive(Debug, Clone, PartialEq)]
+pub enum GoogleDelimiter {
+    /// Numbered `<<n>>` markers, which survive translation far more often than a separator
+    Sentinel,
+    /// A literal separator placed between texts
+    Separator(String),
+}
+
 #[derive(Debug, Clone)]
 pub struct MachineTranslateConfig {
     pub provider: MachineTranslateProvider,
     pub target_lang: String,
     pub source_lang: String,
     pub api_key: Option<String>,
+    /// Most Google requests in flight; the adaptive limit never goes above it
     pub concurrency: usize,
+    /// Fewest Google requests in flight, however often the endpoint answers 429
+    pub min_concurrency: usize,
+    pub google_delimiter: GoogleDelimiter,
+    /// Maximum texts per DeepL request
+    pub deepl_batch_size: usize,
+    /// Consecutive failed requests before the rest of the run is skipped (0 disables)
+    pub max_failures: usize,
+    /// Extra root certificate (PEM) trusted for API requests
+    pub ca_cert: Option<PathBuf>,
+    /// Wait out outages instead of stopping at `max_failures`
+    pub recovery: Option<Recovery>,
 }
 
 impl MachineTranslateConfig {
@@ -53,6 +128,12 @@ impl MachineTranslateConfig {
             source_lang: "en".to_string(),
             api_key: None,
             concurrency: DEFAULT_CONCURRENCY,
+            min_concurrency: 1,
+            google_delimiter: GoogleDelimiter::Sentinel,
+            deepl_batch_size: DEEPL_BATCH_SIZE,
+            max_failures: DEFAULT_MAX_FAILURES,
+            ca_cert: None,
+            recovery: None,
         }
     }
 
@@ -63,9 +144,97 @@ impl MachineTranslateConfig {
             source_lang: "EN".to_string(),
             api_key: Some(api_key),
             concurrency: DEFAULT_CONCURRENCY,
+            min_concurrency: 1,
+            google_delimiter: GoogleDelimiter::Sentinel,
+            deepl_batch_size: DEEPL_BATCH_SIZE,
+            max_failures: DEFAULT_MAX_FAILURES,
+            ca_cert: None,
+            recovery: None,
         }
     }
 
+    /// Force simplified or traditional Chinese when the target is Chinese
+    pub fn with_script(mut self, script: Option<ChineseScript>) -> Self {
+        let Some(script) = script else {
+            return self;
+        };
+
+        let target = match (self.provider, script, self.target_lang.as_str()) {
+            (MachineTranslateProvider::Google, ChineseScript::Traditional, "zh-CN") => "zh-TW",
+            (MachineTranslateProvider::Google, ChineseScript::Simplified, "zh-TW") => "zh-CN",
+            (MachineTranslateProvider::DeepL, ChineseScript::Traditional, "ZH" | "ZH-HANS") => {
+                "ZH-HANT"
+            }
+            (MachineTranslateProvider::DeepL, ChineseScript::Simplified, "ZH-HANT") => "ZH-HANS",
+            _ => return self,
+        };
+        self.target_lang = target.to_string();
+        self
+    }
+
+    /// Use a literal separator for merged Google requests instead of numbered markers
+    pub fn with_google_separator(mut self, separator: Option<String>) -> Self {
+        if let Some(separator) = separator {
+            self.google_delimiter = GoogleDelimiter::Separator(separator);
+        }
+        self
+    }
+
+    /// Send at most this many texts per DeepL request (some plans accept fewer than 50)
+    pub fn with_batch_size(mut self, batch_size: Option<usize>) -> Self {
+        if let Some(size) = batch_size {
+            self.deepl_batch_size = size.max(1);
+        }
+        self
+    }
+
+    /// Give up on the remaining texts after this many consecutive failed requests
+    pub fn with_max_failures(mut self, max_failures: Option<usize>) -> Self {
+        if let Some(max) = max_failures {
+            self.max_failures = max;
+        }
+        self
+    }
+
+    /// Trust an additional root certificate, e.g. for a TLS-inspecting proxy
+    pub fn with_ca_cert(mut self, ca_cert: Option<PathBuf>) -> Self {
+        self.ca_cert = ca_cert;
+        self
+    }
+
+    pub fn with_recovery(mut self, recovery: Option<Recovery>) -> Self {
+        self.recovery = recovery;
+        self
+    }
+
+    /// Bounds for the adaptive number of concurrent Google requests
+    pub fn with_concurrency(mut self, min: Option<usize>, max: Option<usize>) -> Self {
+        if let Some(max) = max {
+            self.concurrency = max.max(1);
+        }
+        if let Some(min) = min {
+            self.min_concurrency = min.max(1);
+        }
+        self.min_concurrency = self.min_concurrency.min(self.concurrency);
+        self
+    }
+
+    /// Translate from this language instead of English
+    pub fn with_source_lang(mut self, source_lang: Option<&str>) -> Self {
+        if let Some(lang) = source_lang {
+            self.source_lang = match self.provider {
+                MachineTranslateProvider::Google => Self::normalize_lang_google(lang),
+                // DeepL source languages carry no regional variant
+                MachineTranslateProvider::DeepL => Self::normalize_lang_deepl(lang)
+                    .split('-')
+                    .next()
+                    .unwrap_or_default()
+                    .to_string(),
+            };
+        }
+        self
+    }
+
     fn normalize_lang_google(lang: &str) -> String {
         match lang.to_lowercase().as_str() {
             "chinese" | "zh-cn" | "zh_cn" | "chs" => "zh-CN".to_string(),
@@ -84,6 +253,7 @@ impl MachineTranslateConfig {
     fn normalize_lang_deepl(lang: &str) -> String {
         match lang.to_lowercase().as_str() {
             "chinese" | "zh-cn" | "zh_cn" | "chs" => "ZH".to_string(),
+            "zh-tw" | "zh_tw" | "cht" | "zh-hant" => "ZH-HANT".to_string(),
             "japanese" | "ja" | "jp" => "JA".to_string(),
             "korean" | "ko" | "kr" => "KO".to_string(),
             "english" | "en" => "EN".to_string(),
@@ -106,9 +276,29 @@ struct DeepLTranslation {
     text: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct DeepLUsage {
+    pub character_count: u64,
+    pub character_limit: u64,
+}
+
+impl DeepLUsage {
+    pub fn remaining(&self) -> u64 {
+        self.character_limit.saturating_sub(self.character_count)
+    }
+}
+
 pub struct MachineTranslateClient {
     config: MachineTranslateConfig,
     client: reqwest::blocking::Client,
+    sentinel_re: Regex,
+    merged_batches: AtomicUsize,
+    merge_fallbacks: AtomicUsize,
+    breaker: CircuitBreaker,
+    /// Google requests in flight, halved on 429s and grown back while requests succeed
+    limiter: AdaptiveConcurrency,
+    /// Successful translations of this run, shared by every batch and file
+    memo: Mutex<HashMap<String, String>>,
 }
 
 pub struct BatchResult {
@@ -119,13 +309,70 @@ pub struct BatchResult {
 
 impl MachineTranslateClient {
     pub fn new(config: MachineTranslateConfig) -> Result<Self> {
-        let client = reqwest::blocking::Client::builder()
+        let mut builder = reqwest::blocking::Client::builder()
             .timeout(Duration::from_secs(30))
-            .pool_max_idle_per_host(config.concurrency)
-            .build()
-            .context("Failed to create HTTP client")?;
+            .pool_max_idle_per_host(config.concurrency);
+        if let Some(ref path) = config.ca_cert {
+            builder = builder.add_root_certificate(load_ca_cert(path)?);
+        }
+        let client = builder.build().context("Failed to create HTTP client")?;
+
+        Ok(Self {
+            breaker: CircuitBreaker::new(config.max_failures).with_recovery(config.recovery),
+            limiter: AdaptiveConcurrency::new(config.min_concurrency, config.concurrency),
+            config,
+            client,
+            // Google sometimes spaces out or converts the angle brackets
+            sentinel_re: Regex::new(r"[<＜«《]{1,2}\s*(\d+)\s*[>＞»》]{1,2}").unwrap(),
+            merged_batches: AtomicUsize::new(0),
+            merge_fallbacks: AtomicUsize::new(0),
+            memo: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Stop translating once `flag` is set. Batches already in flight finish; every text
+    /// not yet sent comes back as a [`Cancelled`] error, so partial results stay usable.
+    pub fn with_cancel_flag(mut self, flag: Arc<AtomicBool>) -> Self {
+        self.breaker = self.breaker.with_cancel(flag);
+        self
+    }
 
-        Ok(Self { config, client })
+    fn is_cancelled(&self) -> bool {
+        self.breaker.is_cancelled()
+    }
+
+    fn cancelled(texts: &[String]) -> Vec<Result<String>> {
+        texts.iter().map(|_| Err(Cancelled.into())).collect()
+    }
+
+    /// A client translating in the opposite direction, for back-translation checks
+    pub fn reversed(&self) -> Result<Self> {
+        let mut config = self.config.clone();
+        match config.provider {
+            MachineTranslateProvider::Google => {
+                std::mem::swap(&mut config.source_lang, &mut config.target_lang);
+            }
+            MachineTranslateProvider::DeepL => {
+                // DeepL source languages have no regional variants, and plain EN is not a target
+                let source = config
+                    .target_lang
+                    .split('-')
+                    .next()
+                    .unwrap_or("")
+                    .to_string();
+                config.target_lang = match config.source_lang.as_str() {
+                    "EN" => "EN-US".to_string(),
+                    other => other.to_string(),
+                };
+                config.source_lang = source;
+            }
+        }
+        Self::new(config)
+    }
+
+    /// Why the run stopped early, if too many requests failed in a row
+    pub fn trip_message(&self) -> Option<String> {
+        self.breaker.trip_message()
     }
 
     pub fn provider_name(&self) -> &'static str {
@@ -143,14 +390,71 @@ impl MachineTranslateClient {
     where
         F: Fn(usize) + Send + Sync,
     {
-        match self.config.provider {
+        self.translate_batch_with_sink(texts, progress_callback, None)
+    }
+
+    fn translate_batch_with_sink<F>(
+        &self,
+        texts: &[String],
+        progress_callback: Option<F>,
+        on_batch: Option<&BatchSink>,
+    ) -> Vec<Result<String>>
+    where
+        F: Fn(usize) + Send + Sync,
+    {
+        // Repeated texts and texts translated earlier in the run are only sent once;
+        // they count as done up front, like cache hits
+        let mut results: Vec<Option<Result<String>>> = {
+            let memo = self.memo.lock().unwrap();
+            texts.iter().map(|t| memo.get(t).cloned().map(Ok)).collect()
+        };
+        let pending: Vec<String> = texts
+            .iter()
+            .zip(&results)
+            .filter(|(_, r)| r.is_none())
+            .map(|(t, _)| t.clone())
+            .collect();
+        let (unique, slots) = coalesce(&pending);
+
+        if unique.is_empty() {
+            if let Some(cb) = progress_callback {
+                cb(texts.len());
+            }
+            return results.into_iter().map(Option::unwrap).collect();
+        }
+
+        let callback = wrap_callback(&progress_callback, texts.len() - unique.len());
+        let translated = match self.config.provider {
             MachineTranslateProvider::DeepL => {
-                self.translate_batch_deepl(texts, &progress_callback, 0)
+                self.translate_batch_deepl(&unique, &callback, 0, on_batch)
             }
             MachineTranslateProvider::Google => {
-                self.translate_batch_google(texts, &progress_callback, 0)
+                self.translate_batch_google(&unique, &callback, 0, on_batch)
+            }
+        };
+
+        {
+            let mut memo = self.memo.lock().unwrap();
+            for (text, result) in unique.iter().zip(&translated) {
+                if let Ok(t) = result
+                    && memo.len() < MAX_MEMO_ENTRIES
+                {
+                    memo.insert(text.clone(), t.clone());
+                }
             }
         }
+
+        let mut slots = slots.into_iter();
+        for result in results.iter_mut().filter(|r| r.is_none()) {
+            let slot = slots.next().expect("one slot per pending text");
+            *result = Some(match &translated[slot] {
+                Ok(t) => Ok(t.clone()),
+                Err(e) if e.is::<Cancelled>() => Err(Cancelled.into()),
+                Err(e) => Err(anyhow::anyhow!("{:#}", e)),
+            });
+        }
+
+        results.into_iter().map(Option::unwrap).collect()
     }
 
     pub fn translate_batch_cached<F>(
@@ -187,7 +491,12 @@ impl MachineTranslateClient {
 
         let api_calls = to_translate.len();
 
-        if to_translate.is_empty() {
+        if cache.cache_only() {
+            for (i, _) in &to_translate {
+                results[*i] = Some(Err(NotCached.into()));
+            }
+        }
+        if to_translate.is_empty() || cache.cache_only() {
             if let Some(cb) = progress_callback {
                 cb(texts.len());
             }
@@ -199,16 +508,30 @@ impl MachineTranslateClient {
         }
 
         let texts_to_translate: Vec<String> = to_translate.iter().map(|(_, t)| t.clone()).collect();
-        let translated = self.translate_batch(
-            &texts_to_translate,
-            wrap_callback(&progress_callback, cache_hits),
-        );
+        let texts_ref = &texts_to_translate;
+        let callback = wrap_callback(&progress_callback, cache_hits);
+
+        // The cache connection stays on this thread; batches are persisted as they
+        // arrive so an interrupted run keeps everything translated so far
+        let (tx, rx) = mpsc::channel::<Vec<(String, String)>>();
+        let translated = thread::scope(|s| {
+            let worker = s.spawn(move || {
+                let sink = move |pairs: Vec<(String, String)>| {
+                    let _ = tx.send(pairs);
+                };
+                self.translate_batch_with_sink(texts_ref, callback, Some(&sink))
+            });
 
-        for ((orig_idx, orig_text), result) in to_translate.into_iter().zip(translated.into_iter())
-        {
-            if let Ok(ref translated_text) = result {
-                let _ = cache.set(&orig_text, lang, provider, translated_text);
+            for pairs in rx {
+                for (source, translated_text) in pairs {
+                    let _ = cache.set(&source, lang, provider, &translated_text);
+                }
             }
+
+            worker.join().expect("translation worker panicked")
+        });
+
+        for ((orig_idx, _), result) in to_translate.into_iter().zip(translated) {
             results[orig_idx] = Some(result);
         }
 
@@ -224,18 +547,23 @@ impl MachineTranslateClient {
         texts: &[String],
         progress_callback: &Option<F>,
         progress_offset: usize,
+        on_batch: Option<&BatchSink>,
     ) -> Vec<Result<String>>
     where
         F: Fn(usize) + Send + Sync,
     {
         let counter = Arc::new(AtomicUsize::new(0));
         let callback = progress_callback;
+        let merged_before = self.merged_batches.load(Ordering::SeqCst);
+        let fallbacks_before = self.merge_fallbacks.load(Ordering::SeqCst);
+        let backoffs_before = self.limiter.backoffs();
 
         let batches: Vec<Vec<String>> = texts
             .chunks(GOOGLE_BATCH_SIZE)
             .map(|c| c.to_vec())
             .collect();
 
+        // One thread per request the limiter may ever allow; it decides how many actually send
         let pool = rayon::ThreadPoolBuilder::new()
             .num_threads(self.config.concurrency)
             .build()
@@ -245,9 +573,16 @@ impl MachineTranslateClient {
             batches
                 .par_iter()
                 .map(|batch| {
+                    if self.is_cancelled() {
+                        return Self::cancelled(batch);
+                    }
                     let result = self.translate_google_merged(batch);
                     let batch_len = batch.len();
 
+                    if let Some(sink) = on_batch {
+                        sink(Self::successful_pairs(batch, &result));
+                    }
+
                     let count = counter.fetch_add(batch_len, Ordering::SeqCst) + batch_len;
                     if let Some(cb) = callback {
                         cb(count + progress_offset);
@@ -258,9 +593,100 @@ impl MachineTranslateClient {
                 .collect()
         });
 
+        let merged = self.merged_batches.load(Ordering::SeqCst) - merged_before;
+        let fallbacks = self.merge_fallbacks.load(Ordering::SeqCst) - fallbacks_before;
+        if fallbacks > 0 {
+            eprintln!(
+                "{}",
+                format!(
+                    "[Google] {}/{} merged batches ({:.1}%) fell back to per-string requests",
+                    fallbacks,
+                    merged,
+                    fallbacks as f64 * 100.0 / merged as f64
+                )
+                .yellow()
+            );
+        }
+
+        let backoffs = self.limiter.backoffs() - backoffs_before;
+        if backoffs > 0 {
+            let (min, max) = self.limiter.bounds();
+            eprintln!(
+                "{}",
+                format!(
+                    "[Google] Rate limited {} time(s); concurrency adjusted to {} (bounds {}-{})",
+                    backoffs,
+                    self.limiter.limit(),
+                    min,
+                    max
+                )
+                .yellow()
+            );
+        }
+
         batch_results.into_iter().flatten().collect()
     }
 
+    fn merge_texts(&self, texts: &[String]) -> String {
+        match &self.config.google_delimiter {
+            GoogleDelimiter::Sentinel => texts
+                .iter()
+                .enumerate()
+                .map(|(i, t)| format!("<<{}>> {}", i + 1, t))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            GoogleDelimiter::Separator(separator) => texts.join(separator),
+        }
+    }
+
+    /// Split a merged translation back into `count` parts, or `None` if the delimiters were lost
+    fn split_merged(&self, translated: &str, count: usize) -> Option<Vec<String>> {
+        match &self.config.google_delimiter {
+            GoogleDelimiter::Sentinel => {
+                let markers: Vec<_> = self.sentinel_re.captures_iter(translated).collect();
+                if markers.len() != count {
+                    return None;
+                }
+
+                let mut parts = Vec::with_capacity(count);
+                for (i, caps) in markers.iter().enumerate() {
+                    let number: usize = caps[1].parse().ok()?;
+                    if number != i + 1 {
+                        return None;
+                    }
+                    let start = caps.get(0)?.end();
+                    let end = markers
+                        .get(i + 1)
+                        .and_then(|next| next.get(0))
+                        .map(|m| m.start())
+                        .unwrap_or(translated.len());
+                    parts.push(translated[start..end].trim().to_string());
+                }
+                Some(parts)
+            }
+            GoogleDelimiter::Separator(separator) => {
+                let parts: Vec<String> = translated
+                    .split(separator.as_str())
+                    .map(|s| s.trim().to_string())
+                    .collect();
+                (parts.len() == count).then_some(parts)
+            }
+        }
+    }
+
+    fn successful_pairs(batch: &[String], results: &[Result<String>]) -> Vec<(String, String)> {
+        batch
+            .iter()
+            .zip(results)
+            .filter_map(|(source, result)| {
+                result
+                    .as_ref()
+                    .ok()
+                    .map(|translated| (source.clone(), translated.clone()))
+            })
+            .collect()
+    }
+
     fn translate_google_merged(&self, texts: &[String]) -> Vec<Result<String>> {
         if texts.is_empty() {
             return vec![];
@@ -269,19 +695,16 @@ impl MachineTranslateClient {
             return vec![self.translate_google(&texts[0])];
         }
 
-        let merged = texts.join(GOOGLE_SEPARATOR);
+        let merged = self.merge_texts(texts);
+        self.merged_batches.fetch_add(1, Ordering::SeqCst);
         match self.translate_google(&merged) {
-            Ok(translated) => {
-                let parts: Vec<&str> = translated.split(GOOGLE_SEPARATOR).collect();
-                if parts.len() == texts.len() {
-                    parts
-                        .into_iter()
-                        .map(|s| Ok(s.trim().to_string()))
-                        .collect()
-                } else {
+            Ok(translated) => match self.split_merged(&translated, texts.len()) {
+                Some(parts) => parts.into_iter().map(Ok).collect(),
+                None => {
+                    self.merge_fallbacks.fetch_add(1, Ordering::SeqCst);
                     texts.iter().map(|t| self.translate_google(t)).collect()
                 }
-            }
+            },
             Err(e) => texts
                 .iter()
                 .map(|_| Err(anyhow::anyhow!("Batch failed: {}", e)))
@@ -294,6 +717,7 @@ impl MachineTranslateClient {
         texts: &[String],
         progress_callback: &Option<F>,
         progress_offset: usize,
+        on_batch: Option<&BatchSink>,
     ) -> Vec<Result<String>>
     where
         F: Fn(usize) + Send + Sync,
@@ -308,21 +732,30 @@ impl MachineTranslateClient {
             }
         };
 
-        let base_url = if api_key.ends_with(":fx") {
-            "https://api-free.deepl.com/v2"
-        } else {
-            "https://api.deepl.com/v2"
-        };
-
-        let url = format!("{}/translate", base_url);
+        let url = format!("{}/translate", Self::deepl_base_url(api_key));
         let mut all_results = Vec::with_capacity(texts.len());
         let mut processed = 0;
 
-        for chunk in texts.chunks(DEEPL_BATCH_SIZE) {
+        for chunk in
+            Self::deepl_chunks(texts, self.config.deepl_batch_size, DEEPL_MAX_REQUEST_BYTES)
+        {
+            if self.is_cancelled() {
+                all_results.extend(Self::cancelled(chunk));
+                continue;
+            }
             let result = self.translate_deepl_batch_request(&url, api_key, chunk);
 
             match result {
                 Ok(translations) => {
+                    if let Some(sink) = on_batch {
+                        sink(
+                            chunk
+                                .iter()
+                                .cloned()
+                                .zip(translations.iter().cloned())
+                                .collect(),
+                        );
+                    }
                     for t in translations {
                         all_results.push(Ok(t));
                         processed += 1;
@@ -346,30 +779,158 @@ impl MachineTranslateClient {
         all_results
     }
 
+    /// Split texts into requests limited by both item count and total size
+    fn deepl_chunks(texts: &[String], max_items: usize, max_bytes: usize) -> Vec<&[String]> {
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut bytes = 0;
+
+        for (i, text) in texts.iter().enumerate() {
+            let full = i - start >= max_items || (i > start && bytes + text.len() > max_bytes);
+            if full {
+                chunks.push(&texts[start..i]);
+                start = i;
+                bytes = 0;
+            }
+            bytes += text.len();
+        }
+        if start < texts.len() {
+            chunks.push(&texts[start..]);
+        }
+
+        chunks
+    }
+
+    fn deepl_base_url(api_key: &str) -> &'static str {
+        if api_key.ends_with(":fx") {
+            "https://api-free.deepl.com/v2"
+        } else {
+            "https://api.deepl.com/v2"
+        }
+    }
+
+    /// Query the character quota of the configured DeepL account
+    pub fn deepl_usage(&self) -> Result<DeepLUsage> {
+        if self.config.provider != MachineTranslateProvider::DeepL {
+            anyhow::bail!("Usage is only available for DeepL");
+        }
+        let api_key = self
+            .config
+            .api_key
+            .as_ref()
+            .context("DeepL API key is required")?;
+
+        let url = format!("{}/usage", Self::deepl_base_url(api_key));
+        let response = self
+            .client
+            .get(&url)
+            .header("Authorization", format!("DeepL-Auth-Key {}", api_key))
+            .send()
+            .context("Failed to send usage request to DeepL")?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().unwrap_or_default();
+            anyhow::bail!("DeepL usage request failed ({}): {}", status, body);
+        }
+
+        response
+            .json()
+            .context("Failed to parse DeepL usage response")
+    }
+
+    /// Characters that would be sent to the API, skipping cached and blank texts
+    pub fn uncached_chars(&self, texts: &[String], cache: Option<&TranslationCache>) -> usize {
+        texts
+            .iter()
+            .filter(|t| !t.trim().is_empty())
+            .filter(|t| {
+                cache
+                    .map(|c| {
+                        c.get(t, &self.config.target_lang, self.provider_name())
+                            .is_none()
+                    })
+                    .unwrap_or(true)
+            })
+            .map(|t| t.chars().count())
+            .sum()
+    }
+
+    /// Texts `translate_batch_cached` will answer without a request
+    pub fn cached_count(&self, texts: &[String], cache: &TranslationCache) -> usize {
+        texts
+            .iter()
+            .filter(|t| {
+                t.trim().is_empty()
+                    || cache
+                        .get(t, &self.config.target_lang, self.provider_name())
+                        .is_some()
+            })
+            .count()
+    }
+
+    /// Print the remaining DeepL quota and warn if the job is likely to exceed it
+    pub fn check_deepl_quota(&self, texts: &[String], cache: Option<&TranslationCache>) {
+        if self.config.provider != MachineTranslateProvider::DeepL {
+            return;
+        }
+
+        let usage = match self.deepl_usage() {
+            Ok(u) => u,
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    format!("[WARN] Could not check DeepL quota: {}", e).yellow()
+                );
+                return;
+            }
+        };
+
+        let needed = self.uncached_chars(texts, cache) as u64;
+        println!(
+            "  DeepL quota: {} of {} characters remaining, job needs ~{}",
+            usage.remaining(),
+            usage.character_limit,
+            needed
+        );
+
+        if needed > usage.remaining() {
+            eprintln!(
+                "{}",
+                format!(
+                    "[WARN] Job needs ~{} characters but only {} remain; translation may fail partway",
+                    needed,
+                    usage.remaining()
+                )
+                .yellow()
+            );
+        }
+    }
+
     fn translate_deepl_batch_request(
         &self,
         url: &str,
         api_key: &str,
         texts: &[String],
     ) -> Result<Vec<String>> {
-        let mut last_error = None;
-
-        for attempt in 0..MAX_RETRIES {
-            if attempt > 0 {
-                let delay = BASE_RETRY_DELAY_MS * 2u64.pow(attempt - 1);
-                thread::sleep(Duration::from_millis(delay));
-            }
+        self.breaker
+            .run(|| Self::with_retries(|| self.do_deepl_batch_request(url, api_key, texts)))
+    }
 
-            match self.do_deepl_batch_request(url, api_key, texts) {
+    /// Retry a request with exponential backoff, returning the last error if all attempts fail
+    fn with_retries<T>(mut request: impl FnMut() -> Result<T>) -> Result<T> {
+        let mut attempt = 1;
+        loop {
+            match request() {
                 Ok(result) => return Ok(result),
-                Err(e) => {
-                    last_error = Some(e);
-                    continue;
+                Err(e) if attempt >= MAX_RETRIES => return Err(e),
+                Err(_) => {
+                    let delay = BASE_RETRY_DELAY_MS * 2u64.pow(attempt - 1);
+                    thread::sleep(Duration::from_millis(delay));
+                    attempt += 1;
                 }
             }
         }
-
-        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("DeepL batch request failed")))
     }
 
     fn do_deepl_batch_request(
@@ -415,62 +976,53 @@ impl MachineTranslateClient {
             urlencoding::encode(&protected)
         );
 
-        let mut last_error = None;
+        let result = self
+            .breaker
+            .run(|| Self::with_retries(|| self.do_google_request(&url)))?;
 
-        for attempt in 0..MAX_RETRIES {
-            if attempt > 0 {
-                let delay = BASE_RETRY_DELAY_MS * 2u64.pow(attempt - 1);
-                thread::sleep(Duration::from_millis(delay));
-            }
-
-            match self.do_google_request(&url) {
-                Ok(result) => {
-                    return Ok(Self::restore_formatting(&result, &placeholders));
-                }
-                Err(e) => {
-                    last_error = Some(e);
-                    continue;
-                }
-            }
-        }
-
-        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("Translation failed")))
+        Ok(Self::restore_formatting(&result, &placeholders))
     }
 
     fn protect_formatting(text: &str) -> (String, Vec<(String, String)>) {
         let mut protected = text.to_string();
-        let mut placeholders = Vec::new();
-
-        // Patterns to protect: \n, \t, [variables], {tags}, %(format)
-        let patterns = [
-            (r"\\n", "⟦NL⟧"),
-            (r"\\t", "⟦TB⟧"),
-            (r"\[([^\]]+)\]", "⟦VAR$1⟧"),
-            (r"\{([^}]+)\}", "⟦TAG$1⟧"),
-            (r"%\(([^)]+)\)s", "⟦FMT$1⟧"),
+        let mut placeholders: Vec<(String, String)> = Vec::new();
+
+        // Patterns to protect: %%, \n, \t, [variables], {tags}, %(format)
+        // `%%` goes first so a literal percent is never read as part of a format spec.
+        // Ren'Py's `[[`, `{{` and `}}` escapes share a pass with `[variables]` and `{tags}`,
+        // so whichever starts first wins, the same way Ren'Py reads them
+        type Placeholder = fn(&Captures) -> String;
+        let rules: [(&str, Placeholder); 5] = [
+            (r"%%", |_| "⟦PCT⟧".to_string()),
+            (r"\\n", |_| "⟦NL⟧".to_string()),
+            (r"\\t", |_| "⟦TB⟧".to_string()),
+            (r"\[\[|\{\{|\}\}|\[([^\]]+)\]|\{([^}]+)\}", |cap| {
+                match (cap.get(1), cap.get(2)) {
+                    (Some(var), _) => format!("⟦VAR{}⟧", var.as_str()),
+                    (_, Some(tag)) => format!("⟦TAG{}⟧", tag.as_str()),
+                    _ => match &cap[0] {
+                        "[[" => "⟦LSB⟧",
+                        "{{" => "⟦LCB⟧",
+                        _ => "⟦RCB⟧",
+                    }
+                    .to_string(),
+                }
+            }),
+            (r"%\(([^)]+)\)s", |cap| format!("⟦FMT{}⟧", &cap[1])),
         ];
 
-        for (pattern, prefix) in patterns {
+        for (pattern, placeholder_for) in rules {
             let re = Regex::new(pattern).unwrap();
-            for cap in re.captures_iter(text) {
-                let original = cap.get(0).unwrap().as_str().to_string();
-                let placeholder = if cap.len() > 1 {
-                    format!(
-                        "{}{}",
-                        prefix.replace("$1", cap.get(1).unwrap().as_str()),
-                        ""
-                    )
-                } else {
-                    prefix.to_string()
-                };
-                if !placeholders.iter().any(|(o, _)| o == &original) {
-                    placeholders.push((original.clone(), placeholder.clone()));
-                }
-            }
-        }
-
-        for (original, placeholder) in &placeholders {
-            protected = protected.replace(original, placeholder);
+            protected = re
+                .replace_all(&protected, |cap: &Captures| {
+                    let original = cap[0].to_string();
+                    let placeholder = placeholder_for(cap);
+                    if !placeholders.iter().any(|(o, _)| o == &original) {
+                        placeholders.push((original, placeholder.clone()));
+                    }
+                    placeholder
+                })
+                .into_owned();
         }
 
         (protected, placeholders)
@@ -478,7 +1030,8 @@ impl MachineTranslateClient {
 
     fn restore_formatting(text: &str, placeholders: &[(String, String)]) -> String {
         let mut restored = text.to_string();
-        for (original, placeholder) in placeholders {
+        // Later placeholders can wrap earlier ones (`[a\nb]`), so unwrap them first
+        for (original, placeholder) in placeholders.iter().rev() {
             restored = restored.replace(placeholder, original);
         }
         // Also restore common mistranslations
@@ -488,6 +1041,7 @@ impl MachineTranslateClient {
     }
 
     fn do_google_request(&self, url: &str) -> Result<String> {
+        let permit = self.limiter.acquire();
         let response = self
             .client
             .get(url)
@@ -495,24 +1049,34 @@ impl MachineTranslateClient {
             .send()
             .context("Failed to send request to Google Translate")?;
 
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            permit.rate_limited();
+            return Err(RateLimited.into());
+        }
         if !response.status().is_success() {
             let status = response.status();
             anyhow::bail!("Google Translate request failed: {}", status);
         }
 
-        let body = response.text().context("Failed to read response")?;
+        let parsed: serde_json::Value = serde_json::from_reader(response)
+            .context("Failed to parse Google Translate response")?;
+        permit.succeeded();
 
-        let parsed: serde_json::Value =
-            serde_json::from_str(&body).context("Failed to parse Google Translate response")?;
+        Self::parse_google_response(&parsed)
+    }
 
-        let mut result = String::new();
-        if let Some(outer) = parsed.get(0).and_then(|v| v.as_array()) {
-            for item in outer {
-                if let Some(translated) = item.get(0).and_then(|v| v.as_str()) {
-                    result.push_str(translated);
-                }
-            }
-        }
+    /// Join the translated segments of a Google response
+    ///
+    /// Long inputs come back split into one `[translated, original, ...]` row per
+    /// sentence; a trailing transliteration row has no translated text and is skipped.
+    fn parse_google_response(parsed: &serde_json::Value) -> Result<String> {
+        let result: String = parsed
+            .get(0)
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|segment| segment.get(0).and_then(|v| v.as_str()))
+            .collect();
 
         if result.is_empty() {
             anyhow::bail!("No translation result from Google");
@@ -521,3 +1085,164 @@ impl MachineTranslateClient {
         Ok(result)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_successful_pairs() {
+        let batch: Vec<String> = ["Hello", "Bye", "Wait"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let results = vec![
+            Ok("你好".to_string()),
+            Err(anyhow::anyhow!("HTTP 500")),
+            Ok("等等".to_string()),
+        ];
+        // Failed texts are left out, so the next run asks for them again
+        assert_eq!(
+            MachineTranslateClient::successful_pairs(&batch, &results),
+            [
+                ("Hello".to_string(), "你好".to_string()),
+                ("Wait".to_string(), "等等".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_coalesce_repeated_texts() {
+        let texts: Vec<String> = ["Yes", "No", "Yes", "...", "No", "Yes"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let (unique, slots) = coalesce(&texts);
+        assert_eq!(unique, ["Yes", "No", "..."]);
+        assert_eq!(slots, [0, 1, 0, 2, 1, 0]);
+    }
+
+    #[test]
+    fn test_cancelled_before_first_batch() {
+        let flag = Arc::new(AtomicBool::new(true));
+        let texts: Vec<String> = (0..45).map(|i| format!("Line {}", i)).collect();
+
+        for config in [
+            MachineTranslateConfig::google("ja"),
+            MachineTranslateConfig::deepl("ja", "key:fx".to_string()),
+        ] {
+            let client = MachineTranslateClient::new(config)
+                .unwrap()
+                .with_cancel_flag(flag.clone());
+            let results = client.translate_batch(&texts, None::<fn(usize)>);
+            assert_eq!(results.len(), texts.len());
+            assert!(
+                results
+                    .iter()
+                    .all(|r| r.as_ref().is_err_and(|e| e.is::<Cancelled>()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_google_multi_segment_response() {
+        let parsed: serde_json::Value = serde_json::from_str(
+            r#"[[["你好。","Hello.",null,null,10],["今天天气很好。 ","The weather is nice today. ",null,null,10],["我们走吧。","Let's go.",null,null,10],[null,null,"Nǐ hǎo."]],null,"en",null,null,null,1.0,[],[["en"],null,[1.0],["en"]]]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            MachineTranslateClient::parse_google_response(&parsed).unwrap(),
+            "你好。今天天气很好。 我们走吧。"
+        );
+
+        let empty: serde_json::Value = serde_json::from_str(r#"[null,null,"en"]"#).unwrap();
+        assert!(MachineTranslateClient::parse_google_response(&empty).is_err());
+    }
+
+    #[test]
+    fn test_literal_percent_protected() {
+        let text = "100%% complete, %(name)s! %%(not_a_var)s";
+        let (protected, placeholders) = MachineTranslateClient::protect_formatting(text);
+        assert_eq!(protected, "100⟦PCT⟧ complete, ⟦FMTname⟧! ⟦PCT⟧(not_a_var)s");
+        assert_eq!(
+            MachineTranslateClient::restore_formatting(&protected, &placeholders),
+            text
+        );
+    }
+
+    #[test]
+    fn test_escaped_brackets_and_braces_protected() {
+        let text = "Press [[A] to continue";
+        let (protected, placeholders) = MachineTranslateClient::protect_formatting(text);
+        assert_eq!(protected, "Press ⟦LSB⟧A] to continue");
+        assert_eq!(
+            MachineTranslateClient::restore_formatting(&protected, &placeholders),
+            text
+        );
+
+        let text = "Use {{braces}}, not {b}[name]{/b}}}";
+        let (protected, placeholders) = MachineTranslateClient::protect_formatting(text);
+        assert_eq!(
+            protected,
+            "Use ⟦LCB⟧braces⟦RCB⟧, not ⟦TAGb⟧⟦VARname⟧⟦TAG/b⟧⟦RCB⟧"
+        );
+        assert_eq!(
+            MachineTranslateClient::restore_formatting(&protected, &placeholders),
+            text
+        );
+
+        // An escape followed by a real variable: `[[[name]` is `[` then `[name]`
+        let (protected, _) = MachineTranslateClient::protect_formatting("[[[name]");
+        assert_eq!(protected, "⟦LSB⟧⟦VARname⟧");
+    }
+
+    #[test]
+    fn test_deepl_chunks() {
+        let texts: Vec<String> = ["a", "bb", "ccc", "dddd", "e"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+
+        let sizes = |chunks: Vec<&[String]>| chunks.iter().map(|c| c.len()).collect::<Vec<_>>();
+        assert_eq!(
+            sizes(MachineTranslateClient::deepl_chunks(&texts, 2, 100)),
+            [2, 2, 1]
+        );
+        assert_eq!(
+            sizes(MachineTranslateClient::deepl_chunks(&texts, 50, 5)),
+            [2, 1, 2]
+        );
+        // A single oversized text still goes out on its own
+        assert_eq!(
+            sizes(MachineTranslateClient::deepl_chunks(&texts, 50, 2)),
+            [1, 1, 1, 1, 1]
+        );
+    }
+
+    #[test]
+    fn test_split_merged_sentinels() {
+        let client = MachineTranslateClient::new(MachineTranslateConfig::google("zh-CN")).unwrap();
+        let texts = vec!["Hello.".to_string(), "Good night.".to_string()];
+        assert_eq!(
+            client.merge_texts(&texts),
+            "<<1>> Hello.\n<<2>> Good night."
+        );
+
+        // Spacing and bracket style changes are tolerated
+        let parts = client
+            .split_merged("<< 1 >> 你好。 《2》晚安。", 2)
+            .unwrap();
+        assert_eq!(parts, ["你好。", "晚安。"]);
+
+        assert_eq!(client.split_merged("<<1>> 你好。晚安。", 2), None);
+        assert_eq!(client.split_merged("<<2>> 晚安。<<1>> 你好。", 2), None);
+
+        let config =
+            MachineTranslateConfig::google("zh-CN").with_google_separator(Some(" ||| ".into()));
+        let client = MachineTranslateClient::new(config).unwrap();
+        assert_eq!(
+            client.split_merged("你好。 ||| 晚安。", 2).unwrap(),
+            ["你好。", "晚安。"]
+        );
+    }
+}
@@ -0,0 +1,190 @@
+//! Per-project translation manifest, independent of the translation provider
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::renpy_tl::{DialogueEntry, StringEntry};
+
+/// Source text -> translation (`null` while still untranslated)
+type Strings = BTreeMap<String, Option<String>>;
+
+/// Source strings seen in previous runs, with the translation each one ended up with
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TranslationManifest {
+    /// Strings kept apart per target language, so one language never fills in another
+    #[serde(default)]
+    pub languages: BTreeMap<String, Strings>,
+    /// Flat map written before manifests were kept per language
+    #[serde(default, skip_serializing)]
+    strings: Strings,
+    /// Language this run reads and updates
+    #[serde(skip)]
+    lang: String,
+}
+
+impl TranslationManifest {
+    /// Load a manifest for `lang`, starting empty when the file does not exist yet
+    pub fn load<P: AsRef<Path>>(path: P, lang: &str) -> Result<Self> {
+        let path = path.as_ref();
+        let mut manifest = if path.exists() {
+            let content = fs::read_to_string(path).context("Failed to read manifest")?;
+            serde_json::from_str(&content).context("Failed to parse manifest")?
+        } else {
+            Self::default()
+        };
+        manifest.adopt_legacy(lang);
+        Ok(manifest)
+    }
+
+    /// Read and update the strings of `lang` from now on
+    fn adopt_legacy(&mut self, lang: &str) {
+        self.lang = lang.to_string();
+        if self.strings.is_empty() {
+            return;
+        }
+        println!(
+            "{}",
+            format!(
+                "[WARN] Manifest predates per-language entries; filing its {} string(s) under {}",
+                self.strings.len(),
+                lang
+            )
+            .yellow()
+        );
+        let target = self.languages.entry(self.lang.clone()).or_default();
+        for (source, translated) in std::mem::take(&mut self.strings) {
+            target.entry(source).or_insert(translated);
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        fs::write(path.as_ref(), content + "\n").context("Failed to write manifest")
+    }
+
+    /// Strings known for this run's language
+    pub fn len(&self) -> usize {
+        self.languages.get(&self.lang).map_or(0, BTreeMap::len)
+    }
+
+    fn translation(&self, source: &str) -> Option<&String> {
+        self.languages.get(&self.lang)?.get(source)?.as_ref()
+    }
+
+    /// Carry over prior translations by source text, returning how many entries were filled
+    pub fn apply(
+        &self,
+        dialogues: &mut HashMap<PathBuf, Vec<DialogueEntry>>,
+        strings: &mut [StringEntry],
+    ) -> usize {
+        let mut carried = 0;
+
+        for entry in dialogues.values_mut().flatten() {
+            if entry.translated_text.is_none()
+                && let Some(translated) = self.translation(&entry.original_text)
+            {
+                entry.translated_text = Some(translated.clone());
+                carried += 1;
+            }
+        }
+
+        for entry in strings.iter_mut() {
+            if entry.translated.is_none()
+                && let Some(translated) = self.translation(&entry.original)
+            {
+                entry.translated = Some(translated.clone());
+                carried += 1;
+            }
+        }
+
+        carried
+    }
+
+    /// Record the outcome of a run; strings from earlier episodes are kept
+    pub fn update(
+        &mut self,
+        dialogues: &HashMap<PathBuf, Vec<DialogueEntry>>,
+        strings: &[StringEntry],
+    ) {
+        let entries = dialogues
+            .values()
+            .flatten()
+            .map(|e| (&e.original_text, &e.translated_text))
+            .chain(strings.iter().map(|s| (&s.original, &s.translated)));
+
+        let known = self.languages.entry(self.lang.clone()).or_default();
+        for (original, translated) in entries {
+            let slot = known.entry(original.clone()).or_default();
+            if translated.is_some() {
+                *slot = translated.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string(original: &str) -> StringEntry {
+        StringEntry {
+            original: original.to_string(),
+            translated: None,
+            context: None,
+            variable: None,
+            layout: false,
+        }
+    }
+
+    #[test]
+    fn test_manifest_carry_over() {
+        let mut manifest = TranslationManifest::default();
+        manifest.adopt_legacy("chinese");
+        let chinese = manifest.languages.entry("chinese".to_string()).or_default();
+        chinese.insert("Start".to_string(), Some("开始".to_string()));
+        chinese.insert("Old episode".to_string(), None);
+
+        let mut dialogues = HashMap::new();
+        let mut strings = vec![string("Start"), string("Quit")];
+        assert_eq!(manifest.apply(&mut dialogues, &mut strings), 1);
+        assert_eq!(strings[0].translated.as_deref(), Some("开始"));
+        assert_eq!(strings[1].translated, None);
+
+        strings[1].translated = Some("退出".to_string());
+        manifest.update(&dialogues, &strings);
+        let chinese = &manifest.languages["chinese"];
+        assert_eq!(chinese["Quit"].as_deref(), Some("退出"));
+        assert!(chinese.contains_key("Old episode"));
+    }
+
+    #[test]
+    fn test_manifest_languages_kept_apart() {
+        let mut manifest: TranslationManifest =
+            serde_json::from_str(r#"{"strings": {"Start": "开始"}}"#).unwrap();
+        manifest.adopt_legacy("chinese");
+        assert_eq!(manifest.len(), 1);
+
+        // Another language sees none of the Chinese strings
+        let json = serde_json::to_string(&manifest).unwrap();
+        let mut manifest: TranslationManifest = serde_json::from_str(&json).unwrap();
+        manifest.adopt_legacy("japanese");
+        assert_eq!(manifest.len(), 0);
+        let mut strings = vec![string("Start")];
+        assert_eq!(manifest.apply(&mut HashMap::new(), &mut strings), 0);
+
+        strings[0].translated = Some("スタート".to_string());
+        manifest.update(&HashMap::new(), &strings);
+        assert_eq!(
+            manifest.languages["chinese"]["Start"].as_deref(),
+            Some("开始")
+        );
+        assert_eq!(
+            manifest.languages["japanese"]["Start"].as_deref(),
+            Some("スタート")
+        );
+    }
+}
@@ -0,0 +1,136 @@
+//! Post-translation validation of Ren'Py text tags and interpolations
+
+/// Compare the `{tags}` and `[variables]` of a translation against its source,
+/// returning a description of each mismatch (empty when the markup is intact)
+pub fn check_markup(source: &str, translated: &str) -> Vec<String> {
+    let mut issues = Vec::new();
+
+    let (source_tags, source_vars) = scan(source);
+    let (translated_tags, translated_vars) = scan(translated);
+
+    if sorted(&source_tags) != sorted(&translated_tags) {
+        issues.push(format!(
+            "tags differ: expected {}, got {}",
+            source_tags.concat(),
+            translated_tags.concat()
+        ));
+    }
+
+    if sorted(&source_vars) != sorted(&translated_vars) {
+        issues.push(format!(
+            "variables differ: expected {}, got {}",
+            source_vars.concat(),
+            translated_vars.concat()
+        ));
+    }
+
+    if let Some(problem) = check_balance(&source_tags, &translated_tags) {
+        issues.push(problem);
+    }
+
+    issues
+}
+
+/// Split text into its `{...}` tags and `[...]` interpolations, skipping `{{` and `[[` escapes
+fn scan(text: &str) -> (Vec<String>, Vec<String>) {
+    let mut tags = Vec::new();
+    let mut vars = Vec::new();
+    let chars: Vec<char> = text.chars().collect();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (open, close) = match chars[i] {
+            '{' => ('{', '}'),
+            '[' => ('[', ']'),
+            _ => {
+                i += 1;
+                continue;
+            }
+        };
+
+        if chars.get(i + 1) == Some(&open) {
+            i += 2;
+            continue;
+        }
+
+        match chars[i..].iter().position(|&c| c == close) {
+            Some(len) => {
+                let token: String = chars[i..=i + len].iter().collect();
+                if open == '{' {
+                    tags.push(token);
+                } else {
+                    vars.push(token);
+                }
+                i += len + 1;
+            }
+            None => break,
+        }
+    }
+
+    (tags, vars)
+}
+
+fn sorted(items: &[String]) -> Vec<&str> {
+    let mut items: Vec<&str> = items.iter().map(|s| s.as_str()).collect();
+    items.sort_unstable();
+    items
+}
+
+fn tag_name(tag: &str) -> &str {
+    let inner = tag.trim_start_matches('{').trim_end_matches('}');
+    let inner = inner.trim_start_matches('/');
+    inner.split('=').next().unwrap_or(inner)
+}
+
+/// Closing tags must match the innermost open tag of the same kind
+fn check_balance(source_tags: &[String], translated_tags: &[String]) -> Option<String> {
+    // Only tags closed somewhere in the source are paired; others ({w}, {p}, ...) stand alone
+    let paired: Vec<&str> = source_tags
+        .iter()
+        .filter(|t| t.starts_with("{/"))
+        .map(|t| tag_name(t))
+        .collect();
+
+    let mut stack: Vec<&str> = Vec::new();
+    for tag in translated_tags {
+        let name = tag_name(tag);
+        if tag.starts_with("{/") {
+            if stack.pop() != Some(name) {
+                return Some(format!("unbalanced closing tag {}", tag));
+            }
+        } else if paired.contains(&name) {
+            stack.push(name);
+        }
+    }
+
+    if let Some(name) = stack.last() {
+        return Some(format!("unclosed tag {{{}}}", name));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intact_markup() {
+        let issues = check_markup("{b}Hello{/b}, [player]!{w}", "{b}你好{/b}，[player]！{w}");
+        assert!(issues.is_empty(), "{:?}", issues);
+    }
+
+    #[test]
+    fn test_escapes_ignored() {
+        assert!(check_markup("{{not a tag}} [[nor this]", "no tags").is_empty());
+    }
+
+    #[test]
+    fn test_dropped_and_unbalanced() {
+        assert_eq!(check_markup("{i}Hi{/i} [name]", "{i}你好 [name]").len(), 2);
+        assert_eq!(check_markup("Hi [name]", "你好 [名字]").len(), 1);
+
+        let issues = check_markup("{b}{i}Hi{/i}{/b}", "{b}{i}你好{/b}{/i}");
+        assert_eq!(issues, ["unbalanced closing tag {/b}"]);
+    }
+}
@@ -1,23 +1,77 @@
+pub mod analyze;
+pub mod back_check;
 pub mod cache;
+pub mod circuit;
+pub mod concurrency;
 pub mod extractor;
 pub mod glossary;
+pub mod lang_detect;
 pub mod llm;
 pub mod machine_translate;
+pub mod manifest;
+pub mod markup;
 pub mod renpy_tl;
+pub mod story;
 
 use anyhow::{Context, Result};
 use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
-use std::collections::HashMap;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
 use walkdir::WalkDir;
 
-use crate::cli::TranslateArgs;
+use crate::cli::{TranslateAction, TranslateArgs};
 use crate::config::Config;
-use extractor::{TextExtractor, TranslatableEntry};
-use llm::{LlmClient, LlmConfig, LlmProvider};
-use machine_translate::{MachineTranslateClient, MachineTranslateConfig};
+use crate::utils::{FileFilter, escape_control_chars};
+use cache::TranslationCache;
+use circuit::Recovery;
+use extractor::{EntryType, SkipReason, SkippedLine, TextExtractor, TranslatableEntry};
+use llm::{ChineseScript, LlmClient, LlmConfig, LlmProvider};
+use machine_translate::{MachineTranslateClient, MachineTranslateConfig, NotCached};
+use renpy_tl::RenpyTranslationGenerator;
+
+/// How generated files are arranged under an output directory (`--target-dir-layout`)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputLayout {
+    /// `tl/<lang>/<file>`, where Ren'Py looks for translations
+    Renpy,
+    /// The input's directory tree, reproduced as-is
+    Mirror,
+    /// Every file directly in the output directory
+    Flat,
+}
+
+impl OutputLayout {
+    pub fn from_str(s: &str) -> Self {
+        match s {
+            "renpy" => Self::Renpy,
+            "flat" => Self::Flat,
+            _ => Self::Mirror,
+        }
+    }
+
+    /// The directory files are placed in, and where shared files such as `strings.rpy` go
+    pub fn root(self, base: &Path, lang: &str) -> PathBuf {
+        match self {
+            Self::Renpy => base.join("tl").join(lang),
+            Self::Mirror | Self::Flat => base.to_path_buf(),
+        }
+    }
+
+    /// Output path for a script at `rel` (relative to the input directory)
+    pub fn path_for(self, base: &Path, lang: &str, rel: &Path) -> PathBuf {
+        let root = self.root(base, lang);
+        match self {
+            Self::Mirror => root.join(rel),
+            Self::Renpy | Self::Flat => root.join(rel.file_name().unwrap_or_default()),
+        }
+    }
+}
 
 pub enum TranslateClient {
     Llm(LlmClient),
@@ -25,34 +79,205 @@ pub enum TranslateClient {
 }
 
 impl TranslateClient {
+    /// `layout_sensitive` marks texts (by index) whose translation must fit the UI
     pub fn translate_batch<F>(
         &self,
         texts: &[String],
+        layout_sensitive: &[bool],
+        cache: Option<&TranslationCache>,
         progress_callback: Option<F>,
     ) -> Vec<Result<String>>
     where
         F: Fn(usize) + Send + Sync,
     {
         match self {
-            Self::Machine(client) => client.translate_batch(texts, progress_callback),
-            Self::Llm(client) => texts
-                .iter()
-                .enumerate()
-                .map(|(i, t)| {
-                    let result = client.translate(t, None);
-                    if let Some(ref cb) = progress_callback {
-                        cb(i + 1);
-                    }
-                    result
-                })
-                .collect(),
+            Self::Machine(client) => match cache {
+                Some(cache) => {
+                    client
+                        .translate_batch_cached(texts, cache, progress_callback)
+                        .translations
+                }
+                None => client.translate_batch(texts, progress_callback),
+            },
+            Self::Llm(client) => client.translate_all(texts, layout_sensitive, progress_callback),
+        }
+    }
+
+    /// Why the run stopped early, if too many requests failed in a row
+    pub fn trip_message(&self) -> Option<String> {
+        match self {
+            Self::Machine(client) => client.trip_message(),
+            Self::Llm(client) => client.trip_message(),
         }
     }
 }
 
-pub fn run(args: TranslateArgs) -> Result<()> {
-    // Load config
-    let cfg = Config::load().unwrap_or_default();
+/// Print the prompts an LLM would receive for the first `count` texts, without calling it
+///
+/// `layout_sensitive` marks texts (by index) that are sent with a length hint.
+pub fn print_prompt_preview(
+    client: &LlmClient,
+    texts: &[String],
+    layout_sensitive: &[bool],
+    count: usize,
+) {
+    println!("{}", "[Dry run] No requests will be sent".cyan());
+    println!("  {}", client.describe());
+
+    let sample = &texts[..count.min(texts.len())];
+    for (i, text) in sample.iter().enumerate() {
+        println!();
+        println!(
+            "{}",
+            format!("--- Prompt {}/{} ---", i + 1, sample.len()).cyan()
+        );
+        let layout = layout_sensitive.get(i).copied().unwrap_or(false);
+        println!("{}", client.render_prompt(text, None, layout));
+    }
+}
+
+/// Outage handling from `--wait-for-recovery`/`--recovery-poll`, falling back to the config file
+pub fn recovery(wait: Option<u64>, poll: Option<u64>, cfg: &Config) -> Option<Recovery> {
+    let minutes = wait.or(cfg.translation.wait_for_recovery)?;
+    let poll = poll
+        .or(cfg.translation.recovery_poll)
+        .unwrap_or(circuit::DEFAULT_RECOVERY_POLL_SECS);
+    Some(Recovery {
+        max_wait: Duration::from_secs(minutes * 60),
+        poll_interval: Duration::from_secs(poll.max(1)),
+    })
+}
+
+/// Open the translation cache, replacing a corrupt database and saying why caching is off otherwise
+pub fn open_cache() -> Option<TranslationCache> {
+    match TranslationCache::open_recovering() {
+        Ok((cache, None)) => Some(cache),
+        Ok((cache, Some(backup))) => {
+            println!(
+                "{}",
+                format!(
+                    "[WARN] Translation cache was corrupt and has been recreated; the old file is at {}",
+                    backup.display()
+                )
+                .yellow()
+            );
+            Some(cache)
+        }
+        Err(e) => {
+            println!(
+                "{}",
+                format!(
+                    "[WARN] Translation cache unavailable, continuing without it: {:#}",
+                    e
+                )
+                .yellow()
+            );
+            None
+        }
+    }
+}
+
+/// Load `--context-file` within the configured token budget
+pub fn load_context(path: Option<&Path>, cfg: &Config) -> Result<Option<String>> {
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let max_tokens = cfg
+        .translation
+        .context_max_tokens
+        .unwrap_or(llm::DEFAULT_CONTEXT_MAX_TOKENS);
+    let (context, truncated) = llm::load_context_file(path, max_tokens)?;
+    if truncated {
+        println!(
+            "{}",
+            format!(
+                "[WARN] Context file exceeds about {} tokens, only the leading part is used",
+                max_tokens
+            )
+            .yellow()
+        );
+    }
+    Ok(Some(context))
+}
+
+/// Read an extra root certificate for the HTTP clients
+pub fn load_ca_cert(path: &Path) -> Result<reqwest::Certificate> {
+    let pem = fs::read(path)
+        .with_context(|| format!("Failed to read CA certificate: {}", path.display()))?;
+    reqwest::Certificate::from_pem(&pem)
+        .with_context(|| format!("Invalid PEM certificate: {}", path.display()))
+}
+
+/// Pick the machine-translation source language, warning when the scripts disagree with it
+pub fn resolve_source_lang(configured: Option<&str>, sample: &[String]) -> Option<String> {
+    let detected = lang_detect::detect_language(sample);
+    match (configured, detected) {
+        (Some(configured), detected) => {
+            if let Some(detected) = detected
+                && !lang_detect::matches_detected(configured, detected)
+            {
+                println!(
+                    "{}",
+                    format!(
+                        "[WARN] Source language is set to '{}' but the scripts look like '{}'",
+                        configured, detected
+                    )
+                    .yellow()
+                );
+            }
+            println!("  Source language: {}", configured);
+            Some(configured.to_string())
+        }
+        (None, Some(detected)) => {
+            println!("  Source language: {} (detected)", detected);
+            Some(detected.to_string())
+        }
+        (None, None) => {
+            println!("  Source language: en (too little text to detect)");
+            None
+        }
+    }
+}
+
+/// Extracted texts from a file, or from the first scripts of a directory
+fn sample_texts(extractor: &TextExtractor, input: &Path, count: usize) -> Result<Vec<String>> {
+    let entries = sample_entries(extractor, input, count)?;
+    Ok(entries.into_iter().map(|e| e.text).collect())
+}
+
+fn sample_entries(
+    extractor: &TextExtractor,
+    input: &Path,
+    count: usize,
+) -> Result<Vec<TranslatableEntry>> {
+    let entries = if input.is_file() {
+        extractor.extract_from_file(input)?
+    } else {
+        WalkDir::new(input)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().extension().is_some_and(|ext| ext == "rpy"))
+            .flat_map(|e| extractor.extract_from_file(e.path()).unwrap_or_default())
+            .take(count)
+            .collect()
+    };
+    Ok(entries)
+}
+
+/// Menu choices and screen text have to fit the UI, so LLMs get a length hint for them
+fn is_layout_sensitive(entry: &TranslatableEntry) -> bool {
+    matches!(
+        entry.entry_type,
+        EntryType::MenuChoice | EntryType::ScreenText
+    )
+}
+
+pub fn run(args: TranslateArgs, cfg: &Config) -> Result<()> {
+    match args.action {
+        Some(TranslateAction::Analyze(analyze_args)) => return analyze::run(analyze_args),
+        Some(TranslateAction::Script(script_args)) => return story::run(script_args),
+        None => {}
+    }
 
     // Determine provider (CLI arg > config > default)
     let provider_str = if args.api != "openai" {
@@ -68,26 +293,138 @@ pub fn run(args: TranslateArgs) -> Result<()> {
     } else {
         cfg.translation.default_language.clone()
     };
+    // Ren'Py identifies a translation by language name, providers by language code
+    let lang_name = args.lang_name.clone().unwrap_or_else(|| lang.clone());
+
+    let comment_pattern = args
+        .translate_comments
+        .as_deref()
+        .map(regex::Regex::new)
+        .transpose()
+        .context("Invalid --translate-comments pattern")?;
+    let encoding = encoding_rs::Encoding::for_label(args.encoding.as_bytes())
+        .with_context(|| format!("Unknown encoding: {}", args.encoding))?;
+    let extractor = TextExtractor::new()
+        .with_comment_pattern(comment_pattern)
+        .with_encoding(encoding);
+    let input = &args.input.clone().context("Input path is required")?;
+    if !args.files.is_empty() && input.is_file() {
+        anyhow::bail!("--files selects scripts in a directory, but the input is a single file");
+    }
 
     // Create appropriate client based on provider
     let client = if provider.is_machine_translate() {
-        create_machine_client(provider, &lang, &cfg, &args)?
+        let sample = sample_texts(&extractor, input, lang_detect::SAMPLE_SIZE).unwrap_or_default();
+        create_machine_client(provider, &lang, cfg, &args, &sample)?
+    } else {
+        create_llm_client(provider, &provider_str, &lang, cfg, &args)?
+    };
+
+    // Machine translations are cached so interrupted runs can resume cheaply
+    let cache = if matches!(client, TranslateClient::Machine(_)) {
+        open_cache().map(|c| {
+            c.with_overwrite(args.overwrite_cache)
+                .with_cache_only(args.from_cache_only)
+        })
     } else {
-        create_llm_client(provider, &provider_str, &lang, &cfg, &args)?
+        None
     };
+    if args.from_cache_only {
+        if !matches!(client, TranslateClient::Machine(_)) {
+            anyhow::bail!("--from-cache-only only applies to cached providers (google, deepl)");
+        }
+        // Without a cache every text would be sent over the network instead
+        if cache.is_none() {
+            anyhow::bail!(
+                "--from-cache-only needs the translation cache, which could not be opened"
+            );
+        }
+        println!(
+            "{}",
+            "[Translate] Cache-only: no requests will be sent".cyan()
+        );
+    }
 
-    let extractor = TextExtractor::new();
-    let input = &args.input;
+    let options = WriteOptions {
+        keep_original: args.keep_original,
+        normalize_quotes: args.normalize_quotes,
+        retry_failed: args.retry_failed,
+        max_concurrent_files: args.max_concurrent_files,
+        show_progress: true,
+        report_skipped: args.report_skipped,
+        in_place: args.in_place,
+        check_quota: true,
+    };
+    if args.in_place && !args.keep_original {
+        println!(
+            "{}",
+            "[WARN] --in-place overwrites source scripts; add --keep-original to keep each original line as a comment"
+                .yellow()
+        );
+    }
+
+    if let Some(count) = args.dry_run_prompts {
+        let TranslateClient::Llm(ref llm) = client else {
+            anyhow::bail!(
+                "--dry-run-prompts only applies to LLM providers (openai, claude, ollama)"
+            );
+        };
+        let entries = sample_entries(&extractor, input, count)?;
+        let texts: Vec<String> = entries.iter().map(|e| e.text.clone()).collect();
+        let layout_sensitive: Vec<bool> = entries.iter().map(is_layout_sensitive).collect();
+        print_prompt_preview(llm, &texts, &layout_sensitive, count);
+        return Ok(());
+    }
+
+    if args.fill_tl {
+        if !input.exists() {
+            anyhow::bail!("Input path does not exist: {}", input.display());
+        }
+        return fill_tl(&client, cache.as_ref(), input, &lang_name);
+    }
+
+    let layout = match args.target_dir_layout.as_deref() {
+        Some(_) if args.output.is_none() => {
+            anyhow::bail!("--target-dir-layout requires an output directory (-o)")
+        }
+        Some(layout) => OutputLayout::from_str(layout),
+        None => OutputLayout::Mirror,
+    };
 
     if input.is_file() {
-        translate_single(&extractor, &client, input, args.output.as_deref())?;
+        let output = match args.output.as_deref() {
+            Some(p) if p.is_dir() || args.target_dir_layout.is_some() => {
+                let name = Path::new(input.file_name().unwrap_or_default());
+                Some(layout.path_for(p, &lang_name, name))
+            }
+            other => other.map(Path::to_path_buf),
+        };
+        let replaced = Mutex::new(Vec::new());
+        let result = translate_single(
+            &extractor,
+            &client,
+            cache.as_ref(),
+            input,
+            output.as_deref(),
+            options,
+            &replaced,
+        );
+        report_replaced(&replaced.into_inner().unwrap(), encoding);
+        result?;
     } else if input.is_dir() {
+        let output = args.output.as_deref().map(|base| OutputDir {
+            base,
+            layout,
+            lang: &lang_name,
+        });
         translate_directory(
             &extractor,
             &client,
+            cache.as_ref(),
             input,
-            args.output.as_deref(),
-            args.recursive,
+            output,
+            find_scripts(input, args.recursive, &FileFilter::new(&args.files))?,
+            options,
         )?;
     } else {
         anyhow::bail!("Input path does not exist: {}", input.display());
@@ -101,6 +438,7 @@ fn create_machine_client(
     lang: &str,
     cfg: &Config,
     args: &TranslateArgs,
+    sample: &[String],
 ) -> Result<TranslateClient> {
     let config = match provider {
         LlmProvider::Google => {
@@ -110,8 +448,11 @@ fn create_machine_client(
         LlmProvider::DeepL => {
             let api_key = args
                 .api_key
-                .clone()
+                .first()
+                .cloned()
                 .or_else(|| cfg.get_api_key("deepl"))
+                // Cache-only runs never reach DeepL, so CI needs no key
+                .or_else(|| args.from_cache_only.then(String::new))
                 .context("DeepL API key required. Get free key at https://www.deepl.com/pro-api")?;
             println!("{}", "[Translate] Using DeepL".cyan());
             MachineTranslateConfig::deepl(lang, api_key)
@@ -119,7 +460,17 @@ fn create_machine_client(
         _ => unreachable!(),
     };
 
-    let client = MachineTranslateClient::new(config)?;
+    let source_lang = resolve_source_lang(args.source_lang.as_deref(), sample);
+    let config = config
+        .with_source_lang(source_lang.as_deref())
+        .with_script(args.script.as_deref().and_then(ChineseScript::from_str))
+        .with_google_separator(cfg.translation.google_separator.clone())
+        .with_batch_size(args.batch_size)
+        .with_max_failures(args.max_failures.or(cfg.translation.max_failures))
+        .with_recovery(recovery(args.wait_for_recovery, args.recovery_poll, cfg))
+        .with_concurrency(args.min_concurrency, args.max_concurrency)
+        .with_ca_cert(cfg.get_ca_cert(args.ca_cert.as_deref()));
+    let client = MachineTranslateClient::new(config)?.with_cancel_flag(circuit::ctrl_c_flag());
     Ok(TranslateClient::Machine(client))
 }
 
@@ -130,12 +481,13 @@ fn create_llm_client(
     cfg: &Config,
     args: &TranslateArgs,
 ) -> Result<TranslateClient> {
-    let api_key = args
-        .api_key
-        .clone()
-        .or_else(|| cfg.get_api_key(provider_str));
+    let api_keys = if args.api_key.is_empty() {
+        cfg.get_api_keys(provider_str)
+    } else {
+        args.api_key.clone()
+    };
 
-    if api_key.is_none() && provider != LlmProvider::Ollama {
+    if api_keys.is_empty() && provider != LlmProvider::Ollama {
         anyhow::bail!(
             "API key required for {}. Set via --api-key, config, or environment variable.\n\
              Run 'derenpy config init' to create a config file.\n\
@@ -151,9 +503,19 @@ fn create_llm_client(
     let model = args.model.clone().or_else(|| cfg.get_model(provider_str));
 
     let config = LlmConfig::new(provider, lang)
-        .with_api_key(api_key)
+        .with_api_keys(api_keys)
         .with_base_url(api_base)
-        .with_model(model);
+        .with_model(model)
+        .with_system_prompt(cfg.get_prompt(lang))
+        .with_script(args.script.as_deref().and_then(ChineseScript::from_str))
+        .with_max_failures(args.max_failures.or(cfg.translation.max_failures))
+        .with_recovery(recovery(args.wait_for_recovery, args.recovery_poll, cfg))
+        .with_ca_cert(cfg.get_ca_cert(args.ca_cert.as_deref()))
+        .with_length_hint(args.length_hint)
+        .with_context(load_context(args.context_file.as_deref(), cfg)?)
+        .with_prompt_log(cfg.get_prompt_log(args.prompt_log.as_deref()))
+        .with_json_mode(args.json_mode)
+        .with_batch_translate(args.batch_translate);
 
     let client = LlmClient::new(config)?;
     Ok(TranslateClient::Llm(client))
@@ -162,21 +524,87 @@ fn create_llm_client(
 fn translate_single(
     extractor: &TextExtractor,
     client: &TranslateClient,
+    cache: Option<&TranslationCache>,
     input: &Path,
     output: Option<&Path>,
+    options: WriteOptions,
+    replaced: &Mutex<Vec<(PathBuf, usize)>>,
 ) -> Result<()> {
     println!("{}", format!("[Translate] {}", input.display()).green());
 
-    let entries = extractor.extract_from_file(input)?;
-
-    if entries.is_empty() {
-        println!("{}", "[WARN] No translatable text found".yellow());
-        return Ok(());
+    let output_path = match output {
+        Some(p) => {
+            if p.is_dir() {
+                p.join(input.file_name().unwrap_or_default())
+            } else {
+                p.to_path_buf()
+            }
+        }
+        None => {
+            let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+            let ext = input.extension().unwrap_or_default().to_string_lossy();
+            input.with_file_name(format!("{}_translated.{}", stem, ext))
+        }
+    };
+    if !options.in_place && is_same_file(input, &output_path) {
+        anyhow::bail!(
+            "Refusing to overwrite the input {}; pass --in-place to translate it in place",
+            input.display()
+        );
     }
+    let failures_path = failures_path(&output_path);
 
-    println!("  Found {} translatable entries", entries.len());
+    let (source_path, source, entries) = if options.retry_failed {
+        if !failures_path.exists() {
+            println!("  No recorded failures, skipping");
+            return Ok(());
+        }
+        let content = fs::read_to_string(&failures_path).context("Failed to read failures file")?;
+        let failed: Vec<FailedEntry> =
+            serde_json::from_str(&content).context("Failed to parse failures file")?;
+        let sources: HashSet<&str> = failed.iter().map(|f| f.source.as_str()).collect();
 
-    let pb = ProgressBar::new(entries.len() as u64);
+        // Failed lines were left untranslated, so they can be found again in the previous output,
+        // which is always UTF-8
+        let previous = fs::read_to_string(&output_path).context("Failed to read output file")?;
+        let entries: Vec<TranslatableEntry> = extractor
+            .extract_from_string(&previous)?
+            .into_iter()
+            .filter(|e| sources.contains(e.text.as_str()))
+            .collect();
+        if entries.is_empty() {
+            println!("  No failed entries remain");
+            fs::remove_file(&failures_path)?;
+            return Ok(());
+        }
+        println!("  Retrying {} failed entries", entries.len());
+        (output_path.clone(), previous, entries)
+    } else {
+        let (content, replaced_chars) = extractor.read_script(input)?;
+        if replaced_chars > 0 {
+            replaced
+                .lock()
+                .unwrap()
+                .push((input.to_path_buf(), replaced_chars));
+        }
+        let (entries, skipped) = extractor.extract_with_skipped(&content)?;
+        if options.report_skipped {
+            report_skipped(input, &skipped);
+        }
+        if entries.is_empty() {
+            println!("{}", "[WARN] No translatable text found".yellow());
+            return Ok(());
+        }
+        println!("  Found {} translatable entries", entries.len());
+        (input.to_path_buf(), content, entries)
+    };
+
+    // Bars from files translated in parallel would overwrite each other
+    let pb = if options.show_progress {
+        ProgressBar::new(entries.len() as u64)
+    } else {
+        ProgressBar::hidden()
+    };
     pb.set_style(
         ProgressStyle::default_bar()
             .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len}")?
@@ -185,31 +613,56 @@ fn translate_single(
     pb.enable_steady_tick(std::time::Duration::from_millis(100));
 
     let mut translations: HashMap<usize, String> = HashMap::new();
+    let mut failures: Vec<FailedEntry> = Vec::new();
 
     // Use batch translation for better performance
     let texts: Vec<String> = entries.iter().map(|e| e.text.clone()).collect();
+    let layout_sensitive: Vec<bool> = entries.iter().map(is_layout_sensitive).collect();
+    let cache_only = cache.is_some_and(|c| c.cache_only());
+    if let TranslateClient::Machine(machine) = client
+        && options.check_quota
+        && !cache_only
+    {
+        pb.suspend(|| machine.check_deepl_quota(&texts, cache));
+    }
     let results = client.translate_batch(
         &texts,
+        &layout_sensitive,
+        cache,
         Some(|count| {
             pb.set_position(count as u64);
         }),
     );
 
-    for (entry, result) in entries.iter().zip(results.into_iter()) {
+    // Once the breaker trips, the remaining failures only repeat the same systemic error
+    let tripped = client.trip_message();
+
+    let mut uncached = 0;
+    for (entry, result) in entries.iter().zip(results) {
         match result {
             Ok(translated) => {
                 translations.insert(entry.id, translated);
             }
+            // Left untranslated on purpose, not a failure to retry
+            Err(e) if e.is::<NotCached>() => uncached += 1,
             Err(e) => {
-                pb.suspend(|| {
-                    eprintln!(
-                        "{}",
-                        format!(
-                            "[ERROR] Failed to translate line {}: {}",
-                            entry.line_number, e
-                        )
-                        .red()
-                    );
+                if tripped.is_none() {
+                    pb.suspend(|| {
+                        eprintln!(
+                            "{}",
+                            format!(
+                                "[ERROR] Failed to translate line {}: {}",
+                                entry.line_number, e
+                            )
+                            .red()
+                        );
+                    });
+                }
+                failures.push(FailedEntry {
+                    id: entry.id,
+                    line: entry.line_number,
+                    source: entry.text.clone(),
+                    error: e.to_string(),
                 });
             }
         }
@@ -217,22 +670,14 @@ fn translate_single(
 
     pb.finish_and_clear();
 
-    let output_path = match output {
-        Some(p) => {
-            if p.is_dir() {
-                p.join(input.file_name().unwrap_or_default())
-            } else {
-                p.to_path_buf()
-            }
-        }
-        None => {
-            let stem = input.file_stem().unwrap_or_default().to_string_lossy();
-            let ext = input.extension().unwrap_or_default().to_string_lossy();
-            input.with_file_name(format!("{}_translated.{}", stem, ext))
-        }
-    };
-
-    write_translated_file(input, &output_path, &entries, &translations)?;
+    write_translated_file(
+        &source_path,
+        &source,
+        &output_path,
+        &entries,
+        &translations,
+        options,
+    )?;
 
     println!(
         "{}",
@@ -243,24 +688,126 @@ fn translate_single(
         )
         .green()
     );
+    if cache_only {
+        println!(
+            "  Cache coverage: {}/{} entries ({:.1}%), {} left untranslated",
+            translations.len(),
+            entries.len(),
+            translations.len() as f64 * 100.0 / entries.len() as f64,
+            uncached
+        );
+    }
+
+    if failures.is_empty() {
+        if failures_path.exists() {
+            fs::remove_file(&failures_path)?;
+        }
+    } else {
+        fs::write(&failures_path, serde_json::to_string_pretty(&failures)?)
+            .context("Failed to write failures file")?;
+        println!(
+            "{}",
+            format!(
+                "[WARN] {} entries failed, see {} (rerun with --retry-failed)",
+                failures.len(),
+                failures_path.display()
+            )
+            .yellow()
+        );
+    }
+
+    if let Some(message) = tripped {
+        anyhow::bail!(message);
+    }
 
     Ok(())
 }
 
-fn translate_directory(
-    extractor: &TextExtractor,
-    client: &TranslateClient,
-    dir: &Path,
-    output: Option<&Path>,
-    recursive: bool,
-) -> Result<()> {
+/// Samples shown per skip reason; the counts cover the rest
+const SKIPPED_SAMPLES: usize = 3;
+
+/// Written as one block so reports of files translated in parallel do not interleave
+fn report_skipped(input: &Path, skipped: &[SkippedLine]) {
+    let mut by_reason: BTreeMap<SkipReason, Vec<&SkippedLine>> = BTreeMap::new();
+    for line in skipped {
+        by_reason.entry(line.reason).or_default().push(line);
+    }
+
+    let counts: Vec<String> = by_reason
+        .iter()
+        .map(|(reason, lines)| format!("{} {}", reason.as_str(), lines.len()))
+        .collect();
+    let mut report = format!("[Skipped] {}: {} line(s)", input.display(), skipped.len());
+    if !counts.is_empty() {
+        report.push_str(&format!(" ({})", counts.join(", ")));
+    }
+    for (reason, lines) in &by_reason {
+        for line in lines.iter().take(SKIPPED_SAMPLES) {
+            report.push_str(&format!(
+                "\n  line {} [{}]: {:?}",
+                line.line_number,
+                reason.as_str(),
+                line.text
+            ));
+        }
+        if lines.len() > SKIPPED_SAMPLES {
+            report.push_str(&format!(
+                "\n  ... {} more {}",
+                lines.len() - SKIPPED_SAMPLES,
+                reason.as_str()
+            ));
+        }
+    }
+    eprintln!("{}", report.yellow());
+}
+
+/// Scripts that did not decode cleanly would put mojibake into the translation,
+/// so say which ones and how to read them correctly
+pub fn report_replaced(replaced: &[(PathBuf, usize)], encoding: &'static encoding_rs::Encoding) {
+    if replaced.is_empty() {
+        return;
+    }
+    let total: usize = replaced.iter().map(|(_, count)| count).sum();
+    println!(
+        "{}",
+        format!(
+            "[WARN] {} invalid {} sequence(s) in {} script(s) were replaced with U+FFFD; \
+             if the scripts use another encoding, pass it with --encoding (e.g. shift_jis)",
+            total,
+            encoding.name(),
+            replaced.len()
+        )
+        .yellow()
+    );
+    for (path, count) in replaced {
+        println!("    {}: {}", path.display(), count);
+    }
+}
+
+/// An entry that could not be translated, recorded for `--retry-failed`
+#[derive(Debug, Serialize, Deserialize)]
+struct FailedEntry {
+    id: usize,
+    line: usize,
+    source: String,
+    error: String,
+}
+
+fn failures_path(output_path: &Path) -> PathBuf {
+    let mut path = output_path.as_os_str().to_owned();
+    path.push(".failures.json");
+    PathBuf::from(path)
+}
+
+/// Scripts under `dir` selected by `--files`, reporting how many matched
+fn find_scripts(dir: &Path, recursive: bool, files: &FileFilter) -> Result<Vec<PathBuf>> {
     let walker = if recursive {
         WalkDir::new(dir)
     } else {
         WalkDir::new(dir).max_depth(1)
     };
 
-    let rpy_files: Vec<_> = walker
+    let scripts: Vec<PathBuf> = walker
         .into_iter()
         .filter_map(|e| e.ok())
         .filter(|e| {
@@ -269,8 +816,45 @@ fn translate_directory(
                 .map(|ext| ext == "rpy" || ext == "rpym")
                 .unwrap_or(false)
         })
+        .map(|e| e.into_path())
+        .collect();
+
+    if files.is_empty() {
+        return Ok(scripts);
+    }
+    let total = scripts.len();
+    let selected: Vec<PathBuf> = scripts
+        .into_iter()
+        .filter(|p| files.matches(p.strip_prefix(dir).unwrap_or(p)))
         .collect();
+    println!(
+        "  {} of {} script file(s) match --files",
+        selected.len(),
+        total
+    );
+    if selected.is_empty() {
+        anyhow::bail!("No script files match --files");
+    }
+    Ok(selected)
+}
+
+/// Where `translate_directory` writes: a base directory arranged by `--target-dir-layout`
+#[derive(Debug, Clone, Copy)]
+struct OutputDir<'a> {
+    base: &'a Path,
+    layout: OutputLayout,
+    lang: &'a str,
+}
 
+fn translate_directory(
+    extractor: &TextExtractor,
+    client: &TranslateClient,
+    cache: Option<&TranslationCache>,
+    dir: &Path,
+    output: Option<OutputDir>,
+    rpy_files: Vec<PathBuf>,
+    options: WriteOptions,
+) -> Result<()> {
     if rpy_files.is_empty() {
         println!("{}", "[WARN] No RPY files found".yellow());
         return Ok(());
@@ -281,39 +865,275 @@ fn translate_directory(
         format!("[Translate] Found {} RPY file(s)", rpy_files.len()).green()
     );
 
-    for entry in rpy_files {
-        let rpy_path = entry.path();
+    let out_path_for = |rpy_path: &Path| match output {
+        Some(out) => {
+            let rel = rpy_path.strip_prefix(dir).unwrap_or(rpy_path);
+            out.layout.path_for(out.base, out.lang, rel)
+        }
+        None => {
+            let stem = rpy_path.file_stem().unwrap_or_default().to_string_lossy();
+            let ext = rpy_path.extension().unwrap_or_default().to_string_lossy();
+            rpy_path.with_file_name(format!("{}_translated.{}", stem, ext))
+        }
+    };
+    // Checked up front, so a mirrored output onto the input fails once instead of once per file
+    if !options.in_place
+        && let Some(rpy_path) = rpy_files.iter().find(|p| is_same_file(p, &out_path_for(p)))
+    {
+        anyhow::bail!(
+            "Refusing to overwrite the input {}; pass --in-place to translate it in place",
+            rpy_path.display()
+        );
+    }
 
-        let out_path = match output {
-            Some(base) => {
-                let rel = rpy_path.strip_prefix(dir).unwrap_or(rpy_path);
-                base.join(rel)
-            }
-            None => {
-                let stem = rpy_path.file_stem().unwrap_or_default().to_string_lossy();
-                let ext = rpy_path.extension().unwrap_or_default().to_string_lossy();
-                rpy_path.with_file_name(format!("{}_translated.{}", stem, ext))
+    let cache_only = cache.is_some_and(|c| c.cache_only());
+    // One usage request for the whole run instead of one per file; a retry only resends
+    // the failed lines, so its estimate would be far off anyway
+    if let TranslateClient::Machine(machine) = client
+        && machine.provider_name() == "deepl"
+        && options.check_quota
+        && !options.retry_failed
+        && !cache_only
+    {
+        let texts: HashSet<String> = rpy_files
+            .iter()
+            .flat_map(|p| extractor.extract_from_file(p).unwrap_or_default())
+            .map(|e| e.text)
+            .collect();
+        machine.check_deepl_quota(&texts.into_iter().collect::<Vec<_>>(), cache);
+    }
+    let options = WriteOptions {
+        check_quota: false,
+        ..options
+    };
+
+    let report = |rpy_path: &Path, e: anyhow::Error| {
+        eprintln!(
+            "{}",
+            format!("[ERROR] Failed to translate {}: {}", rpy_path.display(), e).red()
+        );
+    };
+
+    let replaced = Mutex::new(Vec::new());
+    let report_replaced = || {
+        let mut replaced = replaced.lock().unwrap();
+        replaced.sort();
+        report_replaced(&replaced, extractor.encoding());
+    };
+
+    if options.max_concurrent_files <= 1 {
+        for rpy_path in &rpy_files {
+            let out_path = out_path_for(rpy_path);
+            if let Err(e) = translate_single(
+                extractor,
+                client,
+                cache,
+                rpy_path,
+                Some(&out_path),
+                options,
+                &replaced,
+            ) {
+                if client.trip_message().is_some() {
+                    report_replaced();
+                    return Err(e);
+                }
+                report(rpy_path, e);
             }
-        };
+        }
+        report_replaced();
+        return Ok(());
+    }
 
-        if let Err(e) = translate_single(extractor, client, rpy_path, Some(&out_path)) {
-            eprintln!(
-                "{}",
-                format!("[ERROR] Failed to translate {}: {}", rpy_path.display(), e).red()
-            );
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.max_concurrent_files)
+        .build()
+        .context("Failed to create file worker pool")?;
+    let file_options = WriteOptions {
+        show_progress: false,
+        ..options
+    };
+    let use_cache = cache.is_some();
+    let overwrite = cache.is_some_and(|c| c.overwrites());
+
+    pool.install(|| {
+        rpy_files.par_iter().for_each_init(
+            // SQLite connections cannot be shared across threads; WAL lets each worker open its own
+            || {
+                use_cache
+                    .then(|| TranslationCache::open().ok())
+                    .flatten()
+                    .map(|c| c.with_overwrite(overwrite).with_cache_only(cache_only))
+            },
+            |worker_cache, rpy_path| {
+                if cache_only && worker_cache.is_none() {
+                    report(
+                        rpy_path,
+                        anyhow::anyhow!("Translation cache could not be opened"),
+                    );
+                    return;
+                }
+                let out_path = out_path_for(rpy_path);
+                let result = translate_single(
+                    extractor,
+                    client,
+                    worker_cache.as_ref(),
+                    rpy_path,
+                    Some(&out_path),
+                    file_options,
+                    &replaced,
+                );
+                if let Err(e) = result
+                    && client.trip_message().is_none()
+                {
+                    report(rpy_path, e);
+                }
+            },
+        )
+    });
+    report_replaced();
+
+    if let Some(message) = client.trip_message() {
+        anyhow::bail!(message);
+    }
+
+    Ok(())
+}
+
+/// Translate the empty strings of an SDK-generated `tl/<lang>` scaffold and write them back
+fn fill_tl(
+    client: &TranslateClient,
+    cache: Option<&TranslationCache>,
+    input: &Path,
+    lang: &str,
+) -> Result<()> {
+    let generator = RenpyTranslationGenerator::new(lang);
+
+    let mut files = Vec::new();
+    for entry in WalkDir::new(input).into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.extension().map(|ext| ext != "rpy").unwrap_or(true) {
+            continue;
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let slots = generator.find_empty_slots(&content);
+        if !slots.is_empty() {
+            files.push((path.to_path_buf(), content, slots));
         }
     }
 
+    let mut texts: Vec<String> = Vec::new();
+    let mut index: HashMap<&str, usize> = HashMap::new();
+    for (_, _, slots) in &files {
+        for slot in slots {
+            index.entry(&slot.original).or_insert_with(|| {
+                texts.push(slot.original.clone());
+                texts.len() - 1
+            });
+        }
+    }
+
+    if texts.is_empty() {
+        println!("{}", "[OK] No empty translations found".green());
+        return Ok(());
+    }
+    println!(
+        "{}",
+        format!(
+            "[Translate] Found {} empty translations ({} unique) in {} file(s)",
+            files.iter().map(|(_, _, s)| s.len()).sum::<usize>(),
+            texts.len(),
+            files.len()
+        )
+        .green()
+    );
+
+    let pb = ProgressBar::new(texts.len() as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len}")?
+            .progress_chars("=>-"),
+    );
+    let results = client.translate_batch(
+        &texts,
+        &[],
+        cache,
+        Some(|count| {
+            pb.set_position(count as u64);
+        }),
+    );
+    pb.finish_and_clear();
+
+    let failed = results.iter().filter(|r| r.is_err()).count();
+    let results: Vec<Option<String>> = results.into_iter().map(Result::ok).collect();
+
+    let mut filled = 0;
+    for (path, content, slots) in &files {
+        let translations: HashMap<usize, String> = slots
+            .iter()
+            .filter_map(|slot| {
+                let translated = results[index[slot.original.as_str()]].clone()?;
+                Some((slot.line, translated))
+            })
+            .collect();
+        if translations.is_empty() {
+            continue;
+        }
+        filled += translations.len();
+        fs::write(
+            path,
+            RenpyTranslationGenerator::fill_empty_slots(content, &translations),
+        )
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+
+    println!("{}", format!("[OK] Filled {} translations", filled).green());
+    if failed > 0 {
+        println!(
+            "{}",
+            format!("[WARN] {} texts failed and were left empty", failed).yellow()
+        );
+    }
+    if let Some(message) = client.trip_message() {
+        anyhow::bail!(message);
+    }
+
     Ok(())
 }
 
+/// Whether two paths name the same existing file
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct WriteOptions {
+    keep_original: bool,
+    normalize_quotes: bool,
+    /// Only retranslate entries recorded in the failures file, updating the existing output
+    retry_failed: bool,
+    /// Files translated in parallel by `translate_directory`
+    max_concurrent_files: usize,
+    show_progress: bool,
+    /// Print the strings the extractor passed over, to spot heuristics misfiring on dialogue
+    report_skipped: bool,
+    /// Allow writing the translation over the script it was extracted from
+    in_place: bool,
+    /// Check the DeepL quota before sending; `translate_directory` checks once for all files
+    check_quota: bool,
+}
+
 fn write_translated_file(
     input: &Path,
+    content: &str,
     output: &Path,
     entries: &[TranslatableEntry],
     translations: &HashMap<usize, String>,
+    options: WriteOptions,
 ) -> Result<()> {
-    let content = fs::read_to_string(input).context("Failed to read input file")?;
     let lines: Vec<&str> = content.lines().collect();
 
     let mut result_lines: Vec<String> = lines.iter().map(|s| s.to_string()).collect();
@@ -330,22 +1150,61 @@ fn write_translated_file(
             continue;
         }
 
-        let mut line = result_lines[line_num - 1].clone();
+        let original = lines[line_num - 1];
+        let mut line = original.to_string();
 
         for entry in line_entries {
             if let Some(translated) = translations.get(&entry.id) {
+                let translated = if options.normalize_quotes {
+                    normalize_quotes(translated)
+                } else {
+                    translated.clone()
+                };
+                // Comment text is unquoted: replace it in the comment part of the line only
+                if entry.entry_type == EntryType::Comment {
+                    if let Some(hash) = line.find('#') {
+                        let (code, comment) = line.split_at(hash);
+                        line = format!(
+                            "{}{}",
+                            code,
+                            comment.replacen(&entry.text, &escape_control_chars(&translated), 1)
+                        );
+                    }
+                    continue;
+                }
                 // Simple replacement - find the original text and replace it
                 line = line.replace(
                     &format!("\"{}\"", entry.text),
-                    &format!("\"{}\"", translated),
+                    &format!("\"{}\"", to_literal(&translated, '"')),
+                );
+                line = line.replace(
+                    &format!("'{}'", entry.text),
+                    &format!("'{}'", to_literal(&translated, '\'')),
                 );
-                line = line.replace(&format!("'{}'", entry.text), &format!("'{}'", translated));
             }
         }
 
+        // Ren'Py is indentation-sensitive: never write a line whose indent changed
+        if indent_of(&line) != indent_of(original) || line.contains(['\n', '\r']) {
+            eprintln!(
+                "{}",
+                format!(
+                    "[WARN] {}:{}: translation would change the line's indentation, kept the original",
+                    input.display(),
+                    line_num
+                )
+                .yellow()
+            );
+            continue;
+        }
+
         result_lines[line_num - 1] = line;
     }
 
+    if options.keep_original {
+        result_lines = with_original_comments(&lines, result_lines);
+    }
+
     if let Some(parent) = output.parent() {
         fs::create_dir_all(parent).context("Failed to create output directory")?;
     }
@@ -354,3 +1213,185 @@ fn write_translated_file(
 
     Ok(())
 }
+
+/// Replace curly quotes, which LLMs often produce, with their straight equivalents
+fn normalize_quotes(text: &str) -> String {
+    text.replace(['\u{201C}', '\u{201D}', '\u{201E}'], "\"")
+        .replace(['\u{2018}', '\u{2019}'], "'")
+}
+
+/// Leading whitespace of a line
+fn indent_of(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// Make a translation safe to place inside a one-line string literal
+fn to_literal(text: &str, quote: char) -> String {
+    escape_control_chars(&escape_quote(text, quote))
+}
+
+/// Escape unescaped occurrences of `quote` so the text stays inside its string literal
+fn escape_quote(text: &str, quote: char) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut escaped = false;
+    for c in text.chars() {
+        if c == quote && !escaped {
+            result.push('\\');
+        }
+        escaped = c == '\\' && !escaped;
+        result.push(c);
+    }
+    result
+}
+
+/// Insert each replaced line's original as a comment above it, at the same indentation
+fn with_original_comments(original: &[&str], translated: Vec<String>) -> Vec<String> {
+    let mut result = Vec::with_capacity(translated.len());
+
+    for (orig, line) in original.iter().zip(translated) {
+        if *orig != line {
+            let indent = &orig[..orig.len() - orig.trim_start().len()];
+            result.push(format!("{}# {}", indent, orig.trim_start()));
+        }
+        result.push(line);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keep_original_comment() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let input = temp_dir.path().join("script.rpy");
+        let output = temp_dir.path().join("out.rpy");
+        fs::write(&input, "label start:\n    e \"Hello\"\n    return").unwrap();
+
+        let entries = TextExtractor::new().extract_from_file(&input).unwrap();
+        let translations = HashMap::from([(entries[0].id, "你好".to_string())]);
+        let options = WriteOptions {
+            keep_original: true,
+            normalize_quotes: true,
+            ..Default::default()
+        };
+        let content = fs::read_to_string(&input).unwrap();
+        write_translated_file(&input, &content, &output, &entries, &translations, options).unwrap();
+
+        let content = fs::read_to_string(&output).unwrap();
+        assert_eq!(
+            content,
+            "label start:\n    # e \"Hello\"\n    e \"你好\"\n    return"
+        );
+    }
+
+    #[test]
+    fn test_translate_comments() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let input = temp_dir.path().join("script.rpy");
+        let output = temp_dir.path().join("out.rpy");
+        fs::write(
+            &input,
+            "label start:\n    # TL: Hello there\n    # note: not for players\n    e \"Hello\"",
+        )
+        .unwrap();
+
+        let pattern = regex::Regex::new(r"^#\s*TL:\s*(.+)$").unwrap();
+        let entries = TextExtractor::new()
+            .with_comment_pattern(Some(pattern))
+            .extract_from_file(&input)
+            .unwrap();
+        let texts: Vec<&str> = entries.iter().map(|e| e.text.as_str()).collect();
+        assert_eq!(texts, ["Hello there", "Hello"]);
+
+        let translations = HashMap::from([
+            (entries[0].id, "你好啊".to_string()),
+            (entries[1].id, "你好".to_string()),
+        ]);
+        let options = WriteOptions {
+            normalize_quotes: true,
+            ..Default::default()
+        };
+        let content = fs::read_to_string(&input).unwrap();
+        write_translated_file(&input, &content, &output, &entries, &translations, options).unwrap();
+
+        let content = fs::read_to_string(&output).unwrap();
+        assert_eq!(
+            content,
+            "label start:\n    # TL: 你好啊\n    # note: not for players\n    e \"你好\""
+        );
+
+        // Comments stay skipped by default
+        let entries = TextExtractor::new().extract_from_file(&input).unwrap();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn test_normalize_curly_quotes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let input = temp_dir.path().join("script.rpy");
+        let output = temp_dir.path().join("out.rpy");
+        fs::write(&input, "    e \"He said hi.\"").unwrap();
+
+        let entries = TextExtractor::new().extract_from_file(&input).unwrap();
+        let translations =
+            HashMap::from([(entries[0].id, "他说\u{201C}你好\u{201D}。".to_string())]);
+        let options = WriteOptions {
+            normalize_quotes: true,
+            ..Default::default()
+        };
+        let content = fs::read_to_string(&input).unwrap();
+        write_translated_file(&input, &content, &output, &entries, &translations, options).unwrap();
+
+        let content = fs::read_to_string(&output).unwrap();
+        assert_eq!(content, "    e \"他说\\\"你好\\\"。\"");
+    }
+
+    #[test]
+    fn test_control_chars_escaped() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let input = temp_dir.path().join("script.rpy");
+        let output = temp_dir.path().join("out.rpy");
+        fs::write(&input, "label start:\n    e \"Hello.\"\n    return").unwrap();
+
+        let entries = TextExtractor::new().extract_from_file(&input).unwrap();
+        let translations =
+            HashMap::from([(entries[0].id, "你好，\r\n\t\"朋友\"\u{7}。".to_string())]);
+        let options = WriteOptions::default();
+        let content = fs::read_to_string(&input).unwrap();
+        write_translated_file(&input, &content, &output, &entries, &translations, options).unwrap();
+
+        let content = fs::read_to_string(&output).unwrap();
+        assert_eq!(
+            content,
+            "label start:\n    e \"你好，\\n\\t\\\"朋友\\\"。\"\n    return"
+        );
+    }
+
+    #[test]
+    fn test_indentation_preserved() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let input = temp_dir.path().join("script.rpy");
+        let output = temp_dir.path().join("out.rpy");
+        let source = "label start:\n    e \"Hello\"\n\t\"Tabbed narration\"\n    menu:\n        \"Go left\":\n            \"You went left.\"\n    return";
+        fs::write(&input, source).unwrap();
+
+        let entries = TextExtractor::new().extract_from_file(&input).unwrap();
+        let translations: HashMap<usize, String> = entries
+            .iter()
+            .map(|e| (e.id, format!("  译文\n第二行 {}", e.id)))
+            .collect();
+        let options = WriteOptions::default();
+        let content = fs::read_to_string(&input).unwrap();
+        write_translated_file(&input, &content, &output, &entries, &translations, options).unwrap();
+
+        let content = fs::read_to_string(&output).unwrap();
+        assert_eq!(content.lines().count(), source.lines().count());
+        for (before, after) in source.lines().zip(content.lines()) {
+            assert_eq!(indent_of(before).as_bytes(), indent_of(after).as_bytes());
+        }
+        assert!(content.contains("\"  译文\\n第二行"));
+    }
+}
@@ -1,13 +1,17 @@
 //! Ren'Py translation file generator with MD5-based identifiers
 
 use anyhow::{Context, Result};
+use encoding_rs::Encoding;
 use regex::Regex;
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
-use crate::utils::{is_code_like, is_renpy_keyword, unquote};
+use super::OutputLayout;
+use crate::utils::{
+    escape_control_chars, is_code_like, is_renpy_keyword, opens_style_block, unquote,
+};
 
 #[derive(Debug, Clone)]
 pub struct DialogueEntry {
@@ -22,12 +26,71 @@ pub struct DialogueEntry {
 pub struct StringEntry {
     pub original: String,
     pub translated: Option<String>,
+    /// Comment emitted above the first entry of each group (e.g. the enclosing menu)
+    pub context: Option<String>,
+    /// Variable assigned by a `define`/`default`, translated in a `translate python` block
+    pub variable: Option<String>,
+    /// Menu choice or screen text, whose translation has to fit the layout
+    pub layout: bool,
+}
+
+/// Translations already present in an existing `tl/<lang>` directory
+#[derive(Debug, Default)]
+pub struct ExistingTranslations {
+    /// Dialogue translations keyed by identifier
+    pub dialogues: HashMap<String, String>,
+    /// String translations keyed by original text
+    pub strings: HashMap<String, String>,
+}
+
+impl ExistingTranslations {
+    /// Fill in entries that were translated by hand, returning how many were kept
+    pub fn apply(
+        &self,
+        dialogues: &mut HashMap<PathBuf, Vec<DialogueEntry>>,
+        strings: &mut [StringEntry],
+    ) -> usize {
+        let mut kept = 0;
+
+        for entry in dialogues.values_mut().flatten() {
+            if let Some(translated) = self.dialogues.get(&entry.identifier)
+                && *translated != entry.original_text
+            {
+                entry.translated_text = Some(translated.clone());
+                kept += 1;
+            }
+        }
+
+        for entry in strings.iter_mut() {
+            if let Some(translated) = self.strings.get(&entry.original)
+                && *translated != entry.original
+            {
+                entry.translated = Some(translated.clone());
+                kept += 1;
+            }
+        }
+
+        kept
+    }
+}
+
+/// An empty translation in an SDK-generated `tl/<lang>` file
+#[derive(Debug, Clone)]
+pub struct EmptySlot {
+    /// Zero-based index of the line holding the empty `""`
+    pub line: usize,
+    pub original: String,
 }
 
 pub struct RenpyTranslationGenerator {
     language: String,
     dialogue_re: Regex,
     label_re: Regex,
+    python_assign_re: Regex,
+    quoted_re: Regex,
+    flatten: bool,
+    layout: OutputLayout,
+    encoding: &'static Encoding,
 }
 
 impl RenpyTranslationGenerator {
@@ -39,15 +102,77 @@ impl RenpyTranslationGenerator {
             )
             .unwrap(),
             label_re: Regex::new(r#"^label\s+(\w+)"#).unwrap(),
+            python_assign_re: Regex::new(r#"^(?:(#)|[\w.]+\s*=)\s*("(?:[^"\\]|\\.)*")$"#).unwrap(),
+            quoted_re: Regex::new(r#""((?:[^"\\]|\\.)*)""#).unwrap(),
+            flatten: false,
+            layout: OutputLayout::Renpy,
+            encoding: encoding_rs::UTF_8,
+        }
+    }
+
+    /// Write everything into a single `tl/<lang>/translations.rpy`
+    pub fn with_flatten(mut self, flatten: bool) -> Self {
+        self.flatten = flatten;
+        self
+    }
+
+    /// Arrange generated files under the output directory other than as `tl/<lang>/`
+    pub fn with_layout(mut self, layout: OutputLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// The directory shared files like `strings.rpy` are written to
+    pub fn target_dir(&self, output_dir: &Path) -> PathBuf {
+        self.layout.root(output_dir, &self.language)
+    }
+
+    /// Encode generated files with a legacy encoding instead of UTF-8
+    pub fn with_encoding(mut self, encoding: &'static Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Resolve an encoding label such as `utf-8`, `shift_jis` or `gbk`
+    pub fn encoding_for_label(label: &str) -> Result<&'static Encoding> {
+        let encoding = Encoding::for_label(label.as_bytes())
+            .with_context(|| format!("Unknown encoding: {}", label))?;
+        // UTF-16 and friends can only be decoded, encoding_rs writes them as UTF-8
+        if encoding.output_encoding() != encoding {
+            anyhow::bail!("Encoding {} cannot be used for output", encoding.name());
         }
+        Ok(encoding)
+    }
+
+    fn write_file(&self, path: &Path, content: &str) -> Result<()> {
+        let (bytes, _, had_errors) = self.encoding.encode(content);
+        if had_errors {
+            anyhow::bail!(
+                "{} contains characters that cannot be encoded as {}",
+                path.display(),
+                self.encoding.name()
+            );
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).context("Failed to create translation directory")?;
+        }
+        let mut file = fs::File::create(path).context("Failed to create translation file")?;
+        file.write_all(&bytes)
+            .context("Failed to write translation file")?;
+        Ok(())
     }
 
     pub fn extract_dialogues<P: AsRef<Path>>(&self, path: P) -> Result<Vec<DialogueEntry>> {
         let content = fs::read_to_string(path.as_ref()).context("Failed to read script file")?;
+        Ok(self.extract_dialogues_from_str(&content))
+    }
 
+    pub fn extract_dialogues_from_str(&self, content: &str) -> Vec<DialogueEntry> {
         let mut entries = Vec::new();
         let mut current_label = "script".to_string();
         let mut used_identifiers: HashSet<String> = HashSet::new();
+        let mut block_indent: Option<usize> = None;
 
         for (line_num, line) in content.lines().enumerate() {
             let line_number = line_num + 1;
@@ -57,6 +182,15 @@ impl RenpyTranslationGenerator {
                 continue;
             }
 
+            // Screen and style properties like `alt "..."` look like say statements but are not
+            let indent = line.len() - line.trim_start().len();
+            if block_indent.is_some_and(|i| indent > i) {
+                continue;
+            }
+            block_indent = ((trimmed.starts_with("screen ") && trimmed.ends_with(':'))
+                || opens_style_block(trimmed))
+            .then_some(indent);
+
             if let Some(caps) = self.label_re.captures(trimmed) {
                 current_label = caps
                     .get(1)
@@ -93,7 +227,7 @@ impl RenpyTranslationGenerator {
             }
         }
 
-        Ok(entries)
+        entries
     }
 
     fn build_code_line(character: Option<&str>, text: &str) -> String {
@@ -144,52 +278,270 @@ impl RenpyTranslationGenerator {
         output.push_str("# Generated by Derenpy\n\n");
 
         for entry in dialogues {
-            output.push_str(&format!("# {}:{}\n", source_file, entry.line_number));
-            output.push_str(&format!(
-                "translate {} {}:\n",
-                self.language, entry.identifier
-            ));
+            self.push_dialogue_block(&mut output, entry, source_file);
+        }
+
+        output
+    }
+
+    fn push_dialogue_block(&self, output: &mut String, entry: &DialogueEntry, source_file: &str) {
+        output.push_str(&format!("# {}:{}\n", source_file, entry.line_number));
+        output.push_str(&format!(
+            "translate {} {}:\n",
+            self.language, entry.identifier
+        ));
+
+        let escaped_original = Self::escape_string(&entry.original_text);
+        if let Some(ref char) = entry.character {
+            output.push_str(&format!("    # {} \"{}\"\n", char, escaped_original));
+        } else {
+            output.push_str(&format!("    # \"{}\"\n", escaped_original));
+        }
 
-            let escaped_original = Self::escape_string(&entry.original_text);
-            if let Some(ref char) = entry.character {
-                output.push_str(&format!("    # {} \"{}\"\n", char, escaped_original));
-            } else {
-                output.push_str(&format!("    # \"{}\"\n", escaped_original));
+        let translated = entry
+            .translated_text
+            .as_ref()
+            .unwrap_or(&entry.original_text);
+        let escaped_translated = Self::escape_string(translated);
+
+        if let Some(ref char) = entry.character {
+            output.push_str(&format!("    {} \"{}\"\n", char, escaped_translated));
+        } else {
+            output.push_str(&format!("    \"{}\"\n", escaped_translated));
+        }
+
+        output.push('\n');
+    }
+
+    /// Merge all dialogue blocks and strings into one file, keeping identifiers unique
+    pub fn generate_flat_file(
+        &self,
+        dialogues: &HashMap<PathBuf, Vec<DialogueEntry>>,
+        strings: &[StringEntry],
+    ) -> String {
+        let mut output = String::new();
+        let mut used_identifiers: HashSet<String> = HashSet::new();
+
+        output.push_str(&format!("# Translation file for {}\n", self.language));
+        output.push_str("# Generated by Derenpy\n\n");
+
+        let mut paths: Vec<&PathBuf> = dialogues.keys().collect();
+        paths.sort();
+
+        for path in paths {
+            let source_str = path.to_string_lossy();
+            for entry in &dialogues[path] {
+                if used_identifiers.insert(entry.identifier.clone()) {
+                    self.push_dialogue_block(&mut output, entry, &source_str);
+                } else {
+                    // Same label and line in two files; Ren'Py would reject a duplicate block
+                    let mut renamed = entry.clone();
+                    let mut i = 1;
+                    renamed.identifier = loop {
+                        let candidate = format!("{}_{}", entry.identifier, i);
+                        if used_identifiers.insert(candidate.clone()) {
+                            break candidate;
+                        }
+                        i += 1;
+                    };
+                    self.push_dialogue_block(&mut output, &renamed, &source_str);
+                }
             }
+        }
 
-            let translated = entry
-                .translated_text
-                .as_ref()
-                .unwrap_or(&entry.original_text);
-            let escaped_translated = Self::escape_string(translated);
+        let (variables, strings): (Vec<StringEntry>, Vec<StringEntry>) =
+            strings.iter().cloned().partition(|s| s.variable.is_some());
+        if !strings.is_empty() {
+            self.push_strings_block(&mut output, &strings);
+        }
+        if !variables.is_empty() {
+            self.push_python_block(&mut output, &variables);
+        }
+
+        output
+    }
+
+    fn unescape_string(s: &str) -> String {
+        let mut result = String::with_capacity(s.len());
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                result.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('r') => result.push('\r'),
+                Some('t') => result.push('\t'),
+                Some(other @ ('\\' | '"')) => result.push(other),
+                Some(other) => {
+                    result.push('\\');
+                    result.push(other);
+                }
+                None => result.push('\\'),
+            }
+        }
+        result
+    }
+
+    /// Read the translations from every `.rpy` file in an existing `tl/<lang>` directory
+    pub fn read_existing_translations<P: AsRef<Path>>(
+        &self,
+        tl_dir: P,
+    ) -> Result<ExistingTranslations> {
+        let mut existing = ExistingTranslations::default();
+        let block_prefix = format!("translate {} ", self.language);
+
+        for dir_entry in fs::read_dir(tl_dir.as_ref()).context("Failed to read tl directory")? {
+            let path = dir_entry?.path();
+            if path.extension().map(|e| e != "rpy").unwrap_or(true) {
+                continue;
+            }
+
+            let content = fs::read_to_string(&path).context("Failed to read translation file")?;
+            let mut current_id: Option<String> = None;
+            let mut in_strings = false;
+            let mut in_python = false;
+            let mut pending_old: Option<String> = None;
+
+            for line in content.lines() {
+                let trimmed = line.trim();
+
+                // Python blocks keep the original as a comment above each assignment
+                if in_python && let Some(caps) = self.python_assign_re.captures(trimmed) {
+                    let value = caps.get(2).map(|m| m.as_str()).unwrap_or("");
+                    let value = Self::unescape_string(&unquote(value));
+                    if caps.get(1).is_some() {
+                        pending_old = Some(value);
+                    } else if let Some(old) = pending_old.take() {
+                        existing.strings.insert(old, value);
+                    }
+                    continue;
+                }
+
+                if trimmed.is_empty() || trimmed.starts_with('#') {
+                    continue;
+                }
+
+                if let Some(rest) = trimmed.strip_prefix(&block_prefix) {
+                    let id = rest.trim_end_matches(':').trim();
+                    in_strings = id == "strings";
+                    in_python = id == "python";
+                    current_id = (!in_strings && !in_python).then(|| id.to_string());
+                    continue;
+                }
 
-            if let Some(ref char) = entry.character {
-                output.push_str(&format!("    {} \"{}\"\n", char, escaped_translated));
-            } else {
-                output.push_str(&format!("    \"{}\"\n", escaped_translated));
+                if in_strings {
+                    if let Some(old) = trimmed.strip_prefix("old ") {
+                        pending_old = Some(Self::unescape_string(&unquote(old)));
+                    } else if let Some(new) = trimmed.strip_prefix("new ")
+                        && let Some(old) = pending_old.take()
+                    {
+                        existing
+                            .strings
+                            .insert(old, Self::unescape_string(&unquote(new)));
+                    }
+                } else if let Some(caps) = self.dialogue_re.captures(line)
+                    && let Some(id) = current_id.take()
+                {
+                    let text = caps.get(2).map(|m| m.as_str()).unwrap_or("");
+                    existing
+                        .dialogues
+                        .insert(id, Self::unescape_string(&unquote(text)));
+                }
+            }
+        }
+
+        Ok(existing)
+    }
+
+    /// Find translations left empty by `renpy.sh translate --empty`, in any language block.
+    /// Dialogue sources come from the `# e "..."` comment above each line.
+    pub fn find_empty_slots(&self, content: &str) -> Vec<EmptySlot> {
+        let mut slots = Vec::new();
+        let mut in_strings = false;
+        let mut pending: Option<String> = None;
+
+        for (i, line) in content.lines().enumerate() {
+            let trimmed = line.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+
+            if trimmed.starts_with("translate ") && trimmed.ends_with(':') {
+                let id = trimmed.split_whitespace().nth(2).unwrap_or("");
+                in_strings = id.trim_end_matches(':') == "strings";
+                pending = None;
+                continue;
+            }
+
+            if in_strings {
+                if let Some(old) = trimmed.strip_prefix("old ") {
+                    pending = Some(Self::unescape_string(&unquote(old)));
+                } else if let Some(new) = trimmed.strip_prefix("new ")
+                    && let Some(original) = pending.take()
+                    && new.trim() == "\"\""
+                    && !original.is_empty()
+                {
+                    slots.push(EmptySlot { line: i, original });
+                }
+            } else if let Some(comment) = trimmed.strip_prefix('#') {
+                if let Some(caps) = self.quoted_re.captures(comment) {
+                    pending = Some(Self::unescape_string(&caps[1]));
+                }
+            } else if let Some(original) = pending.take()
+                && Self::first_quote_is_empty(line)
+                && !original.is_empty()
+            {
+                slots.push(EmptySlot { line: i, original });
             }
+        }
+
+        slots
+    }
+
+    fn first_quote_is_empty(line: &str) -> bool {
+        line.find('"')
+            .is_some_and(|pos| line[pos..].starts_with("\"\""))
+    }
+
+    /// Write translations (keyed by line index) into the empty strings found by `find_empty_slots`
+    pub fn fill_empty_slots(content: &str, translations: &HashMap<usize, String>) -> String {
+        let mut output = String::with_capacity(content.len());
 
-            output.push('\n');
+        for (i, line) in content.split_inclusive('\n').enumerate() {
+            match (translations.get(&i), line.find("\"\"")) {
+                (Some(translated), Some(pos)) => {
+                    output.push_str(&line[..pos]);
+                    output.push_str(&format!("\"{}\"", Self::escape_string(translated)));
+                    output.push_str(&line[pos + 2..]);
+                }
+                _ => output.push_str(line),
+            }
         }
 
         output
     }
 
     fn escape_string(s: &str) -> String {
-        s.replace('\\', "\\\\")
-            .replace('"', "\\\"")
-            .replace('\n', "\\n")
-            .replace('\r', "\\r")
-            .replace('\t', "\\t")
+        escape_control_chars(&s.replace('\\', "\\\\").replace('"', "\\\""))
     }
 
     pub fn generate_strings_file(&self, strings: &[StringEntry]) -> String {
         let mut output = String::new();
-        let mut seen = HashSet::new();
 
         output.push_str(&format!("# String translations for {}\n", self.language));
         output.push_str("# Generated by Derenpy\n\n");
 
+        self.push_strings_block(&mut output, strings);
+
+        output
+    }
+
+    fn push_strings_block(&self, output: &mut String, strings: &[StringEntry]) {
+        let mut seen = HashSet::new();
+        let mut current_context: Option<&str> = None;
+
         output.push_str(&format!("translate {} strings:\n\n", self.language));
 
         for entry in strings {
@@ -198,6 +550,13 @@ impl RenpyTranslationGenerator {
             }
             seen.insert(entry.original.clone());
 
+            if let Some(ref context) = entry.context
+                && current_context != Some(context.as_str())
+            {
+                output.push_str(&format!("    # {}\n", context));
+            }
+            current_context = entry.context.as_deref();
+
             let escaped_original = Self::escape_string(&entry.original);
             output.push_str(&format!("    old \"{}\"\n", escaped_original));
 
@@ -205,8 +564,29 @@ impl RenpyTranslationGenerator {
             let escaped_translated = Self::escape_string(translated);
             output.push_str(&format!("    new \"{}\"\n\n", escaped_translated));
         }
+    }
 
-        output
+    /// Reassign translated `define`/`default` values when the language is selected
+    fn push_python_block(&self, output: &mut String, variables: &[StringEntry]) {
+        output.push_str(&format!("translate {} python:\n", self.language));
+
+        for entry in variables {
+            let Some(ref variable) = entry.variable else {
+                continue;
+            };
+            let translated = entry.translated.as_ref().unwrap_or(&entry.original);
+            output.push_str(&format!(
+                "    # \"{}\"\n",
+                Self::escape_string(&entry.original)
+            ));
+            output.push_str(&format!(
+                "    {} = \"{}\"\n",
+                variable,
+                Self::escape_string(translated)
+            ));
+        }
+
+        output.push('\n');
     }
 
     pub fn write_translation_files<P: AsRef<Path>>(
@@ -215,47 +595,310 @@ impl RenpyTranslationGenerator {
         dialogues: &HashMap<PathBuf, Vec<DialogueEntry>>,
         strings: &[StringEntry],
     ) -> Result<Vec<PathBuf>> {
-        let tl_dir = output_dir.as_ref().join("tl").join(&self.language);
+        let tl_dir = self.target_dir(output_dir.as_ref());
         fs::create_dir_all(&tl_dir).context("Failed to create translation directory")?;
 
         let mut created_files = Vec::new();
 
+        if self.flatten {
+            let flat_path = tl_dir.join("translations.rpy");
+            let content = self.generate_flat_file(dialogues, strings);
+
+            self.write_file(&flat_path, &content)?;
+
+            created_files.push(flat_path);
+            return Ok(created_files);
+        }
+
         // Write dialogue files
         for (source_path, entries) in dialogues {
             if entries.is_empty() {
                 continue;
             }
 
-            let filename = source_path
-                .file_name()
-                .unwrap_or_default()
-                .to_string_lossy();
-
-            let output_path = tl_dir.join(filename.as_ref());
+            let output_path =
+                self.layout
+                    .path_for(output_dir.as_ref(), &self.language, source_path);
             let source_str = source_path.to_string_lossy();
             let content = self.generate_translation_file(entries, &source_str);
 
-            let mut file =
-                fs::File::create(&output_path).context("Failed to create translation file")?;
-            file.write_all(content.as_bytes())
-                .context("Failed to write translation file")?;
+            self.write_file(&output_path, &content)?;
 
             created_files.push(output_path);
         }
 
+        let (variables, strings): (Vec<StringEntry>, Vec<StringEntry>) =
+            strings.iter().cloned().partition(|s| s.variable.is_some());
+
         // Write strings file
         if !strings.is_empty() {
             let strings_path = tl_dir.join("strings.rpy");
-            let content = self.generate_strings_file(strings);
+            let content = self.generate_strings_file(&strings);
 
-            let mut file =
-                fs::File::create(&strings_path).context("Failed to create strings file")?;
-            file.write_all(content.as_bytes())
-                .context("Failed to write strings file")?;
+            self.write_file(&strings_path, &content)?;
 
             created_files.push(strings_path);
         }
 
+        // Write translated define/default values
+        if !variables.is_empty() {
+            let defines_path = tl_dir.join("defines.rpy");
+            let mut content = String::new();
+            content.push_str(&format!("# Variable translations for {}\n", self.language));
+            content.push_str("# Generated by Derenpy\n\n");
+            self.push_python_block(&mut content, &variables);
+
+            self.write_file(&defines_path, &content)?;
+
+            created_files.push(defines_path);
+        }
+
         Ok(created_files)
     }
+
+    /// Re-read generated files and report anything Ren'Py would reject: malformed
+    /// `translate` headers, statements outside a block, unbalanced quotes, `old` without
+    /// `new`, and dialogue identifiers or strings translated twice
+    pub fn validate_files(&self, files: &[PathBuf]) -> Result<Vec<String>> {
+        let header_re = Regex::new(r"^translate\s+(\S+)\s+(\w+):$").unwrap();
+        let mut problems = Vec::new();
+        let mut identifiers: HashMap<String, String> = HashMap::new();
+        let mut old_strings: HashMap<String, String> = HashMap::new();
+
+        for path in files {
+            let bytes =
+                fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+            let (content, _, _) = self.encoding.decode(&bytes);
+
+            // Block name, statement count and the line of an `old` still waiting for `new`
+            let mut block: Option<(String, usize)> = None;
+            let mut pending_old: Option<usize> = None;
+            for (i, line) in content.lines().enumerate() {
+                let line_number = i + 1;
+                let location = format!("{}:{}", path.display(), line_number);
+                let trimmed = line.trim();
+                if trimmed.is_empty() || trimmed.starts_with('#') {
+                    continue;
+                }
+
+                if !line.starts_with([' ', '\t']) {
+                    Self::close_block(path, &mut block, &mut pending_old, &mut problems);
+                    let Some(caps) = header_re.captures(trimmed) else {
+                        problems.push(format!("{}: expected a translate block", location));
+                        continue;
+                    };
+                    if caps[1] != self.language {
+                        problems.push(format!(
+                            "{}: language {} does not match {}",
+                            location, &caps[1], self.language
+                        ));
+                    }
+                    let name = caps[2].to_string();
+                    if name != "strings"
+                        && name != "python"
+                        && let Some(first) = identifiers.insert(name.clone(), location.clone())
+                    {
+                        problems.push(format!(
+                            "{}: duplicate identifier {} (first at {})",
+                            location, name, first
+                        ));
+                    }
+                    block = Some((name, 0));
+                    continue;
+                }
+
+                let Some((ref name, ref mut statements)) = block else {
+                    problems.push(format!("{}: statement outside a translate block", location));
+                    continue;
+                };
+                *statements += 1;
+
+                if !Self::quotes_balanced(trimmed) {
+                    problems.push(format!("{}: unbalanced quotes", location));
+                    continue;
+                }
+
+                if name == "strings" {
+                    if let Some(old) = trimmed.strip_prefix("old ") {
+                        if let Some(line) = pending_old.replace(line_number) {
+                            problems.push(format!("{}:{}: old without new", path.display(), line));
+                        }
+                        if let Some(first) = old_strings.insert(old.to_string(), location.clone()) {
+                            problems.push(format!(
+                                "{}: string translated twice (first at {})",
+                                location, first
+                            ));
+                        }
+                    } else if trimmed.starts_with("new ") {
+                        if pending_old.take().is_none() {
+                            problems.push(format!("{}: new without old", location));
+                        }
+                    } else {
+                        problems.push(format!("{}: expected old or new", location));
+                    }
+                }
+            }
+            Self::close_block(path, &mut block, &mut pending_old, &mut problems);
+        }
+
+        Ok(problems)
+    }
+
+    /// Report an unanswered `old` or a dialogue block with nothing in it
+    fn close_block(
+        path: &Path,
+        block: &mut Option<(String, usize)>,
+        pending_old: &mut Option<usize>,
+        problems: &mut Vec<String>,
+    ) {
+        if let Some(line) = pending_old.take() {
+            problems.push(format!("{}:{}: old without new", path.display(), line));
+        }
+        if let Some((name, 0)) = block.take()
+            && name != "strings"
+            && name != "python"
+        {
+            problems.push(format!(
+                "{}: translate block {} is empty",
+                path.display(),
+                name
+            ));
+        }
+    }
+
+    /// Every `"` outside an escape sequence comes in pairs
+    fn quotes_balanced(line: &str) -> bool {
+        let mut quotes = 0;
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    chars.next();
+                }
+                '"' => quotes += 1,
+                _ => {}
+            }
+        }
+        quotes % 2 == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_style_block_properties_skipped() {
+        let content = r##"
+style say_label:
+    text_color "#fff"
+    hover_sound "click Sound"
+
+label start:
+    e "Hello."
+"##;
+        let dialogues =
+            RenpyTranslationGenerator::new("chinese").extract_dialogues_from_str(content);
+        let texts: Vec<&str> = dialogues.iter().map(|d| d.original_text.as_str()).collect();
+        assert_eq!(texts, ["Hello."]);
+    }
+
+    #[test]
+    fn test_validate_files() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let generator = RenpyTranslationGenerator::new("chinese");
+
+        let entry = |identifier: &str, text: &str| DialogueEntry {
+            identifier: identifier.to_string(),
+            line_number: 3,
+            character: Some("e".to_string()),
+            original_text: text.to_string(),
+            translated_text: Some(format!("{} \"quoted\"", text)),
+        };
+        let dialogues = HashMap::from([(
+            PathBuf::from("script.rpy"),
+            vec![entry("start_1", "Hi"), entry("start_2", "Bye")],
+        )]);
+        let strings = [StringEntry {
+            original: "Start".to_string(),
+            translated: Some("开始".to_string()),
+            context: Some("Menu: \"Where?\"".to_string()),
+            variable: None,
+            layout: true,
+        }];
+        let created = generator
+            .write_translation_files(temp_dir.path(), &dialogues, &strings)
+            .unwrap();
+        assert!(generator.validate_files(&created).unwrap().is_empty());
+
+        let broken = temp_dir.path().join("broken.rpy");
+        fs::write(
+            &broken,
+            "translate chinese start_1:\n    e \"unterminated\n\ntranslate japanese start_3:\n\n\
+             translate chinese strings:\n    old \"A\"\n    old \"Start\"\n    new \"B\"\n\
+             e \"stray\"\n",
+        )
+        .unwrap();
+        let files: Vec<PathBuf> = created.iter().cloned().chain([broken]).collect();
+        let problems = generator.validate_files(&files).unwrap();
+        let root = temp_dir.path().display().to_string();
+        let problems: Vec<_> = problems.iter().map(|p| p.replace(&root, "")).collect();
+        assert_eq!(
+            problems,
+            [
+                "/broken.rpy:1: duplicate identifier start_1 (first at /tl/chinese/script.rpy:5)",
+                "/broken.rpy:2: unbalanced quotes",
+                "/broken.rpy:4: language japanese does not match chinese",
+                "/broken.rpy: translate block start_3 is empty",
+                "/broken.rpy:7: old without new",
+                "/broken.rpy:8: string translated twice (first at /tl/chinese/strings.rpy:7)",
+                "/broken.rpy:10: expected a translate block",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fill_empty_slots() {
+        let content = r#"# game/script.rpy:3
+translate chinese start_a170b500:
+
+    # e "Hello, \"world\"."
+    e ""
+
+translate chinese start_b2c3d4e5:
+
+    # e "Already done."
+    e "早就好了。"
+
+translate chinese strings:
+
+    # game/screens.rpy:10
+    old "Start"
+    new ""
+
+    old "Quit"
+    new "退出"
+"#;
+        let generator = RenpyTranslationGenerator::new("chinese");
+        let slots = generator.find_empty_slots(content);
+        assert_eq!(slots.len(), 2);
+        assert_eq!(slots[0].original, "Hello, \"world\".");
+        assert_eq!(slots[1].original, "Start");
+
+        let translations: HashMap<usize, String> = slots
+            .iter()
+            .map(|s| (s.line, format!("<{}>", s.original)))
+            .collect();
+        let filled = RenpyTranslationGenerator::fill_empty_slots(content, &translations);
+        assert!(filled.contains(r#"    e "<Hello, \"world\".>""#));
+        assert!(filled.contains(r#"    new "<Start>""#));
+        assert!(filled.contains(r#"    new "退出""#));
+        assert!(generator.find_empty_slots(&filled).is_empty());
+
+        // A translation split over lines by the model must stay on one line
+        let translations = HashMap::from([(slots[0].line, "你好，\r\n\t世界。".to_string())]);
+        let filled = RenpyTranslationGenerator::fill_empty_slots(content, &translations);
+        assert!(filled.contains(r#"    e "你好，\n\t世界。""#));
+        assert_eq!(filled.lines().count(), content.lines().count());
+    }
 }
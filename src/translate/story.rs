@@ -0,0 +1,186 @@
+//! Read-only export of a game's dialogue as a Markdown document, for proofreading the story
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use super::extractor::TextExtractor;
+use super::renpy_tl::{DialogueEntry, RenpyTranslationGenerator};
+use crate::cli::ScriptArgs;
+use crate::utils::is_tl_dir;
+
+pub fn run(args: ScriptArgs) -> Result<()> {
+    if !args.input.exists() {
+        anyhow::bail!("Input path does not exist: {}", args.input.display());
+    }
+
+    // Ren'Py loads scripts in path order, which is as close to story order as files get
+    let mut files: Vec<PathBuf> = WalkDir::new(&args.input)
+        .into_iter()
+        .filter_entry(|e| !is_tl_dir(e))
+        .filter_map(|e| e.ok())
+        .map(|e| e.into_path())
+        .filter(|p| {
+            p.extension()
+                .is_some_and(|ext| ext == "rpy" || ext == "rpym")
+        })
+        .collect();
+    files.sort();
+
+    let generator = RenpyTranslationGenerator::new("story");
+    let extractor = TextExtractor::new();
+    let mut names = HashMap::new();
+    let mut scripts = Vec::new();
+    for path in &files {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        names.extend(extractor.display_names(&content));
+
+        let dialogues = generator.extract_dialogues(path)?;
+        if !dialogues.is_empty() {
+            let rel = path.strip_prefix(&args.input).unwrap_or(path);
+            let rel = if rel.as_os_str().is_empty() {
+                path.file_name().map(Path::new).unwrap_or(path)
+            } else {
+                rel
+            };
+            scripts.push((rel.to_string_lossy().replace('\\', "/"), dialogues));
+        }
+    }
+
+    let document = render_story(&scripts, &names);
+    let lines: usize = scripts.iter().map(|(_, d)| d.len()).sum();
+
+    match args.output {
+        Some(ref path) => {
+            fs::write(path, document).context("Failed to write story document")?;
+            println!(
+                "{}",
+                format!(
+                    "[OK] Wrote {} line(s) from {} file(s) to {}",
+                    lines,
+                    scripts.len(),
+                    path.display()
+                )
+                .green()
+            );
+        }
+        None => print!("{}", document),
+    }
+
+    Ok(())
+}
+
+/// One `##` section per file, each line as `**Speaker:** text` or plain narration
+pub fn render_story(
+    scripts: &[(String, Vec<DialogueEntry>)],
+    names: &HashMap<String, String>,
+) -> String {
+    let mut output = String::new();
+
+    for (file, dialogues) in scripts {
+        output.push_str(&format!("## {}\n\n", file));
+        for entry in dialogues {
+            let text = escape_markdown(&plain_text(&entry.original_text));
+            match entry.character.as_deref() {
+                Some(var) => {
+                    let speaker = names.get(var).map(String::as_str).unwrap_or(var);
+                    output.push_str(&format!(
+                        "**{}:** {}\n\n",
+                        escape_markdown(&plain_text(speaker)),
+                        text
+                    ));
+                }
+                None => output.push_str(&format!("{}\n\n", text)),
+            }
+        }
+    }
+
+    output
+}
+
+/// Dialogue as the player reads it: text tags dropped, escapes resolved
+fn plain_text(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                result.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                result.push('}');
+            }
+            '{' => {
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        break;
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some('n') => result.push(' '),
+                Some(escaped) => result.push(escaped),
+                None => result.push('\\'),
+            },
+            _ => result.push(c),
+        }
+    }
+
+    result.trim().to_string()
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '_' | '`') {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_story() {
+        let content = r##"define e = Character("Eileen", color="#c8ffc8")
+define m = Character(_('Mysterious *Stranger*'))
+
+label start:
+    "The sun rises over the {i}quiet{/i} campus."
+    e "Good morning, [player_name]! \"Ready?\"{w}"
+    m "Hm."
+    lucy "Use {{braces}} wisely."
+"##;
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("script.rpy");
+        fs::write(&path, content).unwrap();
+
+        let dialogues = RenpyTranslationGenerator::new("story")
+            .extract_dialogues(&path)
+            .unwrap();
+        let document = render_story(
+            &[("script.rpy".to_string(), dialogues)],
+            &TextExtractor::new().display_names(content),
+        );
+
+        assert_eq!(
+            document,
+            "## script.rpy\n\n\
+             The sun rises over the quiet campus.\n\n\
+             **Eileen:** Good morning, [player\\_name]! \"Ready?\"\n\n\
+             **Mysterious \\*Stranger\\*:** Hm.\n\n\
+             **lucy:** Use {braces} wisely.\n\n"
+        );
+    }
+}
@@ -3,36 +3,216 @@ pub mod rpa;
 use anyhow::{Context, Result};
 use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
+use std::collections::HashSet;
 use std::path::Path;
 use walkdir::WalkDir;
 
 use crate::cli::UnpackArgs;
-use rpa::RpaArchive;
+use rpa::{
+    AUDIO_EXTENSIONS, EntryFilter, EntryOrder, IMAGE_EXTENSIONS, RpaArchive, SCRIPT_EXTENSIONS,
+    read_entry_list,
+};
+
+/// How entries are written out, shared by every archive in a run
+struct ExtractOptions {
+    force: bool,
+    /// Read the input as an archive even without an `RPA-`/`ALT-` header
+    force_rpa: bool,
+    order: EntryOrder,
+    filter: EntryFilter,
+    /// Stamp extracted files with the archive's modification time
+    preserve_mtime: bool,
+    /// Resume an interrupted extraction, keeping files that already have the right size
+    skip_existing: bool,
+    /// Entry names from `--from-list`, checked against the archives afterwards
+    listed: Vec<String>,
+}
 
 pub fn run(args: UnpackArgs) -> Result<()> {
     let input = &args.input;
+    let listed = match &args.from_list {
+        Some(path) => read_entry_list(path)?,
+        None => Vec::new(),
+    };
+    if args.from_list.is_some() && listed.is_empty() {
+        anyhow::bail!("List file names no entries");
+    }
+    let options = ExtractOptions {
+        force: args.force,
+        force_rpa: args.force_rpa,
+        order: EntryOrder::from_str(&args.sort),
+        filter: entry_filter(&args, &listed),
+        preserve_mtime: args.preserve_mtime,
+        skip_existing: args.skip_existing,
+        listed,
+    };
+
+    if input.is_file() && !args.force_rpa && !looks_like_archive(input) {
+        anyhow::bail!(
+            "{} does not look like an RPA archive (use --force-rpa to try anyway)",
+            input.display()
+        );
+    }
 
-    if input.is_file() {
-        unpack_single(input, args.output.as_deref(), args.force)?;
+    if args.pretty_print_index {
+        if !input.is_file() {
+            anyhow::bail!("--pretty-print-index expects a single archive file");
+        }
+        let dump = RpaArchive::dump_raw_index(input, args.force_rpa)
+            .context("Failed to read RPA index")?;
+        println!("{}", serde_json::to_string_pretty(&dump)?);
+        return Ok(());
+    }
+
+    let found = if args.info_only && input.is_file() {
+        print_info(input, args.force_rpa)?;
+        return Ok(());
+    } else if input.is_file() {
+        unpack_single(input, args.output.as_deref(), &options)?
     } else if input.is_dir() {
-        unpack_directory(input, args.output.as_deref(), args.recursive, args.force)?;
+        unpack_directory(
+            input,
+            args.output.as_deref(),
+            args.recursive,
+            args.info_only,
+            &options,
+        )?
     } else {
         anyhow::bail!("Input path does not exist: {}", input.display());
+    };
+
+    if !args.info_only {
+        report_missing(&options.listed, &found);
+    }
+
+    Ok(())
+}
+
+/// An `.rpa` name, an `RPA-`/`ALT-` header, or a sibling `.rpi` index
+fn looks_like_archive(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("rpa"))
+        || RpaArchive::has_magic(path)
+        || path.with_extension("rpi").is_file()
+}
+
+/// Point out list entries that no archive contained, so typos in the list do not go unnoticed
+fn report_missing(listed: &[String], found: &HashSet<String>) {
+    let missing: Vec<&String> = listed.iter().filter(|n| !found.contains(*n)).collect();
+    if missing.is_empty() {
+        return;
+    }
+    println!(
+        "{}",
+        format!("[WARN] {} listed entry(s) not found:", missing.len()).yellow()
+    );
+    for name in missing {
+        println!("  {}", name);
+    }
+}
+
+/// The shorthand flags add curated extension sets on top of --include/--exclude
+fn entry_filter(args: &UnpackArgs, listed: &[String]) -> EntryFilter {
+    let mut filter = EntryFilter::new(&args.include, &args.exclude).with_names(listed);
+    if args.scripts_only {
+        filter = filter.with_extensions(SCRIPT_EXTENSIONS);
+    }
+    if args.images_only {
+        filter = filter.with_extensions(IMAGE_EXTENSIONS);
+    }
+    if args.audio_only {
+        filter = filter.with_extensions(AUDIO_EXTENSIONS);
+    }
+    filter
+}
+
+/// Parse an archive's header and index without extracting anything
+fn print_info(input: &Path, force_rpa: bool) -> Result<()> {
+    println!("{}", format!("[Info] {}", input.display()).green());
+
+    let archive = open_archive(input, force_rpa).context("Failed to open RPA archive")?;
+
+    println!("  Version: {}", archive.version);
+    println!("  Files: {}", archive.file_count());
+    warn_replaced_names(&archive);
+    if archive.file_count() == 0 {
+        warn_empty_index();
+    }
+    match archive.index_offset {
+        Some(offset) => println!("  Index offset: {:#x}", offset),
+        None => println!("  Index offset: none (index read from .rpi)"),
+    }
+    match archive.key {
+        Some(key) => println!("  Key: {:#x}", key),
+        None => println!("  Key: none"),
     }
 
     Ok(())
 }
 
-fn unpack_single(input: &Path, output: Option<&Path>, force: bool) -> Result<()> {
+fn open_archive(path: &Path, force_rpa: bool) -> Result<RpaArchive> {
+    if force_rpa {
+        RpaArchive::open_forced(path)
+    } else {
+        RpaArchive::open(path)
+    }
+}
+
+/// Names that were not valid UTF-8 are extracted under their lossy form
+fn warn_replaced_names(archive: &RpaArchive) {
+    if archive.replaced_in_names > 0 {
+        println!(
+            "{}",
+            format!(
+                "[WARN] {} invalid UTF-8 sequence(s) in file names were replaced with U+FFFD",
+                archive.replaced_in_names
+            )
+            .yellow()
+        );
+    }
+}
+
+/// An index that parses but lists nothing usually means a custom packer or encrypted index
+fn warn_empty_index() {
+    println!(
+        "{}",
+        "[WARN] Archive contains no indexed files; the index may be nonstandard or encrypted, nothing was extracted"
+            .yellow()
+    );
+}
+
+/// Returns the `--from-list` names this archive contains
+fn unpack_single(
+    input: &Path,
+    output: Option<&Path>,
+    options: &ExtractOptions,
+) -> Result<HashSet<String>> {
     println!("{}", format!("[Unpack] {}", input.display()).green());
 
-    let archive = RpaArchive::open(input).context("Failed to open RPA archive")?;
+    let archive = open_archive(input, options.force_rpa)
+        .context("Failed to open RPA archive")?
+        .with_preserved_mtime(options.preserve_mtime)?
+        .with_skip_existing(options.skip_existing);
+    let filter = &options.filter;
 
     println!(
         "  Version: {}, Files: {}",
         archive.version,
         archive.file_count()
     );
+    warn_replaced_names(&archive);
+
+    if archive.file_count() == 0 {
+        warn_empty_index();
+        return Ok(HashSet::new());
+    }
+
+    let found: HashSet<String> = options
+        .listed
+        .iter()
+        .filter(|name| archive.index.contains_key(*name))
+        .cloned()
+        .collect();
 
     let output_dir = match output {
         Some(p) => p.to_path_buf(),
@@ -42,34 +222,58 @@ fn unpack_single(input: &Path, output: Option<&Path>, force: bool) -> Result<()>
         }
     };
 
-    if output_dir.exists() && !force {
+    if output_dir.exists() && !options.force && !options.skip_existing {
         anyhow::bail!(
-            "Output directory already exists: {} (use -f to overwrite)",
+            "Output directory already exists: {} (use -f to overwrite or --skip-existing to resume)",
             output_dir.display()
         );
     }
 
     std::fs::create_dir_all(&output_dir).context("Failed to create output directory")?;
 
-    let pb = ProgressBar::new(archive.file_count() as u64);
+    let selected = if filter.is_empty() {
+        archive.file_count()
+    } else {
+        let count = archive
+            .sorted_names(options.order)
+            .into_iter()
+            .filter(|name| filter.matches(name))
+            .count();
+        println!("  Selected {} of {} files", count, archive.file_count());
+        count
+    };
+
+    let pb = ProgressBar::new(selected as u64);
     pb.set_style(
         ProgressStyle::default_bar()
             .template("{spinner:.green} [{bar:40.cyan/blue}] {pos}/{len} {msg}")?
             .progress_chars("=>-"),
     );
 
-    archive.extract_all(&output_dir, Some(&pb))?;
+    let extracted = archive.extract_matching(&output_dir, Some(&pb), options.order, filter)?;
 
     pb.finish_with_message("done");
+    if extracted.len() < selected {
+        println!(
+            "  Skipped {} already extracted file(s)",
+            selected - extracted.len()
+        );
+    }
     println!(
         "{}",
         format!("[OK] Extracted to {}", output_dir.display()).green()
     );
 
-    Ok(())
+    Ok(found)
 }
 
-fn unpack_directory(dir: &Path, output: Option<&Path>, recursive: bool, force: bool) -> Result<()> {
+fn unpack_directory(
+    dir: &Path,
+    output: Option<&Path>,
+    recursive: bool,
+    info_only: bool,
+    options: &ExtractOptions,
+) -> Result<HashSet<String>> {
     let walker = if recursive {
         WalkDir::new(dir)
     } else {
@@ -89,7 +293,7 @@ fn unpack_directory(dir: &Path, output: Option<&Path>, recursive: bool, force: b
 
     if rpa_files.is_empty() {
         println!("{}", "[WARN] No RPA files found".yellow());
-        return Ok(());
+        return Ok(HashSet::new());
     }
 
     println!(
@@ -97,8 +301,19 @@ fn unpack_directory(dir: &Path, output: Option<&Path>, recursive: bool, force: b
         format!("[Unpack] Found {} RPA file(s)", rpa_files.len()).green()
     );
 
+    let mut found = HashSet::new();
     for entry in rpa_files {
         let rpa_path = entry.path();
+        if info_only {
+            if let Err(e) = print_info(rpa_path, options.force_rpa) {
+                eprintln!(
+                    "{}",
+                    format!("[ERROR] Failed to read {}: {}", rpa_path.display(), e).red()
+                );
+            }
+            continue;
+        }
+
         let out_dir = match output {
             Some(base) => {
                 let rel = rpa_path.strip_prefix(dir).unwrap_or(rpa_path);
@@ -111,13 +326,14 @@ fn unpack_directory(dir: &Path, output: Option<&Path>, recursive: bool, force: b
             }
         };
 
-        if let Err(e) = unpack_single(rpa_path, Some(&out_dir), force) {
-            eprintln!(
+        match unpack_single(rpa_path, Some(&out_dir), options) {
+            Ok(names) => found.extend(names),
+            Err(e) => eprintln!(
                 "{}",
                 format!("[ERROR] Failed to unpack {}: {}", rpa_path.display(), e).red()
-            );
+            ),
         }
     }
 
-    Ok(())
+    Ok(found)
 }
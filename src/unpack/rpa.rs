@@ -1,18 +1,31 @@
 //! RPA archive parsing and extraction
-//! Supported versions: RPA-2.0, RPA-3.0, RPA-3.2, RPA-4.0, ALT-1.0
+//! Supported versions: RPA-1.0 (split .rpa/.rpi), RPA-2.0, RPA-3.0, RPA-3.2, RPA-4.0, ALT-1.0
 
 use anyhow::{Context, Result};
 use flate2::read::ZlibDecoder;
+use regex::Regex;
 use serde_pickle::{HashableValue, Value as PickleValue};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
-const ALT_KEY_MASK: u64 = 0xDABE8DF0;
+use crate::utils::{decode_lossy, glob_to_regex};
+
+pub const ALT_KEY_MASK: u64 = 0xDABE8DF0;
+/// Leading bytes of every archive with an inline header, whatever its file extension
+const ARCHIVE_MAGIC: &[&[u8]] = &[b"RPA-", b"ALT-"];
+/// Upper bound for a decompressed index; real games stay far below this
+const MAX_INDEX_SIZE: u64 = 256 * 1024 * 1024;
+/// Entries up to this size are read in one allocation; larger ones are streamed
+const STREAM_THRESHOLD: u64 = 4 * 1024 * 1024;
+/// Buffer used when streaming large entries, so memory per extraction stays bounded
+const STREAM_BUFFER_SIZE: usize = 1024 * 1024;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum RpaVersion {
+    Rpa1,
     Rpa2,
     Rpa3,
     Rpa32,
@@ -23,6 +36,7 @@ pub enum RpaVersion {
 impl std::fmt::Display for RpaVersion {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            RpaVersion::Rpa1 => write!(f, "RPA-1.0"),
             RpaVersion::Rpa2 => write!(f, "RPA-2.0"),
             RpaVersion::Rpa3 => write!(f, "RPA-3.0"),
             RpaVersion::Rpa32 => write!(f, "RPA-3.2"),
@@ -32,6 +46,101 @@ impl std::fmt::Display for RpaVersion {
     }
 }
 
+/// Order in which archive entries are listed and extracted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntryOrder {
+    Name,
+    Offset,
+}
+
+impl EntryOrder {
+    pub fn from_str(s: &str) -> Self {
+        match s {
+            "name" => Self::Name,
+            _ => Self::Offset,
+        }
+    }
+}
+
+pub const SCRIPT_EXTENSIONS: &[&str] = &["rpy", "rpyc", "rpym", "rpymc"];
+pub const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp", "avif", "gif", "bmp"];
+pub const AUDIO_EXTENSIONS: &[&str] = &["ogg", "opus", "mp3", "wav", "flac", "m4a"];
+
+/// Selects which archive entries to extract; an empty filter matches everything
+#[derive(Debug, Default)]
+pub struct EntryFilter {
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+    /// Extensions (case-insensitive) accepted in addition to the include globs
+    extensions: Vec<&'static str>,
+    /// Exact entry names from `--from-list`; when set, nothing outside it matches
+    names: HashSet<String>,
+}
+
+impl EntryFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Self {
+        Self {
+            include: include.iter().map(|p| glob_to_regex(p)).collect(),
+            exclude: exclude.iter().map(|p| glob_to_regex(p)).collect(),
+            extensions: Vec::new(),
+            names: HashSet::new(),
+        }
+    }
+
+    pub fn with_extensions(mut self, extensions: &[&'static str]) -> Self {
+        self.extensions.extend_from_slice(extensions);
+        self
+    }
+
+    pub fn with_names(mut self, names: &[String]) -> Self {
+        self.names.extend(names.iter().cloned());
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty()
+            && self.exclude.is_empty()
+            && self.extensions.is_empty()
+            && self.names.is_empty()
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        if !self.names.is_empty() && !self.names.contains(name) {
+            return false;
+        }
+        if self.exclude.iter().any(|re| re.is_match(name)) {
+            return false;
+        }
+        if self.include.is_empty() && self.extensions.is_empty() {
+            return true;
+        }
+
+        let extension = Path::new(name).extension().and_then(|e| e.to_str());
+        let by_extension = extension.is_some_and(|ext| {
+            self.extensions
+                .iter()
+                .any(|wanted| ext.eq_ignore_ascii_case(wanted))
+        });
+        by_extension || self.include.iter().any(|re| re.is_match(name))
+    }
+}
+
+/// Read a newline-delimited list of archive paths, skipping blank lines and `#` comments
+pub fn read_entry_list(path: &Path) -> Result<Vec<String>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read list file: {}", path.display()))?;
+
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let line = line.replace('\\', "/");
+            line.strip_prefix("./").map(str::to_string).unwrap_or(line)
+        })
+        .collect())
+}
+
 #[derive(Debug, Clone)]
 pub struct RpaEntry {
     pub offset: u64,
@@ -39,17 +148,66 @@ pub struct RpaEntry {
     pub prefix: Vec<u8>,
 }
 
+/// What an archive's header and index location yield before the index is decoded
+struct RawIndex {
+    version: RpaVersion,
+    index_offset: Option<u64>,
+    key: Option<u64>,
+    compressed: Vec<u8>,
+    archive_size: u64,
+}
+
 #[derive(Debug)]
 pub struct RpaArchive {
     path: PathBuf,
     pub version: RpaVersion,
+    /// Where the index starts, or `None` when it was read from a `.rpi` file
+    pub index_offset: Option<u64>,
+    pub key: Option<u64>,
     pub index: HashMap<String, RpaEntry>,
+    /// Invalid UTF-8 sequences in entry names that were replaced with U+FFFD
+    pub replaced_in_names: usize,
+    /// Modification time given to extracted files, if any
+    mtime: Option<SystemTime>,
+    /// Leave files that were already fully extracted by an earlier run
+    skip_existing: bool,
 }
 
 impl RpaArchive {
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let path = path.as_ref().to_path_buf();
-        let file = File::open(&path).context("Failed to open RPA file")?;
+        Self::open_with(path.as_ref(), false)
+    }
+
+    /// Like `open`, but an unknown header tag is read with the usual `<tag> <offset> [<key>]`
+    /// layout, for games that rename the `RPA-`/`ALT-` magic
+    pub fn open_forced<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_with(path.as_ref(), true)
+    }
+
+    fn open_with(path: &Path, force: bool) -> Result<Self> {
+        let path = path.to_path_buf();
+        let raw = Self::read_raw_index(&path, force)?;
+
+        // A `.rpi` index is never obfuscated, whatever the header says
+        let (index, replaced_in_names) =
+            Self::parse_index(&raw.compressed, raw.index_offset.and(raw.key))?;
+        Self::validate_entries(&index, raw.archive_size)?;
+
+        Ok(Self {
+            path,
+            version: raw.version,
+            index_offset: raw.index_offset,
+            key: raw.key,
+            index,
+            replaced_in_names,
+            mtime: None,
+            skip_existing: false,
+        })
+    }
+
+    /// Header fields and the still-compressed index, from the archive or its `.rpi`
+    fn read_raw_index(path: &Path, force: bool) -> Result<RawIndex> {
+        let file = File::open(path).context("Failed to open RPA file")?;
         let mut reader = BufReader::new(file);
 
         let mut first_line = Vec::new();
@@ -57,7 +215,38 @@ impl RpaArchive {
             .read_until(b'\n', &mut first_line)
             .context("Failed to read RPA header")?;
 
-        let (version, index_offset, key) = Self::parse_header(&first_line)?;
+        let archive_size = reader.get_ref().metadata()?.len();
+
+        let (version, index_offset, key) =
+            match Self::parse_header(&first_line, archive_size, force) {
+                Ok(header) => header,
+                Err(e) => {
+                    // Headerless data file: the index lives in a sibling .rpi
+                    let Some(compressed) = Self::read_rpi(path)? else {
+                        if !force && !ARCHIVE_MAGIC.iter().any(|m| first_line.starts_with(m)) {
+                            anyhow::bail!(
+                                "Not an RPA archive: no RPA-/ALT- header and no .rpi index"
+                            );
+                        }
+                        return Err(e);
+                    };
+                    return Ok(RawIndex {
+                        version: RpaVersion::Rpa1,
+                        index_offset: None,
+                        key: None,
+                        compressed,
+                        archive_size,
+                    });
+                }
+            };
+
+        if index_offset > archive_size {
+            anyhow::bail!(
+                "Index offset {:#x} lies beyond the end of the archive ({} bytes)",
+                index_offset,
+                archive_size
+            );
+        }
 
         reader
             .seek(SeekFrom::Start(index_offset))
@@ -68,85 +257,202 @@ impl RpaArchive {
             .read_to_end(&mut compressed)
             .context("Failed to read index data")?;
 
-        let index = Self::parse_index(&compressed, key)?;
+        if compressed.is_empty() {
+            let compressed =
+                Self::read_rpi(path)?.context("Archive has no index and no .rpi file was found")?;
+            return Ok(RawIndex {
+                version,
+                index_offset: None,
+                key,
+                compressed,
+                archive_size,
+            });
+        }
 
-        Ok(Self {
-            path,
+        Ok(RawIndex {
             version,
-            index,
+            index_offset: Some(index_offset),
+            key,
+            compressed,
+            archive_size,
         })
     }
 
-    fn parse_header(header: &[u8]) -> Result<(RpaVersion, u64, Option<u64>)> {
+    /// The index exactly as `serde_pickle` decoded it, before any conversion or unmasking,
+    /// as JSON for debugging archives that fail to parse
+    pub fn dump_raw_index<P: AsRef<Path>>(path: P, force: bool) -> Result<serde_json::Value> {
+        let raw = Self::read_raw_index(path.as_ref(), force)?;
+        let value = Self::decode_index(&raw.compressed)?;
+        Ok(serde_json::json!({
+            "version": raw.version.to_string(),
+            "index_offset": raw.index_offset,
+            "key": raw.key.map(|k| format!("{:#x}", k)),
+            "index": pickle_to_json(&value),
+        }))
+    }
+
+    /// Whether a file starts with an archive header, for archives renamed to `.dat` and the like.
+    /// Headerless RPA-1.0 data files are only recognized by their `.rpi` index.
+    pub fn has_magic<P: AsRef<Path>>(path: P) -> bool {
+        let mut magic = [0u8; 4];
+        File::open(path)
+            .and_then(|mut f| f.read_exact(&mut magic))
+            .is_ok_and(|_| ARCHIVE_MAGIC.contains(&magic.as_slice()))
+    }
+
+    /// Read the compressed index from a standalone `.rpi` file next to the archive, if present
+    fn read_rpi(path: &Path) -> Result<Option<Vec<u8>>> {
+        let rpi_path = path.with_extension("rpi");
+        if !rpi_path.is_file() {
+            return Ok(None);
+        }
+
+        fs::read(&rpi_path)
+            .context("Failed to read RPI index file")
+            .map(Some)
+    }
+
+    /// Parse the first line of an archive. Nonstandard packers pad the line with NULs, add
+    /// extra fields or write decimal numbers, so fields are read leniently.
+    fn parse_header(
+        header: &[u8],
+        archive_size: u64,
+        force: bool,
+    ) -> Result<(RpaVersion, u64, Option<u64>)> {
         let header_str = String::from_utf8_lossy(header);
-        let header_str = header_str.trim();
-
-        // RPA-3.x formats: "RPA-X.X <offset> <key>"
-        if header_str.starts_with("RPA-3.0")
-            || header_str.starts_with("RPA-3.2")
-            || header_str.starts_with("RPA-4.0")
-        {
-            let parts: Vec<&str> = header_str.split_whitespace().collect();
-            if parts.len() >= 3 {
-                let version = match parts[0] {
+        let header_str = header_str.trim_matches(|c: char| c.is_whitespace() || c == '\0');
+        let parts: Vec<&str> = header_str.split_whitespace().collect();
+        let offset_at = |i: usize| -> Result<u64> {
+            let field = parts.get(i).context("Missing index offset in header")?;
+            Self::parse_offset(field, archive_size)
+        };
+        let key_at = |i: usize| -> Result<u64> {
+            let field = parts.get(i).context("Missing key in header")?;
+            Self::parse_key(field)
+        };
+
+        match parts.first().copied().unwrap_or("") {
+            // RPA-3.x formats: "RPA-X.X <offset> <key>"; RPA-4.0 writes a 64-bit key.
+            // Some packers leave the key out, which reads the same as a zero key
+            tag @ ("RPA-3.0" | "RPA-3.2" | "RPA-4.0") => {
+                let version = match tag {
                     "RPA-3.2" => RpaVersion::Rpa32,
                     "RPA-4.0" => RpaVersion::Rpa40,
                     _ => RpaVersion::Rpa3,
                 };
-                let offset =
-                    u64::from_str_radix(parts[1], 16).context("Invalid index offset in header")?;
-                let key = u64::from_str_radix(parts[2], 16).context("Invalid key in header")?;
-                return Ok((version, offset, Some(key)));
+                let offset = offset_at(1).with_context(|| format!("Invalid {} header", tag))?;
+                let key = match parts.get(2) {
+                    Some(_) => key_at(2).with_context(|| format!("Invalid {} header", tag))?,
+                    None => 0,
+                };
+                Ok((version, offset, Some(key)))
             }
-            anyhow::bail!(
-                "Invalid {} header format",
-                parts.first().unwrap_or(&"RPA-3.x")
-            );
+            // ALT-1.0 format: "ALT-1.0 <key^mask> <offset>"
+            "ALT-1.0" => {
+                let key = key_at(1).context("Invalid ALT-1.0 header")? ^ ALT_KEY_MASK;
+                let offset = offset_at(2).context("Invalid ALT-1.0 header")?;
+                Ok((RpaVersion::Alt1, offset, Some(key)))
+            }
+            // RPA-2.0 format: "RPA-2.0 <offset>"
+            "RPA-2.0" => {
+                let offset = offset_at(1).context("Invalid RPA-2.0 header")?;
+                Ok((RpaVersion::Rpa2, offset, None))
+            }
+            // Forced: a renamed tag, read like RPA-3.0 with a key or RPA-2.0 without one
+            _ if force && parts.len() >= 2 => {
+                let offset = offset_at(1).context("Invalid header")?;
+                match parts.get(2) {
+                    Some(_) => Ok((RpaVersion::Rpa3, offset, Some(key_at(2)?))),
+                    None => Ok((RpaVersion::Rpa2, offset, None)),
+                }
+            }
+            _ => anyhow::bail!("Unsupported or invalid RPA format: {}", header_str),
         }
+    }
 
-        // ALT-1.0 format: "ALT-1.0 <key^mask> <offset>"
-        if header_str.starts_with("ALT-1.0") {
-            let parts: Vec<&str> = header_str.split_whitespace().collect();
-            if parts.len() >= 3 {
-                let key_masked =
-                    u64::from_str_radix(parts[1], 16).context("Invalid key in header")?;
-                let key = key_masked ^ ALT_KEY_MASK;
-                let offset =
-                    u64::from_str_radix(parts[2], 16).context("Invalid index offset in header")?;
-                return Ok((RpaVersion::Alt1, offset, Some(key)));
+    /// Offsets are hex, but some packers write decimal; prefer whichever lands inside the file
+    fn parse_offset(field: &str, archive_size: u64) -> Result<u64> {
+        let (digits, prefixed) = match field.strip_prefix("0x").or(field.strip_prefix("0X")) {
+            Some(digits) => (digits, true),
+            None => (field, false),
+        };
+        let hex = u64::from_str_radix(digits, 16).ok();
+        let decimal = if prefixed {
+            None
+        } else {
+            field.parse::<u64>().ok()
+        };
+
+        match (hex, decimal) {
+            (Some(hex), Some(decimal)) if hex > archive_size && decimal <= archive_size => {
+                Ok(decimal)
             }
-            anyhow::bail!("Invalid ALT-1.0 header format");
+            (Some(value), _) | (None, Some(value)) => Ok(value),
+            (None, None) => anyhow::bail!("Invalid index offset: {}", field),
         }
+    }
+
+    fn parse_key(field: &str) -> Result<u64> {
+        let digits = field
+            .strip_prefix("0x")
+            .or(field.strip_prefix("0X"))
+            .unwrap_or(field);
+        u64::from_str_radix(digits, 16)
+            .or_else(|_| field.parse::<u64>())
+            .with_context(|| format!("Invalid key: {}", field))
+    }
 
-        // RPA-2.0 format: "RPA-2.0 <offset>"
-        if header_str.starts_with("RPA-2.0") {
-            let parts: Vec<&str> = header_str.split_whitespace().collect();
-            if parts.len() >= 2 {
-                let offset =
-                    u64::from_str_radix(parts[1], 16).context("Invalid index offset in header")?;
-                return Ok((RpaVersion::Rpa2, offset, None));
+    /// Reject entries pointing outside the archive, so a crafted index cannot trigger huge reads
+    fn validate_entries(index: &HashMap<String, RpaEntry>, archive_size: u64) -> Result<()> {
+        for (name, entry) in index {
+            let end = entry.offset.checked_add(entry.length);
+            if end.is_none_or(|end| end > archive_size) {
+                anyhow::bail!(
+                    "Entry '{}' (offset {}, length {}) lies outside the archive ({} bytes)",
+                    name,
+                    entry.offset,
+                    entry.length,
+                    archive_size
+                );
             }
-            anyhow::bail!("Invalid RPA-2.0 header format");
         }
+        Ok(())
+    }
 
-        anyhow::bail!("Unsupported or invalid RPA format: {}", header_str)
+    /// The entries by name, and how many invalid sequences were replaced in the names
+    fn parse_index(
+        compressed: &[u8],
+        key: Option<u64>,
+    ) -> Result<(HashMap<String, RpaEntry>, usize)> {
+        Self::convert_index(Self::decode_index(compressed)?, key)
     }
 
-    fn parse_index(compressed: &[u8], key: Option<u64>) -> Result<HashMap<String, RpaEntry>> {
-        let mut decoder = ZlibDecoder::new(compressed);
+    fn decode_index(compressed: &[u8]) -> Result<PickleValue> {
+        let mut decoder = ZlibDecoder::new(compressed).take(MAX_INDEX_SIZE + 1);
         let mut decompressed = Vec::new();
         decoder
             .read_to_end(&mut decompressed)
             .context("Failed to decompress index")?;
+        if decompressed.len() as u64 > MAX_INDEX_SIZE {
+            anyhow::bail!(
+                "Decompressed index exceeds {} MiB",
+                MAX_INDEX_SIZE / 1024 / 1024
+            );
+        }
 
-        let pickle_value: PickleValue = serde_pickle::from_slice(&decompressed, Default::default())
-            .context("Failed to parse pickle index")?;
-
-        Self::convert_index(pickle_value, key)
+        // Decoded as a raw value so integers past 64 bits (masked with a 64-bit key) survive,
+        // and Python 3's `bytes` reduce for entry prefixes does not abort the parse
+        let options = serde_pickle::DeOptions::new().replace_unresolved_globals();
+        serde_pickle::value_from_slice(&decompressed, options)
+            .context("Failed to parse pickle index")
     }
 
-    fn convert_index(value: PickleValue, key: Option<u64>) -> Result<HashMap<String, RpaEntry>> {
+    fn convert_index(
+        value: PickleValue,
+        key: Option<u64>,
+    ) -> Result<(HashMap<String, RpaEntry>, usize)> {
         let mut index = HashMap::new();
+        let mut replaced = 0;
 
         let dict = match value {
             PickleValue::Dict(d) => d,
@@ -154,20 +460,20 @@ impl RpaArchive {
         };
 
         for (k, v) in dict {
-            let path = Self::extract_string_from_hashable(&k)?;
+            let (path, path_replaced) = Self::extract_string_from_hashable(&k)?;
             let entry = Self::extract_entry(&v, key)?;
             index.insert(path, entry);
+            replaced += path_replaced;
         }
 
-        Ok(index)
+        Ok((index, replaced))
     }
 
-    fn extract_string_from_hashable(value: &HashableValue) -> Result<String> {
+    /// A name and the number of invalid UTF-8 sequences replaced in it
+    fn extract_string_from_hashable(value: &HashableValue) -> Result<(String, usize)> {
         match value {
-            HashableValue::String(s) => Ok(s.clone()),
-            HashableValue::Bytes(b) => {
-                String::from_utf8(b.clone()).or_else(|_| Ok(String::from_utf8_lossy(b).to_string()))
-            }
+            HashableValue::String(s) => Ok((s.clone(), 0)),
+            HashableValue::Bytes(b) => Ok(decode_lossy(b, encoding_rs::UTF_8)),
             _ => anyhow::bail!("Expected string, got {:?}", value),
         }
     }
@@ -202,10 +508,11 @@ impl RpaArchive {
             Vec::new()
         };
 
-        let (offset, length) = if let Some(k) = key {
-            ((offset as u64) ^ k, (length as u64) ^ k)
-        } else {
-            (offset as u64, length as u64)
+        // XOR over the full 64 bits: RPA-4.0 keys are 64-bit, so masked values routinely
+        // exceed `i64::MAX`; 3.x keys are 32-bit and leave the upper half untouched
+        let (offset, length) = match key {
+            Some(k) => (offset ^ k, length ^ k),
+            None => (offset, length),
         };
 
         Ok(RpaEntry {
@@ -215,12 +522,15 @@ impl RpaArchive {
         })
     }
 
-    fn extract_int(value: &PickleValue) -> Result<i64> {
+    /// Index integers as the 64-bit pattern they are masked with. Python pickles values past
+    /// `i64::MAX` as arbitrary-precision longs; packers storing signed 64-bit values produce
+    /// negatives, which are reinterpreted bit for bit.
+    fn extract_int(value: &PickleValue) -> Result<u64> {
         match value {
-            PickleValue::I64(i) => Ok(*i),
+            PickleValue::I64(i) => Ok(*i as u64),
             PickleValue::Int(i) => i
                 .try_into()
-                .map_err(|_| anyhow::anyhow!("Integer too large")),
+                .map_err(|_| anyhow::anyhow!("Integer does not fit in 64 bits: {}", i)),
             _ => anyhow::bail!("Expected integer, got {:?}", value),
         }
     }
@@ -233,53 +543,180 @@ impl RpaArchive {
         }
     }
 
-    pub fn extract_file<P: AsRef<Path>>(&self, name: &str, output_dir: P) -> Result<PathBuf> {
+    /// Give extracted files the archive's own modification time instead of the current time
+    pub fn with_preserved_mtime(mut self, preserve: bool) -> Result<Self> {
+        self.mtime = if preserve {
+            let modified = fs::metadata(&self.path)
+                .and_then(|m| m.modified())
+                .context("Failed to read archive modification time")?;
+            Some(modified)
+        } else {
+            None
+        };
+        Ok(self)
+    }
+
+    /// Skip entries whose output file already exists with the expected size
+    pub fn with_skip_existing(mut self, skip_existing: bool) -> Self {
+        self.skip_existing = skip_existing;
+        self
+    }
+
+    /// Whether `name` was already extracted to `output_dir`, judged by its size
+    fn already_extracted(&self, name: &str, output_dir: &Path) -> bool {
+        let Some(entry) = self.index.get(name) else {
+            return false;
+        };
+        let expected = entry.length + entry.prefix.len() as u64;
+        fs::metadata(output_dir.join(name)).is_ok_and(|m| m.is_file() && m.len() == expected)
+    }
+
+    /// The archive positioned at an entry's data, limited to its declared length
+    fn open_entry(&self, name: &str) -> Result<(&RpaEntry, std::io::Take<File>)> {
         let entry = self
             .index
             .get(name)
             .context(format!("File '{}' not found in archive", name))?;
 
-        let output_path = output_dir.as_ref().join(name);
-
-        if let Some(parent) = output_path.parent() {
-            fs::create_dir_all(parent).context("Failed to create output directory")?;
-        }
-
         let mut archive = File::open(&self.path).context("Failed to open archive")?;
         archive
             .seek(SeekFrom::Start(entry.offset))
             .context("Failed to seek to file data")?;
 
-        let mut data = vec![0u8; entry.length as usize];
-        archive
-            .read_exact(&mut data)
+        Ok((entry, archive.take(entry.length)))
+    }
+
+    fn check_length(name: &str, entry: &RpaEntry, copied: u64) -> Result<()> {
+        if copied != entry.length {
+            anyhow::bail!(
+                "Archive is truncated: '{}' expected {} bytes, got {}",
+                name,
+                entry.length,
+                copied
+            );
+        }
+        Ok(())
+    }
+
+    /// An entry's full contents (prefix and data) in memory, without touching the disk
+    pub fn read_file_bytes(&self, name: &str) -> Result<Vec<u8>> {
+        let (entry, mut data) = self.open_entry(name)?;
+
+        let mut bytes = Vec::with_capacity(entry.prefix.len() + entry.length as usize);
+        bytes.extend_from_slice(&entry.prefix);
+        let copied = data
+            .read_to_end(&mut bytes)
             .context("Failed to read file data")?;
+        Self::check_length(name, entry, copied as u64)?;
+
+        Ok(bytes)
+    }
 
-        let mut output = File::create(&output_path).context("Failed to create output file")?;
+    /// Size of an entry once extracted, prefix included
+    pub fn entry_size(&self, name: &str) -> Option<u64> {
+        self.index
+            .get(name)
+            .map(|e| e.length + e.prefix.len() as u64)
+    }
+
+    /// MD5 of an entry's extracted contents, streamed so large assets stay out of memory
+    pub fn content_hash(&self, name: &str) -> Result<md5::Digest> {
+        let (entry, data) = self.open_entry(name)?;
+
+        let mut context = md5::Context::new();
+        context.consume(&entry.prefix);
+        let mut reader = BufReader::with_capacity(STREAM_BUFFER_SIZE, data);
+        let copied =
+            std::io::copy(&mut reader, &mut context).context("Failed to read file data")?;
+        Self::check_length(name, entry, copied)?;
+
+        Ok(context.compute())
+    }
 
-        if !entry.prefix.is_empty() {
+    pub fn extract_file<P: AsRef<Path>>(&self, name: &str, output_dir: P) -> Result<PathBuf> {
+        let output_path = output_dir.as_ref().join(name);
+
+        if let Some(parent) = output_path.parent() {
+            fs::create_dir_all(parent).context("Failed to create output directory")?;
+        }
+
+        let small = self
+            .index
+            .get(name)
+            .is_some_and(|e| e.length <= STREAM_THRESHOLD);
+        let output = if small {
+            let bytes = self.read_file_bytes(name)?;
+            let mut output = File::create(&output_path).context("Failed to create output file")?;
+            output
+                .write_all(&bytes)
+                .context("Failed to write file data")?;
+            output
+        } else {
+            let (entry, data) = self.open_entry(name)?;
+            let mut output = File::create(&output_path).context("Failed to create output file")?;
+            if !entry.prefix.is_empty() {
+                output
+                    .write_all(&entry.prefix)
+                    .context("Failed to write prefix")?;
+            }
+            // Multi-GB videos would not fit in memory, especially with parallel extraction
+            let mut reader = BufReader::with_capacity(STREAM_BUFFER_SIZE, data);
+            let copied =
+                std::io::copy(&mut reader, &mut output).context("Failed to write file data")?;
+            Self::check_length(name, entry, copied)?;
+            output
+        };
+
+        if let Some(mtime) = self.mtime {
             output
-                .write_all(&entry.prefix)
-                .context("Failed to write prefix")?;
+                .set_modified(mtime)
+                .context("Failed to set file modification time")?;
         }
-        output
-            .write_all(&data)
-            .context("Failed to write file data")?;
 
         Ok(output_path)
     }
 
+    /// Entry names in a deterministic order; offset order reads the archive sequentially
+    pub fn sorted_names(&self, order: EntryOrder) -> Vec<&str> {
+        let mut entries: Vec<(&String, &RpaEntry)> = self.index.iter().collect();
+        match order {
+            EntryOrder::Name => entries.sort_by(|a, b| a.0.cmp(b.0)),
+            EntryOrder::Offset => {
+                entries.sort_by(|a, b| a.1.offset.cmp(&b.1.offset).then(a.0.cmp(b.0)))
+            }
+        }
+        entries.into_iter().map(|(name, _)| name.as_str()).collect()
+    }
+
     pub fn extract_all<P: AsRef<Path>>(
         &self,
         output_dir: P,
         progress: Option<&indicatif::ProgressBar>,
+        order: EntryOrder,
     ) -> Result<Vec<PathBuf>> {
-        let names: Vec<String> = self.index.keys().cloned().collect();
+        self.extract_matching(output_dir, progress, order, &EntryFilter::default())
+    }
+
+    /// Extract only the entries accepted by `filter`, returning the files actually written
+    pub fn extract_matching<P: AsRef<Path>>(
+        &self,
+        output_dir: P,
+        progress: Option<&indicatif::ProgressBar>,
+        order: EntryOrder,
+        filter: &EntryFilter,
+    ) -> Result<Vec<PathBuf>> {
+        let names: Vec<&str> = self
+            .sorted_names(order)
+            .into_iter()
+            .filter(|name| filter.matches(name))
+            .collect();
         let mut extracted = Vec::with_capacity(names.len());
 
-        for name in &names {
-            let path = self.extract_file(name, output_dir.as_ref())?;
-            extracted.push(path);
+        for name in names {
+            if !(self.skip_existing && self.already_extracted(name, output_dir.as_ref())) {
+                let path = self.extract_file(name, output_dir.as_ref())?;
+                extracted.push(path);
+            }
             if let Some(pb) = progress {
                 pb.inc(1);
             }
@@ -292,3 +729,170 @@ impl RpaArchive {
         self.index.len()
     }
 }
+
+/// Every pickle value tagged with its type (`{"Tuple": [...]}`, `{"I64": 5}`), so the dump
+/// shows tuples vs lists, integer widths and bytes vs string keys
+fn pickle_to_json(value: &PickleValue) -> serde_json::Value {
+    use serde_json::{Value, json};
+    let items = |values: &[PickleValue]| Value::Array(values.iter().map(pickle_to_json).collect());
+    match value {
+        PickleValue::None => json!("None"),
+        PickleValue::Bool(b) => json!({ "Bool": b }),
+        PickleValue::I64(i) => json!({ "I64": i }),
+        PickleValue::Int(i) => json!({ "Int": i.to_string() }),
+        PickleValue::F64(f) => json!({ "F64": f }),
+        PickleValue::Bytes(b) => json!({ "Bytes": String::from_utf8_lossy(b) }),
+        PickleValue::String(s) => json!({ "String": s }),
+        PickleValue::List(l) => json!({ "List": items(l) }),
+        PickleValue::Tuple(t) => json!({ "Tuple": items(t) }),
+        PickleValue::Set(s) => json!({ "Set": s.iter().map(hashable_to_json).collect::<Vec<_>>() }),
+        PickleValue::FrozenSet(s) => {
+            json!({ "FrozenSet": s.iter().map(hashable_to_json).collect::<Vec<_>>() })
+        }
+        PickleValue::Dict(d) => json!({
+            "Dict": d
+                .iter()
+                .map(|(k, v)| json!({ "key": hashable_to_json(k), "value": pickle_to_json(v) }))
+                .collect::<Vec<_>>()
+        }),
+    }
+}
+
+fn hashable_to_json(value: &HashableValue) -> serde_json::Value {
+    pickle_to_json(&value.clone().into_value())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_file_bytes() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let source = temp_dir.path().join("script.rpy");
+        fs::write(&source, "label start:\n    return\n").unwrap();
+        let rpa_path = temp_dir.path().join("scripts.rpa");
+
+        let mut writer = crate::repack::rpa::RpaWriter::with_version(
+            &rpa_path,
+            crate::repack::rpa::RpaWriterVersion::Rpa3,
+        )
+        .unwrap();
+        writer.add_file(&source, Path::new("script.rpy")).unwrap();
+        writer.finish().unwrap();
+
+        let archive = RpaArchive::open(&rpa_path).unwrap();
+        assert_eq!(
+            archive.read_file_bytes("script.rpy").unwrap(),
+            b"label start:\n    return\n"
+        );
+        assert!(archive.read_file_bytes("missing.rpy").is_err());
+    }
+
+    #[test]
+    fn test_sorted_names() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let rpa_path = temp_dir.path().join("scripts.rpa");
+        let mut writer = crate::repack::rpa::RpaWriter::with_version(
+            &rpa_path,
+            crate::repack::rpa::RpaWriterVersion::Rpa3,
+        )
+        .unwrap();
+        // Data is written in the order files are added
+        for name in ["b.rpy", "a.rpy", "c.rpy"] {
+            let source = temp_dir.path().join(name);
+            fs::write(&source, name).unwrap();
+            writer.add_file(&source, Path::new(name)).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let archive = RpaArchive::open(&rpa_path).unwrap();
+        assert_eq!(
+            archive.sorted_names(EntryOrder::from_str("name")),
+            ["a.rpy", "b.rpy", "c.rpy"]
+        );
+        assert_eq!(
+            archive.sorted_names(EntryOrder::from_str("offset")),
+            ["b.rpy", "a.rpy", "c.rpy"]
+        );
+    }
+
+    #[test]
+    fn test_entry_filter() {
+        let all = EntryFilter::default();
+        assert!(all.matches("images/bg.png"));
+
+        let scripts = EntryFilter::new(&[], &[]).with_extensions(SCRIPT_EXTENSIONS);
+        assert!(scripts.matches("script.rpyc"));
+        assert!(scripts.matches("tl/None/common.RPYMC"));
+        assert!(!scripts.matches("images/bg.png"));
+
+        let globs = EntryFilter::new(&["gui/**".to_string()], &["**/*.ttf".to_string()])
+            .with_extensions(AUDIO_EXTENSIONS);
+        assert!(globs.matches("gui/button/idle.png"));
+        assert!(globs.matches("audio/theme.ogg"));
+        assert!(!globs.matches("gui/font.ttf"));
+        assert!(!globs.matches("images/gui/x.png"));
+
+        let listed = EntryFilter::new(&[], &["**/*.ttf".to_string()])
+            .with_names(&["script.rpyc".to_string(), "gui/font.ttf".to_string()]);
+        assert!(listed.matches("script.rpyc"));
+        assert!(!listed.matches("gui/font.ttf"));
+        assert!(!listed.matches("options.rpyc"));
+    }
+
+    #[test]
+    fn test_header_variations() {
+        let parse =
+            |header: &str| RpaArchive::parse_header(header.as_bytes(), 0x10000, false).unwrap();
+
+        assert_eq!(
+            parse("RPA-3.0 0000000000a1b2 42424242\n"),
+            (RpaVersion::Rpa3, 0xa1b2, Some(0x42424242))
+        );
+        // Trailing whitespace, NUL padding and extra fields
+        assert_eq!(
+            parse("RPA-3.0 a1b2 42424242  \t\0\0\r\n"),
+            (RpaVersion::Rpa3, 0xa1b2, Some(0x42424242))
+        );
+        assert_eq!(
+            parse("RPA-3.2 a1b2 42424242 1\n"),
+            (RpaVersion::Rpa32, 0xa1b2, Some(0x42424242))
+        );
+        // 0x prefixes
+        assert_eq!(
+            parse("RPA-3.0 0xa1b2 0X42424242\n"),
+            (RpaVersion::Rpa3, 0xa1b2, Some(0x42424242))
+        );
+        // Decimal offset: as hex it would point past the end of the archive
+        assert_eq!(
+            parse("RPA-3.0 41394 42424242\n"),
+            (RpaVersion::Rpa3, 41394, Some(0x42424242))
+        );
+        assert_eq!(parse("RPA-2.0 a1b2\n"), (RpaVersion::Rpa2, 0xa1b2, None));
+        assert_eq!(
+            parse("ALT-1.0 dabe8df1 a1b2\n"),
+            (RpaVersion::Alt1, 0xa1b2, Some(1))
+        );
+
+        // Two fields only: no key means key 0
+        assert_eq!(parse("RPA-3.0 a1b2\n"), (RpaVersion::Rpa3, 0xa1b2, Some(0)));
+
+        assert!(RpaArchive::parse_header(b"RPA-3.0\n", 0x10000, false).is_err());
+        assert!(RpaArchive::parse_header(b"RPA-3.0 a1b2 zz\n", 0x10000, false).is_err());
+        assert!(RpaArchive::parse_header(b"RPA-3.0 zz 42\n", 0x10000, false).is_err());
+        assert!(RpaArchive::parse_header(b"PK\x03\x04", 0x10000, false).is_err());
+
+        // A renamed tag only parses when forced
+        assert!(RpaArchive::parse_header(b"GAM-3.0 a1b2 42\n", 0x10000, false).is_err());
+        assert_eq!(
+            RpaArchive::parse_header(b"GAM-3.0 a1b2 42\n", 0x10000, true).unwrap(),
+            (RpaVersion::Rpa3, 0xa1b2, Some(0x42))
+        );
+        assert_eq!(
+            RpaArchive::parse_header(b"GAM-2.0 a1b2\n", 0x10000, true).unwrap(),
+            (RpaVersion::Rpa2, 0xa1b2, None)
+        );
+        assert!(RpaArchive::parse_header(b"PK\x03\x04", 0x10000, true).is_err());
+    }
+}
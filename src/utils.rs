@@ -1,5 +1,9 @@
 //! Common utility functions
 
+use anyhow::{Context, Result};
+use encoding_rs::{DecoderResult, Encoding};
+use std::path::PathBuf;
+
 #[allow(dead_code)]
 pub fn truncate_display(s: &str, max_len: usize) -> String {
     if s.chars().count() <= max_len {
@@ -9,6 +13,53 @@ pub fn truncate_display(s: &str, max_len: usize) -> String {
     }
 }
 
+/// Decode `bytes` as `encoding`, replacing malformed sequences with U+FFFD
+///
+/// Also returns how many replacements were made, so callers can tell a file with encoding
+/// problems from one that merely contains U+FFFD. A byte order mark overrides `encoding`.
+pub fn decode_lossy(bytes: &[u8], encoding: &'static Encoding) -> (String, usize) {
+    let mut decoder = encoding.new_decoder();
+    let mut text = String::with_capacity(bytes.len());
+    let mut replaced = 0;
+    let mut src = bytes;
+
+    loop {
+        let (result, read) = decoder.decode_to_string_without_replacement(src, &mut text, true);
+        src = &src[read..];
+        match result {
+            DecoderResult::InputEmpty => break,
+            DecoderResult::OutputFull => text.reserve(
+                decoder
+                    .max_utf8_buffer_length_without_replacement(src.len())
+                    .unwrap_or(src.len() * 3)
+                    .max(4),
+            ),
+            DecoderResult::Malformed(_, _) => {
+                text.push('\u{FFFD}');
+                replaced += 1;
+            }
+        }
+    }
+
+    (text, replaced)
+}
+
+/// Where derenpy keeps its caches: `$DERENPY_CACHE_DIR` if set, else `derenpy` under the
+/// user cache directory
+pub fn cache_root() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("DERENPY_CACHE_DIR") {
+        return Ok(PathBuf::from(dir));
+    }
+    Ok(dirs::cache_dir()
+        .context("Failed to find cache directory")?
+        .join("derenpy"))
+}
+
+/// A `tl/` directory below the walk root, whose scripts are translations rather than sources
+pub fn is_tl_dir(entry: &walkdir::DirEntry) -> bool {
+    entry.depth() > 0 && entry.file_type().is_dir() && entry.file_name() == "tl"
+}
+
 pub fn unquote(s: &str) -> String {
     let s = s.trim();
     if (s.starts_with('"') && s.ends_with('"')) || (s.starts_with('\'') && s.ends_with('\'')) {
@@ -18,15 +69,181 @@ pub fn unquote(s: &str) -> String {
     }
 }
 
+/// Escape control characters that would break a one-line string literal
+///
+/// Newlines (CRLF counted once), carriage returns and tabs become `\n`, `\r` and `\t`;
+/// any other C0 control character has no place in a script and is dropped.
+pub fn escape_control_chars(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if c.is_ascii_control() => {}
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Parse a unix timestamp or a `YYYY-MM-DD` date (UTC midnight) into seconds
+pub fn parse_timestamp(s: &str) -> Option<i64> {
+    let s = s.trim();
+    if let Ok(ts) = s.parse::<i64>() {
+        return Some(ts);
+    }
+
+    let mut parts = s.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days from civil date, proleptic Gregorian calendar
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    Some((era * 146097 + doe - 719468) * 86400)
+}
+
+/// Format a unix timestamp as `YYYY-MM-DD HH:MM:SS` (UTC)
+pub fn format_timestamp(ts: i64) -> String {
+    let days = ts.div_euclid(86400);
+    let secs = ts.rem_euclid(86400);
+
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Compile a glob (`*` within a path segment, `**` across segments, `?`) into an anchored regex
+pub fn glob_to_regex(pattern: &str) -> regex::Regex {
+    let mut re = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                // "**/" also matches zero directories
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    re.push_str("(?:.*/)?");
+                } else {
+                    re.push_str(".*");
+                }
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            _ => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    regex::Regex::new(&re).expect("escaped glob is a valid regex")
+}
+
+/// `--files` globs selecting scripts by path relative to the input directory; a pattern
+/// without `/` matches the file name at any depth
+#[derive(Debug, Default)]
+pub struct FileFilter {
+    patterns: Vec<(regex::Regex, bool)>,
+}
+
+impl FileFilter {
+    pub fn new(globs: &[String]) -> Self {
+        Self {
+            patterns: globs
+                .iter()
+                .map(|g| (glob_to_regex(g), !g.contains('/')))
+                .collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    pub fn matches(&self, rel_path: &std::path::Path) -> bool {
+        if self.patterns.is_empty() {
+            return true;
+        }
+        let path = rel_path.to_string_lossy().replace('\\', "/");
+        let name = rel_path
+            .file_name()
+            .map(|n| n.to_string_lossy())
+            .unwrap_or_default();
+        self.patterns
+            .iter()
+            .any(|(re, name_only)| re.is_match(&path) || (*name_only && re.is_match(&name)))
+    }
+}
+
 pub fn is_code_like(s: &str) -> bool {
-    s.starts_with('[')
-        || s.starts_with('{')
+    is_bracket_only(s)
         || s.contains("%(")
         || s.starts_with("!!")
         || s.chars()
             .all(|c| c.is_ascii_punctuation() || c.is_whitespace())
 }
 
+/// True if the string is nothing but `[...]` interpolations and `{...}` tags, with no prose
+fn is_bracket_only(s: &str) -> bool {
+    let mut rest = s.trim();
+    if !rest.starts_with('[') && !rest.starts_with('{') {
+        return false;
+    }
+
+    while let Some(open) = rest.chars().next() {
+        let close = match open {
+            '[' => ']',
+            '{' => '}',
+            _ => return false,
+        };
+        let mut depth = 0;
+        let mut end = None;
+        for (i, c) in rest.char_indices() {
+            if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    end = Some(i);
+                    break;
+                }
+            }
+        }
+        match end {
+            Some(i) => rest = rest[i + 1..].trim_start(),
+            // Unbalanced: treat as code rather than risk mangling it
+            None => return true,
+        }
+    }
+    true
+}
+
 pub const RENPY_KEYWORDS: &[&str] = &[
     // Control flow
     "label ",
@@ -34,7 +251,7 @@ pub const RENPY_KEYWORDS: &[&str] = &[
     "call ",
     "return",
     "pass",
-    "menu:",
+    "menu",
     "if ",
     "elif ",
     "else:",
@@ -63,7 +280,7 @@ pub const RENPY_KEYWORDS: &[&str] = &[
     "voice ",
     // UI
     "nvl ",
-    "window ",
+    "window",
     "pause",
     // Screen language (ATL & displayables)
     "add ",
@@ -103,6 +320,21 @@ pub const RENPY_KEYWORDS: &[&str] = &[
     "translate ",
 ];
 
+/// Whether a line starts with a Ren'Py statement keyword
+///
+/// Bare-word keywords must end at a word boundary, so `pause 1.0` and `window show`
+/// match while a character named `pauline` or `sidekick` does not.
 pub fn is_renpy_keyword(line: &str) -> bool {
-    RENPY_KEYWORDS.iter().any(|k| line.starts_with(k))
+    RENPY_KEYWORDS.iter().any(|k| {
+        line.strip_prefix(k).is_some_and(|rest| {
+            !k.ends_with(|c: char| c.is_alphanumeric())
+                || !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_')
+        })
+    })
+}
+
+/// Whether a line opens a `style` or `transform` block, whose indented lines are
+/// properties and ATL (`text_color "#fff"`), never player-facing text
+pub fn opens_style_block(line: &str) -> bool {
+    (line.starts_with("style ") || line.starts_with("transform ")) && line.ends_with(':')
 }
@@ -29,6 +29,7 @@ fn test_help_command() {
     assert!(stdout.contains("patch"), "Should list patch command");
     assert!(stdout.contains("auto"), "Should list auto command");
     assert!(stdout.contains("config"), "Should list config command");
+    assert!(stdout.contains("cache"), "Should list cache command");
 }
 
 #[test]
@@ -113,3 +114,127 @@ fn test_missing_input() {
 
     assert!(!output.status.success(), "Should fail without input");
 }
+
+#[test]
+fn test_global_config_flag() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("derenpy.toml");
+    let config_arg = config_path.to_str().unwrap();
+
+    let output = derenpy()
+        .args(["--config", config_arg])
+        .args(["config", "set", "translation.default_language", "japanese"])
+        .output()
+        .expect("Failed to run config set");
+    assert!(output.status.success());
+    assert!(
+        config_path.exists(),
+        "Should write to the given config file"
+    );
+
+    let output = derenpy()
+        .args(["config", "get", "translation.default_language"])
+        .args(["--config", config_arg])
+        .output()
+        .expect("Failed to run config get");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("japanese"));
+
+    let output = derenpy()
+        .args(["--config", config_arg, "config", "path"])
+        .output()
+        .expect("Failed to run config path");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(config_arg));
+}
+
+#[test]
+fn test_config_set_any_field() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("derenpy.toml");
+    let run = |args: &[&str]| {
+        derenpy()
+            .args(["--config", config_path.to_str().unwrap(), "config"])
+            .args(args)
+            .output()
+            .expect("Failed to run config")
+    };
+
+    assert!(
+        run(&["set", "api.deepl_api_key", "abcdefgh12345:fx"])
+            .status
+            .success()
+    );
+    let stdout = String::from_utf8_lossy(&run(&["get", "api.deepl_api_key"]).stdout).to_string();
+    assert!(
+        stdout.contains("abcd...5:fx"),
+        "API keys are masked: {}",
+        stdout
+    );
+
+    assert!(run(&["set", "general.verbose", "true"]).status.success());
+    assert!(!run(&["set", "general.verbose", "maybe"]).status.success());
+
+    let stdout = String::from_utf8_lossy(&run(&["get", "paths.python"]).stdout).to_string();
+    assert!(stdout.contains("(not set)"));
+
+    assert!(!run(&["set", "api.no_such_key", "x"]).status.success());
+    assert!(!run(&["get", "api.no_such_key"]).status.success());
+}
+
+#[test]
+fn test_config_api_key_list() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("derenpy.toml");
+    std::fs::write(
+        &config_path,
+        "[api]\nopenai_api_key = [\"sk-first-key-0001\", \"sk-second-key-0002\"]\n",
+    )
+    .unwrap();
+
+    let output = derenpy()
+        .args(["--config", config_path.to_str().unwrap()])
+        .args(["config", "get", "api.openai_api_key"])
+        .output()
+        .expect("Failed to run config get");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains("sk-first-key-0001"), "{}", stdout);
+    assert!(stdout.contains("[sk-f...0001, sk-s...0002]"), "{}", stdout);
+}
+
+#[test]
+fn test_config_migrates_old_file() {
+    let temp_dir = tempfile::TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("derenpy.toml");
+    std::fs::write(
+        &config_path,
+        "[api]\nclaude_api_key = \"sk-ant-key-0001\"\n",
+    )
+    .unwrap();
+
+    let output = derenpy()
+        .args(["--config", config_path.to_str().unwrap()])
+        .args(["config", "get", "api.anthropic_api_key"])
+        .output()
+        .expect("Failed to run config get");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("sk-a...0001"));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("renamed api.claude_api_key to api.anthropic_api_key"),
+        "{}",
+        stderr
+    );
+
+    // The upgrade is written back, so it only happens once
+    let content = std::fs::read_to_string(&config_path).unwrap();
+    assert!(content.contains("config_version = 1"), "{}", content);
+    assert!(!content.contains("claude_api_key"));
+    let output = derenpy()
+        .args(["--config", config_path.to_str().unwrap()])
+        .args(["config", "get", "api.anthropic_api_key"])
+        .output()
+        .expect("Failed to run config get");
+    assert!(!String::from_utf8_lossy(&output.stderr).contains("Migrated"));
+}
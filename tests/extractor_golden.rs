@@ -0,0 +1,68 @@
+//! Golden tests for the script text extractor
+//!
+//! Each `tests/golden/extractor/<case>.rpy` is extracted and compared line by line with
+//! `<case>.golden` (`line<TAB>type<TAB>text`). Run with `UPDATE_GOLDEN=1` to rewrite the
+//! expectations after an intended change, then review the diff.
+
+// The crate has no library target, so the extractor is compiled into this test directly
+#[allow(dead_code)]
+#[path = "../src/utils.rs"]
+mod utils;
+
+#[allow(dead_code)]
+#[path = "../src/translate/extractor.rs"]
+mod extractor;
+
+use extractor::TextExtractor;
+use std::fs;
+use std::path::Path;
+
+fn render(source: &str) -> String {
+    let extractor = TextExtractor::new()
+        .with_character_names(true)
+        .with_defines(true);
+    extractor
+        .extract_from_string(source)
+        .unwrap()
+        .iter()
+        .map(|e| format!("{}\t{:?}\t{}\n", e.line_number, e.entry_type, e.text))
+        .collect()
+}
+
+#[test]
+fn test_extractor_golden() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/extractor");
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut cases: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|ext| ext == "rpy"))
+        .collect();
+    cases.sort();
+    assert!(!cases.is_empty(), "No golden cases in {}", dir.display());
+
+    let mut mismatches = Vec::new();
+    for case in &cases {
+        let actual = render(&fs::read_to_string(case).unwrap());
+        let golden = case.with_extension("golden");
+
+        if update {
+            fs::write(&golden, &actual).unwrap();
+            continue;
+        }
+
+        let expected =
+            fs::read_to_string(&golden).unwrap_or_else(|_| panic!("Missing {}", golden.display()));
+        if actual != expected {
+            mismatches.push(format!(
+                "{}\n--- expected\n{}--- actual\n{}",
+                case.display(),
+                expected,
+                actual
+            ));
+        }
+    }
+
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
@@ -82,3 +82,838 @@ fn test_rpa_version_header() {
         "Should create RPA-3.0 by default"
     );
 }
+
+/// `tests/fixtures/rpa40.rpa` was written by Python's `pickle` (protocol 2) with the 64-bit key
+/// 0xdeadbeefcafef00d, so its masked offsets and lengths are pickled as longs past `i64::MAX`
+#[test]
+fn test_unpack_rpa40_fixture() {
+    let temp_dir = TempDir::new().unwrap();
+    let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/rpa40.rpa");
+    let extract_dir = temp_dir.path().join("extracted");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args(["unpack", fixture, "-o", extract_dir.to_str().unwrap()])
+        .output()
+        .expect("Failed to run unpack");
+    assert!(
+        output.status.success(),
+        "Unpack should succeed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    assert_eq!(
+        fs::read_to_string(extract_dir.join("script.rpy")).unwrap(),
+        "label start:\n    \"Hello from RPA-4.0\"\n"
+    );
+    assert_eq!(
+        fs::read_to_string(extract_dir.join("images/bg.txt")).unwrap(),
+        "not really an image\n"
+    );
+}
+
+#[test]
+fn test_rpa40_roundtrip() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir_all(source_dir.join("audio")).unwrap();
+    fs::write(source_dir.join("script.rpy"), "label start:\n    return\n").unwrap();
+    let data: Vec<u8> = (0..=255u8).cycle().take(100_000).collect();
+    fs::write(source_dir.join("audio/theme.ogg"), &data).unwrap();
+
+    let rpa_path = temp_dir.path().join("game.rpa");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "repack",
+            source_dir.to_str().unwrap(),
+            "-o",
+            rpa_path.to_str().unwrap(),
+            "--version",
+            "4.0",
+        ])
+        .status()
+        .expect("Failed to run repack");
+    assert!(status.success(), "Repack should succeed");
+
+    let content = fs::read(&rpa_path).unwrap();
+    let header = String::from_utf8_lossy(&content[..42]);
+    let fields: Vec<&str> = header.split_whitespace().collect();
+    assert_eq!(fields[0], "RPA-4.0");
+    assert_eq!(fields[2].len(), 16, "RPA-4.0 keys are 64-bit");
+
+    let extract_dir = temp_dir.path().join("extracted");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "unpack",
+            rpa_path.to_str().unwrap(),
+            "-o",
+            extract_dir.to_str().unwrap(),
+        ])
+        .status()
+        .expect("Failed to run unpack");
+    assert!(status.success(), "Unpack should succeed");
+
+    assert_eq!(
+        fs::read_to_string(extract_dir.join("script.rpy")).unwrap(),
+        "label start:\n    return\n"
+    );
+    assert_eq!(fs::read(extract_dir.join("audio/theme.ogg")).unwrap(), data);
+}
+
+#[test]
+fn test_rpa_split_rpi_index() {
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use std::collections::BTreeMap;
+    use std::io::Write;
+
+    let temp_dir = TempDir::new().unwrap();
+
+    // Headerless data file with its index in a sibling .rpi
+    let rpa_path = temp_dir.path().join("data.rpa");
+    fs::write(&rpa_path, "helloworld!").unwrap();
+
+    let mut index: BTreeMap<&str, Vec<(i64, i64)>> = BTreeMap::new();
+    index.insert("a.txt", vec![(0, 5)]);
+    index.insert("sub/b.txt", vec![(5, 6)]);
+    let pickled = serde_pickle::to_vec(&index, Default::default()).unwrap();
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&pickled).unwrap();
+    fs::write(temp_dir.path().join("data.rpi"), encoder.finish().unwrap()).unwrap();
+
+    let extract_dir = temp_dir.path().join("extracted");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "unpack",
+            rpa_path.to_str().unwrap(),
+            "-o",
+            extract_dir.to_str().unwrap(),
+        ])
+        .status()
+        .expect("Failed to run unpack");
+
+    assert!(status.success(), "Unpack should succeed");
+    assert_eq!(
+        fs::read_to_string(extract_dir.join("a.txt")).unwrap(),
+        "hello"
+    );
+    assert_eq!(
+        fs::read_to_string(extract_dir.join("sub/b.txt")).unwrap(),
+        "world!"
+    );
+}
+
+#[test]
+fn test_rpa_rejects_out_of_bounds_entry() {
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use std::collections::BTreeMap;
+    use std::io::Write;
+
+    let temp_dir = TempDir::new().unwrap();
+
+    // An index claiming an entry far larger than the archive itself
+    let mut index: BTreeMap<&str, Vec<(i64, i64)>> = BTreeMap::new();
+    index.insert("huge.bin", vec![(34, i64::MAX)]);
+    let pickled = serde_pickle::to_vec(&index, Default::default()).unwrap();
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&pickled).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let header = format!("RPA-2.0 {:016x}\n", 34 + 5);
+    let mut archive = header.into_bytes();
+    archive.resize(34, b' ');
+    archive.extend_from_slice(b"data!");
+    archive.extend_from_slice(&compressed);
+
+    let rpa_path = temp_dir.path().join("evil.rpa");
+    fs::write(&rpa_path, archive).unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "unpack",
+            rpa_path.to_str().unwrap(),
+            "-o",
+            temp_dir.path().join("out").to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run unpack");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("lies outside the archive"), "{}", stderr);
+}
+
+#[test]
+fn test_unpack_info_only() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir(&source_dir).unwrap();
+    fs::write(source_dir.join("a.txt"), "a").unwrap();
+    fs::write(source_dir.join("b.txt"), "b").unwrap();
+
+    let rpa_path = temp_dir.path().join("test.rpa");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "repack",
+            source_dir.to_str().unwrap(),
+            "-o",
+            rpa_path.to_str().unwrap(),
+        ])
+        .status()
+        .expect("Failed to run repack");
+    assert!(status.success());
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args(["unpack", rpa_path.to_str().unwrap(), "--info-only"])
+        .output()
+        .expect("Failed to run unpack");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Version: RPA-3.0"), "{}", stdout);
+    assert!(stdout.contains("Files: 2"), "{}", stdout);
+    assert!(stdout.contains("Index offset: 0x"), "{}", stdout);
+    assert!(stdout.contains("Key: 0x"), "{}", stdout);
+    assert!(
+        !temp_dir.path().join("test").exists(),
+        "Nothing should be extracted"
+    );
+}
+
+#[test]
+fn test_repack_excludes_junk() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir_all(source_dir.join("images")).unwrap();
+    fs::write(source_dir.join("script.rpy"), "label start:\n").unwrap();
+    fs::write(source_dir.join("notes.log"), "debug").unwrap();
+    fs::write(source_dir.join(".DS_Store"), "junk").unwrap();
+    fs::write(source_dir.join("images").join("Thumbs.db"), "junk").unwrap();
+    fs::write(source_dir.join("images").join("bg.png"), "png").unwrap();
+
+    let rpa_path = temp_dir.path().join("test.rpa");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "repack",
+            source_dir.to_str().unwrap(),
+            "-o",
+            rpa_path.to_str().unwrap(),
+            "--exclude",
+            "*.log",
+        ])
+        .status()
+        .expect("Failed to run repack");
+    assert!(status.success());
+
+    let extract_dir = temp_dir.path().join("extracted");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "unpack",
+            rpa_path.to_str().unwrap(),
+            "-o",
+            extract_dir.to_str().unwrap(),
+        ])
+        .status()
+        .expect("Failed to run unpack");
+    assert!(status.success());
+
+    assert!(extract_dir.join("script.rpy").exists());
+    assert!(extract_dir.join("images").join("bg.png").exists());
+    assert!(!extract_dir.join("notes.log").exists());
+    assert!(!extract_dir.join(".DS_Store").exists());
+    assert!(!extract_dir.join("images").join("Thumbs.db").exists());
+}
+
+#[test]
+fn test_unpack_preserve_mtime() {
+    use std::time::{Duration, SystemTime};
+
+    let temp_dir = TempDir::new().unwrap();
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir(&source_dir).unwrap();
+    fs::write(source_dir.join("test.txt"), "hello").unwrap();
+
+    let rpa_path = temp_dir.path().join("test.rpa");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "repack",
+            source_dir.to_str().unwrap(),
+            "-o",
+            rpa_path.to_str().unwrap(),
+        ])
+        .status()
+        .expect("Failed to run repack");
+    assert!(status.success());
+
+    let archive_mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    fs::File::options()
+        .write(true)
+        .open(&rpa_path)
+        .unwrap()
+        .set_modified(archive_mtime)
+        .unwrap();
+
+    let extract_dir = temp_dir.path().join("extracted");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "unpack",
+            rpa_path.to_str().unwrap(),
+            "-o",
+            extract_dir.to_str().unwrap(),
+            "--preserve-mtime",
+        ])
+        .status()
+        .expect("Failed to run unpack");
+    assert!(status.success());
+
+    let extracted = fs::metadata(extract_dir.join("test.txt")).unwrap();
+    assert_eq!(extracted.modified().unwrap(), archive_mtime);
+}
+
+#[test]
+fn test_unpack_warns_on_empty_index() {
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use std::collections::BTreeMap;
+    use std::io::Write;
+
+    let temp_dir = TempDir::new().unwrap();
+
+    let index: BTreeMap<&str, Vec<(i64, i64)>> = BTreeMap::new();
+    let pickled = serde_pickle::to_vec(&index, Default::default()).unwrap();
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&pickled).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let mut archive = format!("RPA-2.0 {:016x}\n", 34).into_bytes();
+    archive.resize(34, b' ');
+    archive.extend_from_slice(&compressed);
+
+    let rpa_path = temp_dir.path().join("empty.rpa");
+    fs::write(&rpa_path, archive).unwrap();
+
+    let out_dir = temp_dir.path().join("out");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "unpack",
+            rpa_path.to_str().unwrap(),
+            "-o",
+            out_dir.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run unpack");
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("no indexed files"), "{}", stdout);
+    assert!(
+        !out_dir.exists(),
+        "No empty output directory should be created"
+    );
+}
+
+#[test]
+fn test_unpack_skip_existing() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir(&source_dir).unwrap();
+    fs::write(source_dir.join("done.txt"), "hello").unwrap();
+    fs::write(source_dir.join("partial.txt"), "complete content").unwrap();
+
+    let rpa_path = temp_dir.path().join("test.rpa");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "repack",
+            source_dir.to_str().unwrap(),
+            "-o",
+            rpa_path.to_str().unwrap(),
+        ])
+        .status()
+        .expect("Failed to run repack");
+    assert!(status.success());
+
+    // An interrupted run: one file finished, one cut short
+    let extract_dir = temp_dir.path().join("extracted");
+    fs::create_dir(&extract_dir).unwrap();
+    fs::write(extract_dir.join("done.txt"), "HELLO").unwrap();
+    fs::write(extract_dir.join("partial.txt"), "compl").unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "unpack",
+            rpa_path.to_str().unwrap(),
+            "-o",
+            extract_dir.to_str().unwrap(),
+            "--skip-existing",
+        ])
+        .output()
+        .expect("Failed to run unpack");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Skipped 1 already extracted file(s)"),
+        "{}",
+        stdout
+    );
+    assert_eq!(
+        fs::read_to_string(extract_dir.join("done.txt")).unwrap(),
+        "HELLO"
+    );
+    assert_eq!(
+        fs::read_to_string(extract_dir.join("partial.txt")).unwrap(),
+        "complete content"
+    );
+}
+
+#[test]
+fn test_rpa_roundtrip_large_entry() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir(&source_dir).unwrap();
+
+    // Larger than the single-allocation threshold, so extraction streams it
+    let content: Vec<u8> = (0..5 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+    fs::write(source_dir.join("movie.webm"), &content).unwrap();
+
+    let rpa_path = temp_dir.path().join("large.rpa");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "repack",
+            source_dir.to_str().unwrap(),
+            "-o",
+            rpa_path.to_str().unwrap(),
+        ])
+        .status()
+        .expect("Failed to run repack");
+    assert!(status.success(), "Repack should succeed");
+
+    let extract_dir = temp_dir.path().join("extracted");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "unpack",
+            rpa_path.to_str().unwrap(),
+            "-o",
+            extract_dir.to_str().unwrap(),
+        ])
+        .status()
+        .expect("Failed to run unpack");
+    assert!(status.success(), "Unpack should succeed");
+
+    assert!(fs::read(extract_dir.join("movie.webm")).unwrap() == content);
+}
+
+#[test]
+fn test_rpa_diff() {
+    let temp_dir = TempDir::new().unwrap();
+    let repack = |name: &str, files: &[(&str, &str)]| {
+        let source_dir = temp_dir.path().join(name);
+        fs::create_dir(&source_dir).unwrap();
+        for (file, content) in files {
+            fs::write(source_dir.join(file), content).unwrap();
+        }
+        let rpa_path = temp_dir.path().join(format!("{}.rpa", name));
+        let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .args([
+                "repack",
+                source_dir.to_str().unwrap(),
+                "-o",
+                rpa_path.to_str().unwrap(),
+            ])
+            .status()
+            .expect("Failed to run repack");
+        assert!(status.success(), "Repack should succeed");
+        rpa_path
+    };
+
+    let old = repack(
+        "old",
+        &[
+            ("keep.txt", "same"),
+            ("edit.txt", "abcd"),
+            ("gone.txt", "x"),
+        ],
+    );
+    let new = repack(
+        "new",
+        &[
+            ("keep.txt", "same"),
+            ("edit.txt", "abce"),
+            ("fresh.txt", "y"),
+        ],
+    );
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args(["diff", old.to_str().unwrap(), new.to_str().unwrap(), "-v"])
+        .output()
+        .expect("Failed to run diff");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Added: 1"), "{}", stdout);
+    assert!(stdout.contains("Removed: 1"), "{}", stdout);
+    assert!(stdout.contains("Modified: 1"), "{}", stdout);
+    assert!(stdout.contains("Unchanged: 1"), "{}", stdout);
+    assert!(stdout.contains("+ fresh.txt"), "{}", stdout);
+    assert!(stdout.contains("- gone.txt"), "{}", stdout);
+    assert!(stdout.contains("~ edit.txt (4 -> 4 bytes)"), "{}", stdout);
+}
+
+#[test]
+fn test_from_list() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir_all(source_dir.join("images")).unwrap();
+    fs::write(source_dir.join("script.rpy"), "label start:").unwrap();
+    fs::write(source_dir.join("images/bg.png"), "png").unwrap();
+    fs::write(source_dir.join("notes.txt"), "skip me").unwrap();
+
+    let list = temp_dir.path().join("pack.txt");
+    fs::write(
+        &list,
+        "# selected\nscript.rpy\n./images/bg.png\n\nmissing.rpy\n",
+    )
+    .unwrap();
+
+    let rpa_path = temp_dir.path().join("game.rpa");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "repack",
+            source_dir.to_str().unwrap(),
+            "-o",
+            rpa_path.to_str().unwrap(),
+            "--from-list",
+            list.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run repack");
+    assert!(output.status.success(), "Repack should succeed");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("1 listed file(s) not found"), "{}", stdout);
+    assert!(stdout.contains("missing.rpy"), "{}", stdout);
+
+    let list = temp_dir.path().join("unpack.txt");
+    fs::write(&list, "images/bg.png\nnotes.txt\n").unwrap();
+
+    let extract_dir = temp_dir.path().join("extracted");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "unpack",
+            rpa_path.to_str().unwrap(),
+            "-o",
+            extract_dir.to_str().unwrap(),
+            "--from-list",
+            list.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run unpack");
+    assert!(output.status.success(), "Unpack should succeed");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("1 listed entry(s) not found"), "{}", stdout);
+    assert!(stdout.contains("notes.txt"), "{}", stdout);
+
+    assert!(extract_dir.join("images/bg.png").exists());
+    assert!(!extract_dir.join("script.rpy").exists());
+}
+
+#[test]
+fn test_unpack_renamed_archive() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir(&source_dir).unwrap();
+    fs::write(source_dir.join("script.rpy"), "label start:").unwrap();
+
+    let rpa_path = temp_dir.path().join("game.rpa");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "repack",
+            source_dir.to_str().unwrap(),
+            "-o",
+            rpa_path.to_str().unwrap(),
+        ])
+        .status()
+        .expect("Failed to run repack");
+    assert!(status.success(), "Repack should succeed");
+
+    let dat_path = temp_dir.path().join("game.dat");
+    fs::rename(&rpa_path, &dat_path).unwrap();
+
+    let extract_dir = temp_dir.path().join("extracted");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "unpack",
+            dat_path.to_str().unwrap(),
+            "-o",
+            extract_dir.to_str().unwrap(),
+        ])
+        .status()
+        .expect("Failed to run unpack");
+    assert!(status.success(), "Unpack should recognize the header");
+    assert!(extract_dir.join("script.rpy").exists());
+
+    let junk = temp_dir.path().join("notes.txt");
+    fs::write(&junk, "just text").unwrap();
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args(["unpack", junk.to_str().unwrap()])
+        .output()
+        .expect("Failed to run unpack");
+    assert!(
+        !output.status.success(),
+        "Unpack should reject non-archives"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--force-rpa"), "{}", stderr);
+
+    // A game that renamed the magic: refused by default, read with --force-rpa
+    let mut data = fs::read(&dat_path).unwrap();
+    data[..4].copy_from_slice(b"GAM-");
+    fs::write(&dat_path, data).unwrap();
+    let unpack = |extra: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .args(["unpack", dat_path.to_str().unwrap(), "-o"])
+            .arg(temp_dir.path().join("forced"))
+            .args(extra)
+            .status()
+            .expect("Failed to run unpack")
+    };
+    assert!(!unpack(&[]).success());
+    assert!(unpack(&["--force-rpa"]).success());
+    assert!(temp_dir.path().join("forced/script.rpy").exists());
+}
+
+#[test]
+fn test_pretty_print_index() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir(&source_dir).unwrap();
+    fs::write(source_dir.join("script.rpy"), "label start:").unwrap();
+
+    let rpa_path = temp_dir.path().join("game.rpa");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "repack",
+            source_dir.to_str().unwrap(),
+            "-o",
+            rpa_path.to_str().unwrap(),
+        ])
+        .status()
+        .expect("Failed to run repack");
+    assert!(status.success(), "Repack should succeed");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args(["unpack", rpa_path.to_str().unwrap(), "--pretty-print-index"])
+        .output()
+        .expect("Failed to run unpack");
+    assert!(output.status.success(), "Dump should succeed");
+
+    let dump: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(dump["version"], "RPA-3.0");
+    let entries = dump["index"]["Dict"].as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["key"]["String"], "script.rpy");
+    // The (offset, length, prefix) tuples are shown as stored; values are still masked
+    assert!(entries[0]["value"]["List"][0]["Tuple"][0]["I64"].is_i64());
+    assert!(
+        !temp_dir.path().join("game").exists(),
+        "Nothing is extracted"
+    );
+}
+
+#[test]
+fn test_repack_match_version() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir(&source_dir).unwrap();
+    fs::write(source_dir.join("script.rpy"), "label start:\n    return\n").unwrap();
+
+    let repack = |extra: &[&str], output: &std::path::Path| {
+        let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .args(["repack", source_dir.to_str().unwrap(), "-o"])
+            .arg(output)
+            .args(extra)
+            .status()
+            .expect("Failed to run repack");
+        assert!(status.success(), "Repack should succeed");
+    };
+
+    for (version, tag) in [
+        ("3.2", "RPA-3.2"),
+        ("ALT-1.0", "ALT-1.0"),
+        ("2.0", "RPA-2.0"),
+    ] {
+        let original = temp_dir.path().join(format!("{}.rpa", tag));
+        repack(&["--version", version], &original);
+
+        let repacked = temp_dir.path().join(format!("{}-repacked.rpa", tag));
+        repack(&["--match", original.to_str().unwrap()], &repacked);
+        assert!(
+            fs::read(&repacked).unwrap().starts_with(tag.as_bytes()),
+            "--match should keep {}",
+            tag
+        );
+
+        let extract_dir = temp_dir.path().join(format!("{}-extracted", tag));
+        let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .args(["unpack", repacked.to_str().unwrap(), "-o"])
+            .arg(&extract_dir)
+            .status()
+            .expect("Failed to run unpack");
+        assert!(status.success(), "Unpack of {} should succeed", tag);
+        assert_eq!(
+            fs::read_to_string(extract_dir.join("script.rpy")).unwrap(),
+            "label start:\n    return\n"
+        );
+    }
+}
+
+#[test]
+fn test_repack_update_in_place() {
+    let temp_dir = TempDir::new().unwrap();
+    let archive = temp_dir.path().join("game.rpa");
+    fs::copy(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/rpa40.rpa"),
+        &archive,
+    )
+    .unwrap();
+    let original_size = fs::metadata(&archive).unwrap().len();
+
+    let changes_dir = temp_dir.path().join("changes");
+    fs::create_dir(&changes_dir).unwrap();
+    fs::write(
+        changes_dir.join("script.rpy"),
+        "label start:\n    \"Patched\"\n",
+    )
+    .unwrap();
+    fs::write(changes_dir.join("extra.txt"), "new file\n").unwrap();
+
+    let run = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .args(args)
+            .output()
+            .expect("Failed to run derenpy");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        String::from_utf8_lossy(&output.stdout).to_string()
+    };
+    let check = |extract_dir: &std::path::Path| {
+        run(&[
+            "unpack",
+            archive.to_str().unwrap(),
+            "-o",
+            extract_dir.to_str().unwrap(),
+        ]);
+        assert_eq!(
+            fs::read_to_string(extract_dir.join("script.rpy")).unwrap(),
+            "label start:\n    \"Patched\"\n"
+        );
+        assert_eq!(
+            fs::read_to_string(extract_dir.join("extra.txt")).unwrap(),
+            "new file\n"
+        );
+        assert_eq!(
+            fs::read_to_string(extract_dir.join("images/bg.txt")).unwrap(),
+            "not really an image\n"
+        );
+    };
+
+    let stdout = run(&[
+        "repack",
+        changes_dir.to_str().unwrap(),
+        "-o",
+        archive.to_str().unwrap(),
+        "--update",
+    ]);
+    assert!(stdout.contains("(1 replaced, 1 added)"), "{}", stdout);
+    // Same version and key; the old data and index are still there
+    let updated = fs::read(&archive).unwrap();
+    assert!(updated.starts_with(b"RPA-4.0 "));
+    assert_eq!(&updated[25..42], b"deadbeefcafef00d\n");
+    assert!(updated.len() as u64 > original_size);
+    check(&temp_dir.path().join("updated"));
+
+    run(&[
+        "repack",
+        changes_dir.to_str().unwrap(),
+        "-o",
+        archive.to_str().unwrap(),
+        "--update",
+        "--compact",
+    ]);
+    assert!(fs::metadata(&archive).unwrap().len() < updated.len() as u64);
+    check(&temp_dir.path().join("compacted"));
+}
+
+#[test]
+fn test_repack_preserves_original_order() {
+    let temp_dir = TempDir::new().unwrap();
+    let source_dir = temp_dir.path().join("source");
+    fs::create_dir_all(source_dir.join("images")).unwrap();
+    for name in ["a.txt", "b.txt", "images/c.txt"] {
+        fs::write(source_dir.join(name), format!("contents of {}\n", name)).unwrap();
+    }
+    let list = temp_dir.path().join("order.txt");
+    fs::write(&list, "images/c.txt\na.txt\nb.txt\n").unwrap();
+    let original = temp_dir.path().join("original.rpa");
+    let extract_dir = temp_dir.path().join("extracted");
+    let repacked = temp_dir.path().join("repacked.rpa");
+
+    let run = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .args(args)
+            .output()
+            .expect("Failed to run derenpy");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    };
+    run(&[
+        "repack",
+        source_dir.to_str().unwrap(),
+        "-o",
+        original.to_str().unwrap(),
+        "--from-list",
+        list.to_str().unwrap(),
+        "--order",
+        "list",
+    ]);
+    run(&[
+        "unpack",
+        original.to_str().unwrap(),
+        "-o",
+        extract_dir.to_str().unwrap(),
+    ]);
+    run(&[
+        "repack",
+        extract_dir.to_str().unwrap(),
+        "-o",
+        repacked.to_str().unwrap(),
+        "--match",
+        original.to_str().unwrap(),
+        "--order",
+        "match",
+    ]);
+
+    // Same data region byte for byte: files at the original offsets, index right after
+    let index_offset = |archive: &[u8]| {
+        let header = std::str::from_utf8(&archive[8..24]).unwrap();
+        u64::from_str_radix(header, 16).unwrap() as usize
+    };
+    let original = fs::read(&original).unwrap();
+    let repacked = fs::read(&repacked).unwrap();
+    let data = &original[51..index_offset(&original)];
+    assert!(data.starts_with(b"contents of images/c.txt\ncontents of a.txt\n"));
+    assert_eq!(index_offset(&repacked), index_offset(&original));
+    assert_eq!(&repacked[51..index_offset(&repacked)], data);
+
+    // The list order needs a list
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args(["repack", source_dir.to_str().unwrap(), "--order", "list"])
+        .output()
+        .expect("Failed to run repack");
+    assert!(!output.status.success());
+}
@@ -166,3 +166,938 @@ fn test_escape_sequences_preserved() {
 
     assert!(content.contains("\\\\n"), "Should preserve \\n escape");
 }
+
+#[test]
+fn test_menu_prompt_comment_above_choices() {
+    let temp_dir = TempDir::new().unwrap();
+
+    let script_content = r#"
+label start:
+    menu:
+        "Where should I go?"
+        "Left":
+            pass
+        "Right":
+            pass
+"#;
+    let script_path = temp_dir.path().join("script.rpy");
+    fs::write(&script_path, script_content).unwrap();
+
+    let output_dir = temp_dir.path().join("output");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "patch",
+            script_path.parent().unwrap().to_str().unwrap(),
+            "--template-only",
+            "-o",
+            output_dir.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run patch");
+
+    assert!(output.status.success());
+
+    let strings_file = output_dir.join("tl/chinese/strings.rpy");
+    let content = fs::read_to_string(&strings_file).unwrap();
+
+    let comment = content
+        .find("# Menu (script.rpy:3): \"Where should I go?\"")
+        .expect("Menu prompt should appear as a comment");
+    let left = content.find("old \"Left\"").unwrap();
+    let right = content.find("old \"Right\"").unwrap();
+    assert!(
+        comment < left && left < right,
+        "Prompt should precede its choices"
+    );
+    assert_eq!(
+        content.matches("# Menu").count(),
+        1,
+        "Choices share one group"
+    );
+}
+
+#[test]
+fn test_flatten_tl_single_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("game");
+    fs::create_dir_all(&game_dir).unwrap();
+
+    // Same dialogue before any label in two files yields the same identifier
+    fs::write(game_dir.join("a.rpy"), "\"Hello there.\"\n\"Menu\"\n").unwrap();
+    fs::write(game_dir.join("b.rpy"), "\"Hello there.\"\n").unwrap();
+
+    let output_dir = temp_dir.path().join("output");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "patch",
+            game_dir.to_str().unwrap(),
+            "--template-only",
+            "--flatten-tl",
+            "--validate",
+            "-o",
+            output_dir.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run patch");
+
+    assert!(output.status.success());
+
+    let tl_dir = output_dir.join("tl/chinese");
+    let files: Vec<_> = fs::read_dir(&tl_dir).unwrap().collect();
+    assert_eq!(files.len(), 1, "Only translations.rpy should be written");
+
+    let content = fs::read_to_string(tl_dir.join("translations.rpy")).unwrap();
+    let identifiers: Vec<&str> = content
+        .lines()
+        .filter_map(|l| l.strip_prefix("translate chinese "))
+        .collect();
+    assert_eq!(identifiers.len(), 3);
+    let unique: std::collections::HashSet<_> = identifiers.iter().collect();
+    assert_eq!(
+        unique.len(),
+        identifiers.len(),
+        "Identifiers must be unique"
+    );
+}
+
+#[test]
+fn test_existing_tl_requires_force_or_merge() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("game");
+    fs::create_dir_all(&game_dir).unwrap();
+    fs::write(
+        game_dir.join("script.rpy"),
+        "label start:\n    e \"Good morning.\"\n    menu:\n        \"Stay\":\n            pass\n",
+    )
+    .unwrap();
+
+    let output_dir = temp_dir.path().join("output");
+    let run = |extra: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .args([
+                "patch",
+                game_dir.to_str().unwrap(),
+                "--template-only",
+                "-o",
+                output_dir.to_str().unwrap(),
+            ])
+            .args(extra)
+            .output()
+            .expect("Failed to run patch")
+    };
+
+    assert!(run(&[]).status.success());
+
+    // Simulate a translator's manual edits
+    let tl_dir = output_dir.join("tl/chinese");
+    let script_tl = tl_dir.join("script.rpy");
+    let edited = fs::read_to_string(&script_tl)
+        .unwrap()
+        .replace("    e \"Good morning.\"", "    e \"早上好。\"");
+    fs::write(&script_tl, edited).unwrap();
+    let strings_tl = tl_dir.join("strings.rpy");
+    let edited = fs::read_to_string(&strings_tl)
+        .unwrap()
+        .replace("new \"Stay\"", "new \"留下\"");
+    fs::write(&strings_tl, edited).unwrap();
+
+    let output = run(&[]);
+    assert!(
+        !output.status.success(),
+        "Existing tl dir should be refused"
+    );
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--merge"));
+
+    assert!(run(&["--merge"]).status.success());
+    assert!(
+        fs::read_to_string(&script_tl)
+            .unwrap()
+            .contains("e \"早上好。\"")
+    );
+    assert!(
+        fs::read_to_string(&strings_tl)
+            .unwrap()
+            .contains("new \"留下\"")
+    );
+
+    assert!(run(&["--force"]).status.success());
+    assert!(!fs::read_to_string(&script_tl).unwrap().contains("早上好"));
+}
+
+#[test]
+fn test_translate_defines_python_block() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("game");
+    fs::create_dir_all(&game_dir).unwrap();
+    fs::write(
+        game_dir.join("script.rpy"),
+        "default status_text = \"Waiting for you\"\n\nlabel start:\n    \"Hello.\"\n",
+    )
+    .unwrap();
+
+    let output_dir = temp_dir.path().join("output");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "patch",
+            game_dir.to_str().unwrap(),
+            "--template-only",
+            "--translate-defines",
+            "-o",
+            output_dir.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run patch");
+
+    assert!(output.status.success());
+
+    let content = fs::read_to_string(output_dir.join("tl/chinese/defines.rpy")).unwrap();
+    assert!(content.contains("translate chinese python:"));
+    assert!(content.contains("    status_text = \"Waiting for you\""));
+    assert!(!output_dir.join("tl/chinese/strings.rpy").exists());
+}
+
+#[test]
+fn test_output_encoding_shift_jis() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("game");
+    fs::create_dir_all(&game_dir).unwrap();
+    fs::write(
+        game_dir.join("script.rpy"),
+        "label start:\n    \"こんにちは\"\n",
+    )
+    .unwrap();
+
+    let output_dir = temp_dir.path().join("output");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "patch",
+            game_dir.to_str().unwrap(),
+            "--template-only",
+            "--output-encoding",
+            "shift_jis",
+            "-o",
+            output_dir.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run patch");
+
+    assert!(output.status.success());
+
+    let bytes = fs::read(output_dir.join("tl/chinese/script.rpy")).unwrap();
+    assert!(std::str::from_utf8(&bytes).is_err(), "Should not be UTF-8");
+    let (decoded, had_errors) = encoding_rs::SHIFT_JIS.decode_without_bom_handling(&bytes);
+    assert!(!had_errors);
+    assert!(decoded.contains("\"こんにちは\""));
+}
+
+#[test]
+fn test_translate_failures_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("script.rpy");
+    fs::write(&input, "label start:\n    e \"Hello there.\"\n").unwrap();
+    let output_path = temp_dir.path().join("out.rpy");
+
+    // Nothing listens on port 1, so every request fails
+    let run = |extra: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .args([
+                "translate",
+                input.to_str().unwrap(),
+                "--api",
+                "ollama",
+                "--api-base",
+                "http://127.0.0.1:1",
+                "-o",
+                output_path.to_str().unwrap(),
+            ])
+            .args(extra)
+            .output()
+            .expect("Failed to run translate")
+    };
+
+    assert!(run(&[]).status.success());
+
+    let failures_path = temp_dir.path().join("out.rpy.failures.json");
+    let failures: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&failures_path).unwrap()).unwrap();
+    assert_eq!(failures[0]["line"], 2);
+    assert_eq!(failures[0]["source"], "Hello there.");
+
+    let output = run(&["--retry-failed"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Retrying 1 failed entries"));
+    assert!(failures_path.exists());
+}
+
+#[test]
+fn test_patch_manifest_carry_over() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("game");
+    fs::create_dir(&game_dir).unwrap();
+    fs::write(
+        game_dir.join("script.rpy"),
+        "label start:\n    e \"Welcome back.\"\n    e \"A new episode.\"\n",
+    )
+    .unwrap();
+
+    let manifest_path = temp_dir.path().join("manifest.json");
+    fs::write(
+        &manifest_path,
+        r#"{"strings": {"Welcome back.": "欢迎回来。"}}"#,
+    )
+    .unwrap();
+
+    let output_dir = temp_dir.path().join("output");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "patch",
+            game_dir.to_str().unwrap(),
+            "--template-only",
+            "--manifest",
+            manifest_path.to_str().unwrap(),
+            "-o",
+            output_dir.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run patch");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let content = fs::read_to_string(output_dir.join("tl/chinese/script.rpy")).unwrap();
+    assert!(content.contains("e \"欢迎回来。\""));
+
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    let chinese = &manifest["languages"]["chinese"];
+    assert_eq!(chinese["Welcome back."], "欢迎回来。");
+    assert!(chinese["A new episode."].is_null());
+
+    // A second language starts from nothing instead of reusing the Chinese strings
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "patch",
+            game_dir.to_str().unwrap(),
+            "--template-only",
+            "-l",
+            "japanese",
+            "--manifest",
+            manifest_path.to_str().unwrap(),
+            "-o",
+            output_dir.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run patch");
+    assert!(output.status.success());
+    let content = fs::read_to_string(output_dir.join("tl/japanese/script.rpy")).unwrap();
+    assert!(!content.contains("欢迎回来"));
+    let manifest: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&manifest_path).unwrap()).unwrap();
+    assert!(manifest["languages"]["japanese"]["Welcome back."].is_null());
+    assert_eq!(
+        manifest["languages"]["chinese"]["Welcome back."],
+        "欢迎回来。"
+    );
+}
+
+#[test]
+fn test_auto_plain_rpy_sources() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("MyGame").join("game");
+    fs::create_dir_all(game_dir.join("chapters")).unwrap();
+    fs::write(
+        game_dir.join("script.rpy"),
+        "label start:\n    e \"Hello there.\"\n",
+    )
+    .unwrap();
+    fs::write(
+        game_dir.join("chapters/one.rpy"),
+        "label one:\n    \"Chapter one.\"\n",
+    )
+    .unwrap();
+
+    let run = |input: &std::path::Path, extra: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .args(["auto", input.to_str().unwrap(), "--template-only"])
+            .args(extra)
+            .output()
+            .expect("Failed to run auto")
+    };
+
+    // Project root: tl/ goes into its game/ directory
+    let output = run(game_dir.parent().unwrap(), &[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Plain .rpy sources"));
+    assert!(game_dir.join("tl/chinese/script.rpy").exists());
+    assert!(game_dir.join("tl/chinese/one.rpy").exists());
+
+    // The game/ directory itself, re-run over the generated tl/ without picking it up
+    let output = run(&game_dir, &["--merge"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!game_dir.join("game").exists());
+    assert!(!game_dir.join("tl/chinese/tl").exists());
+}
+
+#[test]
+fn test_translate_dry_run_prompts() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("script.rpy");
+    fs::write(
+        &input,
+        "label start:\n    e \"First line.\"\n    e \"Second line.\"\n",
+    )
+    .unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "translate",
+            input.to_str().unwrap(),
+            "--api",
+            "openai",
+            "--api-key",
+            "sk-secret-value",
+            "--dry-run-prompts",
+            "1",
+        ])
+        .output()
+        .expect("Failed to run translate");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("[system]"));
+    assert!(stdout.contains("Translate: First line."));
+    assert!(!stdout.contains("Second line."));
+    assert!(!stdout.contains("sk-secret-value"));
+    assert!(!temp_dir.path().join("script_translated.rpy").exists());
+}
+
+#[test]
+fn test_translate_rejects_invalid_ca_cert() {
+    let temp_dir = TempDir::new().unwrap();
+    let input = temp_dir.path().join("script.rpy");
+    fs::write(&input, "label start:\n    e \"Hello.\"\n").unwrap();
+    let cert = temp_dir.path().join("proxy.pem");
+    fs::write(&cert, "not a certificate").unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "translate",
+            input.to_str().unwrap(),
+            "--api",
+            "google",
+            "--ca-cert",
+            cert.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run translate");
+
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Invalid PEM certificate"), "{}", stderr);
+}
+
+#[test]
+fn test_patch_files_filter() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("game");
+    fs::create_dir(&game_dir).unwrap();
+    fs::write(
+        game_dir.join("script.rpy"),
+        "label start:\n    \"Opening line.\"\n",
+    )
+    .unwrap();
+    fs::write(
+        game_dir.join("chapter1.rpy"),
+        "label chapter1:\n    \"Chapter one.\"\n",
+    )
+    .unwrap();
+    fs::write(
+        game_dir.join("options.rpy"),
+        "label options_note:\n    \"Not part of the story.\"\n",
+    )
+    .unwrap();
+
+    let output_dir = temp_dir.path().join("output");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "patch",
+            game_dir.to_str().unwrap(),
+            "--template-only",
+            "--files",
+            "script.rpy",
+            "--files",
+            "chapter*.rpy",
+            "-o",
+            output_dir.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run patch");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("2 of 3 match --files"), "{}", stdout);
+
+    let tl_dir = output_dir.join("tl").join("chinese");
+    assert!(tl_dir.join("script.rpy").exists());
+    assert!(tl_dir.join("chapter1.rpy").exists());
+    assert!(!tl_dir.join("options.rpy").exists());
+}
+
+#[test]
+fn test_translate_files_filter_errors() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("game");
+    fs::create_dir_all(&game_dir).unwrap();
+    let script = game_dir.join("script.rpy");
+    fs::write(&script, "label start:\n    \"Hello.\"\n").unwrap();
+
+    let translate = |input: &std::path::Path, files: &str| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .args(["translate", input.to_str().unwrap()])
+            .args(["--api", "google", "--from-cache-only", "--files", files])
+            .env("DERENPY_CACHE_DIR", temp_dir.path().join("cache"))
+            .output()
+            .expect("Failed to run translate")
+    };
+
+    // --files cannot narrow down a single file
+    let output = translate(&script, "script.rpy");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("input is a single file"));
+
+    let output = translate(&game_dir, "chapter*.rpy");
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("No script files match --files"));
+}
+
+#[test]
+fn test_patch_strings_only() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("game");
+    fs::create_dir(&game_dir).unwrap();
+    fs::write(
+        game_dir.join("script.rpy"),
+        "label start:\n    e \"Hello there.\"\n    menu:\n        \"Go left\":\n            pass\n",
+    )
+    .unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "patch",
+            game_dir.to_str().unwrap(),
+            "--api",
+            "openai",
+            "--api-key",
+            "sk-test",
+            "--strings-only",
+            "--dry-run-prompts",
+            "10",
+        ])
+        .output()
+        .expect("Failed to run patch");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Translating strings only"), "{}", stdout);
+    assert!(stdout.contains("Translate: Go left"), "{}", stdout);
+    assert!(!stdout.contains("Translate: Hello there."), "{}", stdout);
+}
+
+#[test]
+fn test_patch_dry_run_prompts_writes_nothing() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("game");
+    fs::create_dir(&game_dir).unwrap();
+    fs::write(game_dir.join("script.rpy"), "label start:\n    e \"Hi.\"\n").unwrap();
+    let output_dir = temp_dir.path().join("output");
+    let tl_file = output_dir.join("tl/chinese/script.rpy");
+    fs::create_dir_all(tl_file.parent().unwrap()).unwrap();
+    fs::write(&tl_file, "# hand-edited\n").unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "patch",
+            game_dir.to_str().unwrap(),
+            "-o",
+            output_dir.to_str().unwrap(),
+            "--api",
+            "openai",
+            "--api-key",
+            "sk-test",
+            "--dry-run-prompts",
+        ])
+        .output()
+        .expect("Failed to run patch");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Translate: Hi."));
+    assert_eq!(fs::read_to_string(&tl_file).unwrap(), "# hand-edited\n");
+}
+
+#[test]
+fn test_translate_analyze_glossary_skeleton() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("game");
+    fs::create_dir(&game_dir).unwrap();
+    fs::write(
+        game_dir.join("script.rpy"),
+        "label start:\n    \"I met Sylvie at the Quad.\"\n    \"Later, Sylvie went back to the Quad.\"\n    \"The day was long.\"\n",
+    )
+    .unwrap();
+    let glossary = temp_dir.path().join("glossary.txt");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "translate",
+            "analyze",
+            game_dir.to_str().unwrap(),
+            "-o",
+            glossary.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run translate analyze");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let content = fs::read_to_string(&glossary).unwrap();
+    assert!(
+        content.contains("Sylvie =  # 2 occurrence(s)"),
+        "{}",
+        content
+    );
+    assert!(content.contains("Quad ="), "{}", content);
+    assert!(!content.contains("The ="), "{}", content);
+}
+
+#[test]
+fn test_patch_target_dir_layout() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("game");
+    fs::create_dir_all(game_dir.join("chapters")).unwrap();
+    fs::write(
+        game_dir.join("script.rpy"),
+        "label start:\n    \"Hello.\"\n",
+    )
+    .unwrap();
+    fs::write(
+        game_dir.join("chapters/one.rpy"),
+        "label one:\n    \"Chapter one.\"\n",
+    )
+    .unwrap();
+
+    let patch = |layout: &str, output_dir: &std::path::Path| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .args([
+                "patch",
+                game_dir.to_str().unwrap(),
+                "--template-only",
+                "--target-dir-layout",
+                layout,
+                "-o",
+                output_dir.to_str().unwrap(),
+            ])
+            .output()
+            .expect("Failed to run patch")
+    };
+
+    let mirror = temp_dir.path().join("mirror");
+    let output = patch("mirror", &mirror);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(mirror.join("script.rpy").exists());
+    assert!(mirror.join("chapters/one.rpy").exists());
+    assert!(!mirror.join("tl").exists());
+
+    let flat = temp_dir.path().join("flat");
+    let output = patch("flat", &flat);
+    assert!(output.status.success());
+    assert!(flat.join("script.rpy").exists());
+    assert!(flat.join("one.rpy").exists());
+
+    let renpy = temp_dir.path().join("renpy");
+    let output = patch("renpy", &renpy);
+    assert!(output.status.success());
+    assert!(renpy.join("tl/chinese/one.rpy").exists());
+}
+
+#[test]
+fn test_patch_lang_name_override() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("game");
+    fs::create_dir_all(&game_dir).unwrap();
+    fs::write(
+        game_dir.join("script.rpy"),
+        "label start:\n    \"Hello.\"\n",
+    )
+    .unwrap();
+
+    let output_dir = temp_dir.path().join("output");
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args([
+            "patch",
+            game_dir.to_str().unwrap(),
+            "--template-only",
+            "-l",
+            "zh-CN",
+            "--lang-name",
+            "schinese",
+            "-o",
+            output_dir.to_str().unwrap(),
+        ])
+        .output()
+        .expect("Failed to run patch");
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // The directory and blocks use the Ren'Py name, never the provider code
+    assert!(!output_dir.join("tl/zh-CN").exists());
+    let content = fs::read_to_string(output_dir.join("tl/schinese/script.rpy")).unwrap();
+    assert!(content.contains("translate schinese start_"));
+    assert!(!content.contains("zh-CN"));
+}
+
+#[test]
+fn test_translate_refuses_to_overwrite_input() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("game");
+    fs::create_dir_all(&game_dir).unwrap();
+    let script = game_dir.join("script.rpy");
+    let original = "label start:\n    \"Hello.\"\n";
+    fs::write(&script, original).unwrap();
+
+    let translate = |input: &std::path::Path, output: &std::path::Path| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .args([
+                "translate",
+                input.to_str().unwrap(),
+                "--api",
+                "google",
+                "-o",
+                output.to_str().unwrap(),
+            ])
+            .output()
+            .expect("Failed to run translate")
+    };
+
+    for output in [translate(&script, &script), translate(&game_dir, &game_dir)] {
+        assert!(!output.status.success());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("--in-place"), "{}", stderr);
+    }
+    assert_eq!(fs::read_to_string(&script).unwrap(), original);
+}
+
+#[test]
+fn test_auto_dry_run_plan() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("game");
+    fs::create_dir_all(&game_dir).unwrap();
+    fs::write(
+        game_dir.join("script.rpy"),
+        "label start:\n    e \"Hello there.\"\n    \"It is late.\"\n    menu:\n        \"Stay\":\n            pass\n",
+    )
+    .unwrap();
+
+    // Packed into an archive too, so the plan reads scripts without extracting them
+    let rpa_path = temp_dir.path().join("game.rpa");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+        .args(["repack", game_dir.to_str().unwrap(), "-o"])
+        .arg(&rpa_path)
+        .status()
+        .expect("Failed to run repack");
+    assert!(status.success());
+
+    for input in [&game_dir, &rpa_path] {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .args([
+                "auto",
+                input.to_str().unwrap(),
+                "--dry-run",
+                "--api",
+                "google",
+            ])
+            .output()
+            .expect("Failed to run auto");
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            stdout.contains("Total: 3 dialogues, 1 strings"),
+            "{}",
+            stdout
+        );
+        assert!(
+            stdout.contains("Estimated requests: 1 (google"),
+            "{}",
+            stdout
+        );
+    }
+
+    assert!(!game_dir.join("tl").exists(), "Nothing is written");
+    assert!(!temp_dir.path().join("game_translation").exists());
+}
+
+#[test]
+fn test_translate_from_cache_only() {
+    let temp_dir = TempDir::new().unwrap();
+    let cache_dir = temp_dir.path().join("cache");
+    let input = temp_dir.path().join("script.rpy");
+    fs::write(
+        &input,
+        "label start:\n    e \"Hello there.\"\n    e \"Not in the cache.\"\n",
+    )
+    .unwrap();
+    let memory = temp_dir.path().join("memory.json");
+    fs::write(
+        &memory,
+        r#"[{"source": "Hello there.", "lang": "JA", "provider": "deepl", "translated": "こんにちは。"}]"#,
+    )
+    .unwrap();
+
+    let derenpy = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .env("DERENPY_CACHE_DIR", &cache_dir)
+            .args(args)
+            .output()
+            .expect("Failed to run derenpy")
+    };
+    assert!(
+        derenpy(&["cache", "import", memory.to_str().unwrap()])
+            .status
+            .success()
+    );
+
+    // DeepL without a key: nothing is ever sent
+    let output_path = temp_dir.path().join("out.rpy");
+    let output = derenpy(&[
+        "translate",
+        input.to_str().unwrap(),
+        "--api",
+        "deepl",
+        "-l",
+        "ja",
+        "--source-lang",
+        "en",
+        "--from-cache-only",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", stdout);
+    assert!(
+        stdout.contains("Cache coverage: 1/2 entries (50.0%), 1 left untranslated"),
+        "{}",
+        stdout
+    );
+
+    let translated = fs::read_to_string(&output_path).unwrap();
+    assert!(translated.contains("e \"こんにちは。\""));
+    assert!(translated.contains("e \"Not in the cache.\""));
+    assert!(!temp_dir.path().join("out.rpy.failures.json").exists());
+}
+
+#[test]
+fn test_patch_reports_undecodable_scripts() {
+    let temp_dir = TempDir::new().unwrap();
+    let game_dir = temp_dir.path().join("game");
+    fs::create_dir_all(&game_dir).unwrap();
+    let (script, _, _) = encoding_rs::SHIFT_JIS.encode("label start:\n    \"こんにちは\"\n");
+    fs::write(game_dir.join("script.rpy"), &script).unwrap();
+
+    let patch = |extra: &[&str], output_dir: &str| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .args(["patch", game_dir.to_str().unwrap(), "--template-only", "-o"])
+            .arg(temp_dir.path().join(output_dir))
+            .args(extra)
+            .output()
+            .expect("Failed to run patch")
+    };
+
+    let output = patch(&[], "as-utf8");
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("invalid UTF-8 sequence(s) in 1 script(s) were replaced with U+FFFD"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("--encoding"));
+
+    let output = patch(&["--encoding", "shift_jis"], "as-sjis");
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("U+FFFD"));
+    let generated =
+        fs::read_to_string(temp_dir.path().join("as-sjis/tl/chinese/script.rpy")).unwrap();
+    assert!(generated.contains("\"こんにちは\""));
+}
+
+#[test]
+fn test_translate_reports_undecodable_scripts() {
+    let temp_dir = TempDir::new().unwrap();
+    let script = temp_dir.path().join("script.rpy");
+    let (bytes, _, _) = encoding_rs::SHIFT_JIS.encode("label start:\n    \"こんにちは\"\n");
+    fs::write(&script, &bytes).unwrap();
+
+    // Translated strictly from an empty cache, so nothing is sent
+    let translate = |extra: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_derenpy"))
+            .args(["translate", script.to_str().unwrap()])
+            .args(["--api", "google", "-l", "ja", "--from-cache-only"])
+            .args(extra)
+            .env("DERENPY_CACHE_DIR", temp_dir.path().join("cache"))
+            .output()
+            .expect("Failed to run translate")
+    };
+
+    let output = translate(&[]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("invalid UTF-8 sequence(s) in 1 script(s) were replaced with U+FFFD"),
+        "{}",
+        stdout
+    );
+
+    let output = translate(&["--encoding", "shift_jis"]);
+    assert!(output.status.success());
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("U+FFFD"));
+}